@@ -0,0 +1,344 @@
+//! Learning progress across a series of snapshots.
+//!
+//! Each snapshot records the learning status of every card at one point in
+//! time. Comparing the last snapshot of every week with the one before shows
+//! how cards move from new to learning to known and how fast the vocabulary
+//! grows.
+
+use crate::duocards::models::{LearningStatus, VocabularyCard};
+use crate::error::{DuoloadError, Result};
+use crate::output::timestamp::UtcDateTime;
+use crate::snapshot::{Snapshot, first_cards};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Number of cards in each learning status.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct StatusCounts {
+    pub new: usize,
+    pub learning: usize,
+    pub known: usize,
+}
+
+impl StatusCounts {
+    fn count(cards: &[VocabularyCard]) -> Self {
+        let mut counts = Self::default();
+        for card in cards {
+            match card.status {
+                LearningStatus::New => counts.new += 1,
+                LearningStatus::Learning => counts.learning += 1,
+                LearningStatus::Known => counts.known += 1,
+            }
+        }
+        counts
+    }
+
+    pub fn total(&self) -> usize {
+        self.new + self.learning + self.known
+    }
+}
+
+/// Progress during one week, measured against the previous week's last snapshot.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WeekProgress {
+    /// Monday of the week
+    pub week_start: String,
+    /// Capture time of the snapshot the week is measured by
+    pub snapshot_at: String,
+    pub counts: StatusCounts,
+    /// Words that were not in the deck before
+    pub added: usize,
+    pub new_to_learning: usize,
+    pub learning_to_known: usize,
+    pub new_to_known: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProgressReport {
+    pub weeks: Vec<WeekProgress>,
+    /// Average number of words added per week after the first, including
+    /// weeks without a snapshot
+    pub words_per_week: f64,
+    /// Average number of cards becoming known per week after the first,
+    /// including weeks without a snapshot
+    pub known_per_week: f64,
+    /// Consecutive weeks, up to the latest, in which at least one card became
+    /// known; a week without a snapshot ends the streak
+    pub known_streak: usize,
+}
+
+impl ProgressReport {
+    /// Builds the report from snapshots of one deck, in any order.
+    pub fn from_snapshots(snapshots: &[Snapshot]) -> Result<Self> {
+        if snapshots.is_empty() {
+            return Err(DuoloadError::Snapshot(
+                "At least one snapshot is needed".to_string(),
+            ));
+        }
+
+        let mut dated = Vec::new();
+        for snapshot in snapshots {
+            let created_at =
+                UtcDateTime::parse(&snapshot.metadata.created_at).ok_or_else(|| {
+                    DuoloadError::Snapshot(format!(
+                        "Invalid capture time {}",
+                        snapshot.metadata.created_at
+                    ))
+                })?;
+            dated.push((created_at, snapshot));
+        }
+        // Capture times with different offsets do not sort as text
+        dated.sort_by_key(|(created_at, _)| created_at.unix_secs());
+
+        // The last snapshot of every week stands for that week
+        let mut weekly: Vec<(i64, &Snapshot)> = Vec::new();
+        for (created_at, snapshot) in &dated {
+            let week = week_number(created_at);
+            match weekly.last_mut() {
+                Some((last_week, last)) if *last_week == week => *last = snapshot,
+                _ => weekly.push((week, snapshot)),
+            }
+        }
+        let week_starts: HashMap<i64, String> = dated
+            .iter()
+            .map(|(created_at, _)| (week_number(created_at), created_at.week_start()))
+            .collect();
+
+        let mut weeks = Vec::new();
+        let mut previous: HashMap<String, LearningStatus> = HashMap::new();
+        for (week_number, snapshot) in &weekly {
+            let week_start = week_starts[week_number].clone();
+            let cards = first_cards(snapshot.convert_pages()?);
+            let mut week = WeekProgress {
+                week_start,
+                snapshot_at: snapshot.metadata.created_at.clone(),
+                counts: StatusCounts::count(&cards),
+                added: 0,
+                new_to_learning: 0,
+                learning_to_known: 0,
+                new_to_known: 0,
+            };
+            for card in &cards {
+                match (previous.get(&card.word), &card.status) {
+                    (None, _) => week.added += 1,
+                    (Some(LearningStatus::New), LearningStatus::Learning) => {
+                        week.new_to_learning += 1
+                    }
+                    (Some(LearningStatus::Learning), LearningStatus::Known) => {
+                        week.learning_to_known += 1
+                    }
+                    (Some(LearningStatus::New), LearningStatus::Known) => week.new_to_known += 1,
+                    _ => {}
+                }
+            }
+            // Everything in the first snapshot counts as added, which says nothing about growth
+            if weeks.is_empty() {
+                week.added = 0;
+            }
+            previous = cards
+                .into_iter()
+                .map(|card| (card.word, card.status))
+                .collect();
+            weeks.push(week);
+        }
+
+        let measured = &weeks[1..];
+        // Weeks without a snapshot count too, so gaps do not inflate the averages
+        let elapsed = weekly[weekly.len() - 1].0 - weekly[0].0;
+        let average = |value: fn(&WeekProgress) -> usize| {
+            if elapsed == 0 {
+                0.0
+            } else {
+                measured.iter().map(value).sum::<usize>() as f64 / elapsed as f64
+            }
+        };
+        let words_per_week = average(|week| week.added);
+        let known_per_week = average(|week| week.learning_to_known + week.new_to_known);
+        let mut known_streak = 0;
+        for i in (1..weeks.len()).rev() {
+            if weeks[i].learning_to_known + weeks[i].new_to_known == 0 {
+                break;
+            }
+            known_streak += 1;
+            if weekly[i - 1].0 + 1 != weekly[i].0 {
+                break;
+            }
+        }
+
+        Ok(Self {
+            weeks,
+            words_per_week,
+            known_per_week,
+            known_streak,
+        })
+    }
+}
+
+/// Number of the week of `time`, counting the weeks from Monday to Sunday.
+fn week_number(time: &UtcDateTime) -> i64 {
+    // 1970-01-01 was a Thursday
+    (time.unix_days() + 3).div_euclid(7)
+}
+
+impl fmt::Display for ProgressReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<12} {:>7} {:>7} {:>9} {:>7} {:>7} {:>11} {:>11} {:>11}",
+            "Week",
+            "Total",
+            "New",
+            "Learning",
+            "Known",
+            "Added",
+            "New>Learn",
+            "Learn>Known",
+            "New>Known"
+        )?;
+        for week in &self.weeks {
+            writeln!(
+                f,
+                "{:<12} {:>7} {:>7} {:>9} {:>7} {:>7} {:>11} {:>11} {:>11}",
+                week.week_start,
+                week.counts.total(),
+                week.counts.new,
+                week.counts.learning,
+                week.counts.known,
+                week.added,
+                week.new_to_learning,
+                week.learning_to_known,
+                week.new_to_known
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "Words added per week: {:.1}", self.words_per_week)?;
+        writeln!(f, "Cards known per week: {:.1}", self.known_per_week)?;
+        write!(
+            f,
+            "Known streak: {} week{}",
+            self.known_streak,
+            if self.known_streak == 1 { "" } else { "s" }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::{SNAPSHOT_FORMAT_VERSION, SnapshotMetadata};
+    use serde_json::{Value, json};
+
+    fn snapshot(created_at: &str, cards: &[(&str, i32)]) -> Snapshot {
+        let edges: Vec<Value> = cards
+            .iter()
+            .map(|(front, known_count)| {
+                json!({
+                    "node": {
+                        "id": front, "front": front, "back": "translation", "hint": null,
+                        "waiting": null, "knownCount": known_count, "svg": null, "__typename": "Card"
+                    },
+                    "cursor": front
+                })
+            })
+            .collect();
+        Snapshot {
+            metadata: SnapshotMetadata {
+                format_version: SNAPSHOT_FORMAT_VERSION,
+                deck_id: "deck".to_string(),
+                created_at: created_at.to_string(),
+                tool_version: "0.1.2".to_string(),
+                pages: 1,
+                complete: true,
+            },
+            pages: vec![json!({
+                "data": {"node": {
+                    "__typename": "Deck",
+                    "cards": {"edges": edges, "pageInfo": {"endCursor": null, "hasNextPage": false}},
+                    "id": "deck"
+                }},
+                "extensions": {"releaseId": null}
+            })],
+            cards: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_weekly_progress() -> Result<()> {
+        let snapshots = vec![
+            // Week 2 is passed out of order and has two snapshots; the later one counts
+            snapshot("2025-06-10T08:00:00Z", &[("a", 1), ("b", 0), ("c", 0)]),
+            snapshot("2025-06-02T08:00:00Z", &[("a", 0), ("b", 0)]),
+            snapshot(
+                "2025-06-12T08:00:00Z",
+                &[("a", 1), ("b", 5), ("c", 0), ("d", 0)],
+            ),
+            snapshot(
+                "2025-06-16T08:00:00Z",
+                &[("a", 5), ("b", 5), ("c", 0), ("d", 0)],
+            ),
+        ];
+
+        let report = ProgressReport::from_snapshots(&snapshots)?;
+        assert_eq!(report.weeks.len(), 3);
+
+        let first = &report.weeks[0];
+        assert_eq!(first.week_start, "2025-06-02");
+        assert_eq!(first.added, 0);
+        assert_eq!(first.counts.new, 2);
+
+        let second = &report.weeks[1];
+        assert_eq!(second.snapshot_at, "2025-06-12T08:00:00Z");
+        assert_eq!(second.added, 2);
+        assert_eq!(second.new_to_learning, 1);
+        assert_eq!(second.new_to_known, 1);
+
+        let third = &report.weeks[2];
+        assert_eq!(third.learning_to_known, 1);
+        assert_eq!(
+            third.counts,
+            StatusCounts {
+                new: 2,
+                learning: 0,
+                known: 2
+            }
+        );
+
+        assert_eq!(report.words_per_week, 1.0);
+        assert_eq!(report.known_per_week, 1.0);
+        assert_eq!(report.known_streak, 2);
+        assert!(report.to_string().contains("Known streak: 2 weeks"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_weeks_without_snapshots() -> Result<()> {
+        let snapshots = vec![
+            snapshot("2025-06-02T08:00:00Z", &[("a", 0), ("b", 0)]),
+            // Earlier than the next one, though it sorts after it as text
+            snapshot("2025-06-10T09:00:00+02:00", &[("a", 5), ("b", 0), ("c", 0)]),
+            snapshot("2025-06-10T08:00:00Z", &[("a", 0), ("b", 0)]),
+            // Nothing was captured in the week of 2025-06-16
+            snapshot(
+                "2025-06-24T08:00:00Z",
+                &[("a", 5), ("b", 5), ("c", 0), ("d", 0)],
+            ),
+        ];
+
+        let report = ProgressReport::from_snapshots(&snapshots)?;
+        assert_eq!(report.weeks.len(), 3);
+        assert_eq!(report.weeks[1].snapshot_at, "2025-06-10T08:00:00Z");
+        assert_eq!(report.weeks[1].added, 0);
+        assert_eq!(report.weeks[2].week_start, "2025-06-23");
+        // Two words and two known cards over three weeks
+        assert_eq!(report.words_per_week, 2.0 / 3.0);
+        assert_eq!(report.known_per_week, 2.0 / 3.0);
+        assert_eq!(report.known_streak, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_requires_snapshots() {
+        assert!(ProgressReport::from_snapshots(&[]).is_err());
+    }
+}
@@ -0,0 +1,105 @@
+//! Scheduling of the cards in full Anki collections (.colpkg).
+//!
+//! A collection package has the same layout as a deck package, but Anki
+//! opens it in place of the current collection instead of importing its
+//! notes, so the cards keep the scheduling written into the collection.
+//! genanki writes every card as new; cards answered correctly in Duocards are
+//! turned into review cards here.
+
+use crate::duocards::models::VocabularyCard;
+use crate::error::Result;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Card type and queue of review cards.
+const REVIEW: i64 = 2;
+
+/// Ease factor of new review cards, 250%.
+const DEFAULT_FACTOR: i64 = 2500;
+
+/// Longest interval given to a card, in days.
+const MAX_INTERVAL: u32 = 256;
+
+const SECS_PER_DAY: i64 = 86400;
+
+/// Review state of a card answered correctly in Duocards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CardSchedule {
+    /// Days between reviews, doubling with every correct answer
+    pub interval: u32,
+    /// Day the card is due, in days since 1970-01-01, or `None` for the day
+    /// the collection is written
+    pub due_day: Option<i64>,
+    /// How often the card was answered correctly
+    pub reps: u32,
+}
+
+impl CardSchedule {
+    /// The schedule of `card`, or `None` if it stays a new card.
+    ///
+    /// Cards without a known count get the lowest count of their status.
+    pub fn from_card(card: &VocabularyCard) -> Option<Self> {
+        let known_count = card
+            .known_count
+            .unwrap_or_else(|| card.status.min_known_count());
+        if known_count <= 0 {
+            return None;
+        }
+        let reps = known_count as u32;
+        Some(Self {
+            interval: 1u32
+                .checked_shl(reps - 1)
+                .unwrap_or(MAX_INTERVAL)
+                .min(MAX_INTERVAL),
+            due_day: card.waiting_until.map(|time| time.unix_days()),
+            reps,
+        })
+    }
+}
+
+/// Makes the cards of the notes with the GUIDs in `schedules`, in the
+/// collection at `path`, review cards with their schedule. Cards without a
+/// due day are due on the day of `timestamp`, in seconds since 1970.
+pub fn schedule_cards(
+    path: &Path,
+    schedules: &HashMap<String, CardSchedule>,
+    timestamp: f64,
+) -> Result<()> {
+    if schedules.is_empty() {
+        return Ok(());
+    }
+    let mut connection = rusqlite::Connection::open(path).map_err(database_error)?;
+    let transaction = connection.transaction().map_err(database_error)?;
+    // Review cards are due on a day counted from the creation of the collection
+    let created: i64 = transaction
+        .query_row("SELECT crt FROM col", [], |row| row.get(0))
+        .map_err(database_error)?;
+    let today = timestamp as i64 / SECS_PER_DAY;
+    {
+        let mut update = transaction
+            .prepare(
+                "UPDATE cards SET type = ?1, queue = ?1, due = ?2, ivl = ?3, factor = ?4,
+                 reps = ?5 WHERE nid IN (SELECT id FROM notes WHERE guid = ?6)",
+            )
+            .map_err(database_error)?;
+        for (guid, schedule) in schedules {
+            let due = schedule.due_day.unwrap_or(today) - created / SECS_PER_DAY;
+            update
+                .execute(rusqlite::params![
+                    REVIEW,
+                    due.max(0),
+                    schedule.interval,
+                    DEFAULT_FACTOR,
+                    schedule.reps,
+                    guid
+                ])
+                .map_err(database_error)?;
+        }
+    }
+    transaction.commit().map_err(database_error)?;
+    Ok(())
+}
+
+fn database_error(e: rusqlite::Error) -> anyhow::Error {
+    anyhow::anyhow!("Failed to write Anki collection: {}", e)
+}
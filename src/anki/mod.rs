@@ -1 +1,5 @@
+pub mod collection;
 pub mod note;
+pub mod package;
+pub mod verify;
+pub mod writer;
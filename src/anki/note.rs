@@ -3,9 +3,16 @@
 //! This module provides functionality to convert vocabulary cards to Anki notes,
 //! handling the mapping between our vocabulary model and Anki's note format.
 
-use crate::duocards::models::VocabularyCard;
-use anyhow::Result;
+use crate::duocards::models::{LearningStatus, VocabularyCard};
+use crate::transform::sanitize::escape_html;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
 use genanki_rs::{Field, Model, Note, Template};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::Path;
 
 /// A note representing a vocabulary item that can be converted to an Anki note.
 #[derive(Debug)]
@@ -13,29 +20,162 @@ pub struct VocabularyNote {
     pub word: String,
     pub translation: String,
     pub example: Option<String>,
+    pub hint: Option<String>,
+    /// File name of the picture in the package's media
+    pub image: Option<String>,
+    /// File name of the pronunciation in the package's media
+    pub audio: Option<String>,
+    /// ID of the card in Duocards
+    pub source_id: Option<String>,
+    pub created_at: Option<String>,
+    pub waiting_until: Option<String>,
+    pub status: LearningStatus,
+    /// How often the card was answered correctly in Duocards
+    pub known_count: Option<i32>,
+    /// Where and when the card was fetched, see [`PROVENANCE_FIELDS`]
+    pub provenance: Option<String>,
+    /// GUID of the note; by default derived from its fields
+    pub guid: Option<String>,
     pub tags: Vec<String>,
+    /// Whether the text fields are escaped, so words such as `a < b` or
+    /// `{{x}}` are shown as they are instead of as HTML; on by default
+    pub escape_html: bool,
+}
+
+/// Prefix of the tags duoload adds to notes, unless changed with
+/// [`TagScheme::prefix`].
+pub const DEFAULT_TAG_PREFIX: &str = "duoload";
+
+/// How the tags duoload adds to notes are named, e.g. to follow the tag
+/// conventions of an existing collection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagScheme {
+    /// Prefix of every tag; tags have none if it is empty
+    pub prefix: String,
+    /// Names of learning statuses in status tags, instead of `new`,
+    /// `learning` and `known`
+    pub status_names: BTreeMap<LearningStatus, String>,
+}
+
+impl Default for TagScheme {
+    fn default() -> Self {
+        Self {
+            prefix: DEFAULT_TAG_PREFIX.to_string(),
+            status_names: BTreeMap::new(),
+        }
+    }
+}
+
+impl TagScheme {
+    /// The tag of notes with `status`, e.g. `duoload_known`, or just the
+    /// status name without a prefix.
+    pub fn status_tag(&self, status: &LearningStatus) -> String {
+        let name = self
+            .status_names
+            .get(status)
+            .map_or(status.as_str(), String::as_str);
+        if self.prefix.is_empty() {
+            tag(name)
+        } else {
+            tag(&format!("{}_{}", self.prefix, name))
+        }
+    }
+
+    /// A hierarchical tag such as `duoload::deck::German`, or `deck::German`
+    /// without a prefix.
+    pub fn tag(&self, kind: &str, value: &str) -> String {
+        if self.prefix.is_empty() {
+            format!("{}::{}", kind, tag(value))
+        } else {
+            format!("{}::{}::{}", tag(&self.prefix), kind, tag(value))
+        }
+    }
+}
+
+/// Parses `status=name`, e.g. `known=mature`, for [`TagScheme::status_names`].
+pub fn parse_status_name(s: &str) -> std::result::Result<(LearningStatus, String), String> {
+    let (status, name) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Expected STATUS=NAME, e.g. known=mature, got {:?}", s))?;
+    let status = LearningStatus::ALL
+        .into_iter()
+        .find(|known| known.as_str() == status.trim())
+        .ok_or_else(|| {
+            format!(
+                "Unknown status {:?}, expected new, learning or known",
+                status
+            )
+        })?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(format!("The tag name of {} is empty", status.as_str()));
+    }
+    Ok((status, name.to_string()))
 }
 
 impl From<VocabularyCard> for VocabularyNote {
     fn from(card: VocabularyCard) -> Self {
-        let tags = match card.status {
-            crate::duocards::models::LearningStatus::New => vec!["duoload_new".to_string()],
-            crate::duocards::models::LearningStatus::Learning => {
-                vec!["duoload_learning".to_string()]
+        Self::new(card, &TagScheme::default())
+    }
+}
+
+impl VocabularyNote {
+    /// The note of `card`, tagged with its status and labels as named by
+    /// `scheme`.
+    pub fn new(card: VocabularyCard, scheme: &TagScheme) -> Self {
+        let mut tags = vec![scheme.status_tag(&card.status)];
+        tags.extend(card.labels.iter().map(|label| scheme.tag("label", label)));
+        if let Some(language) = &card.language {
+            for code in [&language.word, &language.translation] {
+                let language_tag = format!("lang::{}", tag(code));
+                if code != "unknown" && !tags.contains(&language_tag) {
+                    tags.push(language_tag);
+                }
             }
-            crate::duocards::models::LearningStatus::Known => vec!["duoload_known".to_string()],
-        };
+        }
 
         Self {
             word: card.word,
             translation: card.translation,
             example: card.example,
+            hint: card.hint,
+            image: None,
+            audio: None,
+            source_id: card.source_id,
+            created_at: card.created_at,
+            waiting_until: card.waiting_until.map(|time| time.rfc3339()),
+            status: card.status,
+            known_count: card.known_count,
+            provenance: card.provenance.map(|provenance| provenance.to_string()),
+            guid: None,
             tags,
+            escape_html: true,
         }
     }
-}
 
-impl VocabularyNote {
+    /// Adds tags to the status tag, e.g. the deck and export date tags.
+    pub fn with_tags(mut self, tags: impl IntoIterator<Item = String>) -> Self {
+        self.tags.extend(tags);
+        self
+    }
+
+    /// Puts the fields into the note as they are, keeping HTML in them for
+    /// Anki to render, if `enabled` is false.
+    pub fn with_html_escaping(mut self, enabled: bool) -> Self {
+        self.escape_html = enabled;
+        self
+    }
+
+    /// Gives the note the same GUID in every export of the card, see [`note_guid`].
+    ///
+    /// Cards without a Duocards ID keep a GUID derived from their fields.
+    pub fn with_stable_guid(mut self, deck_id: &str) -> Self {
+        if let Some(card_id) = &self.source_id {
+            self.guid = Some(note_guid(deck_id, card_id));
+        }
+        self
+    }
+
     /// Creates a new Anki note from this vocabulary note.
     ///
     /// # Arguments
@@ -46,35 +186,364 @@ impl VocabularyNote {
     ///
     /// A Result containing either the created Anki note or an error if creation fails.
     pub fn to_anki_note(&self, model: &Model) -> Result<Note> {
-        let fields = vec![
-            self.word.as_str(),
-            self.translation.as_str(),
-            self.example.as_deref().unwrap_or(""),
+        self.to_anki_note_with_fields(model, ExtraFields::default())
+    }
+
+    /// Creates an Anki note for a model with the `extra` fields, filling
+    /// them with the Duocards ID, timestamps, status, priority, hint,
+    /// provenance and known count of the card.
+    ///
+    /// Text fields are escaped unless [`VocabularyNote::escape_html`] is
+    /// false; the image and sound references never are.
+    pub fn to_anki_note_with_fields<'a>(
+        &'a self,
+        model: &Model,
+        extra: ExtraFields,
+    ) -> Result<Note> {
+        let image = self
+            .image
+            .as_ref()
+            .map(|name| format!("<img src=\"{}\">", name))
+            .unwrap_or_default();
+        let audio = self
+            .audio
+            .as_ref()
+            .map(|name| format!("[sound:{}]", name))
+            .unwrap_or_default();
+        let text = |value: &'a str| {
+            if self.escape_html {
+                escape_html(value)
+            } else {
+                Cow::Borrowed(value)
+            }
+        };
+        let mut fields = vec![
+            text(&self.word),
+            text(&self.translation),
+            text(self.example.as_deref().unwrap_or("")),
+            Cow::Borrowed(image.as_str()),
+            Cow::Borrowed(audio.as_str()),
         ];
+        if extra.source {
+            fields.extend([
+                text(self.source_id.as_deref().unwrap_or("")),
+                text(self.created_at.as_deref().unwrap_or("")),
+                text(self.waiting_until.as_deref().unwrap_or("")),
+            ]);
+        }
+        let priority = self.priority().to_string();
+        if extra.status {
+            fields.extend([
+                Cow::Borrowed(self.status.as_str()),
+                Cow::Borrowed(priority.as_str()),
+            ]);
+        }
+        if extra.hint {
+            fields.push(text(self.hint.as_deref().unwrap_or("")));
+        }
+        if extra.provenance {
+            fields.push(text(self.provenance.as_deref().unwrap_or("")));
+        }
+        let known_count = self
+            .known_count
+            .map(|count| count.to_string())
+            .unwrap_or_default();
+        if extra.known_count {
+            fields.push(Cow::Borrowed(known_count.as_str()));
+        }
 
+        let fields: Vec<&str> = fields.iter().map(|field| field.as_ref()).collect();
         let mut note = Note::new(model.clone(), fields)?;
         note = note.tags(self.tags.clone());
+        if let Some(guid) = &self.guid {
+            note = note.guid(guid);
+        }
         Ok(note)
     }
+
+    /// Priority of the card for studying, from 0 for known cards to
+    /// [`MAX_PRIORITY`] for cards never answered correctly. Cards without a
+    /// known count get the priority of the lowest count of their status.
+    pub fn priority(&self) -> i32 {
+        let known_count = self
+            .known_count
+            .unwrap_or_else(|| self.status.min_known_count());
+        MAX_PRIORITY - known_count.clamp(0, MAX_PRIORITY)
+    }
+}
+
+/// GUID of the note for a Duocards card, from the IDs of the deck and card.
+///
+/// Anki updates the note with the same GUID when importing a package, so
+/// re-importing a later export updates the notes of changed cards instead of
+/// adding them again, even if their word or translation changed.
+pub fn note_guid(deck_id: &str, card_id: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(deck_id.as_bytes());
+    hasher.update([0x1f]);
+    hasher.update(card_id.as_bytes());
+    hasher.finalize()[..8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Turns `name` into a single Anki tag.
+///
+/// Anki separates tags with spaces, so whitespace becomes `_`.
+pub fn tag(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join("_")
+}
+
+/// Why `card` cannot become an Anki note, if it cannot.
+///
+/// Anki stores the fields of a note joined by the `\x1f` control character,
+/// so fields with control characters other than line breaks and tabs would
+/// corrupt the collection; a note without a word has no front to show.
+pub fn invalid_card_reason(card: &VocabularyCard) -> Option<String> {
+    if card.word.trim().is_empty() {
+        return Some("the word is empty".to_string());
+    }
+    let fields = [
+        ("word", Some(&card.word)),
+        ("translation", Some(&card.translation)),
+        ("example", card.example.as_ref()),
+        ("hint", card.hint.as_ref()),
+        ("source ID", card.source_id.as_ref()),
+        ("creation time", card.created_at.as_ref()),
+    ];
+    let labels = card.labels.iter().map(|label| ("label", Some(label)));
+    fields.into_iter().chain(labels).find_map(|(name, value)| {
+        let bad = value?
+            .chars()
+            .find(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t'))?;
+        Some(format!(
+            "the {} contains the control character U+{:04X}",
+            name, bad as u32
+        ))
+    })
+}
+
+/// Fields with the Duocards ID and timestamps of a card, which models can
+/// have after the regular fields. The default templates do not show them.
+pub const SOURCE_FIELDS: [&str; 3] = ["SourceId", "Created", "WaitingUntil"];
+
+/// Fields with the Duocards status (`new`, `learning` or `known`) and the
+/// [`VocabularyNote::priority`] of a card, which models can have after the
+/// regular and source fields, e.g. to build filtered decks with
+/// `Status:learning` or `Priority:5`. The default templates do not show them.
+pub const STATUS_FIELDS: [&str; 2] = ["Status", "Priority"];
+
+/// Field with the hint of a card, which models can have after the regular,
+/// source and status fields. The default templates show it on the back, or with
+/// [`QuestionField::Hint`] on the front.
+pub const HINT_FIELDS: [&str; 1] = ["Hint"];
+
+/// Field with the [`Provenance`] of a card, which models can have after all
+/// other fields. The default templates do not show it.
+///
+/// [`Provenance`]: crate::duocards::models::Provenance
+pub const PROVENANCE_FIELDS: [&str; 1] = ["Provenance"];
+
+/// Field with how often a card was answered correctly in Duocards, which
+/// models can have after all other fields, e.g. for filtered decks with
+/// `KnownCount:3` or scheduling add-ons. Empty for cards without a count.
+/// The default templates do not show it.
+pub const KNOWN_COUNT_FIELDS: [&str; 1] = ["KnownCount"];
+
+/// Priority of cards never answered correctly; one less per correct answer.
+pub const MAX_PRIORITY: i32 = 5;
+
+/// Optional fields of the vocabulary model, added after the regular fields
+/// in the order of the struct.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExtraFields {
+    /// [`SOURCE_FIELDS`]
+    pub source: bool,
+    /// [`STATUS_FIELDS`]
+    pub status: bool,
+    /// [`HINT_FIELDS`]
+    pub hint: bool,
+    /// [`PROVENANCE_FIELDS`]
+    pub provenance: bool,
+    /// [`KNOWN_COUNT_FIELDS`]
+    pub known_count: bool,
+}
+
+impl ExtraFields {
+    /// Names of the enabled fields, in model order.
+    pub fn names(&self) -> Vec<&'static str> {
+        let mut names = Vec::new();
+        if self.source {
+            names.extend(SOURCE_FIELDS);
+        }
+        if self.status {
+            names.extend(STATUS_FIELDS);
+        }
+        if self.hint {
+            names.extend(HINT_FIELDS);
+        }
+        if self.provenance {
+            names.extend(PROVENANCE_FIELDS);
+        }
+        if self.known_count {
+            names.extend(KNOWN_COUNT_FIELDS);
+        }
+        names
+    }
+
+    /// ID of the vocabulary model with these fields: [`MODEL_ID`] without
+    /// extra fields, and a different ID for each combination of them.
+    ///
+    /// Anki keeps the fields of a note type from its first import, so a
+    /// note type whose fields changed needs an ID of its own.
+    pub fn model_id(&self) -> i64 {
+        let flags = [
+            self.source,
+            self.status,
+            self.hint,
+            self.provenance,
+            self.known_count,
+        ];
+        let bits = flags
+            .iter()
+            .enumerate()
+            .filter(|(_, enabled)| **enabled)
+            .fold(0, |bits, (bit, _)| bits | 1 << bit);
+        MODEL_ID + bits
+    }
+}
+
+/// Model ID - fixed for consistency; models with extra fields get the IDs
+/// after it, see [`ExtraFields::model_id`]
+pub const MODEL_ID: i64 = 1607392319;
+
+const DEFAULT_QFMT: &str = "{{Front}}";
+const DEFAULT_AFMT: &str = "{{FrontSide}}\n\n<hr id=answer>\n\n{{Back}}\n\n{{#Example}}<div class=\"example\">{{Example}}</div>{{/Example}}\n\n{{#Image}}<div class=\"image\">{{Image}}</div>{{/Image}}\n\n{{Audio}}";
+const EXAMPLE_BLOCK: &str = "{{#Example}}<div class=\"example\">{{Example}}</div>{{/Example}}";
+const HINT_BLOCK: &str = "{{#Hint}}<div class=\"hint\">{{Hint}}</div>{{/Hint}}";
+
+/// Field shown below the word on the front of the default templates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum QuestionField {
+    /// Only the word
+    #[default]
+    None,
+    /// The hint, which needs the [`HINT_FIELDS`]
+    Hint,
+    /// The example sentence, then left out of the back
+    Example,
+}
+
+/// HTML of the card sides and the CSS shared by them.
+///
+/// Templates can use the fields `{{Front}}`, `{{Back}}`, `{{Example}}`,
+/// `{{Image}}` and `{{Audio}}`, the enabled [`ExtraFields`], and the back
+/// side `{{FrontSide}}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CardTemplates {
+    pub qfmt: String,
+    pub afmt: String,
+    pub css: String,
+}
+
+impl Default for CardTemplates {
+    fn default() -> Self {
+        Self {
+            qfmt: DEFAULT_QFMT.to_string(),
+            afmt: DEFAULT_AFMT.to_string(),
+            css: String::new(),
+        }
+    }
+}
+
+impl CardTemplates {
+    /// The default templates for a model with the `extra` fields, showing
+    /// `question` on the front and the hint, unless on the front, on the back.
+    pub fn for_fields(extra: ExtraFields, question: QuestionField) -> Self {
+        let mut templates = Self::default();
+        match question {
+            QuestionField::None => {}
+            QuestionField::Hint => {
+                templates.qfmt = format!("{}\n\n{}", DEFAULT_QFMT, HINT_BLOCK);
+            }
+            QuestionField::Example => {
+                templates.qfmt = format!("{}\n\n{}", DEFAULT_QFMT, EXAMPLE_BLOCK);
+                templates.afmt = templates
+                    .afmt
+                    .replace(&format!("{}\n\n", EXAMPLE_BLOCK), "");
+            }
+        }
+        if extra.hint && question != QuestionField::Hint {
+            templates.afmt = templates
+                .afmt
+                .replace("{{#Image}}", &format!("{}\n\n{{{{#Image}}}}", HINT_BLOCK));
+        }
+        templates
+    }
+
+    /// Loads `front.html`, `back.html` and `style.css` from `dir`.
+    ///
+    /// Missing files keep the default template, so a directory with only a
+    /// `style.css` restyles the default cards.
+    pub fn from_dir(dir: &Path) -> Result<Self> {
+        if !dir.is_dir() {
+            anyhow::bail!("Template directory {:?} does not exist", dir);
+        }
+        let mut templates = Self::default();
+        for (name, template) in [
+            ("front.html", &mut templates.qfmt),
+            ("back.html", &mut templates.afmt),
+            ("style.css", &mut templates.css),
+        ] {
+            let path = dir.join(name);
+            match std::fs::read_to_string(&path) {
+                Ok(content) => *template = content,
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(e).with_context(|| format!("Cannot read {:?}", path)),
+            }
+        }
+        Ok(templates)
+    }
 }
 
 /// Creates a vocabulary model for Anki notes.
 ///
 /// This model defines the structure of vocabulary notes in Anki,
-/// including fields for the word, translation, and example.
+/// including fields for the word, translation, example, picture and pronunciation.
 pub fn create_vocabulary_model() -> Model {
+    create_vocabulary_model_with(MODEL_ID, &CardTemplates::default())
+}
+
+/// Creates the vocabulary model under a custom ID, e.g. to avoid a
+/// collision with a note type from another import, and with custom templates.
+pub fn create_vocabulary_model_with(model_id: i64, templates: &CardTemplates) -> Model {
+    create_vocabulary_model_with_fields(model_id, templates, ExtraFields::default())
+}
+
+/// Creates the vocabulary model with the `extra` fields.
+pub fn create_vocabulary_model_with_fields(
+    model_id: i64,
+    templates: &CardTemplates,
+    extra: ExtraFields,
+) -> Model {
+    let mut fields = vec![
+        Field::new("Front"),
+        Field::new("Back"),
+        Field::new("Example"),
+        Field::new("Image"),
+        Field::new("Audio"),
+    ];
+    fields.extend(extra.names().into_iter().map(Field::new));
     Model::new(
-        1607392319, // Model ID - fixed for consistency
+        model_id,
         "Duoload Vocabulary",
-        vec![
-            Field::new("Front"),
-            Field::new("Back"),
-            Field::new("Example"),
-        ],
+        fields,
         vec![
             Template::new("Card 1")
-                .qfmt("{{Front}}")
-                .afmt("{{FrontSide}}\n\n<hr id=answer>\n\n{{Back}}\n\n{{#Example}}<div class=\"example\">{{Example}}</div>{{/Example}}"),
+                .qfmt(&templates.qfmt)
+                .afmt(&templates.afmt),
         ],
     )
+    .css(&templates.css)
 }
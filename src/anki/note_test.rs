@@ -15,6 +15,9 @@ mod tests {
             translation: translation.to_string(),
             example: example.map(|s| s.to_string()),
             status,
+            image: None,
+            created_at: None,
+            waiting_until: None,
         }
     }
 
@@ -0,0 +1,276 @@
+//! Read access to written Anki packages.
+//!
+//! An .apkg file is a zip archive whose `collection.anki2` entry is an
+//! SQLite database; this module extracts it so the notes can be queried.
+//! Packages and collections exported by Anki 2.1 with "Support older Anki
+//! versions" keep the real collection in `collection.anki21` instead.
+
+use crate::error::Result;
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use tempfile::{NamedTempFile, TempDir};
+
+/// Separator between the fields of a note in the `flds` column.
+const FIELD_SEPARATOR: char = '\x1f';
+
+/// A note read back from a package.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageNote {
+    pub guid: String,
+    pub fields: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+impl PackageNote {
+    /// The first field, which holds the word in packages written by duoload.
+    pub fn word(&self) -> &str {
+        self.fields.first().map(String::as_str).unwrap_or_default()
+    }
+}
+
+/// Scheduling of a card read back from a package.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PackageCard {
+    pub id: i64,
+    pub note_id: i64,
+    pub deck_id: i64,
+    /// 0 for new cards, 2 for review cards
+    pub card_type: i64,
+    /// Days between reviews of review cards
+    pub interval: i64,
+    /// Position of new cards, or the day review cards are due, counted from
+    /// the creation of the collection
+    pub due: i64,
+}
+
+/// An Anki collection extracted from a package into a temporary file.
+pub struct PackageCollection {
+    connection: Connection,
+    // Keeps the extracted database alive as long as the connection
+    _file: NamedTempFile,
+}
+
+impl PackageCollection {
+    /// Opens the collection stored inside the .apkg file at `path`.
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut archive = zip::ZipArchive::new(File::open(path)?)
+            .map_err(|e| anyhow::anyhow!("Failed to open Anki package: {}", e))?;
+        let name = collection_name(&archive)?;
+        let mut collection = archive
+            .by_name(name)
+            .map_err(|e| anyhow::anyhow!("Anki package has no collection: {}", e))?;
+
+        let mut file = NamedTempFile::new()?;
+        io::copy(&mut collection, &mut file)?;
+
+        let connection = Connection::open(file.path())
+            .map_err(|e| anyhow::anyhow!("Failed to open Anki collection: {}", e))?;
+
+        Ok(Self {
+            connection,
+            _file: file,
+        })
+    }
+
+    /// Returns the number of notes in the collection.
+    pub fn note_count(&self) -> Result<usize> {
+        let count: i64 = self
+            .connection
+            .query_row("SELECT count(*) FROM notes", rusqlite::params![], |row| {
+                row.get(0)
+            })
+            .map_err(|e| anyhow::anyhow!("Failed to count notes: {}", e))?;
+        Ok(count as usize)
+    }
+
+    /// Returns the notes of the collection in the order they were added.
+    pub fn notes(&self) -> Result<Vec<PackageNote>> {
+        let mut select = self
+            .connection
+            .prepare("SELECT guid, flds, tags FROM notes ORDER BY id")
+            .map_err(|e| anyhow::anyhow!("Failed to read notes: {}", e))?;
+        let notes = select
+            .query_map(rusqlite::params![], |row| {
+                let fields: String = row.get(1)?;
+                let tags: String = row.get(2)?;
+                Ok(PackageNote {
+                    guid: row.get(0)?,
+                    fields: fields.split(FIELD_SEPARATOR).map(str::to_string).collect(),
+                    tags: tags.split_whitespace().map(str::to_string).collect(),
+                })
+            })
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>())
+            .map_err(|e| anyhow::anyhow!("Failed to read notes: {}", e))?;
+        Ok(notes)
+    }
+
+    /// Returns the ID and note type ID of each note, in the order of
+    /// [`PackageCollection::notes`].
+    pub fn note_ids(&self) -> Result<Vec<(i64, i64)>> {
+        let mut select = self
+            .connection
+            .prepare("SELECT id, mid FROM notes ORDER BY id")
+            .map_err(|e| anyhow::anyhow!("Failed to read notes: {}", e))?;
+        let ids = select
+            .query_map(rusqlite::params![], |row| Ok((row.get(0)?, row.get(1)?)))
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>())
+            .map_err(|e| anyhow::anyhow!("Failed to read notes: {}", e))?;
+        Ok(ids)
+    }
+
+    /// Returns the cards of the collection in the order they were added.
+    pub fn cards(&self) -> Result<Vec<PackageCard>> {
+        let mut select = self
+            .connection
+            .prepare("SELECT id, nid, did, type, ivl, due FROM cards ORDER BY id")
+            .map_err(|e| anyhow::anyhow!("Failed to read cards: {}", e))?;
+        let cards = select
+            .query_map(rusqlite::params![], |row| {
+                Ok(PackageCard {
+                    id: row.get(0)?,
+                    note_id: row.get(1)?,
+                    deck_id: row.get(2)?,
+                    card_type: row.get(3)?,
+                    interval: row.get(4)?,
+                    due: row.get(5)?,
+                })
+            })
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>())
+            .map_err(|e| anyhow::anyhow!("Failed to read cards: {}", e))?;
+        Ok(cards)
+    }
+
+    /// Returns the names of the columns of `table`, or none if the
+    /// collection has no such table.
+    pub fn table_columns(&self, table: &str) -> Result<Vec<String>> {
+        let mut select = self
+            .connection
+            .prepare("SELECT name FROM pragma_table_info(?1)")
+            .map_err(|e| anyhow::anyhow!("Failed to read the schema: {}", e))?;
+        let columns = select
+            .query_map(rusqlite::params![table], |row| row.get(0))
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<String>>>())
+            .map_err(|e| anyhow::anyhow!("Failed to read the schema: {}", e))?;
+        Ok(columns)
+    }
+
+    /// Returns the IDs of the decks in the collection, sorted.
+    pub fn deck_ids(&self) -> Result<Vec<i64>> {
+        self.object_ids("decks")
+    }
+
+    /// Returns the IDs of the note types in the collection, sorted.
+    pub fn model_ids(&self) -> Result<Vec<i64>> {
+        self.object_ids("models")
+    }
+
+    /// Returns the note types of the collection as stored by Anki, keyed by ID.
+    pub fn models(&self) -> Result<HashMap<String, serde_json::Value>> {
+        self.objects("models")
+    }
+
+    /// Returns the keys of a JSON column of `col`, which are the IDs of its objects.
+    fn object_ids(&self, column: &str) -> Result<Vec<i64>> {
+        let mut ids: Vec<i64> = self
+            .objects(column)?
+            .keys()
+            .filter_map(|id| id.parse().ok())
+            .collect();
+        ids.sort();
+        Ok(ids)
+    }
+
+    fn objects(&self, column: &str) -> Result<HashMap<String, serde_json::Value>> {
+        let objects: String = self
+            .connection
+            .query_row(
+                &format!("SELECT {} FROM col", column),
+                rusqlite::params![],
+                |row| row.get(0),
+            )
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", column, e))?;
+        Ok(serde_json::from_str(&objects)?)
+    }
+
+    /// Returns the names of the decks in the collection, sorted.
+    pub fn deck_names(&self) -> Result<Vec<String>> {
+        let decks: String = self
+            .connection
+            .query_row("SELECT decks FROM col", rusqlite::params![], |row| {
+                row.get(0)
+            })
+            .map_err(|e| anyhow::anyhow!("Failed to read decks: {}", e))?;
+        let decks: HashMap<String, serde_json::Value> = serde_json::from_str(&decks)?;
+        let mut names: Vec<String> = decks
+            .values()
+            .filter_map(|deck| deck["name"].as_str().map(str::to_string))
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+}
+
+/// Name of the archive entry holding the collection.
+fn collection_name(archive: &zip::ZipArchive<File>) -> Result<&'static str> {
+    let names: Vec<&str> = archive.file_names().collect();
+    if names.contains(&"collection.anki21") {
+        Ok("collection.anki21")
+    } else if names.contains(&"collection.anki21b") {
+        // The collection.anki2 next to it only holds an "update Anki" note
+        Err(anyhow::anyhow!(
+            "The Anki package uses the compressed format of Anki 2.1.50+; \
+             export it again with \"Support older Anki versions\""
+        )
+        .into())
+    } else {
+        Ok("collection.anki2")
+    }
+}
+
+/// Notes and media of an existing package that a new export is merged into.
+#[derive(Debug)]
+pub struct ExistingPackage {
+    pub notes: Vec<PackageNote>,
+    /// Media files extracted under their original names
+    pub media_files: Vec<PathBuf>,
+    // Keeps the extracted media until the new package is written
+    _media_dir: TempDir,
+}
+
+impl ExistingPackage {
+    /// Reads the notes and media of the .apkg or .colpkg file at `path`.
+    pub fn open(path: &Path) -> Result<Self> {
+        let notes = PackageCollection::open(path)?.notes()?;
+
+        let media_dir = tempfile::tempdir()?;
+        let mut archive = zip::ZipArchive::new(File::open(path)?)
+            .map_err(|e| anyhow::anyhow!("Failed to open Anki package: {}", e))?;
+        let media: HashMap<String, String> = match archive.by_name("media") {
+            Ok(entry) => serde_json::from_reader(entry)?,
+            Err(_) => HashMap::new(),
+        };
+        let mut media_files = Vec::new();
+        for (index, name) in media {
+            // Media names come from the package, so never leave the directory
+            let Some(name) = Path::new(&name).file_name() else {
+                continue;
+            };
+            let mut entry = archive
+                .by_name(&index)
+                .map_err(|e| anyhow::anyhow!("Anki package is missing media {}: {}", index, e))?;
+            let target = media_dir.path().join(name);
+            io::copy(&mut entry, &mut File::create(&target)?)?;
+            media_files.push(target);
+        }
+        media_files.sort();
+
+        Ok(Self {
+            notes,
+            media_files,
+            _media_dir: media_dir,
+        })
+    }
+}
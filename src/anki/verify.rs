@@ -0,0 +1,339 @@
+//! Checks of Anki packages before they are imported.
+//!
+//! [`verify_package`] opens a package like Anki would and reports what
+//! would make its import fail or differ from a duoload export: missing
+//! tables or columns, notes and cards that do not belong together, note
+//! types without the vocabulary fields, notes without a status tag and
+//! media missing from the archive.
+
+use crate::anki::note::ExtraFields;
+use crate::anki::package::PackageCollection;
+use crate::error::Result;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+
+/// Tables of an Anki collection with the columns duoload and Anki read.
+const SCHEMA: [(&str, &[&str]); 5] = [
+    (
+        "col",
+        &[
+            "id", "crt", "mod", "scm", "ver", "dty", "usn", "ls", "conf", "models", "decks",
+            "dconf", "tags",
+        ],
+    ),
+    (
+        "notes",
+        &[
+            "id", "guid", "mid", "mod", "usn", "tags", "flds", "sfld", "csum", "flags", "data",
+        ],
+    ),
+    (
+        "cards",
+        &[
+            "id", "nid", "did", "ord", "mod", "usn", "type", "queue", "due", "ivl", "factor",
+            "reps", "lapses", "left", "odue", "odid", "flags", "data",
+        ],
+    ),
+    (
+        "revlog",
+        &[
+            "id", "cid", "usn", "ease", "ivl", "lastIvl", "factor", "time", "type",
+        ],
+    ),
+    ("graves", &["usn", "oid", "type"]),
+];
+
+/// Fields every duoload note type starts with.
+const REGULAR_FIELDS: [&str; 5] = ["Front", "Back", "Example", "Image", "Audio"];
+
+/// Problems reported for each kind before the rest are only counted.
+const MAX_EXAMPLES: usize = 5;
+
+/// What [`verify_package`] found in a package.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PackageReport {
+    pub notes: usize,
+    pub cards: usize,
+    /// Names of the decks, sorted
+    pub decks: Vec<String>,
+    /// Names of the note types, sorted
+    pub note_types: Vec<String>,
+    pub media: usize,
+    /// Everything that does not match a duoload export
+    pub problems: Vec<String>,
+}
+
+impl PackageReport {
+    /// Whether the package can be imported as a duoload export.
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl fmt::Display for PackageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Notes:      {}", self.notes)?;
+        writeln!(f, "Cards:      {}", self.cards)?;
+        writeln!(f, "Decks:      {}", self.decks.join(", "))?;
+        writeln!(f, "Note types: {}", self.note_types.join(", "))?;
+        writeln!(f, "Media:      {}", self.media)?;
+        if self.is_valid() {
+            write!(f, "No problems found")
+        } else {
+            write!(f, "{} problems:", self.problems.len())?;
+            for problem in &self.problems {
+                write!(f, "\n- {}", problem)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Collects problems, keeping the first few of each kind.
+#[derive(Default)]
+struct Problems {
+    found: Vec<String>,
+    /// Problems of each kind beyond [`MAX_EXAMPLES`]
+    omitted: HashMap<&'static str, usize>,
+    seen: HashMap<&'static str, usize>,
+}
+
+impl Problems {
+    fn add(&mut self, kind: &'static str, problem: String) {
+        let seen = self.seen.entry(kind).or_default();
+        *seen += 1;
+        if *seen > MAX_EXAMPLES {
+            *self.omitted.entry(kind).or_default() += 1;
+        } else {
+            self.found.push(problem);
+        }
+    }
+
+    fn into_vec(mut self) -> Vec<String> {
+        let mut omitted: Vec<_> = self.omitted.into_iter().collect();
+        omitted.sort();
+        for (kind, count) in omitted {
+            self.found
+                .push(format!("... and {} more {} problems", count, kind));
+        }
+        self.found
+    }
+}
+
+/// Opens the .apkg or .colpkg file at `path` and checks that it holds a
+/// valid collection written like a duoload export. Notes need a status tag
+/// starting with `tag_prefix` and `_`, unless the prefix is empty.
+///
+/// Fails only if the package cannot be read at all; anything else is
+/// listed in [`PackageReport::problems`].
+pub fn verify_package(path: &Path, tag_prefix: &str) -> Result<PackageReport> {
+    let collection = PackageCollection::open(path)?;
+    let mut problems = Problems::default();
+    let mut report = PackageReport::default();
+
+    let mut complete = true;
+    for (table, columns) in SCHEMA {
+        let found = collection.table_columns(table)?;
+        if found.is_empty() {
+            problems.add("schema", format!("The collection has no {} table", table));
+            complete = false;
+            continue;
+        }
+        for column in columns {
+            if !found.iter().any(|found| found == column) {
+                problems.add(
+                    "schema",
+                    format!("The {} table has no {} column", table, column),
+                );
+                complete = false;
+            }
+        }
+    }
+    if !complete {
+        // The rest cannot be read from an incomplete schema
+        report.problems = problems.into_vec();
+        return Ok(report);
+    }
+
+    let models = collection.models()?;
+    let mut model_fields: HashMap<i64, usize> = HashMap::new();
+    for (id, model) in &models {
+        let name = model["name"].as_str().unwrap_or_default().to_string();
+        let fields: Vec<&str> = model["flds"]
+            .as_array()
+            .map(|fields| {
+                fields
+                    .iter()
+                    .filter_map(|field| field["name"].as_str())
+                    .collect()
+            })
+            .unwrap_or_default();
+        if !is_duoload_model(&fields) {
+            problems.add(
+                "note type",
+                format!(
+                    "Note type {:?} has the fields {} instead of those of a duoload export",
+                    name,
+                    fields.join(", ")
+                ),
+            );
+        }
+        if let Ok(id) = id.parse() {
+            model_fields.insert(id, fields.len());
+        }
+        report.note_types.push(name);
+    }
+    report.note_types.sort();
+    report.decks = collection.deck_names()?;
+    let deck_ids: HashSet<i64> = collection.deck_ids()?.into_iter().collect();
+
+    let notes = collection.notes()?;
+    let note_ids = collection.note_ids()?;
+    report.notes = notes.len();
+    let status_prefix = format!("{}_", tag_prefix);
+    for (note, (_, model_id)) in notes.iter().zip(&note_ids) {
+        let word = note.word();
+        match model_fields.get(model_id) {
+            None => problems.add(
+                "note",
+                format!("Note {:?} has the unknown note type {}", word, model_id),
+            ),
+            Some(&count) if count != note.fields.len() => problems.add(
+                "note",
+                format!(
+                    "Note {:?} has {} fields, its note type {}",
+                    word,
+                    note.fields.len(),
+                    count
+                ),
+            ),
+            Some(_) => {}
+        }
+        if word.trim().is_empty() {
+            problems.add(
+                "note",
+                format!("Note {} has an empty first field", note.guid),
+            );
+        }
+        if !tag_prefix.is_empty()
+            && note
+                .tags
+                .iter()
+                .filter(|tag| tag.starts_with(&status_prefix))
+                .count()
+                != 1
+        {
+            problems.add(
+                "tag",
+                format!(
+                    "Note {:?} needs one status tag {}*, has: {}",
+                    word,
+                    status_prefix,
+                    note.tags.join(" ")
+                ),
+            );
+        }
+    }
+
+    let cards = collection.cards()?;
+    report.cards = cards.len();
+    let known_notes: HashSet<i64> = note_ids.iter().map(|(id, _)| *id).collect();
+    let mut notes_with_cards = HashSet::new();
+    for card in &cards {
+        if !known_notes.contains(&card.note_id) {
+            problems.add(
+                "card",
+                format!(
+                    "Card {} belongs to the missing note {}",
+                    card.id, card.note_id
+                ),
+            );
+        }
+        if !deck_ids.contains(&card.deck_id) {
+            problems.add(
+                "card",
+                format!("Card {} is in the missing deck {}", card.id, card.deck_id),
+            );
+        }
+        notes_with_cards.insert(card.note_id);
+    }
+    for (note, (id, _)) in notes.iter().zip(&note_ids) {
+        if !notes_with_cards.contains(id) {
+            problems.add("card", format!("Note {:?} has no card", note.word()));
+        }
+    }
+
+    let mut archive = zip::ZipArchive::new(File::open(path)?)
+        .map_err(|e| anyhow::anyhow!("Failed to open Anki package: {}", e))?;
+    let media: HashMap<String, String> = match archive.by_name("media") {
+        Ok(entry) => match serde_json::from_reader(entry) {
+            Ok(media) => media,
+            Err(e) => {
+                problems.add("media", format!("The media list cannot be read: {}", e));
+                HashMap::new()
+            }
+        },
+        Err(_) => HashMap::new(),
+    };
+    report.media = media.len();
+    let mut media: Vec<_> = media.into_iter().collect();
+    media.sort();
+    for (index, name) in media {
+        if archive.by_name(&index).is_err() {
+            problems.add(
+                "media",
+                format!("Media file {:?} is missing from the package", name),
+            );
+        }
+    }
+
+    report.problems = problems.into_vec();
+    Ok(report)
+}
+
+/// Whether `fields` are the regular fields followed by any of the optional
+/// ones, in the order duoload writes them.
+fn is_duoload_model(fields: &[&str]) -> bool {
+    let Some(extra) = fields.strip_prefix(&REGULAR_FIELDS[..]) else {
+        return false;
+    };
+    (0..32).any(|bits| {
+        let names = ExtraFields {
+            source: bits & 1 != 0,
+            status: bits & 2 != 0,
+            hint: bits & 4 != 0,
+            provenance: bits & 8 != 0,
+            known_count: bits & 16 != 0,
+        }
+        .names();
+        names == extra
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anki::note::{
+        HINT_FIELDS, KNOWN_COUNT_FIELDS, PROVENANCE_FIELDS, SOURCE_FIELDS, STATUS_FIELDS,
+    };
+
+    #[test]
+    fn test_duoload_models() {
+        assert!(is_duoload_model(&REGULAR_FIELDS));
+        let mut fields = REGULAR_FIELDS.to_vec();
+        fields.extend(SOURCE_FIELDS);
+        fields.extend(HINT_FIELDS);
+        assert!(is_duoload_model(&fields));
+        fields.extend(STATUS_FIELDS);
+        assert!(!is_duoload_model(&fields));
+        let mut fields = REGULAR_FIELDS.to_vec();
+        fields.extend(PROVENANCE_FIELDS);
+        assert!(is_duoload_model(&fields));
+        fields.extend(KNOWN_COUNT_FIELDS);
+        assert!(is_duoload_model(&fields));
+        assert!(!is_duoload_model(&["Front", "Back"]));
+    }
+}
@@ -0,0 +1,429 @@
+//! Writing of .apkg files with settings genanki does not offer.
+//!
+//! genanki writes a legacy package: the collection as `collection.anki2`,
+//! compressed with the default deflate level. Other compression levels, the
+//! Anki 2.1 layout and byte-identical packages are made by repacking that
+//! package.
+//!
+//! genanki only takes file paths as UTF-8 strings, so it only writes
+//! collections into a temporary directory; the package and its media are
+//! written here, at any path the OS accepts.
+
+use crate::anki::collection::{CardSchedule, schedule_cards};
+use crate::error::Result;
+use clap::ValueEnum;
+use genanki_rs::{Deck, Note, Package};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tempfile::TempDir;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// Archive entry of the collection in legacy packages.
+const LEGACY_COLLECTION: &str = "collection.anki2";
+
+/// Archive entry of the collection in Anki 2.1 packages.
+const ANKI21_COLLECTION: &str = "collection.anki21";
+
+/// Deck ID of the note telling Anki 2.0 users to update.
+const STUB_DECK_ID: i64 = 2059400109;
+
+/// Creation time of deterministic packages, 2020-01-01 00:00:00 UTC. Note
+/// and card IDs count up from it.
+const DETERMINISTIC_TIMESTAMP: f64 = 1_577_836_800.0;
+
+/// Offsets of the file change counter and of the "version valid for" number
+/// in the header of a SQLite database.
+const SQLITE_CHANGE_COUNTER: u64 = 24;
+const SQLITE_VERSION_VALID_FOR: u64 = 92;
+
+/// Archive entry mapping the numbered media entries to file names.
+const MEDIA_MAP: &str = "media";
+
+/// Layout of the collection inside the package.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ApkgScheme {
+    /// `collection.anki2`, imported by every Anki version
+    #[default]
+    Legacy,
+    /// `collection.anki21`, as exported by Anki 2.1 before 2.1.50; Anki 2.0
+    /// only finds a note asking to update
+    Anki21,
+}
+
+/// Settings of a written package.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ApkgOptions {
+    /// Deflate level from 0 (stored uncompressed) to 9, or `None` for the
+    /// default level
+    pub compression: Option<u32>,
+    /// Whether downloaded and merged media files are put into the package
+    pub media: bool,
+    pub scheme: ApkgScheme,
+    /// Writes the same bytes for the same notes: without the export date
+    /// tag, with a fixed creation time and with media in a fixed order
+    pub deterministic: bool,
+}
+
+impl Default for ApkgOptions {
+    fn default() -> Self {
+        Self {
+            compression: None,
+            media: true,
+            scheme: ApkgScheme::Legacy,
+            deterministic: false,
+        }
+    }
+}
+
+impl ApkgOptions {
+    /// Whether the package genanki writes has to be repacked.
+    fn needs_repack(&self) -> bool {
+        self.compression.is_some() || self.scheme != ApkgScheme::Legacy || self.deterministic
+    }
+
+    /// Creation time of the collection, or `None` for now.
+    fn timestamp(&self) -> Option<f64> {
+        self.deterministic.then_some(DETERMINISTIC_TIMESTAMP)
+    }
+}
+
+/// Writes `decks` and `media_files` as a package at `path`.
+pub fn write_package(
+    decks: Vec<Deck>,
+    media_files: &[PathBuf],
+    path: &Path,
+    options: &ApkgOptions,
+) -> Result<()> {
+    let mut package = BatchedPackage::new(options)?;
+    package.add_decks(decks)?;
+    package.write(media_files, path, options)
+}
+
+/// Package built from decks added in batches, so that only the notes of one
+/// batch are in memory at a time.
+///
+/// genanki builds the collection of each batch, whose notes and cards are
+/// then copied into a single collection on disk. Decks with the same ID in
+/// several batches are one deck of the package.
+pub struct BatchedPackage {
+    dir: TempDir,
+    collection: PathBuf,
+    timestamp: f64,
+    /// ID of the next note or card; like genanki, IDs count up from the
+    /// creation time in milliseconds
+    next_id: i64,
+    batches: usize,
+}
+
+impl BatchedPackage {
+    pub fn new(options: &ApkgOptions) -> Result<Self> {
+        let timestamp = options.timestamp().unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64()
+        });
+        let dir = tempfile::tempdir()?;
+        Ok(Self {
+            collection: dir.path().join(LEGACY_COLLECTION),
+            dir,
+            timestamp,
+            next_id: (timestamp * 1000.0) as i64,
+            batches: 0,
+        })
+    }
+
+    /// Adds the notes of `decks` to the collection.
+    pub fn add_decks(&mut self, decks: Vec<Deck>) -> Result<()> {
+        let package = self.dir.path().join("batch.apkg");
+        write_genanki(decks, &package, Some(self.timestamp))?;
+        let batch = self.dir.path().join("batch.anki2");
+        let mut archive = ZipArchive::new(File::open(&package)?).map_err(zip_error)?;
+        let mut entry = archive.by_name(LEGACY_COLLECTION).map_err(zip_error)?;
+        io::copy(&mut entry, &mut File::create(&batch)?)?;
+
+        if self.batches == 0 {
+            std::fs::rename(&batch, &self.collection)?;
+        } else {
+            self.merge(&batch)?;
+            std::fs::remove_file(&batch)?;
+        }
+        let connection = rusqlite::Connection::open(&self.collection).map_err(database_error)?;
+        let last_id: Option<i64> = connection
+            .query_row(
+                "SELECT max(id) FROM (SELECT id FROM notes UNION ALL SELECT id FROM cards)",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(database_error)?;
+        if let Some(last_id) = last_id {
+            self.next_id = last_id + 1;
+        }
+        self.batches += 1;
+        Ok(())
+    }
+
+    /// Makes the cards of the notes with the GUIDs in `schedules` review
+    /// cards, see [`schedule_cards`].
+    pub fn schedule_cards(&self, schedules: &HashMap<String, CardSchedule>) -> Result<()> {
+        schedule_cards(&self.collection, schedules, self.timestamp)
+    }
+
+    /// Copies the notes, cards, decks and note types of the collection at
+    /// `batch` into the collection, after its notes and cards.
+    fn merge(&self, batch: &Path) -> Result<()> {
+        let connection = rusqlite::Connection::open(&self.collection).map_err(database_error)?;
+        let batch = temp_path(batch)?;
+        connection
+            .execute("ATTACH DATABASE ?1 AS batch", [batch])
+            .map_err(database_error)?;
+        // The batch counts its IDs up from the same creation time
+        let offset = self.next_id - (self.timestamp * 1000.0) as i64;
+        connection
+            .execute(
+                "INSERT INTO notes SELECT id + ?1, guid, mid, mod, usn, tags, flds, sfld, csum,
+                 flags, data FROM batch.notes",
+                [offset],
+            )
+            .map_err(database_error)?;
+        connection
+            .execute(
+                "INSERT INTO cards SELECT id + ?1, nid + ?1, did, ord, mod, usn, type, queue, due,
+                 ivl, factor, reps, lapses, \"left\", odue, odid, flags, data FROM batch.cards",
+                [offset],
+            )
+            .map_err(database_error)?;
+        for column in ["models", "decks"] {
+            let settings = |schema: &str| -> Result<serde_json::Map<String, serde_json::Value>> {
+                let settings: String = connection
+                    .query_row(
+                        &format!("SELECT {} FROM {}.col", column, schema),
+                        [],
+                        |row| row.get(0),
+                    )
+                    .map_err(database_error)?;
+                Ok(serde_json::from_str(&settings)?)
+            };
+            let mut merged = settings("main")?;
+            for (id, value) in settings("batch")? {
+                merged.entry(id).or_insert(value);
+            }
+            connection
+                .execute(
+                    &format!("UPDATE col SET {} = ?1", column),
+                    [serde_json::to_string(&merged)?],
+                )
+                .map_err(database_error)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the package with `media_files` at `path`.
+    pub fn write(
+        mut self,
+        media_files: &[PathBuf],
+        path: &Path,
+        options: &ApkgOptions,
+    ) -> Result<()> {
+        if self.batches == 0 {
+            self.add_decks(Vec::new())?;
+        }
+        let media_files = if options.media { media_files } else { &[] };
+        let plain = if options.needs_repack() {
+            self.dir.path().join("plain.apkg")
+        } else {
+            path.to_path_buf()
+        };
+
+        // The layout genanki writes
+        let entry_options = FileOptions::default();
+        let mut writer = ZipWriter::new(BufWriter::new(File::create(&plain)?));
+        writer
+            .start_file(LEGACY_COLLECTION, entry_options)
+            .map_err(zip_error)?;
+        io::copy(&mut File::open(&self.collection)?, &mut writer)?;
+        let mut map = serde_json::Map::new();
+        for (index, file) in media_files.iter().enumerate() {
+            let name = file
+                .file_name()
+                .ok_or_else(|| anyhow::anyhow!("Invalid media file {:?}", file))?;
+            map.insert(index.to_string(), name.to_string_lossy().into());
+        }
+        writer
+            .start_file(MEDIA_MAP, entry_options)
+            .map_err(zip_error)?;
+        serde_json::to_writer(&mut writer, &map)?;
+        for (index, file) in media_files.iter().enumerate() {
+            writer
+                .start_file(index.to_string(), entry_options)
+                .map_err(zip_error)?;
+            io::copy(&mut File::open(file)?, &mut writer)?;
+        }
+        writer.finish().map_err(zip_error)?.flush()?;
+
+        if options.needs_repack() {
+            let stub = write_stub(self.dir.path(), options)?;
+            repack(&plain, stub.as_deref(), path, options)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the package Anki 2.0 imports from an Anki 2.1 package into `dir`,
+/// if `options` asks for that layout.
+fn write_stub(dir: &Path, options: &ApkgOptions) -> Result<Option<PathBuf>> {
+    match options.scheme {
+        ApkgScheme::Legacy => Ok(None),
+        ApkgScheme::Anki21 => {
+            let stub = dir.join("stub.apkg");
+            write_genanki(vec![update_notice()?], &stub, options.timestamp())?;
+            Ok(Some(stub))
+        }
+    }
+}
+
+fn zip_error(e: zip::result::ZipError) -> anyhow::Error {
+    anyhow::anyhow!("Failed to write Anki package: {}", e)
+}
+
+fn database_error(e: rusqlite::Error) -> anyhow::Error {
+    anyhow::anyhow!("Failed to write Anki package: {}", e)
+}
+
+/// `path` in the temporary directory as a string, for genanki and SQLite.
+fn temp_path(path: &Path) -> Result<&str> {
+    path.to_str().ok_or_else(|| {
+        anyhow::anyhow!(
+            "Temporary file {:?} is not valid Unicode; set TMPDIR to another directory",
+            path
+        )
+        .into()
+    })
+}
+
+/// Writes `decks` as a package without media at `path`, a file in a
+/// temporary directory.
+fn write_genanki(decks: Vec<Deck>, path: &Path, timestamp: Option<f64>) -> Result<()> {
+    let path = temp_path(path)?;
+    let mut package = Package::new(decks, Vec::new())
+        .map_err(|e| anyhow::anyhow!("Failed to write Anki package: {}", e))?;
+    match timestamp {
+        Some(timestamp) => package.write_to_file_timestamp(path, timestamp),
+        None => package.write_to_file(path),
+    }
+    .map_err(|e| anyhow::anyhow!("Failed to write Anki package: {}", e))?;
+    Ok(())
+}
+
+/// The deck Anki 2.0 imports from an Anki 2.1 package.
+fn update_notice() -> Result<Deck> {
+    let note = Note::new(
+        genanki_rs::basic_model(),
+        vec![
+            "Please update to the latest Anki version, then import the .apkg file again.",
+            "",
+        ],
+    )
+    .map_err(|e| anyhow::anyhow!("Failed to write Anki package: {}", e))?;
+    let mut deck = Deck::new(STUB_DECK_ID, "Default", "");
+    deck.add_note(note);
+    Ok(deck)
+}
+
+/// Copies the entries of the package at `source` to `target` with the
+/// compression and layout of `options`. With `stub`, the collection moves to
+/// `collection.anki21` and the collection of `stub` takes its old place.
+///
+/// genanki writes the media entries, and the media map, in random order;
+/// deterministic packages get them sorted by number.
+fn repack(source: &Path, stub: Option<&Path>, target: &Path, options: &ApkgOptions) -> Result<()> {
+    let entry_options = match options.compression {
+        Some(0) => FileOptions::default().compression_method(CompressionMethod::Stored),
+        level => FileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .compression_level(level.map(|level| level as i32)),
+    };
+    let mut archive = ZipArchive::new(File::open(source)?).map_err(zip_error)?;
+    let mut writer = ZipWriter::new(BufWriter::new(File::create(target)?));
+    let mut names = Vec::with_capacity(archive.len());
+    for index in 0..archive.len() {
+        names.push(
+            archive
+                .by_index(index)
+                .map_err(zip_error)?
+                .name()
+                .to_string(),
+        );
+    }
+    if options.deterministic {
+        names.sort_by_key(|name| (name.parse::<usize>().ok(), name.clone()));
+    }
+    for name in names {
+        let mut entry = archive.by_name(&name).map_err(zip_error)?;
+        let target_name = match name.as_str() {
+            LEGACY_COLLECTION if stub.is_some() => ANKI21_COLLECTION,
+            name => name,
+        };
+        writer
+            .start_file(target_name, entry_options)
+            .map_err(zip_error)?;
+        if name == LEGACY_COLLECTION && options.deterministic {
+            let collection = tempfile::NamedTempFile::new()?;
+            io::copy(&mut entry, &mut collection.as_file())?;
+            sort_collection_settings(collection.path())?;
+            io::copy(&mut File::open(collection.path())?, &mut writer)?;
+        } else if name == MEDIA_MAP && options.deterministic {
+            // Maps are serialized with their keys sorted
+            let map: serde_json::Map<String, serde_json::Value> =
+                serde_json::from_reader(&mut entry)?;
+            serde_json::to_writer(&mut writer, &map)?;
+        } else {
+            io::copy(&mut entry, &mut writer)?;
+        }
+    }
+    if let Some(stub) = stub {
+        let mut archive = ZipArchive::new(File::open(stub)?).map_err(zip_error)?;
+        let mut entry = archive.by_name(LEGACY_COLLECTION).map_err(zip_error)?;
+        writer
+            .start_file(LEGACY_COLLECTION, entry_options)
+            .map_err(zip_error)?;
+        io::copy(&mut entry, &mut writer)?;
+    }
+    writer.finish().map_err(zip_error)?.flush()?;
+    Ok(())
+}
+
+/// Rewrites the JSON settings of the collection at `path`, such as its decks
+/// and note types, with their keys sorted; genanki writes them in random
+/// order.
+fn sort_collection_settings(path: &Path) -> Result<()> {
+    let connection = rusqlite::Connection::open(path).map_err(database_error)?;
+    for column in ["conf", "models", "decks", "dconf", "tags"] {
+        let settings: String = connection
+            .query_row(&format!("SELECT {} FROM col", column), [], |row| row.get(0))
+            .map_err(database_error)?;
+        // Maps are serialized with their keys sorted
+        let settings: serde_json::Value = serde_json::from_str(&settings)?;
+        connection
+            .execute(
+                &format!("UPDATE col SET {} = ?", column),
+                [serde_json::to_string(&settings)?],
+            )
+            .map_err(database_error)?;
+    }
+    connection.execute_batch("VACUUM").map_err(database_error)?;
+    drop(connection);
+
+    // The change counter in the header counts the writes genanki made, which
+    // vary; it must equal the "version valid for" number that follows it
+    let mut file = std::fs::OpenOptions::new().write(true).open(path)?;
+    for offset in [SQLITE_CHANGE_COUNTER, SQLITE_VERSION_VALID_FOR] {
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(&1u32.to_be_bytes())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,81 @@
+//! Serves fixture decks like the Duocards API, for running the whole export
+//! pipeline offline: `duoload --api-url <URL> --deck-id <ID> ...`.
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use duoload::testing::{FixtureDeck, MockDuocardsServer};
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+#[command(
+    name = "duoload-mock-server",
+    version,
+    about = "Serve fixture decks as a mock Duocards GraphQL API"
+)]
+struct Args {
+    #[arg(
+        value_name = "FIXTURE",
+        required = true,
+        help = "JSON file with cardsQuery responses or cards, or a directory of them"
+    )]
+    fixtures: Vec<PathBuf>,
+
+    #[arg(long, default_value = "127.0.0.1", help = "Address to listen on")]
+    host: String,
+
+    #[arg(
+        long,
+        default_value_t = 8642,
+        help = "Port to listen on, 0 for any free port"
+    )]
+    port: u16,
+}
+
+/// The fixture files at `path`: the file itself, or the `.json` files of a
+/// directory in name order.
+fn fixture_files(path: &Path) -> Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(path).with_context(|| format!("Cannot read {:?}", path))? {
+        let file = entry?.path();
+        if file
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            files.push(file);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let mut decks: Vec<FixtureDeck> = Vec::new();
+    for path in &args.fixtures {
+        for file in fixture_files(path)? {
+            let deck = FixtureDeck::load(&file)
+                .with_context(|| format!("Cannot load fixture {:?}", file))?;
+            println!(
+                "{}: {} cards from {}",
+                deck.id,
+                deck.cards.len(),
+                file.display()
+            );
+            // Pages of one deck, e.g. those of a snapshot, make up a single deck
+            match decks.iter_mut().find(|known| known.id == deck.id) {
+                Some(known) => known.cards.extend(deck.cards),
+                None => decks.push(deck),
+            }
+        }
+    }
+
+    let server = MockDuocardsServer::bind((args.host.as_str(), args.port), decks).await?;
+    println!("Serving on {} (Ctrl-C to stop)", server.url());
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
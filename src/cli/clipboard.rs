@@ -0,0 +1,111 @@
+//! Reading a deck ID from the system clipboard.
+//!
+//! The clipboard is read with the tool of the platform (`pbpaste`,
+//! `Get-Clipboard`, `wl-paste`, `xclip` or `xsel`), so no windowing library is
+//! linked in. Whatever was copied from the browser, e.g. a share link, the
+//! address bar or the `deckID` entry of the web app's local storage, is
+//! searched for something [`parse_deck_id`] accepts.
+
+use crate::duocards::deck::parse_deck_id;
+use crate::error::{DuoloadError, Result};
+use std::io::ErrorKind;
+use std::process::Command;
+
+/// Clipboard tools to try, in order, with their arguments.
+#[cfg(target_os = "macos")]
+const TOOLS: &[(&str, &[&str])] = &[("pbpaste", &[])];
+#[cfg(windows)]
+const TOOLS: &[(&str, &[&str])] = &[(
+    "powershell",
+    &["-NoProfile", "-NonInteractive", "-Command", "Get-Clipboard"],
+)];
+#[cfg(not(any(target_os = "macos", windows)))]
+const TOOLS: &[(&str, &[&str])] = &[
+    ("wl-paste", &["--no-newline"]),
+    ("xclip", &["-selection", "clipboard", "-out"]),
+    ("xsel", &["--clipboard", "--output"]),
+];
+
+/// The text on the clipboard.
+pub fn read_clipboard() -> Result<String> {
+    let mut failures = Vec::new();
+    for (tool, args) in TOOLS {
+        match Command::new(tool).args(*args).output() {
+            Ok(output) if output.status.success() => {
+                return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+            }
+            Ok(output) => failures.push(format!(
+                "{} failed: {}",
+                tool,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => failures.push(format!("{} failed: {}", tool, e)),
+        }
+    }
+    if failures.is_empty() {
+        let tools: Vec<&str> = TOOLS.iter().map(|(tool, _)| *tool).collect();
+        failures.push(format!("none of {} is installed", tools.join(", ")));
+    }
+    Err(DuoloadError::Clipboard(format!(
+        "Cannot read the clipboard: {}",
+        failures.join("; ")
+    )))
+}
+
+/// The first deck ID, deck UUID or deck link in `text`, in the base64 form
+/// the API expects.
+pub fn find_deck_id(text: &str) -> Option<String> {
+    let text = text.trim();
+    if let Ok(deck_id) = parse_deck_id(text) {
+        return Some(deck_id);
+    }
+    text.split_whitespace()
+        .map(|word| word.trim_matches(|c: char| "\"'`<>()[]{},;".contains(c)))
+        .find_map(|word| {
+            parse_deck_id(word).ok().or_else(|| {
+                // `deckID:...` as copied from the web app's local storage
+                let (_, value) = word.rsplit_once(':')?;
+                parse_deck_id(value.trim_matches(|c: char| "\"'".contains(c))).ok()
+            })
+        })
+}
+
+/// The deck ID on the clipboard.
+pub fn deck_id_from_clipboard() -> Result<String> {
+    let text = read_clipboard()?;
+    find_deck_id(&text).ok_or_else(|| {
+        DuoloadError::Clipboard(
+            "No deck ID, deck UUID or link to a deck on the clipboard".to_string(),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DECK_ID: &str = "RGVjazo1YjZmMTA3My1hZjA2LTQwMGMtYTQyNC05ZWM5YzFlMGEzZjg=";
+
+    #[test]
+    fn test_find_deck_id() {
+        assert_eq!(
+            find_deck_id(&format!(" {}\n", DECK_ID)).as_deref(),
+            Some(DECK_ID)
+        );
+        assert_eq!(
+            find_deck_id("Learn with me: https://app.duocards.com/deck/5b6f1073-af06-400c-a424-9ec9c1e0a3f8 !")
+                .as_deref(),
+            Some(DECK_ID)
+        );
+        assert_eq!(
+            find_deck_id(&format!("deckID:{}", DECK_ID)).as_deref(),
+            Some(DECK_ID)
+        );
+        assert_eq!(
+            find_deck_id(&format!("{{\"deckID\": \"{}\",", DECK_ID)).as_deref(),
+            Some(DECK_ID)
+        );
+        assert_eq!(find_deck_id("nothing to see here"), None);
+    }
+}
@@ -0,0 +1,3 @@
+//! Helpers for the command line that are not tied to an export.
+
+pub mod clipboard;
@@ -0,0 +1,145 @@
+//! Credentials for decks that are only visible to a logged-in account.
+//!
+//! Duocards has no public login API, so credentials are taken from a logged-in
+//! browser session: either the session cookie or the bearer token the web app
+//! sends with its GraphQL requests. The cookie can also be read from a cookie
+//! jar exported from the browser, see [`cookie_jar`](crate::duocards::cookie_jar).
+
+use crate::duocards::cookie_jar::{cookie_header, read_cookie_jar};
+use reqwest::header::{AUTHORIZATION, COOKIE, HeaderValue};
+use reqwest::{RequestBuilder, Url};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Environment variable holding a session cookie.
+pub const COOKIE_ENV: &str = "DUOCARDS_COOKIE";
+
+/// Environment variable holding a bearer token.
+pub const TOKEN_ENV: &str = "DUOCARDS_TOKEN";
+
+#[derive(Error, Debug)]
+pub enum AuthError {
+    #[error("The session cookie was rejected; log in to Duocards again and copy a fresh cookie")]
+    InvalidCookie,
+
+    #[error("The access token was rejected; log in to Duocards again and copy a fresh token")]
+    InvalidToken,
+
+    #[error("This requires a logged-in Duocards account; pass --cookie or --token")]
+    Unauthenticated,
+
+    #[error("Credentials contain characters not allowed in an HTTP header")]
+    InvalidValue,
+
+    #[error("Cannot read cookie file {path:?}: {reason}")]
+    CookieFile { path: PathBuf, reason: String },
+
+    #[error(
+        "Cookie file {path:?} has no unexpired cookies for {url}; export the cookies again from a logged-in browser"
+    )]
+    NoCookies { path: PathBuf, url: String },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CredentialKind {
+    Cookie,
+    Token,
+}
+
+/// A session cookie or bearer token attached to every API request.
+#[derive(Clone)]
+pub struct Credentials {
+    kind: CredentialKind,
+    value: HeaderValue,
+}
+
+impl Credentials {
+    /// Credentials from a `Cookie` header value, e.g. `session=abc; other=def`.
+    pub fn cookie(cookie: &str) -> Result<Self, AuthError> {
+        Self::new(CredentialKind::Cookie, cookie.trim())
+    }
+
+    /// Credentials from the cookies of the jar at `path` that a browser would
+    /// send to `api_url`.
+    pub fn cookie_file(path: &Path, api_url: &str) -> Result<Self, AuthError> {
+        let file_error = |reason: String| AuthError::CookieFile {
+            path: path.to_path_buf(),
+            reason,
+        };
+        let url = Url::parse(api_url).map_err(|e| file_error(e.to_string()))?;
+        let cookies = read_cookie_jar(path).map_err(file_error)?;
+        let header = cookie_header(&cookies, &url).ok_or_else(|| AuthError::NoCookies {
+            path: path.to_path_buf(),
+            url: api_url.to_string(),
+        })?;
+        Self::cookie(&header)
+    }
+
+    /// Credentials from a bearer token, with or without the `Bearer ` prefix.
+    pub fn token(token: &str) -> Result<Self, AuthError> {
+        let token = token.trim();
+        let token = token.strip_prefix("Bearer ").unwrap_or(token);
+        Self::new(CredentialKind::Token, &format!("Bearer {}", token))
+    }
+
+    fn new(kind: CredentialKind, value: &str) -> Result<Self, AuthError> {
+        let mut value = HeaderValue::from_str(value).map_err(|_| AuthError::InvalidValue)?;
+        // Keeps the value out of debug output and HTTP/2 header compression
+        value.set_sensitive(true);
+        Ok(Self { kind, value })
+    }
+
+    /// Adds the credentials to a request.
+    pub fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+        let header = match self.kind {
+            CredentialKind::Cookie => COOKIE,
+            CredentialKind::Token => AUTHORIZATION,
+        };
+        request.header(header, self.value.clone())
+    }
+
+    /// The error to report when the API refuses these credentials.
+    pub fn rejected(&self) -> AuthError {
+        match self.kind {
+            CredentialKind::Cookie => AuthError::InvalidCookie,
+            CredentialKind::Token => AuthError::InvalidToken,
+        }
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("kind", &self.kind)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_prefix() {
+        let with_prefix = Credentials::token("Bearer abc").unwrap();
+        let without_prefix = Credentials::token(" abc\n").unwrap();
+        assert_eq!(with_prefix.value, "Bearer abc");
+        assert_eq!(without_prefix.value, "Bearer abc");
+    }
+
+    #[test]
+    fn test_invalid_value() {
+        assert!(matches!(
+            Credentials::cookie("session=a\nb"),
+            Err(AuthError::InvalidValue)
+        ));
+    }
+
+    #[test]
+    fn test_debug_hides_value() {
+        let credentials = Credentials::cookie("session=secret").unwrap();
+        assert!(!format!("{:?}", credentials).contains("secret"));
+        assert!(matches!(credentials.rejected(), AuthError::InvalidCookie));
+    }
+}
@@ -0,0 +1,79 @@
+//! Sizes of downloads, as given on the command line and shown in progress.
+
+/// Units of [`parse_byte_size`], longest suffix first.
+const UNITS: [(&str, u64); 10] = [
+    ("KiB", 1 << 10),
+    ("MiB", 1 << 20),
+    ("GiB", 1 << 30),
+    ("KB", 1_000),
+    ("MB", 1_000_000),
+    ("GB", 1_000_000_000),
+    ("K", 1_000),
+    ("M", 1_000_000),
+    ("G", 1_000_000_000),
+    ("B", 1),
+];
+
+/// Parses a number of bytes such as `500000`, `50MB`, `1.5G` or `64KiB`.
+/// Suffixes are case-insensitive; `K`, `M` and `G` are powers of 1000 and
+/// `KiB`, `MiB` and `GiB` powers of 1024.
+pub fn parse_byte_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (number, factor) = UNITS
+        .iter()
+        .find_map(|(unit, factor)| {
+            let split = s.len().checked_sub(unit.len())?;
+            let suffix = s.get(split..)?;
+            suffix
+                .eq_ignore_ascii_case(unit)
+                .then(|| (&s[..split], *factor))
+        })
+        .unwrap_or((s, 1));
+    number
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite() && *number > 0.0)
+        .map(|number| (number * factor as f64).round() as u64)
+        .filter(|bytes| *bytes > 0)
+        .ok_or_else(|| {
+            format!(
+                "Expected a positive size such as 500000, 50MB or 1GiB, got {:?}",
+                s
+            )
+        })
+}
+
+/// Shows `bytes` in the largest decimal unit it reaches, e.g. `1.2 MB`.
+pub fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..1_000 => format!("{} B", bytes),
+        1_000..1_000_000 => format!("{:.1} KB", bytes as f64 / 1e3),
+        1_000_000..1_000_000_000 => format!("{:.1} MB", bytes as f64 / 1e6),
+        _ => format!("{:.1} GB", bytes as f64 / 1e9),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_byte_size() {
+        assert_eq!(parse_byte_size("500000"), Ok(500_000));
+        assert_eq!(parse_byte_size("50MB"), Ok(50_000_000));
+        assert_eq!(parse_byte_size("1.5g"), Ok(1_500_000_000));
+        assert_eq!(parse_byte_size("64KiB"), Ok(65_536));
+        assert_eq!(parse_byte_size("10 b"), Ok(10));
+        for invalid in ["", "0", "-5MB", "MB", "lots"] {
+            assert!(parse_byte_size(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(999), "999 B");
+        assert_eq!(format_bytes(1_234_567), "1.2 MB");
+        assert_eq!(format_bytes(3_000_000_000), "3.0 GB");
+    }
+}
@@ -0,0 +1,170 @@
+//! On-disk cache of fetched pages.
+//!
+//! Every page is stored as the raw GraphQL response, one file per deck and
+//! cursor, so exporting the same deck again, e.g. to another format, reads
+//! the pages from disk instead of the API. In offline mode the API is never
+//! asked, and pages missing from the cache fail the export.
+//!
+//! Next to each page the cache keeps the `ETag` the API sent with it, if
+//! any. When revalidating, cached pages are asked for again with that
+//! validator, and the API only sends the pages that changed.
+
+use crate::error::{DuoloadError, Result};
+use crate::output::manifest::hex_digest;
+use crate::output::write_file_atomically;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// A directory of cached pages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageCache {
+    dir: PathBuf,
+    offline: bool,
+    revalidate: bool,
+}
+
+impl PageCache {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            offline: false,
+            revalidate: false,
+        }
+    }
+
+    /// Only replays cached pages, without requests to the API.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// Asks the API whether cached pages changed, sending the `ETag` they
+    /// were stored with, instead of replaying them as they are. Pages
+    /// stored without one are fetched again.
+    pub fn with_revalidation(mut self, revalidate: bool) -> Self {
+        self.revalidate = revalidate;
+        self
+    }
+
+    pub fn revalidates(&self) -> bool {
+        self.revalidate
+    }
+
+    /// File of the page of `deck_id` after `cursor`, or the first page.
+    pub fn path(&self, deck_id: &str, cursor: Option<&str>) -> PathBuf {
+        let deck = hex_digest(Sha256::digest(deck_id.as_bytes()).as_slice());
+        let page = hex_digest(Sha256::digest(cursor.unwrap_or("").as_bytes()).as_slice());
+        self.dir
+            .join(&deck[..16])
+            .join(format!("{}.json", &page[..16]))
+    }
+
+    /// The cached page, if there is one.
+    pub fn get(&self, deck_id: &str, cursor: Option<&str>) -> Result<Option<Value>> {
+        let path = self.path(deck_id, cursor);
+        match std::fs::read(&path) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The `ETag` the cached page was sent with, if there is one.
+    pub fn etag(&self, deck_id: &str, cursor: Option<&str>) -> Result<Option<String>> {
+        match std::fs::read_to_string(self.path(deck_id, cursor).with_extension("etag")) {
+            Ok(etag) => Ok(Some(etag)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Stores a page fetched from the API, with the `ETag` it was sent with.
+    pub fn put(
+        &self,
+        deck_id: &str,
+        cursor: Option<&str>,
+        page: &Value,
+        etag: Option<&str>,
+    ) -> Result<()> {
+        let path = self.path(deck_id, cursor);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let etag_path = path.with_extension("etag");
+        // A validator of an older version of the page must not outlive it
+        if let Err(e) = std::fs::remove_file(&etag_path)
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            return Err(e.into());
+        }
+        write_file_atomically(&path, |temp| {
+            std::fs::write(temp, serde_json::to_vec(page)?)?;
+            Ok(())
+        })?;
+        if let Some(etag) = etag {
+            write_file_atomically(&etag_path, |temp| {
+                std::fs::write(temp, etag)?;
+                Ok(())
+            })?;
+        }
+        Ok(())
+    }
+
+    /// The error for a page that offline mode cannot fetch.
+    pub fn missing(&self, cursor: Option<&str>) -> DuoloadError {
+        let page = match cursor {
+            Some(cursor) => format!("page after cursor {:?}", cursor),
+            None => "first page".to_string(),
+        };
+        DuoloadError::Cache(format!(
+            "The {} is not in the cache at {:?}; run without --offline to fetch it",
+            page, self.dir
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_pages_are_keyed_by_deck_and_cursor() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = PageCache::new(dir.path());
+
+        cache.put("deck", None, &json!({"page": 1}), None).unwrap();
+        cache
+            .put("deck", Some("100"), &json!({"page": 2}), None)
+            .unwrap();
+
+        assert_eq!(cache.get("deck", None).unwrap(), Some(json!({"page": 1})));
+        assert_eq!(
+            cache.get("deck", Some("100")).unwrap(),
+            Some(json!({"page": 2}))
+        );
+        assert_eq!(cache.get("deck", Some("200")).unwrap(), None);
+        assert_eq!(cache.get("other", None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_etags_are_kept_with_their_page() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = PageCache::new(dir.path());
+
+        cache
+            .put("deck", None, &json!({"page": 1}), Some("\"v1\""))
+            .unwrap();
+        assert_eq!(cache.etag("deck", None).unwrap().as_deref(), Some("\"v1\""));
+        assert_eq!(cache.etag("deck", Some("100")).unwrap(), None);
+
+        // A page sent without a validator drops the old one
+        cache.put("deck", None, &json!({"page": 1}), None).unwrap();
+        assert_eq!(cache.etag("deck", None).unwrap(), None);
+    }
+}
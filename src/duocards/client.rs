@@ -1,30 +1,122 @@
+use crate::duocards::auth::{AuthError, Credentials};
+use crate::duocards::cache::PageCache;
 use crate::duocards::deck;
+use crate::duocards::graphql::{
+    self, CardsQuery, DeckSummaryQuery, DecksQuery, GraphQLOperation, GraphQLRequest, PingQuery,
+    is_persisted_query_not_found, response_errors,
+};
+use crate::duocards::network::NetworkOptions;
+use crate::duocards::retry::{RetryPolicy, is_transient_error, is_transient_status};
+use crate::duocards::throttle::Throttle;
 use crate::duocards::{
     DuocardsClientTrait,
-    models::{CardsQuery, DuocardsResponse, VocabularyCard},
+    models::{DeckSummary, DeckTotals, DuocardsResponse, VocabularyCard},
 };
 use crate::error::{DuoloadError, Result};
+#[cfg(feature = "testing")]
+use crate::testing::{Fault, FaultInjector};
 use async_trait::async_trait;
 use reqwest::{
-    Client,
-    header::{ACCEPT_ENCODING, CONTENT_TYPE, HeaderMap, HeaderValue},
+    Client, RequestBuilder, StatusCode,
+    header::{
+        ACCEPT_ENCODING, CONTENT_TYPE, ETAG, HeaderMap, HeaderValue, IF_NONE_MATCH, RETRY_AFTER,
+    },
 };
-use std::time::Duration;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
 
 const BASE_URL: &str = "https://api.duocards.com/graphql";
 const USER_AGENT: &str = "duoload/1.0";
 const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
-const DEFAULT_PAGE_SIZE: i32 = 100;
+/// Number of cards requested per page.
+pub const DEFAULT_PAGE_SIZE: i32 = 100;
+
+/// How the API answered [`DuocardsClient::ping`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiStatus {
+    /// Time until the answer arrived, retries included
+    pub latency: Duration,
+    /// Release of the API, if it reports one
+    pub release_id: Option<String>,
+    /// Whether the credentials were accepted, or `None` without credentials
+    pub authenticated: Option<bool>,
+}
+
+/// The answer to a request, which is only conditional when it carries the
+/// `ETag` of a cached page.
+enum Reply {
+    Body {
+        body: Value,
+        /// Validator to send with the next request for the same page
+        etag: Option<String>,
+    },
+    /// 304 Not Modified: the cached page is still current
+    NotModified,
+}
+
+/// Counts of the HTTP requests a client has sent, shared by its clones.
+#[derive(Debug, Default)]
+pub struct RequestStats {
+    requests: AtomicU64,
+    retries: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl RequestStats {
+    /// Requests sent, retries included.
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// Requests that repeated a failed one.
+    pub fn retries(&self) -> u64 {
+        self.retries.load(Ordering::Relaxed)
+    }
+
+    /// Bytes of the response bodies received, after decompression.
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    fn add_bytes(&self, bytes: usize) {
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct DuocardsClient {
     client: Client,
     pub base_url: String,
     pub page_limit: Option<u32>,
+    pub persisted_queries: bool,
+    pub retry_policy: RetryPolicy,
+    credentials: Option<Credentials>,
+    request_stats: Arc<RequestStats>,
+    max_bytes: Option<u64>,
+    cache: Option<PageCache>,
+    throttle: Throttle,
+    #[cfg(feature = "testing")]
+    faults: Option<Arc<FaultInjector>>,
+}
+
+/// Outcome of sending a request once.
+enum Attempt {
+    Done(Reply),
+    /// A failure worth retrying, with the wait the server asked for
+    Failed(DuoloadError, Option<Duration>),
 }
 
 impl DuocardsClient {
     pub fn new() -> Result<Self> {
+        Self::new_with_network(&NetworkOptions::default())
+    }
+
+    /// Creates a client that connects through the given proxy and TLS settings.
+    pub fn new_with_network(network: &NetworkOptions) -> Result<Self> {
         let mut headers = HeaderMap::new();
         headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
         // headers.insert(ORIGIN, HeaderValue::from_static("https://app.duocards.com"));
@@ -34,24 +126,88 @@ impl DuocardsClient {
             HeaderValue::from_static("gzip, deflate, br, zstd"),
         );
 
-        let client = Client::builder()
+        let builder = Client::builder()
             .user_agent(USER_AGENT)
             .timeout(DEFAULT_TIMEOUT)
-            .default_headers(headers)
-            .build()?;
+            .default_headers(headers);
+        let client = network.apply(builder)?.build()?;
 
         Ok(Self {
             client,
-            base_url: BASE_URL.to_string(),
+            base_url: network.api_url.as_deref().unwrap_or(BASE_URL).to_string(),
             page_limit: None,
+            persisted_queries: false,
+            retry_policy: RetryPolicy::default(),
+            credentials: None,
+            request_stats: Arc::default(),
+            max_bytes: None,
+            cache: None,
+            throttle: Throttle::new(),
+            #[cfg(feature = "testing")]
+            faults: None,
         })
     }
 
+    /// Counts of the requests sent by this client and its clones.
+    pub fn request_stats(&self) -> Arc<RequestStats> {
+        self.request_stats.clone()
+    }
+
     pub fn with_page_limit(mut self, limit: u32) -> Self {
         self.page_limit = Some(limit);
         self
     }
 
+    /// Sends queries by hash first, falling back to the full document when
+    /// the server has not seen it yet.
+    pub fn with_persisted_queries(mut self, enabled: bool) -> Self {
+        self.persisted_queries = enabled;
+        self
+    }
+
+    /// Sets how requests failing with 429, 5xx, timeouts or connection errors are retried.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Sends a session cookie or token with every request, for private decks.
+    pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    /// Fails instead of sending another request once the responses received
+    /// add up to `max_bytes`, e.g. on a metered connection. The response
+    /// that crosses the limit is still used.
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Reads pages from `cache` when they are in it and stores fetched pages
+    /// there. A revalidating cache only replays the pages the API reports as
+    /// unchanged.
+    pub fn with_cache(mut self, cache: PageCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Makes requests fail on purpose as `injector` picks them, to exercise
+    /// the retry policy and resumed exports. Injected faults count as sent
+    /// requests and are retried like real ones.
+    #[cfg(feature = "testing")]
+    pub fn with_faults(mut self, injector: FaultInjector) -> Self {
+        self.faults = Some(Arc::new(injector));
+        self
+    }
+
+    /// Time to wait before the next request, if the last response asked to
+    /// slow down, see [`Throttle`].
+    pub fn throttle_delay(&self) -> Option<Duration> {
+        self.throttle.delay()
+    }
+
     pub fn should_continue(&self, current_page: u32) -> bool {
         match self.page_limit {
             Some(limit) => current_page <= limit,
@@ -67,20 +223,293 @@ impl DuocardsClient {
         // Validate deck ID before making the request
         deck::validate_deck_id(deck_id)?;
 
-        let query = CardsQuery::new(deck_id, DEFAULT_PAGE_SIZE, cursor);
+        let query = CardsQuery::new(deck_id, DEFAULT_PAGE_SIZE, cursor.clone());
+        let body = self.fetch_page_raw(deck_id, cursor).await?;
+        graphql::decode_response(&query, body)
+    }
+
+    /// Lists the decks of the account the credentials belong to.
+    pub async fn list_decks(&self) -> Result<Vec<DeckSummary>> {
+        let response = self.execute(&DecksQuery::default()).await?;
+        let viewer = response.data.viewer.ok_or(AuthError::Unauthenticated)?;
+        Ok(viewer.decks)
+    }
+
+    /// Sends the smallest query the API answers, to tell whether it is up
+    /// and accepts the credentials.
+    ///
+    /// Rejected credentials are reported in the status; only an API that
+    /// cannot be reached or answers with an error fails.
+    pub async fn ping(&self) -> Result<ApiStatus> {
+        let started = Instant::now();
+        let result = self.execute(&PingQuery::default()).await;
+        let latency = started.elapsed();
+        let (release_id, logged_in) = match result {
+            Ok(response) => (
+                response
+                    .extensions
+                    .and_then(|extensions| extensions.release_id),
+                response.data.viewer.is_some(),
+            ),
+            Err(DuoloadError::Auth(_)) if self.credentials.is_some() => (None, false),
+            Err(e) => return Err(e),
+        };
+        Ok(ApiStatus {
+            latency,
+            release_id,
+            authenticated: self.credentials.is_some().then_some(logged_in),
+        })
+    }
+
+    /// Fetches the name, languages and card count of a deck without
+    /// downloading its cards.
+    pub async fn fetch_deck_summary(&self, deck_id: &str) -> Result<DeckTotals> {
+        deck::validate_deck_id(deck_id)?;
+
+        let response = self.execute(&DeckSummaryQuery::new(deck_id)).await?;
+        Ok(response.data.node)
+    }
+
+    /// Fetches a page of cards as the unmodified JSON the API returned.
+    pub async fn fetch_page_raw(&self, deck_id: &str, cursor: Option<String>) -> Result<Value> {
+        deck::validate_deck_id(deck_id)?;
+
+        // The cached page and the ETag to ask whether it changed
+        let mut cached = None;
+        if let Some(cache) = &self.cache {
+            if let Some(page) = cache.get(deck_id, cursor.as_deref())? {
+                if !cache.revalidates() {
+                    return Ok(page);
+                }
+                cached = cache
+                    .etag(deck_id, cursor.as_deref())?
+                    .map(|etag| (page, etag));
+            } else if cache.is_offline() {
+                return Err(cache.missing(cursor.as_deref()));
+            }
+        }
+
+        let query = CardsQuery::new(deck_id, DEFAULT_PAGE_SIZE, cursor.clone());
+        let if_none_match = cached.as_ref().map(|(_, etag)| etag.as_str());
+        let (page, etag) = match self.execute_reply(&query, if_none_match).await? {
+            Reply::Body { body, etag } => (body, etag),
+            Reply::NotModified => {
+                let (page, _) = cached.expect("only conditional requests are not modified");
+                tracing::debug!("Page after {:?} is unchanged", cursor);
+                return Ok(page);
+            }
+        };
+        if let Some(cache) = &self.cache {
+            cache.put(deck_id, cursor.as_deref(), &page, etag.as_deref())?;
+        }
+        Ok(page)
+    }
+
+    /// Executes a GraphQL operation and returns its validated response.
+    pub async fn execute<O: GraphQLOperation>(&self, operation: &O) -> Result<O::Response> {
+        let body = self.execute_raw(operation).await?;
+        graphql::decode_response(operation, body)
+    }
+
+    /// Executes a GraphQL operation and returns the response body without decoding it.
+    ///
+    /// Fails if the response carries GraphQL errors.
+    pub async fn execute_raw<O: GraphQLOperation>(&self, operation: &O) -> Result<Value> {
+        match self.execute_reply(operation, None).await? {
+            Reply::Body { body, .. } => Ok(body),
+            Reply::NotModified => Err(DuoloadError::Api(
+                "API answered an unconditional request with 304 Not Modified".to_string(),
+            )),
+        }
+    }
+
+    /// Executes a GraphQL operation, conditionally if `if_none_match` is the
+    /// `ETag` of a cached response.
+    async fn execute_reply<O: GraphQLOperation>(
+        &self,
+        operation: &O,
+        if_none_match: Option<&str>,
+    ) -> Result<Reply> {
+        let reply = if self.persisted_queries {
+            let reply = self
+                .post(&GraphQLRequest::persisted(operation), if_none_match)
+                .await?;
+            match reply {
+                Reply::Body { body, .. }
+                    if is_persisted_query_not_found(&response_errors(&body)) =>
+                {
+                    self.post(&GraphQLRequest::register(operation), if_none_match)
+                        .await?
+                }
+                reply => reply,
+            }
+        } else {
+            self.post(&GraphQLRequest::new(operation), if_none_match)
+                .await?
+        };
 
-        let response = self.client.post(&self.base_url).json(&query).send().await?;
+        if let Reply::Body { body, .. } = &reply {
+            graphql::check_errors::<O>(body)?;
+        }
+        Ok(reply)
+    }
 
-        if !response.status().is_success() {
-            return Err(DuoloadError::Api(format!(
-                "API request failed with status {}: {}",
-                response.status(),
-                response.text().await?
-            )));
+    /// Posts a request, retrying transient failures according to the retry
+    /// policy. With `if_none_match`, the API may answer that the response
+    /// with that `ETag` is still current.
+    async fn post<T: Serialize>(&self, request: &T, if_none_match: Option<&str>) -> Result<Reply> {
+        let mut retry = 0;
+        loop {
+            if let Some(limit) = self.max_bytes {
+                let downloaded = self.request_stats.bytes();
+                if downloaded >= limit {
+                    return Err(DuoloadError::ByteLimit { limit, downloaded });
+                }
+            }
+            let mut http_request = self.client.post(&self.base_url).json(request);
+            if let Some(credentials) = &self.credentials {
+                http_request = credentials.apply(http_request);
+            }
+            if let Some(etag) = if_none_match {
+                http_request = http_request.header(IF_NONE_MATCH, etag);
+            }
+            self.request_stats.requests.fetch_add(1, Ordering::Relaxed);
+            if retry > 0 {
+                self.request_stats.retries.fetch_add(1, Ordering::Relaxed);
+            }
+            #[cfg(feature = "testing")]
+            let fault = self.faults.as_ref().and_then(|faults| faults.next_fault());
+            #[cfg(feature = "testing")]
+            let attempt = match fault {
+                Some(fault) => {
+                    self.send_with_fault(http_request, fault, if_none_match)
+                        .await?
+                }
+                None => self.send(http_request, if_none_match).await?,
+            };
+            #[cfg(not(feature = "testing"))]
+            let attempt = self.send(http_request, if_none_match).await?;
+            let (error, retry_after) = match attempt {
+                Attempt::Done(reply) => return Ok(reply),
+                Attempt::Failed(error, retry_after) => (error, retry_after),
+            };
+
+            retry += 1;
+            if retry > self.retry_policy.retries {
+                return Err(error);
+            }
+            // The server's own estimate wins if it asks for a longer wait
+            let delay = self
+                .retry_policy
+                .delay(retry)
+                .max(retry_after.unwrap_or_default());
+            tracing::warn!(
+                "Request failed: {}. Retrying in {:?} (retry {}/{})...",
+                error,
+                delay,
+                retry,
+                self.retry_policy.retries
+            );
+            sleep(delay).await;
         }
+    }
 
-        let response: DuocardsResponse = response.json().await?;
-        Ok(response)
+    /// Sends a request once. Failures worth retrying are returned as
+    /// [`Attempt::Failed`], all others as errors.
+    async fn send(
+        &self,
+        http_request: RequestBuilder,
+        if_none_match: Option<&str>,
+    ) -> Result<Attempt> {
+        let reply = match http_request.send().await {
+            Ok(response) if response.status().is_success() => {
+                let headers = response.headers().clone();
+                let bytes = response.bytes().await?;
+                self.request_stats.add_bytes(bytes.len());
+                let body = match serde_json::from_slice(&bytes) {
+                    Ok(body) => body,
+                    Err(e) => {
+                        return Ok(Attempt::Failed(DuoloadError::MalformedResponse(e), None));
+                    }
+                };
+                self.throttle.observe(&headers, &body);
+                let etag = headers
+                    .get(ETAG)
+                    .and_then(|etag| etag.to_str().ok())
+                    .map(str::to_string);
+                Reply::Body { body, etag }
+            }
+            Ok(response)
+                if response.status() == StatusCode::NOT_MODIFIED && if_none_match.is_some() =>
+            {
+                Reply::NotModified
+            }
+            Ok(response)
+                if matches!(
+                    response.status(),
+                    StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+                ) =>
+            {
+                let error = match &self.credentials {
+                    Some(credentials) => credentials.rejected(),
+                    None => AuthError::Unauthenticated,
+                };
+                return Err(error.into());
+            }
+            Ok(response) => {
+                let status = response.status();
+                let retry_after = retry_after(response.headers());
+                let text = response.text().await?;
+                self.request_stats.add_bytes(text.len());
+                if !is_transient_status(status) {
+                    return Err(DuoloadError::Api(format!(
+                        "API request failed with status {}: {}",
+                        status, text
+                    )));
+                }
+                let error = DuoloadError::TransientStatus { status, body: text };
+                return Ok(Attempt::Failed(error, retry_after));
+            }
+            Err(e) if is_transient_error(&e) => return Ok(Attempt::Failed(e.into(), None)),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Attempt::Done(reply))
+    }
+
+    /// Fails a request with an injected `fault` instead of sending it, or
+    /// for malformed bodies, sends it and cuts off the response.
+    #[cfg(feature = "testing")]
+    async fn send_with_fault(
+        &self,
+        http_request: RequestBuilder,
+        fault: Fault,
+        if_none_match: Option<&str>,
+    ) -> Result<Attempt> {
+        let error = match fault {
+            Fault::Timeout => {
+                let timeout = self
+                    .faults
+                    .as_ref()
+                    .map_or(DEFAULT_TIMEOUT, |faults| faults.faults().timeout_after);
+                sleep(timeout).await;
+                DuoloadError::Timeout(timeout)
+            }
+            Fault::ServerError => DuoloadError::TransientStatus {
+                status: StatusCode::INTERNAL_SERVER_ERROR,
+                body: "injected fault".to_string(),
+            },
+            Fault::Malformed => {
+                if let Attempt::Failed(error, retry_after) =
+                    self.send(http_request, if_none_match).await?
+                {
+                    return Ok(Attempt::Failed(error, retry_after));
+                }
+                let truncated = serde_json::from_str::<Value>(r#"{"data":{"node":{"#)
+                    .expect_err("the body is cut off");
+                DuoloadError::MalformedResponse(truncated)
+            }
+        };
+        Ok(Attempt::Failed(error, None))
     }
 
     // Helper method to convert API response to our internal card format
@@ -96,6 +525,18 @@ impl DuocardsClient {
     }
 }
 
+/// Wait requested by a `Retry-After` header given in seconds.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let seconds = headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
 #[async_trait]
 impl DuocardsClientTrait for DuocardsClient {
     async fn fetch_page(&self, deck_id: &str, cursor: Option<String>) -> Result<DuocardsResponse> {
@@ -113,4 +554,12 @@ impl DuocardsClientTrait for DuocardsClient {
     fn page_limit(&self) -> Option<u32> {
         self.page_limit
     }
+
+    fn request_stats(&self) -> Option<Arc<RequestStats>> {
+        Some(self.request_stats())
+    }
+
+    fn throttle_delay(&self) -> Option<Duration> {
+        self.throttle_delay()
+    }
 }
@@ -0,0 +1,228 @@
+//! Cookie jars exported from a browser.
+//!
+//! Browser extensions export the cookies of a profile either as a Netscape
+//! `cookies.txt` file, as curl and wget read them, or as JSON: an array of
+//! cookies (Cookie-Editor, EditThisCookie) or an object with a `cookies`
+//! array (Playwright storage state). The cookies a browser would send to the
+//! API are turned into a `Cookie` header, so the session of a logged-in
+//! browser can be reused without logging in again.
+
+use reqwest::Url;
+use serde::Deserialize;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Prefix of Netscape lines holding HttpOnly cookies, which are not comments.
+const HTTP_ONLY_PREFIX: &str = "#HttpOnly_";
+
+/// A cookie of a jar.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JarCookie {
+    /// Domain of the cookie, without a leading dot
+    pub domain: String,
+    /// Whether the cookie is also sent to subdomains of `domain`
+    pub include_subdomains: bool,
+    pub path: String,
+    /// Whether the cookie is only sent over HTTPS
+    pub secure: bool,
+    /// When the cookie expires, in seconds since 1970, or `None` for
+    /// session cookies
+    pub expires: Option<f64>,
+    pub name: String,
+    pub value: String,
+}
+
+impl JarCookie {
+    /// Whether a browser would send the cookie with a request to `url` at
+    /// `now`, in seconds since 1970.
+    fn matches(&self, url: &Url, now: f64) -> bool {
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        let domain_matches = host.eq_ignore_ascii_case(&self.domain)
+            || (self.include_subdomains
+                && host
+                    .to_ascii_lowercase()
+                    .ends_with(&format!(".{}", self.domain.to_ascii_lowercase())));
+        domain_matches
+            && url.path().starts_with(&self.path)
+            && (!self.secure || url.scheme() == "https")
+            && self.expires.is_none_or(|expires| expires > now)
+    }
+}
+
+/// A cookie of a JSON export, with the field names of the common extensions.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonCookie {
+    name: String,
+    value: String,
+    domain: String,
+    #[serde(default)]
+    path: Option<String>,
+    #[serde(default)]
+    secure: bool,
+    #[serde(default)]
+    host_only: Option<bool>,
+    /// Cookie-Editor and EditThisCookie
+    #[serde(default)]
+    expiration_date: Option<f64>,
+    /// Playwright, -1 for session cookies
+    #[serde(default)]
+    expires: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonJar {
+    Cookies(Vec<JsonCookie>),
+    StorageState { cookies: Vec<JsonCookie> },
+}
+
+impl From<JsonCookie> for JarCookie {
+    fn from(cookie: JsonCookie) -> Self {
+        let include_subdomains = match cookie.host_only {
+            Some(host_only) => !host_only,
+            None => cookie.domain.starts_with('.'),
+        };
+        Self {
+            domain: cookie.domain.trim_start_matches('.').to_string(),
+            include_subdomains,
+            path: cookie.path.unwrap_or_else(|| "/".to_string()),
+            secure: cookie.secure,
+            expires: cookie
+                .expiration_date
+                .or(cookie.expires)
+                .filter(|expires| *expires > 0.0),
+            name: cookie.name,
+            value: cookie.value,
+        }
+    }
+}
+
+/// Reads the cookies of a jar file in Netscape or JSON form.
+pub fn read_cookie_jar(path: &Path) -> Result<Vec<JarCookie>, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    parse_cookie_jar(&content)
+}
+
+/// Parses the cookies of a jar in Netscape or JSON form.
+pub fn parse_cookie_jar(content: &str) -> Result<Vec<JarCookie>, String> {
+    let content = content.trim_start_matches('\u{feff}');
+    if matches!(content.trim_start().chars().next(), Some('[' | '{')) {
+        let jar: JsonJar = serde_json::from_str(content).map_err(|e| e.to_string())?;
+        let cookies = match jar {
+            JsonJar::Cookies(cookies) | JsonJar::StorageState { cookies } => cookies,
+        };
+        return Ok(cookies.into_iter().map(JarCookie::from).collect());
+    }
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let line = line.trim_end_matches('\r');
+            let line = line.strip_prefix(HTTP_ONLY_PREFIX).unwrap_or(line);
+            if line.trim().is_empty() || line.starts_with('#') {
+                return None;
+            }
+            Some(
+                parse_netscape_line(line)
+                    .ok_or_else(|| format!("line {} is not a Netscape cookie line", index + 1)),
+            )
+        })
+        .collect()
+}
+
+/// Parses `domain  subdomains  path  secure  expires  name  value`, separated
+/// by tabs.
+fn parse_netscape_line(line: &str) -> Option<JarCookie> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    let [domain, subdomains, path, secure, expires, name, value] = fields[..] else {
+        return None;
+    };
+    let flag = |text: &str| text.eq_ignore_ascii_case("TRUE");
+    let expires: f64 = expires.trim().parse().ok()?;
+    Some(JarCookie {
+        domain: domain.trim_start_matches('.').to_string(),
+        include_subdomains: flag(subdomains) || domain.starts_with('.'),
+        path: path.to_string(),
+        secure: flag(secure),
+        expires: (expires > 0.0).then_some(expires),
+        name: name.to_string(),
+        value: value.to_string(),
+    })
+}
+
+/// The `Cookie` header a browser would send with a request to `url`, or
+/// `None` if no unexpired cookie of `cookies` applies to it.
+pub fn cookie_header(cookies: &[JarCookie], url: &Url) -> Option<String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+    let pairs: Vec<String> = cookies
+        .iter()
+        .filter(|cookie| cookie.matches(url, now))
+        .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+        .collect();
+    (!pairs.is_empty()).then(|| pairs.join("; "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api() -> Url {
+        Url::parse("https://api.duocards.com/graphql").unwrap()
+    }
+
+    #[test]
+    fn test_netscape_jar() {
+        let jar = "# Netscape HTTP Cookie File\n\
+            .duocards.com\tTRUE\t/\tTRUE\t0\tsession\tabc\n\
+            #HttpOnly_api.duocards.com\tFALSE\t/\tTRUE\t4102444800\tauth\tdef\n\
+            app.duocards.com\tFALSE\t/\tTRUE\t0\tapp\tghi\n\
+            .duocards.com\tTRUE\t/\tFALSE\t1\texpired\tjkl\n\
+            example.com\tTRUE\t/\tFALSE\t0\tother\tmno\n";
+        let cookies = parse_cookie_jar(jar).unwrap();
+        assert_eq!(cookies.len(), 5);
+        assert_eq!(
+            cookie_header(&cookies, &api()),
+            Some("session=abc; auth=def".to_string())
+        );
+        // Secure cookies stay off plain HTTP
+        let http = Url::parse("http://api.duocards.com/graphql").unwrap();
+        assert_eq!(cookie_header(&cookies, &http), None);
+    }
+
+    #[test]
+    fn test_json_jars() {
+        let editor = r#"[
+            {"domain": ".duocards.com", "name": "session", "value": "abc", "path": "/",
+             "secure": true, "hostOnly": false, "expirationDate": 4102444800.5},
+            {"domain": "app.duocards.com", "name": "app", "value": "def", "hostOnly": true,
+             "session": true}
+        ]"#;
+        let cookies = parse_cookie_jar(editor).unwrap();
+        assert_eq!(
+            cookie_header(&cookies, &api()),
+            Some("session=abc".to_string())
+        );
+
+        let playwright = r#"{"cookies": [{"name": "session", "value": "abc",
+            "domain": "api.duocards.com", "path": "/", "expires": -1, "secure": true}],
+            "origins": []}"#;
+        let cookies = parse_cookie_jar(playwright).unwrap();
+        assert_eq!(cookies[0].expires, None);
+        assert_eq!(
+            cookie_header(&cookies, &api()),
+            Some("session=abc".to_string())
+        );
+    }
+
+    #[test]
+    fn test_malformed_jar() {
+        assert!(parse_cookie_jar("duocards.com\tTRUE\t/").is_err());
+        assert!(parse_cookie_jar("[{\"name\": \"session\"}]").is_err());
+    }
+}
@@ -44,3 +44,62 @@ pub fn validate_deck_id(deck_id: &str) -> Result<()> {
 
     Ok(())
 }
+
+/// Turns what a user pasted as deck ID into the base64 form the API expects.
+///
+/// Accepts the base64 encoded `Deck:<UUID4>` itself, the bare UUID, or a
+/// `https://app.duocards.com/...` share link containing either of them.
+pub fn parse_deck_id(input: &str) -> Result<String> {
+    let input = input.trim();
+    if input.starts_with("https://") || input.starts_with("http://") {
+        // The ID is usually the last segment, so look from the end
+        return input
+            .split(['/', '?', '&', '=', '#'])
+            .rev()
+            .map(percent_decode)
+            .find_map(|segment| deck_id_from(&segment))
+            .ok_or_else(|| DeckIdError::InvalidFormat("No deck ID in URL".to_string()).into());
+    }
+    if let Ok(uuid) = Uuid::parse_str(input) {
+        return encode_deck_id(uuid);
+    }
+    validate_deck_id(input)?;
+    Ok(input.to_string())
+}
+
+/// Encodes a deck UUID into the base64 `Deck:<UUID4>` form.
+fn encode_deck_id(uuid: Uuid) -> Result<String> {
+    let deck_id = BASE64.encode(format!("Deck:{}", uuid.hyphenated()));
+    validate_deck_id(&deck_id)?;
+    Ok(deck_id)
+}
+
+/// The deck ID in a URL segment, if the segment is one.
+fn deck_id_from(segment: &str) -> Option<String> {
+    if let Ok(uuid) = Uuid::parse_str(segment) {
+        return encode_deck_id(uuid).ok();
+    }
+    validate_deck_id(segment).ok()?;
+    Some(segment.to_string())
+}
+
+/// Decodes `%XX` escapes, e.g. the `%3D` padding of a base64 ID in a link.
+fn percent_decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = segment
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,303 @@
+use crate::duocards::DuocardsClientTrait;
+use crate::duocards::models::{
+    Card, CardAudio, CardConnection, CardEdge, CardImage, CardSource, Deck, DuocardsResponse,
+    Extensions, PageInfo, ResponseData, SharedCard, Theory, VocabularyCard,
+};
+use crate::error::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::Path;
+
+/// Number of cards per page, the same as requested from the API.
+const PAGE_SIZE: usize = 100;
+
+/// JSON output written with a metadata header.
+#[derive(Deserialize)]
+struct CardDocument {
+    cards: Vec<VocabularyCard>,
+}
+
+/// Reads cards from a local JSON or CSV export instead of the Duocards API.
+///
+/// The cards are served in pages like the API serves them, with the card
+/// index as cursor, so an offline dump goes through the same transfer
+/// pipeline as a live export.
+pub struct FileSourceClient {
+    cards: Vec<VocabularyCard>,
+    page_limit: Option<u32>,
+}
+
+impl FileSourceClient {
+    pub fn new(cards: Vec<VocabularyCard>) -> Self {
+        Self {
+            cards,
+            page_limit: None,
+        }
+    }
+
+    /// Reads the cards of a `--json-file` export: a JSON array, or one card
+    /// per line as written with `--ndjson`, with or without `--json-metadata`.
+    pub fn from_json(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let cards = if content.trim_start().starts_with('[') {
+            serde_json::from_str(&content)?
+        } else if let Ok(document) = serde_json::from_str::<CardDocument>(&content) {
+            document.cards
+        } else {
+            content
+                .lines()
+                .filter(|line| !line.trim().is_empty() && !is_metadata_line(line))
+                .map(serde_json::from_str)
+                .collect::<std::result::Result<_, _>>()?
+        };
+        Ok(Self::new(cards))
+    }
+
+    /// Reads the cards of a `--csv-file` export, with its header row.
+    pub fn from_csv(path: &Path) -> Result<Self> {
+        let mut reader = csv::Reader::from_path(path)?;
+        let cards = reader
+            .deserialize()
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(Self::new(cards))
+    }
+
+    pub fn with_page_limit(mut self, limit: u32) -> Self {
+        self.page_limit = Some(limit);
+        self
+    }
+
+    /// Number of cards read from the file.
+    pub fn len(&self) -> usize {
+        self.cards.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cards.is_empty()
+    }
+}
+
+/// Whether `line` is the `{"deck": {...}}` line starting JSON Lines output.
+fn is_metadata_line(line: &str) -> bool {
+    serde_json::from_str::<Value>(line)
+        .is_ok_and(|value| value.get("deck").is_some() && value.get("word").is_none())
+}
+
+/// The card as the API would return it.
+fn card_node(index: usize, card: &VocabularyCard) -> Card {
+    // Exports without the count only have the status
+    let known_count = card
+        .known_count
+        .unwrap_or_else(|| card.status.min_known_count());
+    Card {
+        id: index.to_string(),
+        front: card.word.clone(),
+        back: card.translation.clone(),
+        hint: card.example.clone(),
+        created_at: card.created_at.clone().map(Value::String),
+        waiting: card.waiting_until.map(|time| Value::String(time.rfc3339())),
+        known_count,
+        svg: card.image.as_ref().map(|url| CardImage {
+            flat_id: None,
+            url: Some(url.clone()),
+            id: None,
+        }),
+        audio: card.audio.as_ref().map(|url| CardAudio {
+            url: Some(url.clone()),
+            id: None,
+        }),
+        // Labels come from the kind and course of the source
+        source: (!card.labels.is_empty()).then(|| {
+            let label = |index: usize| card.labels.get(index).cloned().map(Value::String);
+            CardSource {
+                kind: label(0),
+                course: label(1),
+                id: None,
+            }
+        }),
+        s_card: card.hint.as_ref().map(|hint| SharedCard {
+            theory: Some(Theory {
+                theory: None,
+                theory_native: Some(hint.clone()),
+                theory_en: None,
+            }),
+            id: None,
+        }),
+        typename: "Card".to_string(),
+    }
+}
+
+#[async_trait]
+impl DuocardsClientTrait for FileSourceClient {
+    async fn fetch_page(&self, deck_id: &str, cursor: Option<String>) -> Result<DuocardsResponse> {
+        let start = cursor
+            .and_then(|cursor| cursor.parse::<usize>().ok())
+            .map_or(0, |index| index + 1)
+            .min(self.cards.len());
+        let end = (start + PAGE_SIZE).min(self.cards.len());
+
+        let edges: Vec<CardEdge> = self.cards[start..end]
+            .iter()
+            .enumerate()
+            .map(|(offset, card)| CardEdge {
+                node: card_node(start + offset, card),
+                cursor: (start + offset).to_string(),
+            })
+            .collect();
+
+        Ok(DuocardsResponse {
+            data: ResponseData {
+                node: Deck {
+                    __typename: "Deck".to_string(),
+                    cards: CardConnection {
+                        page_info: PageInfo {
+                            end_cursor: edges.last().map(|edge| edge.cursor.clone()),
+                            has_next_page: end < self.cards.len(),
+                        },
+                        edges,
+                    },
+                    id: deck_id.to_string(),
+                    name: None,
+                    front: None,
+                    back: None,
+                },
+            },
+            extensions: Extensions { release_id: None },
+        })
+    }
+
+    fn convert_to_vocabulary_cards(&self, response: &DuocardsResponse) -> Vec<VocabularyCard> {
+        // Cursors are indexes into the file, so the cards come back unchanged
+        response
+            .data
+            .node
+            .cards
+            .edges
+            .iter()
+            .filter_map(|edge| self.cards.get(edge.cursor.parse::<usize>().ok()?))
+            .cloned()
+            .collect()
+    }
+
+    fn should_continue(&self, current_page: u32) -> bool {
+        match self.page_limit {
+            Some(limit) => current_page <= limit,
+            None => true,
+        }
+    }
+
+    fn page_limit(&self) -> Option<u32> {
+        self.page_limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duocards::models::LearningStatus;
+    use std::io::Write;
+
+    fn card(word: &str, status: LearningStatus) -> VocabularyCard {
+        VocabularyCard {
+            word: word.to_string(),
+            translation: format!("{} translated", word),
+            status,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pages_follow_card_indexes() {
+        let cards: Vec<_> = (0..150)
+            .map(|i| card(&format!("word{}", i), LearningStatus::New))
+            .collect();
+        let client = FileSourceClient::new(cards.clone());
+
+        let first = client.fetch_page("deck", None).await.unwrap();
+        let page_info = &first.data.node.cards.page_info;
+        assert_eq!(page_info.end_cursor.as_deref(), Some("99"));
+        assert!(page_info.has_next_page);
+        assert_eq!(client.convert_to_vocabulary_cards(&first), cards[..100]);
+
+        let second = client
+            .fetch_page("deck", Some("99".to_string()))
+            .await
+            .unwrap();
+        assert!(!second.data.node.cards.page_info.has_next_page);
+        assert_eq!(client.convert_to_vocabulary_cards(&second), cards[100..]);
+    }
+
+    #[tokio::test]
+    async fn test_labels_survive_the_api_form() {
+        let labelled = VocabularyCard {
+            labels: vec!["course".to_string(), "Spanish A1".to_string()],
+            ..card("hola", LearningStatus::New)
+        };
+        let client = FileSourceClient::new(vec![labelled.clone()]);
+
+        let page = client.fetch_page("deck", None).await.unwrap();
+        let cards = client.convert_to_vocabulary_cards(&page);
+        assert_eq!(cards[0].labels, labelled.labels);
+    }
+
+    #[test]
+    fn test_reads_json_lines_and_csv() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let json_path = dir.path().join("cards.jsonl");
+        let mut file = std::fs::File::create(&json_path).unwrap();
+        writeln!(
+            file,
+            r#"{{"word":"hola","translation":"hello","example":null,"status":"known"}}"#
+        )
+        .unwrap();
+        writeln!(
+            file,
+            r#"{{"word":"adiós","translation":"bye","example":"¡Adiós!","status":"learning"}}"#
+        )
+        .unwrap();
+        let client = FileSourceClient::from_json(&json_path).unwrap();
+        assert_eq!(client.len(), 2);
+        assert_eq!(client.cards[0].status, LearningStatus::Known);
+        assert_eq!(client.cards[1].example.as_deref(), Some("¡Adiós!"));
+
+        let csv_path = dir.path().join("cards.csv");
+        std::fs::write(
+            &csv_path,
+            "word,translation,example,status\nhola,hello,,new\nadiós,bye,¡Adiós!,learning\n",
+        )
+        .unwrap();
+        let client = FileSourceClient::from_csv(&csv_path).unwrap();
+        assert_eq!(client.len(), 2);
+        assert_eq!(client.cards[0].example, None);
+        assert_eq!(client.cards[1].status, LearningStatus::Learning);
+    }
+
+    #[test]
+    fn test_reads_json_with_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let card = r#"{"word":"Haus","translation":"house","example":null,"status":"new"}"#;
+
+        let document_path = dir.path().join("deck.json");
+        std::fs::write(
+            &document_path,
+            format!(
+                r#"{{"deck": {{"id": "1", "name": "German"}}, "cards": [{}]}}"#,
+                card
+            ),
+        )
+        .unwrap();
+        let client = FileSourceClient::from_json(&document_path).unwrap();
+        assert_eq!(client.cards[0].word, "Haus");
+
+        let lines_path = dir.path().join("deck.jsonl");
+        std::fs::write(
+            &lines_path,
+            format!("{{\"deck\": {{\"id\": \"1\"}}}}\n{}\n", card),
+        )
+        .unwrap();
+        let client = FileSourceClient::from_json(&lines_path).unwrap();
+        assert_eq!(client.len(), 1);
+    }
+}
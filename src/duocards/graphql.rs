@@ -0,0 +1,449 @@
+//! Typed access to the Duocards GraphQL API.
+//!
+//! Every request duoload sends is described by a [`GraphQLOperation`]: the
+//! query document, its variables and the shape of the response. Requests can
+//! be sent in full or as persisted queries, where only the SHA-256 hash of the
+//! document is transmitted and the full text is sent once if the server does
+//! not know the hash yet.
+//!
+//! ```no_run
+//! # async fn example() -> duoload::error::Result<()> {
+//! use duoload::duocards::DuocardsClient;
+//! use duoload::duocards::graphql::CardsQuery;
+//!
+//! let client = DuocardsClient::new()?.with_persisted_queries(true);
+//! let query = CardsQuery::new("RGVjazo0NmYyYjllZC1hYmYzLTRiZDgtYTA1NC02OGRmYTRhNDIwM2U=", 30, None)
+//!     .search("haus");
+//! let response = client.execute(&query).await?;
+//! println!("{} cards", response.data.node.cards.edges.len());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::duocards::models::{DeckSummaryResponse, DecksResponse, DuocardsResponse, PingResponse};
+use crate::error::{DuoloadError, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// Query document for fetching a page of cards from a deck.
+pub const CARDS_QUERY: &str = include_str!("queries/cards.graphql");
+
+/// Query document for listing the decks of the logged-in account.
+pub const DECKS_QUERY: &str = include_str!("queries/decks.graphql");
+
+/// Query document for the card count of a deck.
+pub const DECK_SUMMARY_QUERY: &str = include_str!("queries/deck_summary.graphql");
+
+/// Query document for checking that the API answers.
+pub const PING_QUERY: &str = include_str!("queries/ping.graphql");
+
+/// Error message servers use when they do not know a persisted query hash.
+const PERSISTED_QUERY_NOT_FOUND: &str = "PersistedQueryNotFound";
+
+/// A GraphQL operation with typed variables and response.
+pub trait GraphQLOperation {
+    /// Operation name as declared in the query document.
+    const OPERATION_NAME: &'static str;
+    /// The query document.
+    const QUERY: &'static str;
+
+    type Variables: Serialize;
+    type Response: DeserializeOwned;
+
+    fn variables(&self) -> &Self::Variables;
+
+    /// Checks a decoded response for consistency beyond what serde enforces.
+    fn validate(&self, _response: &Self::Response) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Request body for a GraphQL operation.
+#[derive(Debug, Serialize)]
+pub struct GraphQLRequest<'a, V> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<&'a str>,
+    #[serde(rename = "operationName")]
+    pub operation_name: &'a str,
+    pub variables: &'a V,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<RequestExtensions>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RequestExtensions {
+    #[serde(rename = "persistedQuery")]
+    pub persisted_query: PersistedQuery,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PersistedQuery {
+    pub version: u32,
+    #[serde(rename = "sha256Hash")]
+    pub sha256_hash: String,
+}
+
+impl<'a, V: Serialize> GraphQLRequest<'a, V> {
+    /// A request carrying the full query document.
+    pub fn new<O: GraphQLOperation<Variables = V>>(operation: &'a O) -> Self {
+        Self {
+            query: Some(O::QUERY),
+            operation_name: O::OPERATION_NAME,
+            variables: operation.variables(),
+            extensions: None,
+        }
+    }
+
+    /// A persisted-query request carrying only the hash of the document.
+    pub fn persisted<O: GraphQLOperation<Variables = V>>(operation: &'a O) -> Self {
+        Self {
+            query: None,
+            extensions: Some(RequestExtensions {
+                persisted_query: PersistedQuery {
+                    version: 1,
+                    sha256_hash: query_hash(O::QUERY),
+                },
+            }),
+            ..Self::new(operation)
+        }
+    }
+
+    /// A persisted-query request that also registers the full document.
+    pub fn register<O: GraphQLOperation<Variables = V>>(operation: &'a O) -> Self {
+        Self {
+            query: Some(O::QUERY),
+            ..Self::persisted(operation)
+        }
+    }
+}
+
+/// Hex-encoded SHA-256 hash of a query document, as used by persisted queries.
+pub fn query_hash(query: &str) -> String {
+    Sha256::digest(query.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// An entry of the `errors` array of a GraphQL response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphQLError {
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<Value>,
+}
+
+/// Extracts the `errors` array from a raw GraphQL response.
+pub fn response_errors(body: &Value) -> Vec<GraphQLError> {
+    body.get("errors")
+        .and_then(|errors| serde_json::from_value(errors.clone()).ok())
+        .unwrap_or_default()
+}
+
+/// Whether the server asked for the full document of a persisted query.
+pub fn is_persisted_query_not_found(errors: &[GraphQLError]) -> bool {
+    errors.iter().any(|error| {
+        error.message == PERSISTED_QUERY_NOT_FOUND
+            || error
+                .extensions
+                .as_ref()
+                .and_then(|extensions| extensions.get("code"))
+                .and_then(Value::as_str)
+                == Some("PERSISTED_QUERY_NOT_FOUND")
+    })
+}
+
+/// Fails if a raw response for `operation` carries GraphQL errors.
+pub fn check_errors<O: GraphQLOperation>(body: &Value) -> Result<()> {
+    let errors = response_errors(body);
+    if errors.is_empty() {
+        return Ok(());
+    }
+    let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+    Err(DuoloadError::GraphQL {
+        operation: O::OPERATION_NAME,
+        message: messages.join("; "),
+    })
+}
+
+/// Decodes a raw response for `operation`, rejecting GraphQL errors and invalid data.
+///
+/// A response that does not have the expected shape, e.g. because a field
+/// was renamed in the API, fails with [`DuoloadError::SchemaDrift`] naming
+/// where in the response the problem is.
+pub fn decode_response<O: GraphQLOperation>(operation: &O, body: Value) -> Result<O::Response> {
+    check_errors::<O>(&body)?;
+
+    let response: O::Response =
+        serde_path_to_error::deserialize(body).map_err(|e| DuoloadError::SchemaDrift {
+            operation: O::OPERATION_NAME,
+            path: e.path().to_string(),
+            message: e.inner().to_string(),
+        })?;
+    operation.validate(&response)?;
+    Ok(response)
+}
+
+/// Fetches a page of cards from a deck (`cardsQuery`).
+#[derive(Debug, Serialize)]
+pub struct CardsQuery {
+    pub variables: CardsQueryVariables,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CardsQueryVariables {
+    pub count: i32,
+    pub cursor: Option<String>,
+    #[serde(rename = "deckId")]
+    pub deck_id: String,
+    pub search: String,
+    #[serde(rename = "cardState")]
+    pub card_state: Option<String>,
+}
+
+impl CardsQuery {
+    pub fn new(deck_id: &str, count: i32, cursor: Option<String>) -> Self {
+        Self {
+            variables: CardsQueryVariables {
+                count,
+                cursor,
+                deck_id: deck_id.to_string(),
+                search: String::new(),
+                card_state: None,
+            },
+        }
+    }
+
+    /// Restricts the page to cards matching a search string.
+    pub fn search(mut self, search: impl Into<String>) -> Self {
+        self.variables.search = search.into();
+        self
+    }
+
+    /// Restricts the page to cards in a Duocards card state.
+    pub fn card_state(mut self, card_state: impl Into<String>) -> Self {
+        self.variables.card_state = Some(card_state.into());
+        self
+    }
+}
+
+impl GraphQLOperation for CardsQuery {
+    const OPERATION_NAME: &'static str = "cardsQuery";
+    const QUERY: &'static str = CARDS_QUERY;
+
+    type Variables = CardsQueryVariables;
+    type Response = DuocardsResponse;
+
+    fn variables(&self) -> &Self::Variables {
+        &self.variables
+    }
+
+    fn validate(&self, response: &Self::Response) -> Result<()> {
+        let node = &response.data.node;
+        if node.__typename != "Deck" {
+            return Err(DuoloadError::Api(format!(
+                "Expected deck {} but the API returned a {}",
+                self.variables.deck_id, node.__typename
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Lists the decks of the logged-in account (`decksQuery`).
+#[derive(Debug, Default, Serialize)]
+pub struct DecksQuery {
+    pub variables: DecksQueryVariables,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct DecksQueryVariables {}
+
+impl GraphQLOperation for DecksQuery {
+    const OPERATION_NAME: &'static str = "decksQuery";
+    const QUERY: &'static str = DECKS_QUERY;
+
+    type Variables = DecksQueryVariables;
+    type Response = DecksResponse;
+
+    fn variables(&self) -> &Self::Variables {
+        &self.variables
+    }
+}
+
+/// Asks the API for nothing but whether the request is logged in
+/// (`pingQuery`).
+#[derive(Debug, Default, Serialize)]
+pub struct PingQuery {
+    pub variables: PingQueryVariables,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct PingQueryVariables {}
+
+impl GraphQLOperation for PingQuery {
+    const OPERATION_NAME: &'static str = "pingQuery";
+    const QUERY: &'static str = PING_QUERY;
+
+    type Variables = PingQueryVariables;
+    type Response = PingResponse;
+
+    fn variables(&self) -> &Self::Variables {
+        &self.variables
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_request_serialization() {
+        let query = CardsQuery::new("deck", 30, None).search("haus");
+
+        let full = serde_json::to_value(GraphQLRequest::new(&query)).unwrap();
+        assert_eq!(full["query"], CARDS_QUERY);
+        assert_eq!(full["operationName"], "cardsQuery");
+        assert_eq!(full["variables"]["deckId"], "deck");
+        assert_eq!(full["variables"]["search"], "haus");
+        assert!(full.get("extensions").is_none());
+
+        let persisted = serde_json::to_value(GraphQLRequest::persisted(&query)).unwrap();
+        assert!(persisted.get("query").is_none());
+        assert_eq!(
+            persisted["extensions"]["persistedQuery"]["sha256Hash"],
+            query_hash(CARDS_QUERY)
+        );
+
+        let register = serde_json::to_value(GraphQLRequest::register(&query)).unwrap();
+        assert_eq!(register["query"], CARDS_QUERY);
+        assert_eq!(register["extensions"]["persistedQuery"]["version"], 1);
+    }
+
+    #[test]
+    fn test_query_hash() {
+        assert_eq!(
+            query_hash(""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn test_persisted_query_not_found() {
+        let body = json!({"errors": [{"message": "PersistedQueryNotFound"}]});
+        assert!(is_persisted_query_not_found(&response_errors(&body)));
+
+        let body = json!({"errors": [{"message": "nope", "extensions": {"code": "PERSISTED_QUERY_NOT_FOUND"}}]});
+        assert!(is_persisted_query_not_found(&response_errors(&body)));
+
+        let body = json!({"errors": [{"message": "Deck not found"}]});
+        assert!(!is_persisted_query_not_found(&response_errors(&body)));
+    }
+
+    #[test]
+    fn test_decode_rejects_errors_and_wrong_node() {
+        let query = CardsQuery::new("deck", 30, None);
+
+        let body = json!({"errors": [{"message": "Deck not found"}]});
+        let err = decode_response(&query, body).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("cardsQuery failed: Deck not found")
+        );
+
+        let body = json!({
+            "data": {"node": {
+                "__typename": "User",
+                "cards": {"edges": [], "pageInfo": {"endCursor": null, "hasNextPage": false}},
+                "id": "deck"
+            }},
+            "extensions": {"releaseId": null}
+        });
+        assert!(decode_response(&query, body).is_err());
+    }
+
+    #[test]
+    fn test_decode_reports_schema_drift() {
+        let query = CardsQuery::new("deck", 30, None);
+        let body = json!({
+            "data": {"node": {
+                "__typename": "Deck",
+                "cards": {
+                    "edges": [{"node": {
+                        "id": "1",
+                        "front": "Haus",
+                        "back": "house",
+                        "hint": null,
+                        "waiting": null,
+                        "svg": null,
+                        "__typename": "Card"
+                    }, "cursor": "1"}],
+                    "pageInfo": {"endCursor": "1", "hasNextPage": false}
+                },
+                "id": "deck"
+            }},
+            "extensions": {"releaseId": null}
+        });
+
+        match decode_response(&query, body).unwrap_err() {
+            DuoloadError::SchemaDrift {
+                operation,
+                path,
+                message,
+            } => {
+                assert_eq!(operation, "cardsQuery");
+                assert_eq!(path, "data.node.cards.edges[0].node");
+                assert_eq!(message, "missing field `knownCount`");
+            }
+            other => panic!("expected schema drift, got {}", other),
+        }
+    }
+}
+
+/// Fetches the size of a deck without its cards (`deckSummaryQuery`).
+#[derive(Debug, Serialize)]
+pub struct DeckSummaryQuery {
+    pub variables: DeckSummaryQueryVariables,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeckSummaryQueryVariables {
+    #[serde(rename = "deckId")]
+    pub deck_id: String,
+}
+
+impl DeckSummaryQuery {
+    pub fn new(deck_id: &str) -> Self {
+        Self {
+            variables: DeckSummaryQueryVariables {
+                deck_id: deck_id.to_string(),
+            },
+        }
+    }
+}
+
+impl GraphQLOperation for DeckSummaryQuery {
+    const OPERATION_NAME: &'static str = "deckSummaryQuery";
+    const QUERY: &'static str = DECK_SUMMARY_QUERY;
+
+    type Variables = DeckSummaryQueryVariables;
+    type Response = DeckSummaryResponse;
+
+    fn variables(&self) -> &Self::Variables {
+        &self.variables
+    }
+
+    fn validate(&self, response: &Self::Response) -> Result<()> {
+        let node = &response.data.node;
+        if node.__typename != "Deck" {
+            return Err(DuoloadError::Api(format!(
+                "Expected deck {} but the API returned a {}",
+                self.variables.deck_id, node.__typename
+            )));
+        }
+        Ok(())
+    }
+}
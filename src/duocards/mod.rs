@@ -1,12 +1,25 @@
+use crate::duocards::client::RequestStats;
 use crate::duocards::models::{DuocardsResponse, VocabularyCard};
 use crate::error::Result;
 use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::Duration;
 
+pub mod auth;
+pub mod bandwidth;
+pub mod cache;
 pub mod client;
+pub mod cookie_jar;
 pub mod deck;
+pub mod file_source;
+pub mod graphql;
 pub mod models;
+pub mod network;
+pub mod retry;
+pub mod throttle;
 
 pub use client::DuocardsClient;
+pub use file_source::FileSourceClient;
 
 #[async_trait]
 pub trait DuocardsClientTrait: Send + Sync {
@@ -14,4 +27,15 @@ pub trait DuocardsClientTrait: Send + Sync {
     fn convert_to_vocabulary_cards(&self, response: &DuocardsResponse) -> Vec<VocabularyCard>;
     fn should_continue(&self, current_page: u32) -> bool;
     fn page_limit(&self) -> Option<u32>;
+
+    /// Counts of the HTTP requests sent, for sources that talk to the API.
+    fn request_stats(&self) -> Option<Arc<RequestStats>> {
+        None
+    }
+
+    /// Time to wait before the next request because the server asked to
+    /// slow down, for sources that talk to the API.
+    fn throttle_delay(&self) -> Option<Duration> {
+        None
+    }
 }
@@ -1,5 +1,8 @@
+use crate::duocards::client::DEFAULT_PAGE_SIZE;
+use crate::output::timestamp::UtcDateTime;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::time::{Duration, UNIX_EPOCH};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DuocardsResponse {
@@ -17,6 +20,72 @@ pub struct Deck {
     pub __typename: String,
     pub cards: CardConnection,
     pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Language of the card fronts
+    #[serde(default)]
+    pub front: Option<String>,
+    /// Language of the card backs
+    #[serde(default)]
+    pub back: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeckSummaryResponse {
+    pub data: DeckSummaryData,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeckSummaryData {
+    pub node: DeckTotals,
+}
+
+/// Name and size of a deck, fetched without its cards.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeckTotals {
+    pub __typename: String,
+    pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Language of the card fronts
+    #[serde(default)]
+    pub front: Option<String>,
+    /// Language of the card backs
+    #[serde(default)]
+    pub back: Option<String>,
+    #[serde(rename = "cardsCount", default)]
+    pub cards_count: u64,
+}
+
+impl DeckTotals {
+    /// Number of pages an export of the whole deck requests.
+    pub fn pages(&self) -> u64 {
+        self.cards_count.div_ceil(DEFAULT_PAGE_SIZE as u64)
+    }
+}
+
+/// Name and languages of an exported deck.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeckMetadata {
+    pub id: String,
+    pub name: Option<String>,
+    /// Language of the words being learned, on the card fronts
+    pub source_language: Option<String>,
+    /// Language of the translations, on the card backs
+    pub target_language: Option<String>,
+}
+
+impl DuocardsResponse {
+    /// Name and languages of the deck the page belongs to.
+    pub fn deck_metadata(&self) -> DeckMetadata {
+        let deck = &self.data.node;
+        DeckMetadata {
+            id: deck.id.clone(),
+            name: deck.name.clone(),
+            source_language: deck.front.clone(),
+            target_language: deck.back.clone(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,14 +107,101 @@ pub struct Card {
     pub front: String,
     pub back: String,
     pub hint: Option<String>,
+    /// When the card was added, absent from older responses
+    #[serde(rename = "createdAt", default)]
+    pub created_at: Option<Value>,
+    /// When the card is next due for review
     pub waiting: Option<Value>,
     #[serde(rename = "knownCount")]
     pub known_count: i32,
     pub svg: Option<CardImage>,
+    /// Pronunciation of the front, absent from older responses
+    #[serde(default)]
+    pub audio: Option<CardAudio>,
+    /// Where the card comes from, e.g. a course; absent from older responses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<CardSource>,
+    /// The shared card of the word, with its theory; absent from older
+    /// responses
+    #[serde(rename = "sCard", default, skip_serializing_if = "Option::is_none")]
+    pub s_card: Option<SharedCard>,
     #[serde(rename = "__typename")]
     pub typename: String,
 }
 
+/// Origin of a card, which groups cards into sections of the deck.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CardSource {
+    pub kind: Option<Value>,
+    pub course: Option<Value>,
+    pub id: Option<Value>,
+}
+
+impl CardSource {
+    /// Labels of the cards from this source: its kind and course, if given.
+    pub fn labels(&self) -> Vec<String> {
+        let mut labels: Vec<String> = [&self.kind, &self.course]
+            .into_iter()
+            .flatten()
+            .filter_map(|value| match value {
+                Value::String(text) => Some(text.trim().to_string()),
+                Value::Number(number) => Some(number.to_string()),
+                _ => None,
+            })
+            .filter(|label| !label.is_empty())
+            .collect();
+        labels.dedup();
+        labels
+    }
+}
+
+/// The card of a word shared by all decks learning it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharedCard {
+    pub theory: Option<Theory>,
+    pub id: Option<String>,
+}
+
+/// Explanation of a word written by Duocards, which the app shows as a hint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Theory {
+    /// In the language being learned
+    pub theory: Option<String>,
+    /// In the language of the translations
+    #[serde(rename = "theoryNative")]
+    pub theory_native: Option<String>,
+    #[serde(rename = "theoryEn")]
+    pub theory_en: Option<String>,
+}
+
+impl Card {
+    /// The hint of the card: its theory, preferably in the language of the
+    /// translations.
+    ///
+    /// Despite its name, the `hint` field of a card holds the example
+    /// sentence typed by the user.
+    pub fn theory_hint(&self) -> Option<String> {
+        let theory = self.s_card.as_ref()?.theory.as_ref()?;
+        [&theory.theory_native, &theory.theory, &theory.theory_en]
+            .into_iter()
+            .flatten()
+            .map(|text| text.trim())
+            .find(|text| !text.is_empty())
+            .map(str::to_string)
+    }
+
+    /// When the card is next due for review, if `waiting` holds a time.
+    ///
+    /// A card forgotten in Duocards is due again right away, so its time
+    /// lies in the past.
+    pub fn waiting_until(&self) -> Option<UtcDateTime> {
+        match self.waiting.as_ref()? {
+            Value::String(text) => UtcDateTime::parse(text),
+            value => date_time(value),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CardImage {
     #[serde(rename = "flatId")]
@@ -54,6 +210,12 @@ pub struct CardImage {
     pub id: Option<String>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CardAudio {
+    pub url: Option<String>,
+    pub id: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PageInfo {
     #[serde(rename = "endCursor")]
@@ -62,6 +224,57 @@ pub struct PageInfo {
     pub has_next_page: bool,
 }
 
+/// Response of the query listing the decks of an account.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecksResponse {
+    pub data: DecksData,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecksData {
+    /// `None` when the request is not logged in
+    pub viewer: Option<Viewer>,
+}
+
+/// The logged-in account.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Viewer {
+    pub decks: Vec<DeckSummary>,
+}
+
+/// Response of the smallest query the API answers, sent to check that it
+/// is up.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PingResponse {
+    pub data: PingData,
+    #[serde(default)]
+    pub extensions: Option<Extensions>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PingData {
+    /// `None` when the request is not logged in
+    pub viewer: Option<PingViewer>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PingViewer {
+    pub id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeckSummary {
+    /// Deck ID as accepted by `--deck-id`
+    pub id: String,
+    pub name: String,
+    /// Language of the card fronts
+    #[serde(default)]
+    pub front: Option<String>,
+    /// Language of the card backs
+    #[serde(default)]
+    pub back: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Extensions {
     #[serde(rename = "releaseId")]
@@ -69,16 +282,101 @@ pub struct Extensions {
 }
 
 // Our internal representation of a vocabulary card
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct VocabularyCard {
     pub word: String,
     pub translation: String,
     pub example: Option<String>,
+    /// Hint shown while recalling the translation, from the theory of the
+    /// shared Duocards card
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
     pub status: LearningStatus,
+    /// URL of the card's picture
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// URL of the pronunciation of the word
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio: Option<String>,
+    /// ID of the card in Duocards
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_id: Option<String>,
+    /// When the card was added in Duocards, in RFC 3339 form
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    /// When the card is next due for review in Duocards, written in
+    /// RFC 3339 form
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub waiting_until: Option<UtcDateTime>,
+    /// How often the card was answered correctly in Duocards, from which
+    /// its status is derived
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub known_count: Option<i32>,
+    /// Sections of the deck the card belongs to, from its Duocards source
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+    /// Detected languages of the word and translation, with the `lang`
+    /// feature and `--detect-languages`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<CardLanguage>,
+    /// Where and when the card was fetched, with `--provenance`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+}
+
+/// Languages of the word and translation of a card, as codes such as `de`;
+/// `unknown` if nothing could be detected.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CardLanguage {
+    pub word: String,
+    pub translation: String,
+}
+
+/// Where and when a card was fetched, so cards of merged exports can be
+/// traced back to their deck.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// ID of the deck the card was fetched from
+    pub deck_id: String,
+    /// When the page holding the card was fetched
+    pub fetched_at: UtcDateTime,
+    /// Number of the page holding the card, from 1
+    pub page: u32,
+    /// Version of duoload that fetched the card
+    pub duoload_version: String,
+}
+
+impl Provenance {
+    /// The provenance of cards on page `page` of deck `deck_id`, fetched at
+    /// `fetched_at` by this version of duoload.
+    pub fn new(deck_id: &str, page: u32, fetched_at: UtcDateTime) -> Self {
+        Self {
+            deck_id: deck_id.to_string(),
+            fetched_at,
+            page,
+            duoload_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+}
+
+/// `deck <id>, page <n>, fetched <RFC 3339 time>, duoload <version>`, as
+/// written to CSV columns and Anki fields.
+impl std::fmt::Display for Provenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "deck {}, page {}, fetched {}, duoload {}",
+            self.deck_id,
+            self.page,
+            self.fetched_at.rfc3339(),
+            self.duoload_version
+        )
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum LearningStatus {
+    #[default]
     #[serde(rename = "new")]
     New,
     #[serde(rename = "learning")]
@@ -87,55 +385,87 @@ pub enum LearningStatus {
     Known,
 }
 
-impl From<Card> for VocabularyCard {
-    fn from(card: Card) -> Self {
-        // Convert known_count to LearningStatus
-        let status = if card.known_count >= 5 {
+impl LearningStatus {
+    /// Every status, from new to known.
+    pub const ALL: [LearningStatus; 3] = [
+        LearningStatus::New,
+        LearningStatus::Learning,
+        LearningStatus::Known,
+    ];
+
+    /// The status of a card answered correctly `known_count` times.
+    pub fn from_known_count(known_count: i32) -> Self {
+        if known_count >= 5 {
             LearningStatus::Known
-        } else if card.known_count > 0 {
+        } else if known_count > 0 {
             LearningStatus::Learning
         } else {
             LearningStatus::New
-        };
+        }
+    }
 
-        Self {
-            word: card.front,
-            translation: card.back,
-            example: card.hint,
-            status,
+    /// The lowest known count giving this status.
+    pub fn min_known_count(&self) -> i32 {
+        match self {
+            LearningStatus::New => 0,
+            LearningStatus::Learning => 1,
+            LearningStatus::Known => 5,
+        }
+    }
+
+    /// The status as written in exports, e.g. `learning`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LearningStatus::New => "new",
+            LearningStatus::Learning => "learning",
+            LearningStatus::Known => "known",
         }
     }
 }
 
-// GraphQL query types
-#[derive(Debug, Serialize)]
-pub struct CardsQuery {
-    pub query: String,
-    pub variables: CardsQueryVariables,
+/// Reads a timestamp of the API, given either as text or as Unix time in
+/// seconds or milliseconds.
+fn timestamp(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) if !text.is_empty() => Some(text.clone()),
+        value => date_time(value).map(|time| time.rfc3339()),
+    }
 }
 
-#[derive(Debug, Serialize)]
-pub struct CardsQueryVariables {
-    pub count: i32,
-    pub cursor: Option<String>,
-    #[serde(rename = "deckId")]
-    pub deck_id: String,
-    pub search: String,
-    #[serde(rename = "cardState")]
-    pub card_state: Option<String>,
+/// Reads a timestamp of the API given as Unix time in seconds or milliseconds.
+fn date_time(value: &Value) -> Option<UtcDateTime> {
+    let number = value.as_u64()?;
+    // Milliseconds from 1973 on, seconds otherwise
+    let secs = if number >= 100_000_000_000 {
+        number / 1000
+    } else {
+        number
+    };
+    Some(UtcDateTime::from_system_time(
+        UNIX_EPOCH + Duration::from_secs(secs),
+    ))
 }
 
-impl CardsQuery {
-    pub fn new(deck_id: &str, count: i32, cursor: Option<String>) -> Self {
+impl From<Card> for VocabularyCard {
+    fn from(card: Card) -> Self {
         Self {
-            query: include_str!("../../internal_docs/duocards/query.graphql").to_string(),
-            variables: CardsQueryVariables {
-                count,
-                cursor,
-                deck_id: deck_id.to_string(),
-                search: String::new(),
-                card_state: None,
-            },
+            hint: card.theory_hint(),
+            waiting_until: card.waiting_until(),
+            status: LearningStatus::from_known_count(card.known_count),
+            known_count: Some(card.known_count),
+            source_id: Some(card.id),
+            created_at: card.created_at.as_ref().and_then(timestamp),
+            word: card.front,
+            translation: card.back,
+            example: card.hint,
+            image: card.svg.and_then(|svg| svg.url),
+            audio: card.audio.and_then(|audio| audio.url),
+            labels: card
+                .source
+                .map(|source| source.labels())
+                .unwrap_or_default(),
+            language: None,
+            provenance: None,
         }
     }
 }
@@ -0,0 +1,98 @@
+//! Proxy and TLS settings shared by the HTTP clients.
+//!
+//! Without settings, requests go through the proxies named by the
+//! `HTTPS_PROXY`, `HTTP_PROXY` and `ALL_PROXY` environment variables, except
+//! for the hosts in `NO_PROXY`.
+
+use crate::error::{DuoloadError, Result};
+use reqwest::{Certificate, ClientBuilder, Proxy};
+use std::path::PathBuf;
+
+/// How the HTTP clients reach the network.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetworkOptions {
+    /// Proxy for all requests, e.g. `http://proxy.example.com:3128`,
+    /// instead of the proxies from the environment
+    pub proxy: Option<String>,
+    /// Connect directly, ignoring `proxy` and the proxy environment variables
+    pub no_proxy: bool,
+    /// PEM file with certificates to trust in addition to the system ones,
+    /// e.g. of a proxy that inspects TLS traffic
+    pub ca_cert: Option<PathBuf>,
+    /// GraphQL endpoint to use instead of the Duocards API, e.g. of a
+    /// `duoload-mock-server`
+    pub api_url: Option<String>,
+}
+
+impl NetworkOptions {
+    /// Applies the settings to `builder`.
+    pub fn apply(&self, mut builder: ClientBuilder) -> Result<ClientBuilder> {
+        if self.no_proxy {
+            builder = builder.no_proxy();
+        } else if let Some(url) = &self.proxy {
+            let proxy = Proxy::all(url).map_err(|e| {
+                DuoloadError::Network(format!("Invalid proxy URL {:?}: {}", url, e))
+            })?;
+            builder = builder.proxy(proxy);
+        }
+        if let Some(path) = &self.ca_cert {
+            let pem = std::fs::read(path).map_err(|e| {
+                DuoloadError::Network(format!("Cannot read CA certificate {:?}: {}", path, e))
+            })?;
+            let certificates = Certificate::from_pem_bundle(&pem).map_err(|e| {
+                DuoloadError::Network(format!("Invalid CA certificate {:?}: {}", path, e))
+            })?;
+            if certificates.is_empty() {
+                return Err(DuoloadError::Network(format!(
+                    "No certificates in {:?}",
+                    path
+                )));
+            }
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Client;
+
+    #[test]
+    fn test_invalid_settings_are_reported() {
+        let options = NetworkOptions {
+            proxy: Some("not a url".to_string()),
+            ..NetworkOptions::default()
+        };
+        assert!(matches!(
+            options.apply(Client::builder()),
+            Err(DuoloadError::Network(_))
+        ));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ca.pem");
+        std::fs::write(&path, "no certificate here").unwrap();
+        let options = NetworkOptions {
+            ca_cert: Some(path),
+            ..NetworkOptions::default()
+        };
+        assert!(matches!(
+            options.apply(Client::builder()),
+            Err(DuoloadError::Network(_))
+        ));
+    }
+
+    #[test]
+    fn test_no_proxy_wins() {
+        let options = NetworkOptions {
+            proxy: Some("not a url".to_string()),
+            no_proxy: true,
+            ca_cert: None,
+            api_url: None,
+        };
+        assert!(options.apply(Client::builder()).unwrap().build().is_ok());
+    }
+}
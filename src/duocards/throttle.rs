@@ -0,0 +1,185 @@
+//! Slowing down on rate-limit hints of the server.
+//!
+//! Successful responses may tell how much of the rate limit is left: as
+//! `X-RateLimit-*` or `RateLimit-*` headers, a `Retry-After` header, or a
+//! GraphQL `cost` extension with the remaining query budget. The client
+//! records the latest hints here, and the transfer waits long enough between
+//! pages that the limit is not hit instead of running into 429 responses.
+
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Share of the rate limit below which requests are spread over the rest of
+/// the window.
+const LOW_BUDGET: f64 = 0.1;
+
+/// Longest wait a hint can ask for, so a bogus reset time does not stall
+/// the export.
+const MAX_HINT_DELAY: Duration = Duration::from_secs(300);
+
+/// Reset values above this are Unix times rather than seconds from now.
+const UNIX_TIME_THRESHOLD: u64 = 1_000_000_000;
+
+/// Rate-limit state reported by the server, shared by the clones of a client.
+#[derive(Debug, Clone, Default)]
+pub struct Throttle {
+    state: Arc<Mutex<Option<Hint>>>,
+}
+
+/// The wait asked for by the last response, and when it was received.
+#[derive(Debug, Clone, Copy)]
+struct Hint {
+    delay: Duration,
+    received: Instant,
+}
+
+impl Throttle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the hints of a successful response with `headers` and `body`.
+    pub fn observe(&self, headers: &HeaderMap, body: &Value) {
+        let delay = [header_delay(headers), cost_delay(body)]
+            .into_iter()
+            .flatten()
+            .max()
+            .map(|delay| delay.min(MAX_HINT_DELAY));
+        *self.state.lock().unwrap() = delay.map(|delay| Hint {
+            delay,
+            received: Instant::now(),
+        });
+    }
+
+    /// Time to wait before the next request, if the server asked to slow down.
+    pub fn delay(&self) -> Option<Duration> {
+        let hint = (*self.state.lock().unwrap())?;
+        let left = hint.delay.saturating_sub(hint.received.elapsed());
+        (!left.is_zero()).then_some(left)
+    }
+}
+
+/// Wait asked for by rate-limit headers.
+///
+/// With the budget used up, the wait lasts until the window resets; with
+/// less than a tenth left, the remaining requests are spread over the window.
+fn header_delay(headers: &HeaderMap) -> Option<Duration> {
+    let number = |names: &[&str]| {
+        names.iter().find_map(|name| {
+            headers
+                .get(*name)?
+                .to_str()
+                .ok()?
+                .split([',', ';'])
+                .next()?
+                .trim()
+                .parse::<f64>()
+                .ok()
+        })
+    };
+    let retry_after = number(&[RETRY_AFTER.as_str()]).map(Duration::from_secs_f64);
+    let remaining = number(&["x-ratelimit-remaining", "ratelimit-remaining"]);
+    let limit = number(&["x-ratelimit-limit", "ratelimit-limit"]);
+    let reset = number(&["x-ratelimit-reset", "ratelimit-reset"]).map(reset_delay);
+
+    let budget = match (remaining, reset) {
+        (Some(remaining), Some(reset)) if remaining < 1.0 => Some(reset),
+        (Some(remaining), Some(reset))
+            if limit.is_some_and(|limit| remaining < limit * LOW_BUDGET) =>
+        {
+            Some(reset.div_f64(remaining))
+        }
+        _ => None,
+    };
+    retry_after.max(budget)
+}
+
+/// Time until a reset given either in seconds from now or as a Unix time.
+fn reset_delay(reset: f64) -> Duration {
+    if reset >= UNIX_TIME_THRESHOLD as f64 {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        Duration::from_secs_f64(reset).saturating_sub(now)
+    } else {
+        Duration::from_secs_f64(reset.max(0.0))
+    }
+}
+
+/// Wait asked for by a GraphQL `cost` extension, as sent by servers metering
+/// queries by cost: until the budget has been restored enough for another
+/// query of the same cost.
+fn cost_delay(body: &Value) -> Option<Duration> {
+    let cost = body.get("extensions")?.get("cost")?;
+    let status = cost.get("throttleStatus")?;
+    let available = status.get("currentlyAvailable")?.as_f64()?;
+    let restore_rate = status.get("restoreRate")?.as_f64()?;
+    let needed = cost
+        .get("requestedQueryCost")
+        .or_else(|| cost.get("actualQueryCost"))?
+        .as_f64()?;
+    if available >= needed || restore_rate <= 0.0 {
+        return None;
+    }
+    Some(Duration::from_secs_f64((needed - available) / restore_rate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use serde_json::json;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_no_hints() {
+        let throttle = Throttle::new();
+        throttle.observe(&HeaderMap::new(), &json!({"data": {}}));
+        assert_eq!(throttle.delay(), None);
+    }
+
+    #[test]
+    fn test_exhausted_budget_waits_for_reset() {
+        let delay = header_delay(&headers(&[
+            ("x-ratelimit-limit", "100"),
+            ("x-ratelimit-remaining", "0"),
+            ("x-ratelimit-reset", "30"),
+        ]));
+        assert_eq!(delay, Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_low_budget_spreads_requests() {
+        let delay = header_delay(&headers(&[
+            ("ratelimit-limit", "100"),
+            ("ratelimit-remaining", "5"),
+            ("ratelimit-reset", "60"),
+        ]));
+        assert_eq!(delay, Some(Duration::from_secs(12)));
+        // Plenty left
+        let delay = header_delay(&headers(&[
+            ("ratelimit-limit", "100"),
+            ("ratelimit-remaining", "50"),
+            ("ratelimit-reset", "60"),
+        ]));
+        assert_eq!(delay, None);
+    }
+
+    #[test]
+    fn test_cost_extension() {
+        let body = json!({"extensions": {"cost": {
+            "requestedQueryCost": 100,
+            "throttleStatus": {"currentlyAvailable": 40, "restoreRate": 20}
+        }}});
+        assert_eq!(cost_delay(&body), Some(Duration::from_secs(3)));
+    }
+}
@@ -1,5 +1,11 @@
+use crate::duocards::auth::AuthError;
+use crate::duocards::retry::is_transient_status;
+use crate::transfer::duplicates::DuplicatePolicy;
+use reqwest::StatusCode;
 use reqwest::header::InvalidHeaderValue;
 use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -28,20 +34,184 @@ pub enum DuoloadError {
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
 
+    #[error("CSV error: {0}")]
+    Csv(#[from] csv::Error),
+
+    #[error("YAML error: {0}")]
+    Yaml(#[from] serde_norway::Error),
+
+    #[error("MessagePack error: {0}")]
+    MessagePack(String),
+
+    #[error("CBOR error: {0}")]
+    Cbor(String),
+
     #[error("API error: {0}")]
     Api(String),
 
+    /// A rate limit or server error that may be gone on a later attempt.
+    #[error("API request failed with status {status}: {body}")]
+    TransientStatus { status: StatusCode, body: String },
+
+    /// A response body that is not JSON, e.g. because the connection broke
+    /// off while it was received.
+    #[error("Malformed API response: {0}")]
+    MalformedResponse(#[source] serde_json::Error),
+
+    #[error("No response within {0:?}")]
+    Timeout(Duration),
+
+    #[error("GraphQL operation {operation} failed: {message}")]
+    GraphQL {
+        operation: &'static str,
+        message: String,
+    },
+
+    #[error(
+        "Unexpected response to {operation} at {path}: {message}. \
+         The Duocards API may have changed; update duoload or report the issue with this message"
+    )]
+    SchemaDrift {
+        operation: &'static str,
+        path: String,
+        message: String,
+    },
+
     #[error("Invalid header value: {0}")]
     InvalidHeader(#[from] InvalidHeaderValue),
 
+    #[error("Authentication error: {0}")]
+    Auth(#[from] AuthError),
+
     #[error("Deck ID error: {0}")]
     DeckId(#[from] DeckIdError),
 
     #[error("Other error: {0}")]
     Other(#[from] anyhow::Error),
 
-    #[error("Anki output is only supported for file output")]
-    AnkiOutputNotSupported,
+    #[error("Missing export setting: {0}")]
+    MissingSetting(&'static str),
+
+    #[error("SQLite output is only supported for file output")]
+    SqliteOutputNotSupported,
+
+    #[error("Splitting output is only supported for file output")]
+    SplitOutputNotSupported,
+
+    #[error("Output verification failed: expected {expected} notes, found {found}")]
+    VerificationFailed { expected: usize, found: usize },
+
+    #[error("{0}")]
+    Format(String),
+
+    #[error("Anki package has {0} problems")]
+    InvalidPackage(usize),
+
+    #[error("Cache error: {0}")]
+    Cache(String),
+
+    #[error("Network settings error: {0}")]
+    Network(String),
+
+    #[error("Encryption error: {0}")]
+    Encryption(String),
+
+    #[error("Checkpoint error: {0}")]
+    Checkpoint(String),
+
+    #[error("Snapshot error: {0}")]
+    Snapshot(String),
+
+    #[error("Clipboard error: {0}")]
+    Clipboard(String),
+
+    #[error("Duplicate store error: {0}")]
+    DedupeStore(String),
+
+    #[error("Output file {0:?} already exists; use --force to overwrite it")]
+    OutputExists(PathBuf),
+
+    #[error("Export aborted")]
+    Aborted,
+
+    #[error("Duplicate word: {0}")]
+    DuplicateWord(String),
+
+    #[error("Duplicate policy {0} needs the whole deck and cannot be used with streaming output")]
+    StreamingDuplicatePolicy(DuplicatePolicy),
+
+    #[error(
+        "Pagination stalled on page {page}: cursor {cursor:?} does not advance (page payload hash {payload_hash})"
+    )]
+    PaginationStalled {
+        page: u32,
+        cursor: Option<String>,
+        payload_hash: String,
+    },
+
+    #[error("Card {word:?} cannot be exported: {reason}")]
+    BadCard { word: String, reason: String },
+
+    #[error("Output does not match the JSON Schema: {0}")]
+    InvalidOutput(String),
+
+    #[error("Download limit of {limit} bytes reached after {downloaded} bytes")]
+    ByteLimit { limit: u64, downloaded: u64 },
+}
+
+/// Exit code used when the written output does not match the export statistics.
+pub const EXIT_VERIFICATION_FAILED: i32 = 3;
+
+impl DuoloadError {
+    /// Process exit code for this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            DuoloadError::VerificationFailed { .. } | DuoloadError::InvalidPackage(_) => {
+                EXIT_VERIFICATION_FAILED
+            }
+            _ => 1,
+        }
+    }
+
+    /// Whether the error comes from talking to the API and may go away on a later attempt.
+    ///
+    /// Only transport failures and transient statuses qualify; errors the API
+    /// would repeat, such as rejected requests or GraphQL errors, do not.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            DuoloadError::Request(e) => {
+                !e.is_builder() && e.status().is_none_or(is_transient_status)
+            }
+            DuoloadError::TransientStatus { .. }
+            | DuoloadError::MalformedResponse(_)
+            | DuoloadError::Timeout(_) => true,
+            _ => false,
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, DuoloadError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_retryable() {
+        let unavailable = DuoloadError::TransientStatus {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            body: String::new(),
+        };
+        assert!(unavailable.is_retryable());
+        let truncated = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+        assert!(DuoloadError::MalformedResponse(truncated).is_retryable());
+        assert!(
+            !DuoloadError::Api("API request failed with status 400".to_string()).is_retryable()
+        );
+        let graphql = DuoloadError::GraphQL {
+            operation: "cardsQuery",
+            message: "Deck not found".to_string(),
+        };
+        assert!(!graphql.is_retryable());
+    }
+}
@@ -0,0 +1,434 @@
+//! High-level API for embedding the exporter in other tools.
+//!
+//! [`Duoload`] bundles the client, processor and output builder the command
+//! line tool wires together, so a whole export is a few builder calls:
+//!
+//! ```no_run
+//! use duoload::{Duoload, Output};
+//!
+//! # async fn example() -> duoload::error::Result<()> {
+//! let stats = Duoload::builder()
+//!     .deck_id("RGVjazo1YjZmMTA3My1hZjA2LTQwMGMtYTQyNC05ZWM5YzFlMGEzZjg=")
+//!     .output(Output::new("anki", "vocabulary.apkg"))
+//!     .build()?
+//!     .run()
+//!     .await?;
+//! println!("Exported {} cards", stats.total_cards);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::duocards::DuocardsClient;
+use crate::duocards::auth::Credentials;
+use crate::duocards::cache::PageCache;
+use crate::duocards::deck;
+use crate::duocards::network::NetworkOptions;
+use crate::duocards::retry::RetryPolicy;
+use crate::error::{DuoloadError, Result};
+use crate::media::{MediaFetcher, MediaStore};
+use crate::output::registry::{FormatContext, OutputRegistry, RegisteredFormat};
+use crate::output::sort::SortOrder;
+use crate::output::{OutputBuilder, OutputFormat};
+use crate::transfer::duplicates::{DedupeKey, DuplicatePolicy};
+use crate::transfer::processor::{
+    DEFAULT_CHANNEL_CAPACITY, DEFAULT_PAGE_DELAY, TransferProcessor, TransferStats,
+};
+use crate::transfer::progress::{ConsoleReporter, ProgressReporter};
+use crate::transform::TransformPipeline;
+use crate::transform::filter::CardFilter;
+use crate::transform::mapping::FieldMapping;
+use crate::transform::normalize::NormalForm;
+use crate::transform::sanitize::SanitizeOptions;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Where and in which format to write the exported cards.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Output {
+    /// Name of the format in the [`OutputRegistry`], e.g. `anki` or `csv`
+    pub format: String,
+    /// File to write; `-` writes to stdout
+    pub path: PathBuf,
+}
+
+impl Output {
+    pub fn new(format: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        Self {
+            format: format.into(),
+            path: path.into(),
+        }
+    }
+}
+
+/// A configured export of one Duocards deck, created by [`Duoload::builder`].
+pub struct Duoload {
+    client: DuocardsClient,
+    deck_id: String,
+    format: RegisteredFormat,
+    path: PathBuf,
+    deck_name: Option<String>,
+    images: bool,
+    audio: bool,
+    network: NetworkOptions,
+    duplicates: DuplicatePolicy,
+    dedupe_key: DedupeKey,
+    sort: SortOrder,
+    deterministic: bool,
+    sanitize: SanitizeOptions,
+    normalize: Option<NormalForm>,
+    filter: CardFilter,
+    mapping: FieldMapping,
+    concurrency: usize,
+    page_delay: Duration,
+    max_cards: Option<usize>,
+    verify: bool,
+    reporter: Arc<dyn ProgressReporter>,
+}
+
+impl Duoload {
+    pub fn builder() -> DuoloadBuilder {
+        DuoloadBuilder::default()
+    }
+
+    /// Fetches the deck and writes it to the output.
+    pub async fn run(self) -> Result<TransferStats> {
+        // The HTTP client is shared, so cloning it is cheap
+        let processor = TransferProcessor::new(self.client.clone(), self.deck_id.clone());
+        let mut context = FormatContext {
+            deck_id: self.deck_id.clone(),
+            deck_name: self.deck_name.clone(),
+            deterministic: self.deterministic,
+            ..FormatContext::default()
+        };
+        // Media is only needed until the package is written
+        let media_dir = tempfile::tempdir()?;
+        let mut media = None;
+        if self.format.builtin() == Some(OutputFormat::Anki) && (self.images || self.audio) {
+            let store = MediaStore::new(media_dir.path());
+            context.anki.media = Some(store.clone());
+            let fetcher = MediaFetcher::new_with_network(store, &self.network)?
+                .with_images(self.images)
+                .with_audio(self.audio);
+            media = Some(fetcher);
+        }
+        let builder = self.format.new_builder(&context);
+        self.transfer(processor, builder, media, &self.path).await
+    }
+
+    /// Changes applied to every card: sanitize, normalize, filter and map.
+    fn transforms(&self) -> TransformPipeline {
+        let mut transforms = TransformPipeline::new().then(self.sanitize);
+        if let Some(form) = self.normalize {
+            transforms = transforms.then(form);
+        }
+        transforms.then(self.filter.clone()).then(self.mapping)
+    }
+
+    async fn transfer(
+        &self,
+        processor: TransferProcessor<DuocardsClient>,
+        builder: Box<dyn OutputBuilder>,
+        media: Option<MediaFetcher>,
+        path: &Path,
+    ) -> Result<TransferStats> {
+        let mut processor = processor
+            .output(builder, path)
+            .with_channel_capacity(DEFAULT_CHANNEL_CAPACITY)
+            .with_concurrency(self.concurrency)
+            .with_page_delay(self.page_delay)
+            .with_duplicate_policy(self.duplicates)
+            .with_dedupe_key(self.dedupe_key)
+            .with_sort_order(self.sort)
+            .with_transforms(self.transforms())
+            .with_verification(self.verify)
+            .with_progress_reporter(self.reporter.clone());
+        if let Some(fetcher) = media {
+            processor = processor.with_media_fetcher(fetcher);
+        }
+        if let Some(max_cards) = self.max_cards {
+            processor = processor.with_max_cards(max_cards);
+        }
+        processor.process().await?;
+        Ok(processor.stats().clone())
+    }
+}
+
+/// Builder for [`Duoload`]; only the deck ID and the output are required.
+pub struct DuoloadBuilder {
+    client: Option<DuocardsClient>,
+    deck_id: Option<String>,
+    output: Option<Output>,
+    registry: OutputRegistry,
+    credentials: Option<Credentials>,
+    page_limit: Option<u32>,
+    retry_policy: RetryPolicy,
+    network: NetworkOptions,
+    cache: Option<PageCache>,
+    deck_name: Option<String>,
+    images: bool,
+    audio: bool,
+    duplicates: DuplicatePolicy,
+    dedupe_key: DedupeKey,
+    sort: Option<SortOrder>,
+    deterministic: bool,
+    sanitize: SanitizeOptions,
+    normalize: Option<NormalForm>,
+    filter: CardFilter,
+    mapping: FieldMapping,
+    concurrency: usize,
+    page_delay: Duration,
+    max_cards: Option<usize>,
+    verify: bool,
+    reporter: Arc<dyn ProgressReporter>,
+}
+
+impl Default for DuoloadBuilder {
+    fn default() -> Self {
+        Self {
+            client: None,
+            deck_id: None,
+            output: None,
+            registry: OutputRegistry::new(),
+            credentials: None,
+            page_limit: None,
+            retry_policy: RetryPolicy::default(),
+            network: NetworkOptions::default(),
+            cache: None,
+            deck_name: None,
+            images: false,
+            audio: false,
+            duplicates: DuplicatePolicy::default(),
+            dedupe_key: DedupeKey::default(),
+            sort: None,
+            deterministic: false,
+            sanitize: SanitizeOptions::default(),
+            normalize: None,
+            filter: CardFilter::default(),
+            mapping: FieldMapping::default(),
+            concurrency: 1,
+            page_delay: DEFAULT_PAGE_DELAY,
+            max_cards: None,
+            verify: true,
+            reporter: Arc::new(ConsoleReporter),
+        }
+    }
+}
+
+impl DuoloadBuilder {
+    /// The deck to export, base64 encoded `Deck:<UUID>` as in the share link.
+    pub fn deck_id(mut self, deck_id: impl Into<String>) -> Self {
+        self.deck_id = Some(deck_id.into());
+        self
+    }
+
+    pub fn output(mut self, output: Output) -> Self {
+        self.output = Some(output);
+        self
+    }
+
+    /// Looks up the format of the output in `registry`, e.g. one with
+    /// formats of the application.
+    pub fn registry(mut self, registry: OutputRegistry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Talks to the API through `client`, e.g. one with a custom base URL.
+    pub fn client(mut self, client: DuocardsClient) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Logs in to export a private deck.
+    pub fn credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    /// Exports only the first `pages` pages.
+    pub fn page_limit(mut self, pages: u32) -> Self {
+        self.page_limit = Some(pages);
+        self
+    }
+
+    /// Exports only the first `cards` cards, not counting duplicates.
+    pub fn max_cards(mut self, cards: usize) -> Self {
+        self.max_cards = Some(cards);
+        self
+    }
+
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Reads the pages from `cache` when they are in it and stores fetched pages there.
+    pub fn cache(mut self, cache: PageCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Connects through a proxy or trusts extra CA certificates.
+    ///
+    /// Not applied to a client given with [`DuoloadBuilder::client`].
+    pub fn network(mut self, network: NetworkOptions) -> Self {
+        self.network = network;
+        self
+    }
+
+    /// Name of the deck in Anki packages and the category in Mnemosyne
+    /// files; by default the name of the Duocards deck.
+    pub fn deck_name(mut self, name: impl Into<String>) -> Self {
+        self.deck_name = Some(name.into());
+        self
+    }
+
+    /// Downloads card pictures into Anki packages.
+    pub fn images(mut self, images: bool) -> Self {
+        self.images = images;
+        self
+    }
+
+    /// Downloads word pronunciations into Anki packages.
+    pub fn audio(mut self, audio: bool) -> Self {
+        self.audio = audio;
+        self
+    }
+
+    pub fn duplicates(mut self, policy: DuplicatePolicy) -> Self {
+        self.duplicates = policy;
+        self
+    }
+
+    /// What makes two cards duplicates, the word by default.
+    pub fn dedupe_key(mut self, key: DedupeKey) -> Self {
+        self.dedupe_key = key;
+        self
+    }
+
+    /// Order of the cards in the output, the order of the deck by default
+    /// and alphabetical for deterministic exports.
+    pub fn sort(mut self, order: SortOrder) -> Self {
+        self.sort = Some(order);
+        self
+    }
+
+    /// Writes identical files for identical cards, e.g. to diff exports:
+    /// sorted cards, no export times and Anki packages with fixed IDs. Off
+    /// by default; SQLite databases are not affected.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Cleans up card fields before duplicate detection and output.
+    pub fn sanitize(mut self, options: SanitizeOptions) -> Self {
+        self.sanitize = options;
+        self
+    }
+
+    /// Converts card fields to a Unicode normal form after they are cleaned.
+    pub fn normalize(mut self, form: NormalForm) -> Self {
+        self.normalize = Some(form);
+        self
+    }
+
+    /// Exports only the cards matching `filter`.
+    pub fn filter(mut self, filter: CardFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Puts other card fields on the front, back and example of the output.
+    pub fn field_mapping(mut self, mapping: FieldMapping) -> Self {
+        self.mapping = mapping;
+        self
+    }
+
+    /// Requests up to `concurrency` pages at once.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Wait between page requests, one second by default.
+    pub fn page_delay(mut self, delay: Duration) -> Self {
+        self.page_delay = delay;
+        self
+    }
+
+    /// Re-reads the written file to check the note count; on by default.
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Receives progress events instead of the default stderr messages.
+    ///
+    /// An empty [`ProgressReporters`](crate::transfer::progress::ProgressReporters)
+    /// silences them.
+    pub fn reporter(mut self, reporter: Arc<dyn ProgressReporter>) -> Self {
+        self.reporter = reporter;
+        self
+    }
+
+    /// Checks the settings and creates the export.
+    pub fn build(self) -> Result<Duoload> {
+        let deck_id = self
+            .deck_id
+            .ok_or(DuoloadError::MissingSetting("deck ID"))?;
+        let deck_id = deck::parse_deck_id(&deck_id)?;
+        let output = self.output.ok_or(DuoloadError::MissingSetting("output"))?;
+        let format = self.registry.find(&output.format)?.clone();
+        let sort = match (self.sort, self.deterministic) {
+            (Some(SortOrder::Random), true) => {
+                return Err(DuoloadError::Format(
+                    "Random order cannot be used for deterministic exports".to_string(),
+                ));
+            }
+            (Some(order), _) => order,
+            (None, true) => SortOrder::Alphabetical,
+            (None, false) => SortOrder::Original,
+        };
+
+        let mut client = match self.client {
+            Some(client) => client,
+            None => DuocardsClient::new_with_network(&self.network)?,
+        };
+        client = client.with_retry_policy(self.retry_policy);
+        if let Some(limit) = self.page_limit {
+            client = client.with_page_limit(limit);
+        }
+        if let Some(credentials) = self.credentials {
+            client = client.with_credentials(credentials);
+        }
+        if let Some(cache) = self.cache {
+            client = client.with_cache(cache);
+        }
+
+        Ok(Duoload {
+            client,
+            deck_id,
+            format,
+            path: output.path,
+            deck_name: self.deck_name,
+            images: self.images,
+            audio: self.audio,
+            network: self.network,
+            duplicates: self.duplicates,
+            dedupe_key: self.dedupe_key,
+            sort,
+            deterministic: self.deterministic,
+            sanitize: self.sanitize,
+            normalize: self.normalize,
+            filter: self.filter,
+            mapping: self.mapping,
+            concurrency: self.concurrency,
+            page_delay: self.page_delay,
+            max_cards: self.max_cards,
+            verify: self.verify,
+            reporter: self.reporter,
+        })
+    }
+}
@@ -0,0 +1,205 @@
+//! Language detection for vocabulary cards.
+//!
+//! Single words are too short for reliable detection, so a deck is analyzed
+//! as a whole: cards whose word and example are recognized with confidence
+//! decide which languages the deck contains, and every card is then assigned
+//! to the closest of those languages.
+//!
+//! Only available with the `lang` feature.
+
+use crate::duocards::models::{CardLanguage, VocabularyCard};
+use std::collections::HashMap;
+use whatlang::{Detector, Lang};
+
+/// Languages with a smaller share of the confidently detected cards are
+/// treated as detection noise rather than a language of the deck.
+const MIN_LANGUAGE_SHARE: f64 = 0.1;
+
+/// Language assigned to cards when nothing could be detected at all.
+pub const UNKNOWN_LANGUAGE: &str = "unknown";
+
+/// Detects the language of each card's word, returning one code per card.
+///
+/// Codes are ISO 639-1 where one exists (`de`, `en`) and ISO 639-3 otherwise.
+pub fn detect_languages(cards: &[VocabularyCard]) -> Vec<String> {
+    detect(&cards.iter().map(card_text).collect::<Vec<_>>())
+}
+
+/// Detects the languages of the word and of the translation of each card.
+/// Translations are analyzed as a whole as well, apart from the words.
+pub fn detect_card_languages(cards: &[VocabularyCard]) -> Vec<CardLanguage> {
+    let translations: Vec<String> = cards.iter().map(|card| card.translation.clone()).collect();
+    detect_languages(cards)
+        .into_iter()
+        .zip(detect(&translations))
+        .map(|(word, translation)| CardLanguage { word, translation })
+        .collect()
+}
+
+/// Sets the [`VocabularyCard::language`] of every card.
+pub fn annotate_languages(cards: &mut [VocabularyCard]) {
+    let languages = detect_card_languages(cards);
+    for (card, language) in cards.iter_mut().zip(languages) {
+        card.language = Some(language);
+    }
+}
+
+/// Detects the language of each text, in the context of all `texts`.
+fn detect(texts: &[String]) -> Vec<String> {
+    let detector = Detector::new();
+    let mut reliable: HashMap<Lang, usize> = HashMap::new();
+    for text in texts {
+        if let Some(info) = detector.detect(text)
+            && info.is_reliable()
+        {
+            *reliable.entry(info.lang()).or_default() += 1;
+        }
+    }
+
+    let detected: usize = reliable.values().sum();
+    let mut languages: Vec<(Lang, usize)> = reliable
+        .into_iter()
+        .filter(|(_, count)| *count as f64 >= detected as f64 * MIN_LANGUAGE_SHARE)
+        .collect();
+    // Most common first, so ties in detection fall back to the main language
+    languages.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.code().cmp(b.0.code())));
+
+    let Some(&(main, _)) = languages.first() else {
+        return vec![UNKNOWN_LANGUAGE.to_string(); texts.len()];
+    };
+    if languages.len() == 1 {
+        return vec![language_code(main).to_string(); texts.len()];
+    }
+
+    let detector = Detector::with_allowlist(languages.iter().map(|(lang, _)| *lang).collect());
+    texts
+        .iter()
+        .map(|text| {
+            let lang = detector.detect_lang(text).unwrap_or(main);
+            language_code(lang).to_string()
+        })
+        .collect()
+}
+
+/// The text detection runs on: the word, plus its example for more context.
+fn card_text(card: &VocabularyCard) -> String {
+    match &card.example {
+        Some(example) => format!("{} {}", card.word, example),
+        None => card.word.clone(),
+    }
+}
+
+/// Short code for a language, as used in file names and deck names.
+pub fn language_code(lang: Lang) -> &'static str {
+    match lang {
+        Lang::Ara => "ar",
+        Lang::Ces => "cs",
+        Lang::Cmn => "zh",
+        Lang::Dan => "da",
+        Lang::Deu => "de",
+        Lang::Ell => "el",
+        Lang::Eng => "en",
+        Lang::Epo => "eo",
+        Lang::Fin => "fi",
+        Lang::Fra => "fr",
+        Lang::Heb => "he",
+        Lang::Hin => "hi",
+        Lang::Hun => "hu",
+        Lang::Ita => "it",
+        Lang::Jpn => "ja",
+        Lang::Kor => "ko",
+        Lang::Nld => "nl",
+        Lang::Nob => "nb",
+        Lang::Pol => "pl",
+        Lang::Por => "pt",
+        Lang::Ron => "ro",
+        Lang::Rus => "ru",
+        Lang::Spa => "es",
+        Lang::Swe => "sv",
+        Lang::Tur => "tr",
+        Lang::Ukr => "uk",
+        other => other.code(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duocards::models::LearningStatus;
+
+    fn card(word: &str, example: &str) -> VocabularyCard {
+        VocabularyCard {
+            word: word.to_string(),
+            example: Some(example.to_string()),
+            status: LearningStatus::New,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_detects_mixed_deck() {
+        let cards = vec![
+            card(
+                "Haus",
+                "Das Haus ist sehr groß und hat einen schönen Garten.",
+            ),
+            card(
+                "Hund",
+                "Der Hund schläft jeden Tag unter dem Tisch in der Küche.",
+            ),
+            card(
+                "perro",
+                "El perro duerme todos los días debajo de la mesa de la cocina.",
+            ),
+            card("casa", "La casa es muy grande y tiene un jardín precioso."),
+        ];
+
+        assert_eq!(detect_languages(&cards), vec!["de", "de", "es", "es"]);
+    }
+
+    #[test]
+    fn test_single_language_deck() {
+        let cards = vec![
+            card(
+                "Haus",
+                "Das Haus ist sehr groß und hat einen schönen Garten.",
+            ),
+            // Too short to detect on its own; follows the rest of the deck
+            VocabularyCard {
+                example: None,
+                ..card("ja", "")
+            },
+        ];
+        assert_eq!(detect_languages(&cards), vec!["de", "de"]);
+    }
+
+    #[test]
+    fn test_annotates_word_and_translation() {
+        let mut cards = vec![
+            VocabularyCard {
+                translation: "The house is very big and has a beautiful garden.".to_string(),
+                ..card(
+                    "Haus",
+                    "Das Haus ist sehr groß und hat einen schönen Garten.",
+                )
+            },
+            VocabularyCard {
+                translation: "dog".to_string(),
+                ..card("Hund", "Der Hund schläft jeden Tag unter dem Tisch.")
+            },
+        ];
+        annotate_languages(&mut cards);
+
+        let expected = CardLanguage {
+            word: "de".to_string(),
+            translation: "en".to_string(),
+        };
+        assert_eq!(cards[0].language.as_ref(), Some(&expected));
+        assert_eq!(cards[1].language.as_ref(), Some(&expected));
+    }
+
+    #[test]
+    fn test_nothing_detected() {
+        assert_eq!(detect_languages(&[card("1", "2")]), vec![UNKNOWN_LANGUAGE]);
+    }
+}
@@ -1,5 +1,27 @@
+//! Transfer vocabulary from Duocards to Anki, JSON, CSV or SQLite.
+//!
+//! [`Duoload`] runs a whole export; the modules below expose the client,
+//! the transfer processor and the output builders it is made of.
+
+pub mod analytics;
 pub mod anki;
+pub mod cli;
 pub mod duocards;
 pub mod error;
+pub mod export;
+#[cfg(feature = "lang")]
+pub mod lang;
+pub mod media;
 pub mod output;
+pub mod picker;
+pub mod snapshot;
+#[cfg(feature = "stem")]
+pub mod stem;
+#[cfg(feature = "testing")]
+pub mod testing;
 pub mod transfer;
+pub mod transform;
+#[cfg(feature = "tui")]
+pub mod tui;
+
+pub use export::{Duoload, DuoloadBuilder, Output};
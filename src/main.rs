@@ -1,53 +1,516 @@
-use clap::Parser;
-use std::path::PathBuf;
-
-mod anki;
-mod duocards;
-mod error;
-mod output;
-mod transfer;
-
-use crate::output::anki::AnkiPackageBuilder;
-use crate::output::json::JsonOutputBuilder;
-use duocards::DuocardsClient;
-use duocards::deck;
-use error::{DuoloadError, Result};
-use transfer::processor::TransferProcessor;
+use clap::{ArgGroup, Args as ClapArgs, Parser, Subcommand, ValueEnum};
+use regex::Regex;
+use std::io::{BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::level_filters::LevelFilter;
+use tracing::{error, info, warn};
+use tracing_subscriber::EnvFilter;
+
+use duoload::analytics::ProgressReport;
+use duoload::anki::note::{
+    CardTemplates, DEFAULT_TAG_PREFIX, QuestionField, TagScheme, parse_status_name,
+};
+use duoload::anki::package::ExistingPackage;
+use duoload::anki::verify::verify_package;
+use duoload::anki::writer::{ApkgOptions, ApkgScheme};
+use duoload::cli::clipboard;
+use duoload::duocards::auth::{AuthError, COOKIE_ENV, Credentials, TOKEN_ENV};
+use duoload::duocards::bandwidth::parse_byte_size;
+use duoload::duocards::cache::PageCache;
+use duoload::duocards::deck;
+use duoload::duocards::models::LearningStatus;
+use duoload::duocards::network::NetworkOptions;
+use duoload::duocards::retry::{DEFAULT_RETRIES, RetryPolicy};
+use duoload::duocards::{DuocardsClient, DuocardsClientTrait, FileSourceClient};
+use duoload::error::{DuoloadError, Result};
+use duoload::media::{MediaFetcher, MediaStore};
+use duoload::output::anki::DECK_ID;
+use duoload::output::chunked::{self, ChunkedOutputBuilder};
+use duoload::output::csv::{CsvHeaders, CsvQuoting};
+use duoload::output::encrypted::{EncryptedOutputBuilder, Encryption};
+use duoload::output::json::JsonOutputBuilder;
+use duoload::output::manifest::{self, Manifest};
+use duoload::output::markdown::MarkdownStyle;
+use duoload::output::multi::{MultiOutputBuilder, status_path};
+use duoload::output::raw;
+use duoload::output::registry::{
+    AnkiSettings, CsvSettings, FormatContext, JsonSettings, OutputRegistry, RegisteredFormat,
+};
+use duoload::output::retention::{self, RetentionPolicy};
+use duoload::output::schema;
+use duoload::output::sort::SortOrder;
+use duoload::output::{self, OutputBuilder, OutputDestination, OutputFormat};
+#[cfg(not(feature = "tui"))]
+use duoload::picker;
+use duoload::snapshot::Snapshot;
+#[cfg(feature = "testing")]
+use duoload::testing::{FaultInjector, Faults};
+use duoload::transfer::control::TransferControl;
+use duoload::transfer::duplicates::{DedupeKey, DuplicatePolicy};
+use duoload::transfer::key_store::{DedupeStore, KeyStoreKind};
+use duoload::transfer::processor::{
+    DEFAULT_AUTO_RETRY_DELAY, DEFAULT_CHANNEL_CAPACITY, TransferProcessor,
+};
+use duoload::transfer::progress::{ConsoleReporter, ProgressReporter, ProgressReporters};
+use duoload::transfer::stats::{RunStats, StatsRecorder, summary_table};
+use duoload::transfer::webhook::WebhookReporter;
+use duoload::transform::TransformPipeline;
+use duoload::transform::filter::CardFilter;
+use duoload::transform::mapping::{FieldMapping, Swap};
+use duoload::transform::normalize::NormalForm;
+use duoload::transform::sanitize::SanitizeOptions;
+use duoload::transform::sentences::SentenceExtractor;
 
 #[derive(Parser)]
 #[command(name = "duoload")]
-#[command(about = "Transfer vocabulary from Duocards to Anki or JSON")]
+#[command(about = "Transfer vocabulary from Duocards to Anki, JSON or CSV")]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+#[command(group(ArgGroup::new("output_format").multiple(true)))]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(
         long,
         value_name = "DECK_ID",
-        help = "Duocards deck ID (base64 encoded Deck:UUID)"
+        required_unless_present_any = ["list_decks", "from_json", "from_csv", "cookie", "cookie_file", "token", "deck_from_clipboard"],
+        help = "Duocards deck ID (base64 encoded Deck:UUID), its UUID or a share link to the deck; \
+                without it, logged-in users pick one of their decks"
+    )]
+    deck_id: Option<String>,
+
+    #[arg(
+        long,
+        conflicts_with = "deck_id",
+        help = "Take the deck ID, UUID or share link from the clipboard, e.g. copied from the browser"
     )]
-    deck_id: String,
+    deck_from_clipboard: bool,
 
     #[arg(
         long,
         value_name = "FILE",
-        help = "Output Anki package file (.apkg)",
-        group = "output_format"
+        conflicts_with_all = ["from_csv", "list_decks", "concurrency"],
+        help = "Read the cards from a JSON or JSON Lines export instead of the Duocards API"
+    )]
+    from_json: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["list_decks", "concurrency"],
+        help = "Read the cards from a CSV export instead of the Duocards API"
+    )]
+    from_csv: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["from_json", "from_csv"],
+        help = "Keep fetched pages in DIR and read them from there on later runs instead of fetching them again"
+    )]
+    cache_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        requires = "cache_dir",
+        help = "Only read pages from --cache-dir, without requests to Duocards"
+    )]
+    offline: bool,
+
+    #[arg(
+        long,
+        requires = "cache_dir",
+        conflicts_with = "offline",
+        help = "Ask Duocards whether the pages in --cache-dir changed, with their ETags, and only fetch those that did"
+    )]
+    revalidate: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["deck_id", "output_format"],
+        help = "List the decks of the logged-in account with their IDs and exit"
+    )]
+    list_decks: bool,
+
+    #[arg(
+        long,
+        requires = "deck_id",
+        conflicts_with_all = ["list_decks", "output_format", "from_json", "from_csv"],
+        help = "Print the number of cards in the deck and the pages an export requests, without downloading the cards"
+    )]
+    count_only: bool,
+
+    #[command(flatten)]
+    auth: AuthArgs,
+
+    #[command(flatten)]
+    network: NetworkArgs,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Output Anki package file (.apkg), or - for stdout",
+        group = "output_format",
+        group = "anki_output"
     )]
     anki_file: Option<PathBuf>,
 
+    #[arg(
+        long,
+        value_name = "NAME",
+        requires = "output",
+        conflicts_with_all = ["anki_file", "json_file", "csv_file", "sqlite_file", "mnemosyne_file", "markdown_file", "yaml_file", "msgpack_file", "cbor_file", "json"],
+        help = "Output format: anki, json, csv, sqlite, mnemosyne, markdown, yaml, msgpack or cbor, written to --output",
+        group = "output_format",
+        group = "anki_output",
+        group = "markdown_output",
+        group = "csv_output",
+        group = "json_output",
+        group = "image_output"
+    )]
+    format: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        requires = "format",
+        help = "Output file of --format, or - for stdout"
+    )]
+    output: Option<PathBuf>,
+
+    /// Formats known to --format
+    #[arg(skip)]
+    registry: OutputRegistry,
+
+    /// Format of --format, looked up in `registry`
+    #[arg(skip)]
+    selected_format: Option<RegisteredFormat>,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Name of the Anki deck or Mnemosyne category; use :: for Anki subdecks, e.g. Languages::German [default: the name of the Duocards deck]"
+    )]
+    deck_name: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "ID",
+        default_value_t = DECK_ID,
+        value_parser = clap::value_parser!(i64).range(2..),
+        help = "ID of the Anki deck; exports with different IDs are imported as separate decks"
+    )]
+    anki_deck_id: i64,
+
+    #[arg(
+        long,
+        value_name = "ID",
+        value_parser = clap::value_parser!(i64).range(1..),
+        help = "ID of the Anki note type, to avoid collisions with other imports [default: 1607392319, \
+                with a different ID for each set of extra fields]"
+    )]
+    anki_model_id: Option<i64>,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        requires = "anki_output",
+        help = "Load the card templates from front.html, back.html and style.css in DIR"
+    )]
+    template_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        requires = "anki_output",
+        overrides_with = "allow_html",
+        help = "Escape <, & and {{ in the fields of the Anki notes so they are shown as text [default]"
+    )]
+    escape_html: bool,
+
+    #[arg(
+        long,
+        requires = "anki_output",
+        overrides_with = "escape_html",
+        help = "Keep HTML in the fields of the Anki notes for the cards to render, instead of escaping it"
+    )]
+    allow_html: bool,
+
+    #[arg(
+        long = "tag",
+        value_name = "TAG",
+        requires = "anki_output",
+        help = "Add TAG to every note of the Anki package; can be repeated"
+    )]
+    tags: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "PREFIX",
+        default_value = DEFAULT_TAG_PREFIX,
+        requires = "anki_output",
+        help = "Prefix of the status, label, deck and export date tags of the Anki notes, e.g. PREFIX_known and PREFIX::deck::<name>; an empty prefix leaves it out"
+    )]
+    tag_prefix: String,
+
+    #[arg(
+        long,
+        value_name = "STATUS=NAME",
+        value_parser = parse_status_name,
+        requires = "anki_output",
+        help = "Name the status tag of cards with STATUS (new, learning or known) NAME instead, e.g. known=mature; can be repeated"
+    )]
+    tag_map: Vec<(LearningStatus, String)>,
+
+    #[arg(
+        long,
+        requires = "anki_output",
+        conflicts_with = "merge_into",
+        help = "Put cards into New, Learning and Known subdecks of the Anki deck"
+    )]
+    split_by_status: bool,
+
+    #[arg(
+        long,
+        requires = "anki_output",
+        help = "Add hidden SourceId, Created and WaitingUntil fields with the Duocards ID and timestamps of each card"
+    )]
+    anki_source_fields: bool,
+
+    #[arg(
+        long,
+        requires = "anki_output",
+        help = "Add hidden Status and Priority fields with the Duocards status and a priority from 5 (never answered correctly) \
+                to 0 (known) of each card, for filtered decks"
+    )]
+    anki_status_fields: bool,
+
+    #[arg(
+        long,
+        requires = "anki_output",
+        help = "Add a Hint field with the Duocards hint of each card, shown on the back"
+    )]
+    anki_hint_field: bool,
+
+    #[arg(
+        long,
+        requires = "anki_output",
+        help = "Add a hidden KnownCount field with how often each card was answered correctly in Duocards, \
+                for filtered decks and scheduling add-ons"
+    )]
+    include_known_count: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = QuestionField::None,
+        requires = "anki_output",
+        conflicts_with = "template_dir",
+        help = "Show the hint or the example below the word on the front of the cards; hint adds the Hint field"
+    )]
+    anki_question_field: QuestionField,
+
     #[arg(
         long,
         value_name = "FILE",
         help = "Output JSON file (.json)",
-        group = "output_format"
+        group = "output_format",
+        group = "json_output",
+        group = "image_output"
     )]
     json_file: Option<PathBuf>,
 
     #[arg(
         long,
-        help = "Output JSON to stdout (for piping to other tools)",
+        value_name = "FILE",
+        help = "Output CSV file (.csv)",
+        group = "output_format",
+        group = "csv_output",
+        group = "image_output"
+    )]
+    csv_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "NAMES",
+        requires = "csv_output",
+        help = "Comma-separated CSV column names, e.g. \"Wort,Übersetzung,Beispiel,Status\"; fewer names than columns write only the first columns"
+    )]
+    csv_headers: Option<CsvHeaders>,
+
+    #[arg(
+        long,
+        value_name = "CHAR",
+        default_value = ",",
+        value_parser = duoload::output::csv::parse_delimiter,
+        requires = "csv_output",
+        help = "CSV field delimiter, e.g. ; for spreadsheets in European locales, or tab"
+    )]
+    csv_delimiter: u8,
+
+    #[arg(
+        long,
+        value_name = "STYLE",
+        value_enum,
+        default_value_t = CsvQuoting::Necessary,
+        requires = "csv_output",
+        help = "Which CSV fields are quoted"
+    )]
+    csv_quoting: CsvQuoting,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Output SQLite database (.db), updating the rows of this deck",
         group = "output_format"
     )]
+    sqlite_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Output Mnemosyne XML file (.xml), for Mnemosyne's Mnemosyne 1.x XML import",
+        group = "output_format"
+    )]
+    mnemosyne_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Output Markdown file (.md) listing word, translation and example",
+        group = "output_format",
+        group = "markdown_output"
+    )]
+    markdown_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Output YAML file (.yaml) with a list of cards",
+        group = "output_format",
+        group = "image_output"
+    )]
+    yaml_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Output MessagePack file (.msgpack) with an array of cards, for embedding in other applications",
+        group = "output_format"
+    )]
+    msgpack_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Output CBOR file (.cbor) with an array of cards, for embedding in other applications",
+        group = "output_format"
+    )]
+    cbor_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "STYLE",
+        value_enum,
+        default_value_t = MarkdownStyle::Table,
+        requires = "markdown_output",
+        help = "Layout of the Markdown file: a table, or flashcards for Obsidian's spaced repetition plugin"
+    )]
+    markdown_style: MarkdownStyle,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["from_json", "from_csv", "anki_file", "json_file", "csv_file", "sqlite_file", "mnemosyne_file", "markdown_file", "yaml_file", "msgpack_file", "cbor_file", "json"],
+        help = "Output JSON file with the cards as returned by the Duocards API, with all their fields and cursors",
+        group = "output_format"
+    )]
+    raw_json_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Output JSON to stdout (for piping to other tools)",
+        group = "output_format",
+        group = "json_output",
+        group = "image_output"
+    )]
     json: bool,
 
+    #[arg(
+        long,
+        conflicts_with_all = ["anki_file", "csv_file", "sqlite_file", "mnemosyne_file", "markdown_file", "yaml_file", "msgpack_file", "cbor_file", "raw_json_file", "encrypt"],
+        help = "Write JSON Lines, one card per line as soon as it is fetched, instead of a JSON array"
+    )]
+    ndjson: bool,
+
+    #[arg(
+        long,
+        requires = "json_output",
+        help = "Start JSON output with the deck name and languages: {\"deck\": {...}, \"cards\": [...]}"
+    )]
+    json_metadata: bool,
+
+    #[arg(
+        long,
+        requires = "json_output",
+        conflicts_with_all = ["ndjson", "json_metadata"],
+        help = "Wrap JSON output in {\"deck\": {...}, \"stats\": {...}, \"cards\": [...]} with the deck and the number of cards and duplicates"
+    )]
+    json_envelope: bool,
+
+    #[arg(
+        long,
+        requires = "json_output",
+        conflicts_with_all = ["ndjson", "json_pretty"],
+        help = "Write JSON on a single line instead of indented"
+    )]
+    json_compact: bool,
+
+    #[arg(
+        long,
+        requires = "json_output",
+        conflicts_with = "ndjson",
+        help = "Write indented JSON, as by default"
+    )]
+    json_pretty: bool,
+
+    #[arg(
+        long,
+        requires = "json_output",
+        help = "Check JSON output against its JSON Schema (see `duoload schema`) before writing it"
+    )]
+    validate_output: bool,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        requires = "image_output",
+        conflicts_with = "anki_file",
+        help = "Download card pictures into DIR and write their paths, relative to the output file, into the image field of JSON, CSV or YAML output"
+    )]
+    download_images: Option<PathBuf>,
+
+    #[arg(
+        long,
+        requires = "image_output",
+        help = "Write picture URLs into the image field, also with --download-images; adds an image column to CSV output"
+    )]
+    image_urls: bool,
+
+    #[arg(
+        long,
+        help = "Record the deck ID, page, fetch time and duoload version of each card: a provenance field in JSON, a provenance column in CSV and a hidden Provenance field in Anki"
+    )]
+    provenance: bool,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Also write a deck of the example sentences, with each word in bold and its translation on the back: an Anki package when exporting one, else a JSON array"
+    )]
+    sentences_deck: Option<PathBuf>,
+
     #[arg(
         long,
         value_name = "N",
@@ -55,82 +518,2165 @@ struct Args {
         value_parser = validate_page_limit
     )]
     pages: Option<u32>,
-}
-
-/// Validate that the page limit is a positive integer
-fn validate_page_limit(s: &str) -> std::result::Result<u32, String> {
-    match s.parse::<u32>() {
-        Ok(n) if n > 0 => Ok(n),
-        Ok(_) => Err("Page limit must be a positive integer".to_string()),
-        Err(_) => Err("Page limit must be a valid positive integer".to_string()),
-    }
-}
-
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
 
-    // Validate that exactly one output format is specified
-    if args.anki_file.is_none() && args.json_file.is_none() && !args.json {
-        return Err(DuoloadError::Api(
-            "Please specify either --anki-file, --json-file, or --json".to_string(),
-        ));
-    }
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Stop once N cards have been exported, in the middle of a page if need be"
+    )]
+    max_cards: Option<u64>,
 
-    let mut client = match DuocardsClient::new() {
-        Ok(client) => client,
-        Err(e) => {
-            return Err(DuoloadError::Api(format!(
-                "Failed to initialize client: {}",
-                e
-            )));
-        }
-    };
+    #[arg(
+        long,
+        help = "Leave out cards the output cannot hold, like cards with control characters in an Anki package, instead of failing"
+    )]
+    skip_bad_cards: bool,
 
-    // Set page limit if specified
-    if let Some(limit) = args.pages {
-        client = client.with_page_limit(limit);
-    }
+    #[arg(
+        long,
+        value_name = "FILE",
+        default_value = "duoload-errors.json",
+        requires = "skip_bad_cards",
+        help = "JSON report of the cards left out by --skip-bad-cards"
+    )]
+    errors_file: PathBuf,
 
-    // Validate deck ID
-    eprintln!("Validating deck ID...");
-    if let Err(e) = deck::validate_deck_id(&args.deck_id) {
-        return Err(DuoloadError::Api(format!("Invalid deck ID: {}", e)));
-    }
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = DEFAULT_CHANNEL_CAPACITY,
+        help = "Number of fetched pages buffered ahead of the output writer",
+        value_parser = validate_buffer_pages
+    )]
+    buffer_pages: usize,
 
-    let processor = TransferProcessor::new(client, args.deck_id);
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = DEFAULT_RETRIES,
+        help = "Retry a request failing with 429, 5xx, a timeout or a connection error up to N times"
+    )]
+    retries: u32,
+
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_byte_size,
+        help = "Stop with an error instead of sending another request once the API responses add up to SIZE, e.g. 50MB or 1GiB, for metered connections"
+    )]
+    max_bytes: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value = "1",
+        help = "Wait before the first request retry, doubled after each further failure",
+        value_parser = parse_seconds
+    )]
+    retry_delay: Duration,
+
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        help = "Request up to N pages at once",
+        value_parser = validate_concurrency
+    )]
+    concurrency: usize,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value = "1",
+        help = "Wait between page requests, or batches of concurrent requests",
+        value_parser = parse_seconds
+    )]
+    page_delay: Duration,
+
+    #[arg(
+        long,
+        conflicts_with = "concurrency",
+        help = "Be conservative with the API: one page at a time, at least 3 seconds between pages \
+                and 5 seconds before the first retry"
+    )]
+    polite: bool,
+
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        help = "Resume a failed export up to N times, waiting longer after each failure"
+    )]
+    auto_retry: u32,
+
+    #[cfg(feature = "testing")]
+    #[arg(
+        long,
+        value_name = "FAULTS",
+        conflicts_with_all = ["from_json", "from_csv"],
+        help = "Make requests fail on purpose, e.g. timeout=0.1,server-error=0.05,malformed=0.01,seed=42, \
+                to check that retries and resumed exports work; timeout-after=SECONDS sets how long timeouts hang"
+    )]
+    inject_faults: Option<Faults>,
+
+    #[arg(
+        long,
+        requires = "anki_output",
+        help = "Download card pictures and embed them in the Anki package"
+    )]
+    images: bool,
+
+    #[arg(
+        long,
+        requires = "anki_output",
+        help = "Download the pronunciation of each word and add it to the Anki package"
+    )]
+    audio: bool,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "anki_output",
+        help = "Keep the notes of an existing Anki package (.apkg or .colpkg) and add only words it does not have yet"
+    )]
+    merge_into: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "LEVEL",
+        requires = "anki_output",
+        value_parser = clap::value_parser!(u32).range(0..=9),
+        help = "Deflate level of the Anki package, from 0 (uncompressed) to 9"
+    )]
+    apkg_compression: Option<u32>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = ApkgScheme::Legacy,
+        requires = "anki_output",
+        help = "Layout of the Anki package: legacy for every Anki version, anki21 for Anki 2.1 and later"
+    )]
+    apkg_scheme: ApkgScheme,
+
+    #[arg(
+        long,
+        requires = "anki_output",
+        help = "Leave pictures, pronunciations and merged media files out of the Anki package"
+    )]
+    skip_media: bool,
+
+    #[arg(
+        long,
+        requires = "anki_output",
+        help = "Keep the cards of the Anki package in a temporary database on disk instead of memory, for very large decks"
+    )]
+    low_memory: bool,
+
+    #[arg(
+        long,
+        requires = "anki_output",
+        help = "Write a full Anki collection (.colpkg) that replaces the collection when opened, \
+                with cards learned in Duocards scheduled for review"
+    )]
+    anki_collection: bool,
+
+    #[arg(
+        long,
+        value_name = "STATE_FILE",
+        help = "Save progress to STATE_FILE after every page and continue from it if it exists"
+    )]
+    resume: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "POLICY",
+        value_enum,
+        default_value_t = DuplicatePolicy::First,
+        help = "How to handle words that appear more than once"
+    )]
+    duplicates: DuplicatePolicy,
+
+    #[arg(
+        long,
+        value_name = "KEY",
+        value_enum,
+        default_value_t = DedupeKey::Word,
+        help = "What makes two cards duplicates: the same word, the same word and translation, or the same Duocards card"
+    )]
+    dedupe_key: DedupeKey,
+
+    #[arg(
+        long,
+        value_name = "STORE",
+        value_enum,
+        default_value_t = KeyStoreKind::Memory,
+        help = "Where to keep track of the cards seen: in memory, or in a temporary database on disk for decks of millions of cards"
+    )]
+    dedupe_store: KeyStoreKind,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Keep track of the cards seen in the database FILE, created if missing, and skip cards that earlier exports with the same FILE wrote"
+    )]
+    dedupe_state: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "ORDER",
+        value_enum,
+        conflicts_with = "ndjson",
+        help = "Order of the cards in the output: as in the deck, alphabetical by word, by status or random [default: original, alphabetical with --deterministic]"
+    )]
+    sort: Option<SortOrder>,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["encrypt", "sqlite_file"],
+        help = "Write identical files for identical cards, to diff exports: sort the cards, leave out export times and write Anki packages with fixed IDs"
+    )]
+    deterministic: bool,
+
+    #[arg(
+        long,
+        help = "Skip re-reading the written file to check the note count"
+    )]
+    no_verify: bool,
+
+    #[arg(long, help = "Overwrite the output file if it exists")]
+    force: bool,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Write the output to PATH if writing it fails, e.g. on a full disk, instead of losing the fetched cards"
+    )]
+    fallback_output: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "RECIPIENT",
+        help = "Encrypt JSON or CSV output for age:<recipient> or gpg:<recipient>"
+    )]
+    encrypt: Option<Encryption>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write statistics of the run as JSON to FILE, also when it fails"
+    )]
+    stats_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Print statistics of the run as one line of JSON to stderr"
+    )]
+    stats_json: bool,
+
+    #[arg(
+        long,
+        help = "Print a table of the exported cards by status, cards per page, word lengths and first letters"
+    )]
+    stats: bool,
+
+    #[arg(
+        long,
+        value_name = "URL",
+        value_parser = reqwest::Url::parse,
+        help = "POST progress events as JSON to URL, e.g. to follow a long export from CI or a phone"
+    )]
+    progress_webhook: Option<reqwest::Url>,
+
+    #[arg(
+        long,
+        help = "Write a sidecar manifest with the SHA-256 hash of the output and the card counts"
+    )]
+    manifest: bool,
+
+    #[arg(
+        long,
+        help = "Add a UTC timestamp to the output file name, e.g. deck-20250601-031500.apkg"
+    )]
+    dated: bool,
+
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "dated",
+        help = "After a successful dated export, keep only the N newest exports",
+        value_parser = validate_keep_last
+    )]
+    keep_last: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "DAYS",
+        requires = "dated",
+        help = "After a successful dated export, remove exports older than DAYS days"
+    )]
+    keep_days: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        requires = "dated",
+        conflicts_with_all = ["from_json", "from_csv", "raw_json_file", "resume"],
+        help = "Keep running and export the deck again every SECONDS, e.g. 86400 for nightly backups",
+        value_parser = parse_seconds
+    )]
+    watch: Option<Duration>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = ["json", "ndjson", "sqlite_file", "merge_into"],
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Split the output into files of at most N cards: deck-001.apkg, deck-002.apkg, ..."
+    )]
+    split_every: Option<u64>,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["json", "sqlite_file", "merge_into", "split_by_status", "split_every"],
+        help = "Write the cards of each learning status to their own files: deck-new.json, deck-learning.json and deck-known.json"
+    )]
+    split_by_status_files: bool,
+
+    #[cfg(feature = "lang")]
+    #[arg(
+        long,
+        conflicts_with_all = ["ndjson", "merge_into", "split_every"],
+        help = "Detect the language of each word and split the export: one subdeck per language in Anki packages, one file per language for JSON"
+    )]
+    split_languages: bool,
+
+    #[cfg(feature = "lang")]
+    #[arg(
+        long,
+        conflicts_with = "ndjson",
+        help = "Detect the languages of each word and translation: lang::de style tags in Anki packages, a language field in JSON"
+    )]
+    detect_languages: bool,
+
+    #[cfg(feature = "tui")]
+    #[arg(long, help = "Show a full-screen dashboard while exporting")]
+    tui: bool,
+
+    #[cfg(feature = "tui")]
+    #[arg(
+        long,
+        conflicts_with = "tui",
+        help = "Show live counters and recent words below a colored log while exporting"
+    )]
+    fancy: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORM",
+        help = "Convert card fields to a Unicode normal form, so differently typed accents compare equal"
+    )]
+    normalize: Option<NormalForm>,
+
+    #[arg(
+        long = "map",
+        value_name = "MAPPING",
+        default_value_t = FieldMapping::default(),
+        hide_default_value = true,
+        help = "Choose the card fields of the output front, back and example, e.g. front=translation,back=word"
+    )]
+    mapping: FieldMapping,
+
+    #[arg(
+        long,
+        help = "Exchange the word and translation of every card, for learning in the reverse direction of the deck"
+    )]
+    swap: bool,
+
+    #[arg(
+        long,
+        value_name = "REGEX",
+        value_parser = Regex::new,
+        help = "Export only cards whose word or translation matches REGEX, e.g. '\\s' for phrases"
+    )]
+    include_regex: Option<Regex>,
+
+    #[arg(
+        long,
+        value_name = "REGEX",
+        value_parser = Regex::new,
+        help = "Skip cards whose word or translation matches REGEX, e.g. '\\d' for numbers"
+    )]
+    exclude_regex: Option<Regex>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Export only cards answered correctly at least N times in Duocards (known from 5)"
+    )]
+    min_known: Option<i32>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Export only cards answered correctly at most N times in Duocards"
+    )]
+    max_known: Option<i32>,
+
+    #[arg(
+        long = "filter-label",
+        value_name = "LABEL",
+        help = "Export only cards with the Duocards label LABEL, e.g. their course; can be repeated to allow several"
+    )]
+    filter_labels: Vec<String>,
+
+    #[command(flatten)]
+    logging: LoggingArgs,
+
+    #[command(flatten)]
+    sanitize: SanitizeArgs,
+}
+
+impl Args {
+    /// Cards selected by --include-regex, --exclude-regex, --min-known,
+    /// --max-known and --filter-label.
+    fn card_filter(&self) -> CardFilter {
+        let mut filter = CardFilter::new();
+        if let Some(pattern) = &self.include_regex {
+            filter = filter.with_include(pattern.clone());
+        }
+        if let Some(pattern) = &self.exclude_regex {
+            filter = filter.with_exclude(pattern.clone());
+        }
+        if let Some(count) = self.min_known {
+            filter = filter.with_min_known(count);
+        }
+        if let Some(count) = self.max_known {
+            filter = filter.with_max_known(count);
+        }
+        for label in &self.filter_labels {
+            filter = filter.with_label(label);
+        }
+        filter
+    }
+
+    /// Order of the written cards: the one given, or a stable order for
+    /// deterministic output.
+    fn sort_order(&self) -> Result<SortOrder> {
+        match (self.sort, self.deterministic) {
+            (Some(SortOrder::Random), true) => Err(DuoloadError::Format(
+                "--sort random cannot be used with --deterministic".to_string(),
+            )),
+            (Some(order), _) => Ok(order),
+            (None, true) => Ok(SortOrder::Alphabetical),
+            (None, false) => Ok(SortOrder::Original),
+        }
+    }
+
+    /// The store of seen cards for --dedupe-store or --dedupe-state, if not
+    /// in memory.
+    fn dedupe_store(&self) -> Result<Option<DedupeStore>> {
+        match (&self.dedupe_state, self.dedupe_store) {
+            (Some(path), _) => Ok(Some(DedupeStore::open(path)?)),
+            (None, KeyStoreKind::Disk) => Ok(Some(DedupeStore::temporary()?)),
+            (None, KeyStoreKind::Memory) => Ok(None),
+        }
+    }
+
+    /// Raises the page and retry delays to the --polite minimums.
+    fn apply_polite(&mut self) {
+        if self.polite {
+            self.page_delay = self.page_delay.max(POLITE_PAGE_DELAY);
+            self.retry_delay = self.retry_delay.max(POLITE_RETRY_DELAY);
+        }
+    }
+
+    /// Changes applied to every card: sanitize, normalize, filter, swap and
+    /// map.
+    fn transforms(&self) -> TransformPipeline {
+        let mut transforms =
+            TransformPipeline::new().then(self.sanitize.options(self.output_format()));
+        if let Some(form) = self.normalize {
+            transforms = transforms.then(form);
+        }
+        transforms = transforms.then(self.card_filter());
+        if self.swap {
+            transforms = transforms.then(Swap);
+        }
+        transforms.then(self.mapping)
+    }
+
+    /// The outputs given on the command line, with their formats and paths;
+    /// `-` is stdout.
+    fn outputs(&self) -> Vec<(RegisteredFormat, PathBuf)> {
+        let mut outputs = Vec::new();
+        if let (Some(format), Some(path)) = (&self.selected_format, &self.output) {
+            outputs.push((format.clone(), path.clone()));
+        }
+        let stdout = self.json.then(|| PathBuf::from("-"));
+        let files = [
+            (OutputFormat::Anki, &self.anki_file),
+            (OutputFormat::Json, &stdout),
+            (OutputFormat::Json, &self.json_file),
+            (OutputFormat::Csv, &self.csv_file),
+            (OutputFormat::Sqlite, &self.sqlite_file),
+            (OutputFormat::Mnemosyne, &self.mnemosyne_file),
+            (OutputFormat::Markdown, &self.markdown_file),
+            (OutputFormat::Yaml, &self.yaml_file),
+            (OutputFormat::Msgpack, &self.msgpack_file),
+            (OutputFormat::Cbor, &self.cbor_file),
+        ];
+        for (format, path) in files {
+            if let Some(path) = path {
+                outputs.push((self.registry.builtin(format).clone(), path.clone()));
+            }
+        }
+        outputs
+    }
+
+    /// Format of the first output; formats that are not built in are
+    /// cleaned up like JSON.
+    fn output_format(&self) -> OutputFormat {
+        self.outputs()
+            .first()
+            .and_then(|(format, _)| format.builtin())
+            .unwrap_or(OutputFormat::Json)
+    }
+}
+
+/// Cleanup of card fields, each for all output formats or only some.
+#[derive(ClapArgs)]
+struct SanitizeArgs {
+    #[arg(
+        long,
+        value_name = "FORMATS",
+        num_args = 0..=1,
+        require_equals = true,
+        value_delimiter = ',',
+        help = "Remove HTML tags from card fields; =anki,json,csv limits it to those outputs"
+    )]
+    strip_html: Option<Vec<OutputFormat>>,
+
+    #[arg(
+        long,
+        value_name = "FORMATS",
+        num_args = 0..=1,
+        require_equals = true,
+        value_delimiter = ',',
+        help = "Decode HTML entities such as &amp; in card fields; =FORMATS limits it to those outputs"
+    )]
+    decode_entities: Option<Vec<OutputFormat>>,
+
+    #[arg(
+        long,
+        value_name = "FORMATS",
+        num_args = 0..=1,
+        require_equals = true,
+        value_delimiter = ',',
+        help = "Trim card fields and collapse repeated whitespace; =FORMATS limits it to those outputs"
+    )]
+    trim_whitespace: Option<Vec<OutputFormat>>,
+}
+
+impl SanitizeArgs {
+    /// The cleanups enabled for `format`.
+    fn options(&self, format: OutputFormat) -> SanitizeOptions {
+        let enabled = |formats: &Option<Vec<OutputFormat>>| {
+            formats
+                .as_ref()
+                .is_some_and(|formats| formats.is_empty() || formats.contains(&format))
+        };
+        SanitizeOptions {
+            strip_html: enabled(&self.strip_html),
+            decode_entities: enabled(&self.decode_entities),
+            trim_whitespace: enabled(&self.trim_whitespace),
+        }
+    }
+}
+
+/// Where log messages go and how they look.
+#[derive(ClapArgs)]
+struct LoggingArgs {
+    #[arg(
+        long,
+        global = true,
+        value_name = "LEVEL",
+        default_value = "info",
+        help = "Most detailed messages to log: off, error, warn, info, debug or trace; RUST_LOG overrides it"
+    )]
+    log_level: LevelFilter,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "FORMAT",
+        value_enum,
+        default_value_t = LogFormat::Text,
+        help = "Format of the log messages written to stderr"
+    )]
+    log_format: LogFormat,
+}
+
+impl LoggingArgs {
+    /// Sends log messages to stderr.
+    fn init(&self) {
+        let filter = EnvFilter::builder()
+            .with_default_directive(self.log_level.into())
+            .from_env_lossy();
+        let subscriber = tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_ansi(std::io::stderr().is_terminal())
+            .with_writer(std::io::stderr);
+        match self.log_format {
+            LogFormat::Text => subscriber.without_time().with_target(false).init(),
+            LogFormat::Json => subscriber.json().init(),
+        }
+    }
+}
+
+/// Credentials for private decks, copied from a logged-in browser session.
+#[derive(ClapArgs)]
+struct AuthArgs {
+    #[arg(
+        long,
+        value_name = "COOKIE",
+        env = COOKIE_ENV,
+        hide_env_values = true,
+        help = "Session cookie of a logged-in Duocards account; takes precedence over --token"
+    )]
+    cookie: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with = "cookie",
+        help = "Read the session cookies from a cookie jar exported from a logged-in browser, as Netscape cookies.txt or JSON; takes precedence over --token"
+    )]
+    cookie_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "TOKEN",
+        env = TOKEN_ENV,
+        hide_env_values = true,
+        help = "Bearer token of a logged-in Duocards account"
+    )]
+    token: Option<String>,
+}
+
+impl AuthArgs {
+    fn is_given(&self) -> bool {
+        self.cookie.is_some() || self.cookie_file.is_some() || self.token.is_some()
+    }
+
+    /// The credentials to send to the API at `api_url`.
+    fn credentials(&self, api_url: &str) -> Result<Option<Credentials>> {
+        Ok(match (&self.cookie, &self.cookie_file, &self.token) {
+            (Some(cookie), _, _) => Some(Credentials::cookie(cookie)?),
+            (None, Some(path), _) => Some(Credentials::cookie_file(path, api_url)?),
+            (None, None, Some(token)) => Some(Credentials::token(token)?),
+            (None, None, None) => None,
+        })
+    }
+
+    /// Attaches the credentials, if any, to `client`.
+    fn apply(&self, client: DuocardsClient) -> Result<DuocardsClient> {
+        Ok(match self.credentials(&client.base_url)? {
+            Some(credentials) => client.with_credentials(credentials),
+            None => client,
+        })
+    }
+}
+
+/// Proxy and TLS settings for corporate networks.
+#[derive(ClapArgs)]
+struct NetworkArgs {
+    #[arg(
+        long,
+        value_name = "URL",
+        help = "Send requests through this HTTP(S) proxy [default: from HTTPS_PROXY, HTTP_PROXY or ALL_PROXY]"
+    )]
+    proxy: Option<String>,
+
+    #[arg(
+        long,
+        conflicts_with = "proxy",
+        help = "Connect directly, ignoring the proxy environment variables"
+    )]
+    no_proxy: bool,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Also trust the CA certificates in this PEM file, e.g. of a TLS-inspecting proxy"
+    )]
+    cacert: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "URL",
+        help = "Send GraphQL requests to this endpoint instead of the Duocards API, e.g. a duoload-mock-server"
+    )]
+    api_url: Option<String>,
+}
+
+impl NetworkArgs {
+    fn options(&self) -> NetworkOptions {
+        NetworkOptions {
+            proxy: self.proxy.clone(),
+            no_proxy: self.no_proxy,
+            ca_cert: self.cacert.clone(),
+            api_url: self.api_url.clone(),
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Save a lossless snapshot bundle (.duoload) of a deck
+    Snapshot(Box<SnapshotArgs>),
+    /// Regenerate an output file from a snapshot bundle
+    Restore {
+        #[arg(value_name = "BUNDLE", help = "Snapshot bundle (.duoload)")]
+        bundle: PathBuf,
+
+        #[arg(
+            long,
+            value_name = "FORMAT",
+            help = "Output format, any of --format; apkg is the same as anki"
+        )]
+        to: String,
+
+        #[arg(long, value_name = "FILE", help = "Output file, or - for stdout")]
+        output: PathBuf,
+
+        #[arg(
+            long,
+            value_name = "NAME",
+            help = "Name of the Anki deck or Mnemosyne category [default: the name of the Duocards deck]"
+        )]
+        deck_name: Option<String>,
+
+        #[arg(long, help = "Overwrite the output file if it exists")]
+        force: bool,
+
+        #[arg(
+            long,
+            value_name = "POLICY",
+            value_enum,
+            default_value_t = DuplicatePolicy::First,
+            help = "How to handle words that appear more than once"
+        )]
+        duplicates: DuplicatePolicy,
+    },
+    /// Report learning progress per week from a series of snapshot bundles
+    Progress {
+        #[arg(
+            value_name = "BUNDLE",
+            required = true,
+            help = "Snapshot bundles (.duoload) of the same deck"
+        )]
+        bundles: Vec<PathBuf>,
+
+        #[arg(
+            long,
+            value_name = "FORMAT",
+            value_enum,
+            default_value_t = ReportFormat::Table,
+            help = "Report format"
+        )]
+        format: ReportFormat,
+    },
+    /// Print the JSON Schema of the JSON output
+    Schema,
+    /// Check that the Duocards API answers and, if given, accepts the credentials
+    Ping(Box<PingArgs>),
+    /// Check that an Anki package is a valid duoload export before importing it
+    VerifyApkg {
+        #[arg(value_name = "FILE", help = "Anki package (.apkg or .colpkg)")]
+        package: PathBuf,
+
+        #[arg(
+            long,
+            value_name = "PREFIX",
+            default_value = DEFAULT_TAG_PREFIX,
+            help = "Prefix of the status tags the notes must have, as given to --tag-prefix for the export; empty to skip the tag check"
+        )]
+        tag_prefix: String,
+
+        #[arg(
+            long,
+            value_name = "FORMAT",
+            value_enum,
+            default_value_t = ReportFormat::Table,
+            help = "Report format"
+        )]
+        format: ReportFormat,
+    },
+}
+
+#[derive(ClapArgs)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct SnapshotArgs {
+    #[command(subcommand)]
+    command: Option<SnapshotCommand>,
+
+    #[arg(
+        long,
+        value_name = "DECK_ID",
+        required = true,
+        help = "Duocards deck ID (base64 encoded Deck:UUID), its UUID or a share link to the deck"
+    )]
+    deck_id: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        required = true,
+        help = "Output snapshot file (.duoload)"
+    )]
+    output: Option<PathBuf>,
+
+    #[command(flatten)]
+    auth: AuthArgs,
+
+    #[command(flatten)]
+    network: NetworkArgs,
+
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Limit the snapshot to N pages (default: all pages)",
+        value_parser = validate_page_limit
+    )]
+    pages: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value = "1",
+        help = "Wait between page requests",
+        value_parser = parse_seconds
+    )]
+    page_delay: Duration,
+}
+
+#[derive(ClapArgs)]
+struct PingArgs {
+    #[command(flatten)]
+    auth: AuthArgs,
+
+    #[command(flatten)]
+    network: NetworkArgs,
+}
+
+#[derive(Subcommand)]
+enum SnapshotCommand {
+    /// Show the cards added, removed and changed between two snapshots
+    Diff {
+        #[arg(value_name = "BEFORE")]
+        before: PathBuf,
+        #[arg(value_name = "AFTER")]
+        after: PathBuf,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LogFormat {
+    /// Plain lines with the level
+    Text,
+    /// One JSON object per line, with the spans of the export and page
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ReportFormat {
+    /// Human-readable table
+    Table,
+    /// JSON for dashboards
+    Json,
+}
+
+/// The registered name of the format `restore --to` asks for; `apkg` was
+/// the name of the Anki format before `restore` took every output format.
+fn restore_format_name(to: &str) -> &str {
+    if to.eq_ignore_ascii_case("apkg") {
+        "anki"
+    } else {
+        to
+    }
+}
+
+/// Validate that the page limit is a positive integer
+fn validate_page_limit(s: &str) -> std::result::Result<u32, String> {
+    match s.parse::<u32>() {
+        Ok(n) if n > 0 => Ok(n),
+        Ok(_) => Err("Page limit must be a positive integer".to_string()),
+        Err(_) => Err("Page limit must be a valid positive integer".to_string()),
+    }
+}
+
+/// Validate that the page buffer holds at least one page
+fn validate_buffer_pages(s: &str) -> std::result::Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        Ok(_) => Err("Buffer size must be a positive integer".to_string()),
+        Err(_) => Err("Buffer size must be a valid positive integer".to_string()),
+    }
+}
+
+/// Validate that at least the export just written is kept
+fn validate_keep_last(s: &str) -> std::result::Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        Ok(_) => Err("Number of kept exports must be a positive integer".to_string()),
+        Err(_) => Err("Number of kept exports must be a valid positive integer".to_string()),
+    }
+}
+
+/// Parse a non-negative number of seconds, fractions allowed
+fn parse_seconds(s: &str) -> std::result::Result<Duration, String> {
+    s.parse::<f64>()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .ok_or_else(|| "Expected a non-negative number of seconds".to_string())
+}
+
+/// Least wait between pages with --polite
+const POLITE_PAGE_DELAY: Duration = Duration::from_secs(3);
+
+/// Least wait before the first retry with --polite
+const POLITE_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Upper bound for --concurrency, to stay friendly to the API
+const MAX_CONCURRENCY: usize = 8;
+
+fn validate_concurrency(s: &str) -> std::result::Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(n) if (1..=MAX_CONCURRENCY).contains(&n) => Ok(n),
+        _ => Err(format!(
+            "Concurrency must be an integer between 1 and {}",
+            MAX_CONCURRENCY
+        )),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    args.logging.init();
+
+    if let Err(e) = run(args).await {
+        error!("{}", e);
+        std::process::exit(e.exit_code());
+    }
+}
+
+async fn run(mut args: Args) -> Result<()> {
+    match args.command {
+        Some(Command::Snapshot(snapshot_args)) => return snapshot(*snapshot_args).await,
+        Some(Command::Restore {
+            bundle,
+            to,
+            output,
+            deck_name,
+            force,
+            duplicates,
+        }) => {
+            let format = args.registry.find(restore_format_name(&to))?;
+            return restore(&bundle, format, deck_name, &output, force, duplicates);
+        }
+        Some(Command::Progress { bundles, format }) => return progress(&bundles, format),
+        Some(Command::Schema) => {
+            print!("{}", schema::JSON_SCHEMA);
+            return Ok(());
+        }
+        Some(Command::Ping(ping_args)) => return ping(*ping_args).await,
+        Some(Command::VerifyApkg {
+            package,
+            tag_prefix,
+            format,
+        }) => return verify_apkg(&package, &tag_prefix, format),
+        None => {}
+    }
+    args.apply_polite();
+    if args.deck_from_clipboard {
+        let deck_id = clipboard::deck_id_from_clipboard()?;
+        info!("Deck ID from the clipboard: {}", deck_id);
+        args.deck_id = Some(deck_id);
+    }
+    if args.list_decks {
+        return list_decks(&args).await;
+    }
+    if args.count_only {
+        return count_only(&args).await;
+    }
+    if let Some(path) = &args.raw_json_file {
+        return raw_export(&args, path).await;
+    }
+    if let Some(name) = args.format.clone() {
+        select_format(&mut args, &name)?;
+    }
+    // Validate that at least one output format is specified
+    if args.outputs().is_empty() {
+        return Err(DuoloadError::Api(
+            "Please specify either --format with --output, --anki-file, --json-file, --csv-file, --sqlite-file, --mnemosyne-file, --markdown-file, --yaml-file, --msgpack-file, --cbor-file, or --json"
+                .to_string(),
+        ));
+    }
+
+    if args.ndjson && !args.duplicates.is_streamable() {
+        return Err(DuoloadError::StreamingDuplicatePolicy(args.duplicates));
+    }
+
+    if args.encrypt.is_some()
+        && args.outputs().iter().any(|(format, _)| {
+            !matches!(
+                format.builtin(),
+                Some(OutputFormat::Json | OutputFormat::Csv)
+            )
+        })
+    {
+        return Err(DuoloadError::Encryption(
+            "--encrypt is only supported for JSON and CSV output".to_string(),
+        ));
+    }
 
-    if let Some(path) = args.anki_file {
+    if let Some(source) = args.from_json.as_ref().or(args.from_csv.as_ref()) {
+        let mut client = if args.from_json.is_some() {
+            FileSourceClient::from_json(source)?
+        } else {
+            FileSourceClient::from_csv(source)?
+        };
+        info!("Read {} cards from {:?}", client.len(), source);
         if let Some(limit) = args.pages {
-            eprintln!(
-                "Exporting to Anki package '{:?}' (limited to {} pages)...",
-                path, limit
-            );
+            client = client.with_page_limit(limit);
+        }
+        // Without a deck ID the file name identifies the deck
+        let deck_id = args.deck_id.clone().unwrap_or_else(|| {
+            source
+                .file_stem()
+                .map_or_else(|| "file".to_string(), |stem| stem.to_string_lossy().into())
+        });
+        // Reading a file needs no pause between pages
+        args.page_delay = Duration::ZERO;
+        let processor = TransferProcessor::new(client, deck_id.clone());
+        return export_deck(processor, &deck_id, &args).await;
+    }
+
+    let deck_id = resolve_deck_id(&args).await?;
+    // Replaying the cache needs no pause between pages
+    if args.offline {
+        args.page_delay = Duration::ZERO;
+    }
+    if let Some(interval) = args.watch {
+        return watch(&args, &deck_id, interval).await;
+    }
+
+    let processor = TransferProcessor::new(api_client(&args)?, deck_id.clone());
+    export_deck(processor, &deck_id, &args).await
+}
+
+/// The client for exports from the Duocards API.
+fn api_client(args: &Args) -> Result<DuocardsClient> {
+    let mut client = match DuocardsClient::new_with_network(&args.network.options()) {
+        Ok(client) => client,
+        Err(e) => {
+            return Err(DuoloadError::Api(format!(
+                "Failed to initialize client: {}",
+                e
+            )));
+        }
+    };
+
+    // Set page limit if specified
+    if let Some(limit) = args.pages {
+        client = client.with_page_limit(limit);
+    }
+    client = client.with_retry_policy(RetryPolicy::new(args.retries, args.retry_delay));
+    if let Some(max_bytes) = args.max_bytes {
+        client = client.with_max_bytes(max_bytes);
+    }
+    client = args.auth.apply(client)?;
+    if let Some(cache) = page_cache(args) {
+        client = client.with_cache(cache);
+    }
+
+    #[cfg(feature = "testing")]
+    if let Some(faults) = args.inject_faults {
+        warn!("Injecting faults into requests: {}", faults);
+        client = client.with_faults(FaultInjector::new(faults));
+    }
+    Ok(client)
+}
+
+/// Exports the deck every `interval` for --watch, until Ctrl-C between two
+/// runs.
+///
+/// A failed run is logged and the next one starts on schedule, so a night
+/// without network does not end unattended backups.
+async fn watch(args: &Args, deck_id: &str, interval: Duration) -> Result<()> {
+    loop {
+        let started = Instant::now();
+        let processor = TransferProcessor::new(api_client(args)?, deck_id.to_string());
+        if let Err(e) = export_deck(processor, deck_id, args).await {
+            error!("Export failed, trying again at the next run: {}", e);
+        }
+
+        let wait = interval.saturating_sub(started.elapsed());
+        info!("Next export in {:.0}s", wait.as_secs_f64());
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = tokio::signal::ctrl_c() => {
+                info!("Stopped watching");
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Resolves `--format NAME` in the registry of `args`, checking that the
+/// format-specific options given apply to it.
+fn select_format(args: &mut Args, name: &str) -> Result<()> {
+    let format = args.registry.find(name)?.clone();
+    let builtin = format.builtin();
+    let only_for = |format: OutputFormat, options: &[(&str, bool)]| match options
+        .iter()
+        .find(|(_, given)| *given)
+    {
+        Some((option, _)) if builtin != Some(format) => Err(DuoloadError::Format(format!(
+            "{} cannot be used with --format {}",
+            option, name
+        ))),
+        _ => Ok(()),
+    };
+    only_for(
+        OutputFormat::Anki,
+        &[
+            ("--template-dir", args.template_dir.is_some()),
+            ("--escape-html", args.escape_html),
+            ("--allow-html", args.allow_html),
+            ("--tag", !args.tags.is_empty()),
+            ("--tag-prefix", args.tag_prefix != DEFAULT_TAG_PREFIX),
+            ("--tag-map", !args.tag_map.is_empty()),
+            ("--split-by-status", args.split_by_status),
+            ("--anki-source-fields", args.anki_source_fields),
+            ("--anki-status-fields", args.anki_status_fields),
+            ("--anki-hint-field", args.anki_hint_field),
+            ("--include-known-count", args.include_known_count),
+            (
+                "--anki-question-field",
+                args.anki_question_field != QuestionField::None,
+            ),
+            ("--images", args.images),
+            ("--audio", args.audio),
+            ("--merge-into", args.merge_into.is_some()),
+            ("--apkg-compression", args.apkg_compression.is_some()),
+            ("--apkg-scheme", args.apkg_scheme != ApkgScheme::Legacy),
+            ("--skip-media", args.skip_media),
+            ("--low-memory", args.low_memory),
+            ("--anki-collection", args.anki_collection),
+        ],
+    )?;
+    only_for(
+        OutputFormat::Csv,
+        &[
+            ("--csv-headers", args.csv_headers.is_some()),
+            ("--csv-delimiter", args.csv_delimiter != b','),
+            ("--csv-quoting", args.csv_quoting != CsvQuoting::Necessary),
+        ],
+    )?;
+    only_for(
+        OutputFormat::Markdown,
+        &[(
+            "--markdown-style",
+            args.markdown_style != MarkdownStyle::Table,
+        )],
+    )?;
+    only_for(
+        OutputFormat::Json,
+        &[
+            ("--ndjson", args.ndjson),
+            ("--json-metadata", args.json_metadata),
+            ("--json-envelope", args.json_envelope),
+            ("--json-compact", args.json_compact),
+            ("--json-pretty", args.json_pretty),
+            ("--validate-output", args.validate_output),
+        ],
+    )?;
+
+    let images = [
+        ("--download-images", args.download_images.is_some()),
+        ("--image-urls", args.image_urls),
+    ];
+    if let Some((option, _)) = images.iter().find(|(_, given)| *given)
+        && !matches!(
+            builtin,
+            Some(OutputFormat::Json | OutputFormat::Csv | OutputFormat::Yaml)
+        )
+    {
+        return Err(DuoloadError::Format(format!(
+            "{} cannot be used with --format {}",
+            option, name
+        )));
+    }
+
+    args.selected_format = Some(format);
+    Ok(())
+}
+
+/// Exports the cards of `processor` in the output format chosen on the command line.
+async fn export_deck<C: DuocardsClientTrait>(
+    processor: TransferProcessor<C>,
+    deck_id: &str,
+    args: &Args,
+) -> Result<()> {
+    let outputs = args.outputs();
+    if outputs.len() > 1 || args.sentences_deck.is_some() || args.split_by_status_files {
+        return export_outputs(processor, deck_id, args).await;
+    }
+    let (format, base) = outputs.into_iter().next().expect("an output was given");
+    let mut context = format_context(args, deck_id)?;
+    if let Some(limit) = args.pages {
+        info!("Limited to {} pages", limit);
+    }
+    match format.builtin() {
+        // The database is updated in place, so it is never dated or pruned
+        Some(OutputFormat::Sqlite) => {
+            info!("Exporting to SQLite database {:?}...", base);
+            let builder = format.new_builder(&context);
+            return export(processor, builder, None, base, args).await;
+        }
+        Some(OutputFormat::Json) if args.ndjson => {
+            let path = output_path(args, &base)?;
+            info!("Streaming JSON Lines to {:?}...", path);
+            export_ndjson(processor, path, args).await?;
+            return prune_exports(args, &base);
+        }
+        _ => {}
+    }
+    let path = output_path(args, &base)?;
+    if path.as_os_str() == "-" {
+        info!("Exporting to stdout...");
+    } else {
+        info!("Exporting to {} file {:?}...", format.name(), path);
+    }
+    // Media is only needed until the package is written
+    let media_dir = tempfile::tempdir()?;
+    let media = if format.builtin() == Some(OutputFormat::Anki) {
+        let (store, fetcher) = anki_media(args, media_dir.path())?;
+        context.anki.media = store;
+        fetcher
+    } else {
+        image_fetcher(args, &path)?
+    };
+    export_format(processor, format, context, media, path, args).await?;
+    prune_exports(args, &base)
+}
+
+/// The deck to export: the one given by `--deck-id`, or else one picked by the user.
+async fn resolve_deck_id(args: &Args) -> Result<String> {
+    let deck_id = match &args.deck_id {
+        Some(deck_id) => deck_id.clone(),
+        None => pick_deck(args).await?,
+    };
+    info!("Validating deck ID...");
+    match deck::parse_deck_id(&deck_id) {
+        Ok(deck_id) => Ok(deck_id),
+        Err(e) => Err(DuoloadError::Api(format!("Invalid deck ID: {}", e))),
+    }
+}
+
+/// Writes the cards of the deck unconverted, as the API returns them.
+async fn raw_export(args: &Args, base: &Path) -> Result<()> {
+    let deck_id = resolve_deck_id(args).await?;
+    let path = output_path(args, base)?;
+    let mut client = DuocardsClient::new_with_network(&args.network.options())?
+        .with_retry_policy(RetryPolicy::new(args.retries, args.retry_delay));
+    if let Some(limit) = args.pages {
+        client = client.with_page_limit(limit);
+    }
+    if let Some(max_bytes) = args.max_bytes {
+        client = client.with_max_bytes(max_bytes);
+    }
+    let mut client = args.auth.apply(client)?;
+    if let Some(cache) = page_cache(args) {
+        client = client.with_cache(cache);
+    }
+
+    info!("Exporting raw cards to {:?}...", path);
+    let snapshot = Snapshot::capture(&client, &deck_id, args.page_delay, &ConsoleReporter).await?;
+    let cards = raw::raw_cards(&snapshot.pages)?;
+    output::write_file_atomically(&path, |temp| {
+        raw::write_raw_cards(&cards, OutputDestination::File(temp))
+    })?;
+    info!("Wrote {} cards to {:?}", cards.len(), path);
+    prune_exports(args, base)
+}
+
+/// Whether the user can be asked questions on the terminal, which the
+/// dashboard and the status line take over while exporting.
+#[cfg_attr(not(feature = "tui"), allow(unused_variables))]
+fn can_prompt(args: &Args) -> bool {
+    #[cfg(feature = "tui")]
+    if args.tui || args.fancy {
+        return false;
+    }
+    std::io::stdin().is_terminal() && std::io::stderr().is_terminal()
+}
+
+/// Asks on the terminal where to write the output after writing it failed
+/// with `error`; an empty answer gives up.
+fn prompt_fallback_output(error: &DuoloadError) -> Option<PathBuf> {
+    let mut stderr = std::io::stderr();
+    writeln!(stderr, "Writing the output failed: {}", error).ok()?;
+    write!(stderr, "Path to write it to instead (empty to give up): ").ok()?;
+    stderr.flush().ok()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).ok()?;
+    let answer = answer.trim();
+    (!answer.is_empty()).then(|| PathBuf::from(answer))
+}
+
+/// Asks which deck of the logged-in account to export, for runs without `--deck-id`.
+async fn pick_deck(args: &Args) -> Result<String> {
+    if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+        return Err(DuoloadError::MissingSetting(
+            "deck ID (--deck-id is required when not running in a terminal)",
+        ));
+    }
+    let client = args
+        .auth
+        .apply(DuocardsClient::new_with_network(&args.network.options())?)?;
+    let decks = client.list_decks().await?;
+    if decks.is_empty() {
+        return Err(DuoloadError::Api("The account has no decks".to_string()));
+    }
+
+    #[cfg(feature = "tui")]
+    let deck = duoload::tui::pick_deck(&decks)?.ok_or(DuoloadError::Aborted)?;
+    #[cfg(not(feature = "tui"))]
+    let deck = picker::prompt_deck(&decks, std::io::stdin().lock(), std::io::stderr())?;
+
+    info!("Exporting deck {:?}", deck.name);
+    Ok(deck.id.clone())
+}
+
+/// Prints the decks of the logged-in account.
+async fn list_decks(args: &Args) -> Result<()> {
+    if !args.auth.is_given() {
+        return Err(AuthError::Unauthenticated.into());
+    }
+    let client = args
+        .auth
+        .apply(DuocardsClient::new_with_network(&args.network.options())?)?;
+    let decks = client.list_decks().await?;
+    if decks.is_empty() {
+        info!("The account has no decks");
+    }
+    for deck in decks {
+        let languages = match (deck.front, deck.back) {
+            (Some(front), Some(back)) => format!(" ({} -> {})", front, back),
+            _ => String::new(),
+        };
+        println!("{}\t{}{}", deck.id, deck.name, languages);
+    }
+    Ok(())
+}
+
+/// Sends one query to the API and prints how long the answer took, the
+/// release of the API and whether the credentials were accepted.
+async fn ping(args: PingArgs) -> Result<()> {
+    let client = DuocardsClient::new_with_network(&args.network.options())?
+        .with_retry_policy(RetryPolicy::none());
+    let credentials = args.auth.credentials(&client.base_url)?;
+    let client = match &credentials {
+        Some(credentials) => client.with_credentials(credentials.clone()),
+        None => client,
+    };
+    let status = client.ping().await?;
+    println!("api\t{}", client.base_url);
+    println!("latency\t{} ms", status.latency.as_millis());
+    println!(
+        "release\t{}",
+        status.release_id.as_deref().unwrap_or("unknown")
+    );
+    let auth = match status.authenticated {
+        None => "not configured",
+        Some(true) => "valid",
+        Some(false) => "rejected",
+    };
+    println!("auth\t{}", auth);
+    if let (Some(false), Some(credentials)) = (status.authenticated, &credentials) {
+        return Err(credentials.rejected().into());
+    }
+    Ok(())
+}
+
+/// The cache of fetched pages chosen with --cache-dir, --offline and
+/// --revalidate.
+fn page_cache(args: &Args) -> Option<PageCache> {
+    let dir = args.cache_dir.as_ref()?;
+    Some(
+        PageCache::new(dir)
+            .with_offline(args.offline)
+            .with_revalidation(args.revalidate),
+    )
+}
+
+/// Prints the size of the deck without downloading its cards.
+async fn count_only(args: &Args) -> Result<()> {
+    let deck_id = deck::parse_deck_id(args.deck_id.as_deref().expect("clap requires --deck-id"))?;
+    let client = DuocardsClient::new_with_network(&args.network.options())?
+        .with_retry_policy(RetryPolicy::new(args.retries, args.retry_delay));
+    let client = args.auth.apply(client)?;
+    let deck = client.fetch_deck_summary(&deck_id).await?;
+    if let Some(name) = &deck.name {
+        info!("Deck {:?}", name);
+    }
+    let pages = match args.pages {
+        Some(limit) => deck.pages().min(u64::from(limit)),
+        None => deck.pages(),
+    };
+    println!("cards\t{}", deck.cards_count);
+    println!("pages\t{}", pages);
+    Ok(())
+}
+
+/// Captures a snapshot bundle of a deck, or compares two bundles.
+async fn snapshot(args: SnapshotArgs) -> Result<()> {
+    if let Some(SnapshotCommand::Diff { before, after }) = args.command {
+        let diff = Snapshot::read(&before)?.diff(&Snapshot::read(&after)?)?;
+        println!("{}", diff);
+        return Ok(());
+    }
+
+    let deck_id = deck::parse_deck_id(&args.deck_id.expect("clap requires --deck-id"))?;
+    let output = args.output.expect("clap requires --output");
+    let mut client = args
+        .auth
+        .apply(DuocardsClient::new_with_network(&args.network.options())?)?;
+    if let Some(limit) = args.pages {
+        client = client.with_page_limit(limit);
+    }
+
+    info!("Capturing snapshot to {:?}...", output);
+    let snapshot = Snapshot::capture(&client, &deck_id, args.page_delay, &ConsoleReporter).await?;
+    snapshot.write(&output)?;
+    info!(
+        "Snapshot written: {} pages, {} cards",
+        snapshot.metadata.pages,
+        snapshot.cards.len()
+    );
+    Ok(())
+}
+
+/// Prints weekly learning progress across snapshot bundles.
+fn progress(bundles: &[PathBuf], format: ReportFormat) -> Result<()> {
+    let snapshots = bundles
+        .iter()
+        .map(|bundle| Snapshot::read(bundle))
+        .collect::<Result<Vec<_>>>()?;
+    if let Some(other) = snapshots
+        .iter()
+        .find(|snapshot| snapshot.metadata.deck_id != snapshots[0].metadata.deck_id)
+    {
+        return Err(DuoloadError::Snapshot(format!(
+            "Snapshots are of different decks: {} and {}",
+            snapshots[0].metadata.deck_id, other.metadata.deck_id
+        )));
+    }
+
+    let report = ProgressReport::from_snapshots(&snapshots)?;
+    match format {
+        ReportFormat::Table => println!("{}", report),
+        ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+    }
+    Ok(())
+}
+
+/// Prints what is in the Anki package at `package` and fails if it does not
+/// look like a duoload export.
+fn verify_apkg(package: &Path, tag_prefix: &str, format: ReportFormat) -> Result<()> {
+    let report = verify_package(package, tag_prefix)?;
+    match format {
+        ReportFormat::Table => println!("{}", report),
+        ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+    }
+    if !report.is_valid() {
+        return Err(DuoloadError::InvalidPackage(report.problems.len()));
+    }
+    Ok(())
+}
+
+/// Regenerates an output file from a snapshot bundle.
+fn restore(
+    bundle: &Path,
+    format: &RegisteredFormat,
+    deck_name: Option<String>,
+    output: &Path,
+    force: bool,
+    duplicates: DuplicatePolicy,
+) -> Result<()> {
+    let snapshot = Snapshot::read(bundle)?;
+    info!(
+        "Restoring deck {} captured at {}...",
+        snapshot.metadata.deck_id, snapshot.metadata.created_at
+    );
+    let context = FormatContext {
+        deck_id: snapshot.metadata.deck_id.clone(),
+        deck_name,
+        ..FormatContext::default()
+    };
+    restore_into(
+        &snapshot,
+        format.new_builder(&context),
+        output,
+        force,
+        duplicates,
+    )
+}
+
+fn restore_into<B: OutputBuilder>(
+    snapshot: &Snapshot,
+    mut builder: B,
+    output: &Path,
+    force: bool,
+    duplicates: DuplicatePolicy,
+) -> Result<()> {
+    // A database is updated in place, as by an export
+    check_overwrite(output, force || builder.writes_in_place())?;
+    builder.set_duplicate_policy(duplicates);
+    let stats = snapshot.restore(&mut builder)?;
+    if output.as_os_str() == "-" {
+        builder.write(OutputDestination::Writer(&mut std::io::stdout()))?;
+    } else if builder.writes_in_place() {
+        builder.write(OutputDestination::File(output))?;
+    } else {
+        output::write_atomically(&builder, output)?;
+    }
+    info!(
+        "Restored {} cards ({} duplicates skipped)",
+        stats.total_cards, stats.duplicates
+    );
+    Ok(())
+}
+
+/// The file to write, with a timestamp added for dated exports.
+///
+/// Fails if the file exists, or with --split-every any of its chunks,
+/// unless --force was given or the file is the package given to
+/// --merge-into.
+fn output_path(args: &Args, path: &Path) -> Result<PathBuf> {
+    let path = if args.dated && path.as_os_str() != "-" {
+        retention::dated_path(path, SystemTime::now())
+    } else {
+        path.to_path_buf()
+    };
+    if path.as_os_str() == "-" || args.force {
+        return Ok(path);
+    }
+    if args.split_every.is_some() {
+        if let Some((_, chunk)) = chunked::existing_chunks(&path)?.into_iter().next() {
+            return Err(DuoloadError::OutputExists(chunk));
+        }
+        return Ok(path);
+    }
+    // Updating a package in place is what --merge-into is for
+    let merging = args.merge_into.as_deref() == Some(path.as_path());
+    check_overwrite(&path, merging)?;
+    Ok(path)
+}
+
+/// Fails if the file at `path` exists, unless `force` is given or `path`
+/// is `-` for stdout.
+fn check_overwrite(path: &Path, force: bool) -> Result<()> {
+    if path.as_os_str() != "-" && !force && path.exists() {
+        return Err(DuoloadError::OutputExists(path.to_path_buf()));
+    }
+    Ok(())
+}
+
+/// Removes old dated exports of `base` according to --keep-last and --keep-days.
+fn prune_exports(args: &Args, base: &Path) -> Result<()> {
+    if base.as_os_str() == "-" {
+        return Ok(());
+    }
+    let policy = RetentionPolicy {
+        keep_last: args.keep_last,
+        keep_for: args
+            .keep_days
+            .map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+    };
+    for removed in retention::prune(base, &policy, SystemTime::now())? {
+        info!("Removed old export {:?}", removed);
+    }
+    Ok(())
+}
+
+/// Streams a JSON export as JSON Lines.
+async fn export_ndjson<C: DuocardsClientTrait>(
+    processor: TransferProcessor<C>,
+    path: PathBuf,
+    args: &Args,
+) -> Result<()> {
+    let builder = if path.as_os_str() == "-" {
+        JsonOutputBuilder::ndjson(std::io::stdout())
+    } else {
+        JsonOutputBuilder::ndjson_file(&path)?
+    }
+    .with_metadata_header(args.json_metadata)
+    .with_validation(args.validate_output);
+    let media = image_fetcher(args, &path)?;
+    export(processor, builder, media, path, args).await
+}
+
+/// Runs an export into builders of `format`, split into chunks for
+/// --split-every and into a file per language for --split-languages, and
+/// encrypted for --encrypt.
+async fn export_format<C: DuocardsClientTrait>(
+    processor: TransferProcessor<C>,
+    format: RegisteredFormat,
+    context: FormatContext,
+    media: Option<MediaFetcher>,
+    path: PathBuf,
+    args: &Args,
+) -> Result<()> {
+    // Anki packages put each language into a subdeck instead
+    #[cfg(feature = "lang")]
+    let split_languages = args.split_languages && format.builtin() != Some(OutputFormat::Anki);
+    let new_builder = move || format.new_builder(&context);
+    #[cfg(feature = "lang")]
+    if split_languages {
+        use duoload::output::languages::LanguageSplitBuilder;
+
+        if path.as_os_str() == "-" {
+            return Err(DuoloadError::SplitOutputNotSupported);
+        }
+        return match args.encrypt.clone() {
+            Some(encryption) => {
+                let builder = LanguageSplitBuilder::new(move || {
+                    EncryptedOutputBuilder::new(new_builder(), encryption.clone())
+                });
+                export(processor, builder, media, path, args).await
+            }
+            None => {
+                let builder = LanguageSplitBuilder::new(new_builder);
+                export(processor, builder, media, path, args).await
+            }
+        };
+    }
+
+    if let Some(size) = args.split_every {
+        if path.as_os_str() == "-" {
+            return Err(DuoloadError::SplitOutputNotSupported);
+        }
+        let size = size as usize;
+        return match args.encrypt.clone() {
+            Some(encryption) => {
+                let builder = ChunkedOutputBuilder::new(size, move || {
+                    EncryptedOutputBuilder::new(new_builder(), encryption.clone())
+                });
+                export(processor, builder, media, path, args).await
+            }
+            None => {
+                let builder = ChunkedOutputBuilder::new(size, new_builder);
+                export(processor, builder, media, path, args).await
+            }
+        };
+    }
+
+    match args.encrypt.clone() {
+        Some(encryption) => {
+            let builder = EncryptedOutputBuilder::new(new_builder(), encryption);
+            export(processor, builder, media, path, args).await
+        }
+        None => export(processor, new_builder(), media, path, args).await,
+    }
+}
+
+/// Runs one export into every output given on the command line, split into
+/// a file per status with --split-by-status-files, and the deck of
+/// --sentences-deck, fetching the deck only once.
+async fn export_outputs<C: DuocardsClientTrait>(
+    processor: TransferProcessor<C>,
+    deck_id: &str,
+    args: &Args,
+) -> Result<()> {
+    let unsupported = [
+        ("--ndjson", args.ndjson),
+        ("--encrypt", args.encrypt.is_some()),
+        ("--split-every", args.split_every.is_some()),
+        ("--fallback-output", args.fallback_output.is_some()),
+        #[cfg(feature = "lang")]
+        ("--split-languages", args.split_languages),
+    ];
+    if let Some((option, _)) = unsupported.iter().find(|(_, given)| *given) {
+        return Err(DuoloadError::Format(format!(
+            "{} cannot be used with several outputs",
+            option
+        )));
+    }
+    let outputs = args.outputs();
+    if outputs
+        .iter()
+        .filter(|(_, path)| path.as_os_str() == "-")
+        .count()
+        > 1
+    {
+        return Err(DuoloadError::Format(
+            "Only one output can be written to stdout".to_string(),
+        ));
+    }
+    if args.split_by_status_files && outputs.iter().any(|(_, path)| path.as_os_str() == "-") {
+        return Err(DuoloadError::Format(
+            "--split-by-status-files cannot write to stdout".to_string(),
+        ));
+    }
+    // Cards are cleaned up once for all outputs, formats that are not built
+    // in like JSON
+    let sanitize = |format: &RegisteredFormat| {
+        args.sanitize
+            .options(format.builtin().unwrap_or(OutputFormat::Json))
+    };
+    if outputs
+        .iter()
+        .any(|(format, _)| sanitize(format) != sanitize(&outputs[0].0))
+    {
+        return Err(DuoloadError::Format(
+            "--strip-html, --decode-entities and --trim-whitespace must apply to all outputs alike"
+                .to_string(),
+        ));
+    }
+
+    let mut context = format_context(args, deck_id)?;
+    let media_dir = tempfile::tempdir()?;
+    let mut media = None;
+    let anki = outputs
+        .iter()
+        .any(|(format, _)| format.builtin() == Some(OutputFormat::Anki));
+    if anki {
+        let (store, fetcher) = anki_media(args, media_dir.path())?;
+        context.anki.media = store;
+        media = fetcher;
+    }
+    let mut builder = MultiOutputBuilder::new();
+    let statuses: Vec<Option<LearningStatus>> = if args.split_by_status_files {
+        LearningStatus::ALL.into_iter().map(Some).collect()
+    } else {
+        vec![None]
+    };
+    for ((format, base), status) in outputs
+        .iter()
+        .flat_map(|output| statuses.iter().map(move |status| (output, status)))
+    {
+        let mut context = context.clone();
+        // Each status is imported as a deck of its own
+        if let Some(status) = status {
+            context.anki.name_suffix = format!(" {}", status.as_str());
+        }
+        let output = format.new_builder(&context);
+        // The database is updated in place, so it is never dated
+        let path = match (format.builtin(), status) {
+            (Some(OutputFormat::Sqlite), _) => base.clone(),
+            (_, Some(status)) => output_path(args, &status_path(base, status))?,
+            (_, None) => output_path(args, base)?,
+        };
+        info!("Exporting to {} output {:?}...", format.name(), path);
+        builder = match status {
+            Some(status) => builder.with_filtered_output(
+                output,
+                path,
+                CardFilter::new().with_status(status.clone()),
+            ),
+            None => builder.with_output(output, path),
+        };
+    }
+    if let Some(base) = &args.sentences_deck {
+        // The package escapes the sentence before the word is marked in bold
+        let (format, escape_html) = if anki {
+            (OutputFormat::Anki, !args.allow_html)
         } else {
-            eprintln!("Exporting to Anki package '{:?}'...", path);
+            (OutputFormat::Json, false)
+        };
+        let mut context = context.clone();
+        context.anki.name_suffix = " sentences".to_string();
+        context.anki.escape_html = false;
+        context.anki.existing = None;
+        let output = args.registry.builtin(format).new_builder(&context);
+        let path = output_path(args, base)?;
+        info!("Exporting example sentences to {:?}...", path);
+        let extractor = SentenceExtractor::new().with_html_escaping(escape_html);
+        builder = builder.with_transformed_output(output, path, extractor);
+    }
+    if let Some(limit) = args.pages {
+        info!("Limited to {} pages", limit);
+    }
+    // Picture paths are relative to the first output
+    if media.is_none() {
+        media = image_fetcher(args, &outputs[0].1)?;
+    }
+
+    export(processor, builder, media, PathBuf::new(), args).await?;
+    for (format, base) in &outputs {
+        if format.builtin() == Some(OutputFormat::Sqlite) {
+            continue;
         }
-        let mut processor = processor.output(AnkiPackageBuilder::new("Duocards Vocabulary"), path);
-        processor.process().await?;
-    } else if args.json {
-        if let Some(limit) = args.pages {
-            eprintln!("Exporting to stdout (limited to {} pages)...", limit);
+        if args.split_by_status_files {
+            for status in LearningStatus::ALL {
+                prune_exports(args, &status_path(base, &status))?;
+            }
         } else {
-            eprintln!("Exporting to stdout...");
+            prune_exports(args, base)?;
         }
-        let mut processor = processor.output(JsonOutputBuilder::new(), PathBuf::from("-"));
-        processor.process().await?;
-    } else {
-        let path = args.json_file.unwrap();
-        if let Some(limit) = args.pages {
-            eprintln!(
-                "Exporting to JSON file {:?} (limited to {} pages)...",
-                path, limit
+    }
+    if let Some(base) = &args.sentences_deck {
+        prune_exports(args, base)?;
+    }
+    Ok(())
+}
+
+/// Settings of the built-in formats given on the command line, opening the
+/// package given to --merge-into, if any.
+fn format_context(args: &Args, deck_id: &str) -> Result<FormatContext> {
+    let templates = match &args.template_dir {
+        Some(dir) => Some(CardTemplates::from_dir(dir)?),
+        None => None,
+    };
+    let existing = match &args.merge_into {
+        Some(path) => {
+            let package = ExistingPackage::open(path)?;
+            info!(
+                "Merging into {:?} with {} existing notes...",
+                path,
+                package.notes.len()
             );
-        } else {
-            eprintln!("Exporting to JSON file {:?}...", path);
+            Some(Arc::new(package))
+        }
+        None => None,
+    };
+    Ok(FormatContext {
+        deck_id: deck_id.to_string(),
+        deck_name: args.deck_name.clone(),
+        deterministic: args.deterministic,
+        provenance: args.provenance,
+        anki: AnkiSettings {
+            deck_id: args.anki_deck_id,
+            model_id: args.anki_model_id,
+            templates,
+            tags: args.tags.clone(),
+            tag_scheme: TagScheme {
+                prefix: args.tag_prefix.clone(),
+                status_names: args.tag_map.iter().cloned().collect(),
+            },
+            status_subdecks: args.split_by_status,
+            source_fields: args.anki_source_fields,
+            status_fields: args.anki_status_fields,
+            hint_field: args.anki_hint_field,
+            known_count_field: args.include_known_count,
+            question_field: args.anki_question_field,
+            escape_html: !args.allow_html,
+            package_options: ApkgOptions {
+                compression: args.apkg_compression,
+                media: !args.skip_media,
+                scheme: args.apkg_scheme,
+                deterministic: args.deterministic,
+            },
+            disk_store: args.low_memory,
+            collection: args.anki_collection,
+            media: None,
+            existing,
+            name_suffix: String::new(),
+            #[cfg(feature = "lang")]
+            language_subdecks: args.split_languages,
+            #[cfg(feature = "lang")]
+            language_tags: args.detect_languages,
+        },
+        json: JsonSettings {
+            metadata_header: args.json_metadata,
+            envelope: args.json_envelope,
+            pretty: !args.json_compact,
+            validate: args.validate_output,
+            #[cfg(feature = "lang")]
+            languages: args.detect_languages,
+        },
+        csv: CsvSettings {
+            headers: args.csv_headers.clone().map(|CsvHeaders(headers)| headers),
+            delimiter: args.csv_delimiter,
+            quoting: args.csv_quoting,
+            image_column: args.download_images.is_some() || args.image_urls,
+        },
+        markdown_style: args.markdown_style,
+    })
+}
+
+/// The media store in `dir` and its fetcher, if --images or --audio was given.
+fn anki_media(args: &Args, dir: &Path) -> Result<(Option<MediaStore>, Option<MediaFetcher>)> {
+    if !args.images && !args.audio {
+        return Ok((None, None));
+    }
+    let store = MediaStore::new(dir);
+    let fetcher = MediaFetcher::new_with_network(store.clone(), &args.network.options())?
+        .with_images(args.images)
+        .with_audio(args.audio);
+    Ok((Some(store), Some(fetcher)))
+}
+
+/// Downloads card pictures for --download-images, linked relative to the
+/// directory of the output at `path` unless --image-urls was given.
+fn image_fetcher(args: &Args, path: &Path) -> Result<Option<MediaFetcher>> {
+    let Some(dir) = &args.download_images else {
+        return Ok(None);
+    };
+    std::fs::create_dir_all(dir)?;
+    info!("Downloading card pictures into {:?}", dir);
+    let fetcher = MediaFetcher::new_with_network(MediaStore::new(dir), &args.network.options())?;
+    if args.image_urls {
+        return Ok(Some(fetcher));
+    }
+    Ok(Some(
+        fetcher.with_links(path.parent().unwrap_or(Path::new(""))),
+    ))
+}
+
+/// Stops fetching on the first Ctrl-C, so the cards fetched so far are still
+/// written, and exits on the second.
+async fn handle_interrupts(control: TransferControl) {
+    if tokio::signal::ctrl_c().await.is_err() {
+        return;
+    }
+    warn!("Interrupted, writing the cards fetched so far. Press Ctrl-C again to quit");
+    control.stop();
+    if tokio::signal::ctrl_c().await.is_ok() {
+        error!("Interrupted again, quitting without writing the output");
+        std::process::exit(130);
+    }
+}
+
+/// Adds the statistics recorder and the webhook, if any, to a progress reporter.
+fn with_recorder(
+    reporter: Arc<dyn ProgressReporter>,
+    recorder: &Option<Arc<StatsRecorder>>,
+    webhook: &Option<Arc<WebhookReporter>>,
+) -> Arc<dyn ProgressReporter> {
+    if recorder.is_none() && webhook.is_none() {
+        return reporter;
+    }
+    let mut reporters = vec![reporter];
+    if let Some(recorder) = recorder {
+        reporters.push(recorder.clone());
+    }
+    if let Some(webhook) = webhook {
+        reporters.push(webhook.clone());
+    }
+    Arc::new(ProgressReporters(reporters))
+}
+
+fn write_stats(stats: &RunStats, args: &Args) -> Result<()> {
+    if let Some(path) = &args.stats_file {
+        let mut writer = BufWriter::new(std::fs::File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, stats)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        info!("Statistics written to {:?}", path);
+    }
+    if args.stats_json {
+        eprintln!("{}", serde_json::to_string(stats)?);
+    }
+    Ok(())
+}
+
+/// Starts the `--fancy` status line, unless stderr is not a terminal that can
+/// show it; plain log lines are written then.
+#[cfg(feature = "tui")]
+fn status_line(args: &Args) -> Option<(duoload::tui::TuiReporter, duoload::tui::StatusLine)> {
+    if !args.fancy || !std::io::stderr().is_terminal() {
+        return None;
+    }
+    match duoload::tui::StatusLine::start() {
+        Ok(started) => Some(started),
+        Err(e) => {
+            warn!(
+                "Cannot show the status line, logging progress instead: {}",
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Runs the export into `builder` with the options shared by every output format.
+async fn export<C: DuocardsClientTrait, B: OutputBuilder + 'static>(
+    processor: TransferProcessor<C>,
+    builder: B,
+    media: Option<MediaFetcher>,
+    path: PathBuf,
+    args: &Args,
+) -> Result<()> {
+    let recorder = (args.stats_file.is_some() || args.stats_json).then(|| {
+        let mut recorder = StatsRecorder::new();
+        if let Some(requests) = processor.client().request_stats() {
+            recorder = recorder.with_request_stats(requests);
+        }
+        Arc::new(recorder)
+    });
+    let control = TransferControl::new();
+    let interrupts = tokio::spawn(handle_interrupts(control.clone()));
+    let webhook = match &args.progress_webhook {
+        Some(url) => Some(Arc::new(WebhookReporter::new(
+            url.clone(),
+            &args.network.options(),
+        )?)),
+        None => None,
+    };
+    let mut processor = processor
+        .output(builder, &path)
+        .with_channel_capacity(args.buffer_pages)
+        .with_concurrency(args.concurrency)
+        .with_page_delay(args.page_delay)
+        .with_auto_retry(args.auto_retry, DEFAULT_AUTO_RETRY_DELAY)
+        .with_duplicate_policy(args.duplicates)
+        .with_dedupe_key(args.dedupe_key)
+        .with_sort_order(args.sort_order()?)
+        .with_transforms(args.transforms())
+        .with_provenance(args.provenance)
+        .with_verification(!args.no_verify)
+        .with_control(control.clone());
+    if let Some(max_cards) = args.max_cards {
+        processor = processor.with_max_cards(max_cards as usize);
+    }
+    if let Some(state) = &args.resume {
+        processor = processor.with_checkpoint(state);
+    }
+    if args.skip_bad_cards {
+        processor = processor.with_skip_bad_cards(&args.errors_file);
+    }
+    if let Some(store) = args.dedupe_store()? {
+        processor = processor.with_dedupe_store(store);
+    }
+    if let Some(fetcher) = media {
+        processor = processor.with_media_fetcher(fetcher);
+    }
+    if let Some(fallback) = &args.fallback_output {
+        processor = processor.with_fallback_output(fallback);
+    }
+    // Several outputs have paths of their own, which cannot be replaced
+    if !path.as_os_str().is_empty() && can_prompt(args) {
+        processor = processor.with_fallback_prompt(Arc::new(prompt_fallback_output));
+    }
+    let deck_id = processor.deck_id().to_string();
+
+    #[cfg(feature = "tui")]
+    let result = if args.tui {
+        let (reporter, dashboard) = duoload::tui::Dashboard::start(control);
+        let mut processor = processor.with_progress_reporter(with_recorder(
+            Arc::new(reporter),
+            &recorder,
+            &webhook,
+        ));
+        let result = processor.process().await;
+        let written = (
+            processor.stats().clone(),
+            processor.output_path().to_path_buf(),
+            processor.written_files(),
+        );
+        // Dropping the processor drops the reporter, which closes the dashboard
+        drop(processor);
+        dashboard.finish()?;
+        result.map(|()| written)
+    } else if let Some((reporter, status)) = status_line(args) {
+        let mut processor = processor.with_progress_reporter(with_recorder(
+            Arc::new(reporter),
+            &recorder,
+            &webhook,
+        ));
+        let result = processor.process().await;
+        let written = (
+            processor.stats().clone(),
+            processor.output_path().to_path_buf(),
+            processor.written_files(),
+        );
+        // Dropping the processor drops the reporter, which stops the status line
+        drop(processor);
+        // The status line is cosmetic; the export went through either way
+        if let Err(e) = status.finish() {
+            warn!("Status line failed: {}", e);
+        }
+        result.map(|()| written)
+    } else {
+        let mut processor = processor.with_progress_reporter(with_recorder(
+            Arc::new(ConsoleReporter),
+            &recorder,
+            &webhook,
+        ));
+        let result = processor.process().await;
+        result.map(|()| {
+            (
+                processor.stats().clone(),
+                processor.output_path().to_path_buf(),
+                processor.written_files(),
+            )
+        })
+    };
+    #[cfg(not(feature = "tui"))]
+    let result = {
+        let mut processor = processor.with_progress_reporter(with_recorder(
+            Arc::new(ConsoleReporter),
+            &recorder,
+            &webhook,
+        ));
+        let result = processor.process().await;
+        result.map(|()| {
+            (
+                processor.stats().clone(),
+                processor.output_path().to_path_buf(),
+                processor.written_files(),
+            )
+        })
+    };
+
+    interrupts.abort();
+
+    if let Some(webhook) = &webhook {
+        let error = result.as_ref().err().map(ToString::to_string);
+        webhook.finish(error).await;
+    }
+    // Failed runs are recorded too, so scripts can tell what went wrong
+    if let Some(recorder) = &recorder {
+        let error = result.as_ref().err().map(ToString::to_string);
+        let mut run_stats = recorder.finish(error);
+        // Only the processor sees the cards behind the counts
+        if let Ok((stats, _, _)) = &result {
+            run_stats.transfer = stats.clone();
+        }
+        write_stats(&run_stats, args)?;
+    }
+    // The output went to a fallback path if writing to its own path failed
+    let (stats, path, files) = result?;
+
+    if args.stats {
+        eprint!("{}", summary_table(&stats));
+    }
+
+    // Several outputs have no path of their own; the manifest goes next to the first
+    let files: Vec<PathBuf> = files
+        .into_iter()
+        .filter(|file| file.as_os_str() != "-")
+        .collect();
+    if let (true, Some(first)) = (args.manifest, files.first()) {
+        let mut manifest = Manifest::new(&deck_id, &stats);
+        for file in &files {
+            manifest.add_file(file)?;
         }
-        let mut processor = processor.output(JsonOutputBuilder::new(), path);
-        processor.process().await?;
+        let manifest_path = if path.as_os_str().is_empty() {
+            manifest::manifest_path(first)
+        } else {
+            manifest::manifest_path(&path)
+        };
+        manifest.write(&manifest_path)?;
+        info!("Manifest written to {:?}", manifest_path);
     }
 
     Ok(())
@@ -0,0 +1,270 @@
+//! Card pictures, pronunciations and other media files.
+//!
+//! Media referenced by cards is downloaded into a [`MediaStore`] directory
+//! while the deck is transferred, so output builders can embed the files
+//! without touching the network themselves.
+
+use crate::duocards::models::VocabularyCard;
+use crate::duocards::network::NetworkOptions;
+use crate::error::{DuoloadError, Result};
+use crate::output::manifest::hex_digest;
+use reqwest::Client;
+use sha2::{Digest, Sha256};
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The kinds of media a card can reference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaKind {
+    Image,
+    Audio,
+}
+
+impl MediaKind {
+    /// Extension used when a URL does not reveal the file type; Duocards
+    /// pictures are SVGs and pronunciations MP3s.
+    fn default_extension(self) -> &'static str {
+        match self {
+            MediaKind::Image => "svg",
+            MediaKind::Audio => "mp3",
+        }
+    }
+}
+
+/// A directory holding downloaded media, one file per URL.
+#[derive(Debug, Clone)]
+pub struct MediaStore {
+    dir: PathBuf,
+}
+
+impl MediaStore {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Stable file name for the media at `url`, e.g. `duoload-1a2b3c4d5e6f7a8b.svg`.
+    ///
+    /// Anki keeps all media in one flat folder, so names are derived from a
+    /// hash of the URL to stay unique across decks.
+    pub fn file_name(url: &str, kind: MediaKind) -> String {
+        let path = url.split(['?', '#']).next().unwrap_or(url);
+        let extension = path
+            .rsplit('/')
+            .next()
+            .and_then(|name| name.rsplit_once('.'))
+            .map(|(_, extension)| extension.to_ascii_lowercase())
+            .filter(|extension| {
+                !extension.is_empty() && extension.chars().all(|c| c.is_ascii_alphanumeric())
+            })
+            .unwrap_or_else(|| kind.default_extension().to_string());
+        let hash = hex_digest(Sha256::digest(url.as_bytes()).as_slice());
+        format!("duoload-{}.{}", &hash[..16], extension)
+    }
+
+    /// Where the media at `url` is stored once downloaded.
+    pub fn path(&self, url: &str, kind: MediaKind) -> PathBuf {
+        self.dir.join(Self::file_name(url, kind))
+    }
+
+    /// The downloaded file for `url`, if there is one.
+    pub fn get(&self, url: &str, kind: MediaKind) -> Option<PathBuf> {
+        let path = self.path(url, kind);
+        path.is_file().then_some(path)
+    }
+}
+
+/// Downloads media into a [`MediaStore`].
+///
+/// Card pictures are downloaded by default, pronunciations only when
+/// enabled with [`MediaFetcher::with_audio`].
+#[derive(Debug, Clone)]
+pub struct MediaFetcher {
+    client: Client,
+    store: MediaStore,
+    images: bool,
+    audio: bool,
+    /// Directory the downloaded pictures are linked relative to
+    links: Option<PathBuf>,
+}
+
+impl MediaFetcher {
+    pub fn new(store: MediaStore) -> Result<Self> {
+        Self::new_with_network(store, &NetworkOptions::default())
+    }
+
+    /// Creates a fetcher that connects through the given proxy and TLS settings.
+    pub fn new_with_network(store: MediaStore, network: &NetworkOptions) -> Result<Self> {
+        let builder = Client::builder()
+            .user_agent(concat!("duoload/", env!("CARGO_PKG_VERSION")))
+            .timeout(DOWNLOAD_TIMEOUT);
+        let client = network.apply(builder)?.build()?;
+        Ok(Self {
+            client,
+            store,
+            images: true,
+            audio: false,
+            links: None,
+        })
+    }
+
+    pub fn with_images(mut self, enabled: bool) -> Self {
+        self.images = enabled;
+        self
+    }
+
+    pub fn with_audio(mut self, enabled: bool) -> Self {
+        self.audio = enabled;
+        self
+    }
+
+    /// The media of `card` this fetcher downloads.
+    pub fn media_of<'a>(&self, card: &'a VocabularyCard) -> Vec<(&'a str, MediaKind)> {
+        let image = card.image.as_deref().filter(|_| self.images);
+        let audio = card.audio.as_deref().filter(|_| self.audio);
+        image
+            .map(|url| (url, MediaKind::Image))
+            .into_iter()
+            .chain(audio.map(|url| (url, MediaKind::Audio)))
+            .collect()
+    }
+
+    /// Points the image of each card at its downloaded picture, as a path
+    /// relative to `base`, e.g. the directory of the output file, so apps
+    /// reading the output can show pictures without the network. Cards
+    /// whose picture could not be downloaded keep its URL.
+    pub fn with_links<P: AsRef<Path>>(mut self, base: P) -> Self {
+        self.links = Some(base.as_ref().to_path_buf());
+        self
+    }
+
+    /// `card` with its image pointing at the downloaded picture, if links
+    /// are enabled with [`MediaFetcher::with_links`].
+    pub fn link(&self, mut card: VocabularyCard) -> VocabularyCard {
+        if let Some(base) = &self.links
+            && let Some(url) = &card.image
+            && let Some(path) = self.store.get(url, MediaKind::Image)
+        {
+            card.image = Some(relative_path(&path, base));
+        }
+        card
+    }
+
+    pub fn store(&self) -> &MediaStore {
+        &self.store
+    }
+
+    /// Downloads the media at `url` unless it is already in the store.
+    pub async fn fetch(&self, url: &str, kind: MediaKind) -> Result<PathBuf> {
+        if let Some(path) = self.store.get(url, kind) {
+            return Ok(path);
+        }
+
+        let response = self.client.get(url).send().await?;
+        if !response.status().is_success() {
+            return Err(DuoloadError::Api(format!(
+                "Media download failed with status {}: {}",
+                response.status(),
+                url
+            )));
+        }
+        let content = response.bytes().await?;
+
+        // Written under a temporary name so an interrupted download is never mistaken for a complete one
+        std::fs::create_dir_all(&self.store.dir)?;
+        let path = self.store.path(url, kind);
+        let mut partial = path.clone().into_os_string();
+        partial.push(".part");
+        std::fs::write(&partial, &content)?;
+        std::fs::rename(&partial, &path)?;
+        Ok(path)
+    }
+}
+
+/// `path` relative to the directory `base`, with `/` between the parts
+/// as in URLs, e.g. `../images/duoload-1a2b3c4d5e6f7a8b.svg`.
+fn relative_path(path: &Path, base: &Path) -> String {
+    let absolute = |path: &Path| {
+        let path = if path.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            path
+        };
+        path.canonicalize()
+            .unwrap_or_else(|_| std::env::current_dir().unwrap_or_default().join(path))
+    };
+    let (path, base) = (absolute(path), absolute(base));
+    let path: Vec<Component> = path.components().collect();
+    let base: Vec<Component> = base.components().collect();
+    let common = path.iter().zip(&base).take_while(|(a, b)| a == b).count();
+    let mut parts: Vec<String> = vec!["..".to_string(); base.len() - common];
+    parts.extend(
+        path[common..]
+            .iter()
+            .map(|part| part.as_os_str().to_string_lossy().into_owned()),
+    );
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relative_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let images = dir.path().join("site/images");
+        let data = dir.path().join("site/data");
+        std::fs::create_dir_all(&images).unwrap();
+        std::fs::create_dir_all(&data).unwrap();
+        let picture = images.join("duoload-1.svg");
+        std::fs::write(&picture, "<svg/>").unwrap();
+
+        assert_eq!(relative_path(&picture, &data), "../images/duoload-1.svg");
+        assert_eq!(
+            relative_path(&picture, &dir.path().join("site")),
+            "images/duoload-1.svg"
+        );
+    }
+
+    #[test]
+    fn test_file_name() {
+        let name = MediaStore::file_name(
+            "https://cdn.duocards.com/svg/house.SVG?v=2",
+            MediaKind::Image,
+        );
+        assert!(name.starts_with("duoload-"));
+        assert!(name.ends_with(".svg"));
+        assert_eq!(
+            name,
+            MediaStore::file_name(
+                "https://cdn.duocards.com/svg/house.SVG?v=2",
+                MediaKind::Image
+            )
+        );
+        assert_ne!(
+            name,
+            MediaStore::file_name("https://cdn.duocards.com/svg/dog.svg", MediaKind::Image)
+        );
+
+        assert!(
+            MediaStore::file_name("https://example.com/picture.png", MediaKind::Image)
+                .ends_with(".png")
+        );
+        assert!(
+            MediaStore::file_name("https://example.com/picture", MediaKind::Image)
+                .ends_with(".svg")
+        );
+        assert!(
+            MediaStore::file_name("https://example.com/v1.2/picture", MediaKind::Image)
+                .ends_with(".svg")
+        );
+        assert!(
+            MediaStore::file_name("https://example.com/tts/haus", MediaKind::Audio)
+                .ends_with(".mp3")
+        );
+    }
+}
@@ -1,20 +1,89 @@
-use crate::anki::note::{VocabularyNote, create_vocabulary_model};
-use crate::duocards::models::VocabularyCard;
+use crate::anki::collection::CardSchedule;
+use crate::anki::note::{
+    CardTemplates, ExtraFields, QuestionField, TagScheme, VocabularyNote, create_vocabulary_model,
+    create_vocabulary_model_with_fields, invalid_card_reason, note_guid, tag,
+};
+use crate::anki::package::{ExistingPackage, PackageCollection, PackageNote};
+use crate::anki::writer::{ApkgOptions, BatchedPackage};
+use crate::duocards::models::{DeckMetadata, LearningStatus, VocabularyCard};
 use crate::error::{DuoloadError, Result};
+use crate::media::{MediaKind, MediaStore};
+use crate::output::sort::SortOrder;
+use crate::output::store::CardStore;
+use crate::output::timestamp::UtcDateTime;
 use crate::output::{OutputBuilder, OutputDestination};
-use genanki_rs::Deck;
-use std::collections::HashSet;
+use crate::transfer::DuplicateHandler;
+use crate::transfer::duplicates::{DedupeKey, DuplicatePolicy};
+use crate::transfer::key_store::DedupeStore;
+use crate::transform::sanitize::decode_entities;
+use genanki_rs::{Deck, Note};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Deck name used unless another one is given
+pub const DEFAULT_DECK_NAME: &str = "Duocards Vocabulary";
+
+/// Deck ID - fixed for consistency
+pub const DECK_ID: i64 = 2059400110;
+
+/// Number of fields of the vocabulary model without [`ExtraFields`].
+const MODEL_FIELDS: usize = 5;
+
+/// Cards turned into notes at a time when they are kept in a [`CardStore`].
+pub const STORE_BATCH_SIZE: usize = 1000;
 
 /// Builder for creating Anki packages from vocabulary cards.
 ///
 /// This struct manages the creation of an Anki package, handling:
 /// - Deck creation and configuration
-/// - Note addition with duplicate detection
+/// - Card collection with duplicate resolution
 /// - Package file generation
+///
+/// Notes are generated when the package is written, so a duplicate policy
+/// can still replace or merge cards that were added earlier. The cards are
+/// kept in memory, or with [`AnkiPackageBuilder::with_disk_store`] on disk.
 pub struct AnkiPackageBuilder {
-    pub deck: Deck,
+    pub deck_name: String,
     pub model: genanki_rs::Model,
-    existing_words: HashSet<String>,
+    /// ID given to [`AnkiPackageBuilder::with_model_id`], or `None` for the
+    /// one of the extra fields
+    model_id: Option<i64>,
+    /// Custom templates, or `None` for the default ones
+    templates: Option<CardTemplates>,
+    question: QuestionField,
+    deck_id: i64,
+    cards: Vec<VocabularyCard>,
+    disk_store: bool,
+    batch_size: usize,
+    /// Keeps the cards instead of `cards` once the first one is added
+    store: Option<CardStore>,
+    duplicates: DuplicateHandler,
+    sort: SortOrder,
+    media: Option<MediaStore>,
+    existing: Option<Arc<ExistingPackage>>,
+    tags: Vec<String>,
+    tag_scheme: TagScheme,
+    exported: UtcDateTime,
+    name_from_deck: bool,
+    /// Appended to the deck name, see [`AnkiPackageBuilder::with_name_suffix`]
+    name_suffix: String,
+    /// ID of the exported Duocards deck, for the note GUIDs
+    source_deck_id: String,
+    status_subdecks: bool,
+    extra_fields: ExtraFields,
+    escape_html: bool,
+    package_options: ApkgOptions,
+    collection: bool,
+    #[cfg(feature = "lang")]
+    language_subdecks: bool,
+    #[cfg(feature = "lang")]
+    language_tags: bool,
 }
 
 impl AnkiPackageBuilder {
@@ -28,55 +97,651 @@ impl AnkiPackageBuilder {
     ///
     /// A new AnkiPackageBuilder instance configured with the specified deck name.
     pub fn new(deck_name: &str) -> Self {
-        let model = create_vocabulary_model();
+        Self {
+            deck_name: deck_name.to_string(),
+            model: create_vocabulary_model(),
+            model_id: None,
+            templates: None,
+            question: QuestionField::None,
+            deck_id: DECK_ID,
+            cards: Vec::new(),
+            disk_store: false,
+            batch_size: STORE_BATCH_SIZE,
+            store: None,
+            duplicates: DuplicateHandler::new(),
+            sort: SortOrder::default(),
+            media: None,
+            existing: None,
+            tags: Vec::new(),
+            tag_scheme: TagScheme::default(),
+            exported: UtcDateTime::from_system_time(SystemTime::now()),
+            name_from_deck: false,
+            name_suffix: String::new(),
+            source_deck_id: String::new(),
+            status_subdecks: false,
+            extra_fields: ExtraFields::default(),
+            escape_html: true,
+            package_options: ApkgOptions::default(),
+            collection: false,
+            #[cfg(feature = "lang")]
+            language_subdecks: false,
+            #[cfg(feature = "lang")]
+            language_tags: false,
+        }
+    }
+
+    /// Names the deck like the exported Duocards deck once its metadata is
+    /// known, keeping the name given to [`AnkiPackageBuilder::new`] for
+    /// decks without a name.
+    pub fn with_name_from_deck(mut self, enabled: bool) -> Self {
+        self.name_from_deck = enabled;
+        self
+    }
 
-        let deck = Deck::new(
-            2059400110, // Deck ID - fixed for consistency
-            deck_name,
+    /// Appends `suffix` to the name of the deck, e.g. for a deck of the
+    /// example sentences of another one. The deck gets an ID derived from
+    /// the suffix, so Anki imports it next to the other deck.
+    pub fn with_name_suffix(mut self, suffix: &str) -> Self {
+        self.name_suffix = suffix.to_string();
+        self
+    }
+
+    /// Writes the deck under `deck_id` instead of the default [`DECK_ID`].
+    ///
+    /// Anki identifies decks by ID, so exports with different IDs are
+    /// imported as separate decks.
+    pub fn with_deck_id(mut self, deck_id: i64) -> Self {
+        self.deck_id = deck_id;
+        self
+    }
+
+    /// Writes the notes with a vocabulary model under `model_id`, instead
+    /// of the ID derived from the extra fields, see [`ExtraFields::model_id`].
+    pub fn with_model_id(mut self, model_id: i64) -> Self {
+        self.model_id = Some(model_id);
+        self.update_model();
+        self
+    }
+
+    /// Styles the cards with custom templates and CSS.
+    ///
+    /// Custom templates are used as they are, whatever the
+    /// [`AnkiPackageBuilder::with_question_field`].
+    pub fn with_templates(mut self, templates: CardTemplates) -> Self {
+        self.templates = Some(templates);
+        self.update_model();
+        self
+    }
+
+    /// Adds hidden `SourceId`, `Created` and `WaitingUntil` fields with the
+    /// Duocards ID and timestamps of each card to the note type.
+    ///
+    /// Anki cannot import notes whose note type has other fields than an
+    /// earlier import with the same model ID, so unless
+    /// [`AnkiPackageBuilder::with_model_id`] is given, the note type gets an
+    /// ID of its own for each set of extra fields.
+    pub fn with_source_fields(mut self, enabled: bool) -> Self {
+        self.extra_fields.source = enabled;
+        self.update_model();
+        self
+    }
+
+    /// Adds hidden `Status` and `Priority` fields with the Duocards learning
+    /// status and study priority of each card to the note type, after the
+    /// source fields. Like [`AnkiPackageBuilder::with_source_fields`], this
+    /// changes the fields of the note type.
+    pub fn with_status_fields(mut self, enabled: bool) -> Self {
+        self.extra_fields.status = enabled;
+        self.update_model();
+        self
+    }
+
+    /// Adds a `Hint` field with the hint of each card to the note type,
+    /// after the status fields, which the default templates show on the
+    /// back. Like [`AnkiPackageBuilder::with_source_fields`], this changes
+    /// the fields of the note type.
+    pub fn with_hint_field(mut self, enabled: bool) -> Self {
+        self.extra_fields.hint = enabled;
+        self.update_model();
+        self
+    }
+
+    /// Adds a hidden `Provenance` field with the deck, page and time each
+    /// card was fetched from to the note type, after all other fields. Like
+    /// [`AnkiPackageBuilder::with_source_fields`], this changes the fields of
+    /// the note type.
+    pub fn with_provenance_field(mut self, enabled: bool) -> Self {
+        self.extra_fields.provenance = enabled;
+        self.update_model();
+        self
+    }
+
+    /// Adds a hidden `KnownCount` field with how often each card was
+    /// answered correctly in Duocards to the note type, after the
+    /// provenance field. Like [`AnkiPackageBuilder::with_source_fields`],
+    /// this changes the fields of the note type.
+    pub fn with_known_count_field(mut self, enabled: bool) -> Self {
+        self.extra_fields.known_count = enabled;
+        self.update_model();
+        self
+    }
+
+    /// Shows the hint or example below the word on the front of the
+    /// default templates. Showing the hint adds the `Hint` field.
+    pub fn with_question_field(mut self, field: QuestionField) -> Self {
+        self.question = field;
+        if field == QuestionField::Hint {
+            self.extra_fields.hint = true;
+        }
+        self.update_model();
+        self
+    }
+
+    /// Escapes the fields of new notes so they are shown as text (the
+    /// default), or with `false` keeps them as HTML for the cards to render,
+    /// e.g. the `<b>` in examples.
+    pub fn with_html_escaping(mut self, enabled: bool) -> Self {
+        self.escape_html = enabled;
+        self
+    }
+
+    fn update_model(&mut self) {
+        let templates = self
+            .templates
+            .clone()
+            .unwrap_or_else(|| CardTemplates::for_fields(self.extra_fields, self.question));
+        let model_id = self
+            .model_id
+            .unwrap_or_else(|| self.extra_fields.model_id());
+        self.model = create_vocabulary_model_with_fields(model_id, &templates, self.extra_fields);
+    }
+
+    /// Number of fields of the notes.
+    fn field_count(&self) -> usize {
+        MODEL_FIELDS + self.extra_fields.names().len()
+    }
+
+    /// Adds `tags` to every exported note, next to the automatic
+    /// `duoload::deck::<name>` and `duoload::exported::<date>` tags. The
+    /// latter is left out of deterministic packages.
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    /// Names the status, label, deck and export date tags with `scheme`
+    /// instead of `duoload_<status>` and `duoload::<kind>::<value>`.
+    pub fn with_tag_scheme(mut self, scheme: TagScheme) -> Self {
+        self.tag_scheme = scheme;
+        self
+    }
+
+    /// Dates the `duoload::exported::<date>` tag with `time` instead of now.
+    pub fn with_export_time(mut self, time: SystemTime) -> Self {
+        self.exported = UtcDateTime::from_system_time(time);
+        self
+    }
+
+    /// Embeds card pictures and pronunciations downloaded into `store` in the package.
+    ///
+    /// Cards whose media is missing from the store are written without it.
+    pub fn with_media(mut self, store: MediaStore) -> Self {
+        self.media = Some(store);
+        self
+    }
+
+    /// Sets the compression, layout and media of the written package.
+    ///
+    /// Without media files, notes still refer to the pictures and
+    /// pronunciations of their cards, which Anki then expects in its media
+    /// folder, e.g. from an earlier import.
+    pub fn with_package_options(mut self, options: ApkgOptions) -> Self {
+        self.package_options = options;
+        self
+    }
+
+    /// Writes a collection package (.colpkg), which Anki opens in place of
+    /// the current collection, instead of a deck package.
+    ///
+    /// Cards answered correctly in Duocards become review cards, due when
+    /// Duocards would show them next, with an interval doubling with every
+    /// correct answer. Every note gets a GUID, derived from its word and
+    /// translation for cards without a Duocards ID.
+    pub fn with_collection(mut self, enabled: bool) -> Self {
+        self.collection = enabled;
+        self
+    }
+
+    /// Keeps the added cards in a temporary [`CardStore`] on disk instead
+    /// of memory and builds the notes a batch at a time, so very large decks
+    /// are written with roughly constant memory.
+    ///
+    /// Languages for subdecks and tags are detected within each batch of
+    /// cards instead of the whole deck.
+    pub fn with_disk_store(mut self, enabled: bool) -> Self {
+        self.disk_store = enabled;
+        self
+    }
+
+    /// Builds `size` notes at a time with a disk store instead of
+    /// [`STORE_BATCH_SIZE`]; a size of 0 is taken as 1.
+    pub fn with_batch_size(mut self, size: usize) -> Self {
+        self.batch_size = size.max(1);
+        self
+    }
+
+    /// Keeps the notes and media of an existing package and only adds cards
+    /// for words it does not contain yet, or whose note it contains under an
+    /// older word, which they replace.
+    ///
+    /// The kept notes retain their GUIDs, so importing the new package into
+    /// Anki updates them in place. Their fields are mapped onto the vocabulary
+    /// model in order. Not combined with subdecks. A shared package can be
+    /// merged into by several builders.
+    pub fn with_existing(mut self, package: impl Into<Arc<ExistingPackage>>) -> Self {
+        let package = package.into();
+        for note in &package.notes {
+            self.duplicates.seed(note.word());
+            // The word as the card had it before it was escaped
+            let word = decode_entities(note.word());
+            if word != note.word() {
+                self.duplicates.seed(&word);
+            }
+        }
+        self.existing = Some(package);
+        self
+    }
+
+    /// Puts cards into one subdeck per learning status: `Vocabulary::New`,
+    /// `Vocabulary::Learning` and `Vocabulary::Known`.
+    ///
+    /// Combined with language subdecks, each language gets status subdecks,
+    /// e.g. `Vocabulary::de::Known`.
+    pub fn with_status_subdecks(mut self, enabled: bool) -> Self {
+        self.status_subdecks = enabled;
+        self
+    }
+
+    /// Puts cards into one subdeck per detected language, e.g. `Vocabulary::de`.
+    #[cfg(feature = "lang")]
+    pub fn with_language_subdecks(mut self, enabled: bool) -> Self {
+        self.language_subdecks = enabled;
+        self
+    }
+
+    /// Tags every note with the detected languages of its word and
+    /// translation, e.g. `lang::de` and `lang::en`.
+    #[cfg(feature = "lang")]
+    pub fn with_language_tags(mut self, enabled: bool) -> Self {
+        self.language_tags = enabled;
+        self
+    }
+
+    /// `cards` with their languages if language tags are enabled.
+    fn annotated<'a>(&self, cards: &'a [VocabularyCard]) -> Cow<'a, [VocabularyCard]> {
+        #[cfg(feature = "lang")]
+        if self.language_tags {
+            let mut cards = cards.to_vec();
+            crate::lang::annotate_languages(&mut cards);
+            return Cow::Owned(cards);
+        }
+        Cow::Borrowed(cards)
+    }
+
+    /// Name of the written deck.
+    fn full_deck_name(&self) -> String {
+        format!("{}{}", self.deck_name, self.name_suffix)
+    }
+
+    /// ID of the written deck.
+    fn full_deck_id(&self) -> i64 {
+        if self.name_suffix.is_empty() {
+            self.deck_id
+        } else {
+            subdeck_id(self.deck_id, &self.name_suffix)
+        }
+    }
+
+    /// Tags of every exported note besides its learning status.
+    fn note_tags(&self) -> Vec<String> {
+        let mut tags = vec![self.tag_scheme.tag("deck", &self.full_deck_name())];
+        // Deterministic packages are the same whenever they are exported
+        if !self.package_options.deterministic {
+            tags.push(self.tag_scheme.tag("exported", &self.exported.date()));
+        }
+        tags.extend(self.tags.iter().map(|name| tag(name)));
+        tags
+    }
+
+    /// Subdeck of each of `cards` below the deck, e.g. `de::Known`, or
+    /// `None` if all cards go into the deck itself.
+    fn subdecks(&self, cards: &[VocabularyCard]) -> Option<Vec<String>> {
+        #[cfg(feature = "lang")]
+        let languages = self
+            .language_subdecks
+            .then(|| crate::lang::detect_languages(cards));
+        #[cfg(not(feature = "lang"))]
+        let languages: Option<Vec<String>> = None;
+
+        if languages.is_none() && !self.status_subdecks {
+            return None;
+        }
+        let subdecks = cards
+            .iter()
+            .enumerate()
+            .map(|(index, card)| {
+                let mut parts = Vec::new();
+                if let Some(languages) = &languages {
+                    parts.push(languages[index].as_str());
+                }
+                if self.status_subdecks {
+                    parts.push(status_subdeck(&card.status));
+                }
+                parts.join("::")
+            })
+            .collect();
+        Some(subdecks)
+    }
+
+    /// Builds the Anki decks from the collected cards.
+    fn build_decks(&self) -> Result<Vec<Deck>> {
+        let cards = self.annotated(&self.cards);
+        let order = self.sort.order(&cards);
+        let subdecks = self.subdecks(&self.cards);
+        self.decks(&cards, &order, subdecks.as_deref(), &self.kept_notes()?)
+    }
+
+    /// Builds the Anki decks with `cards` in `order`: in their `subdecks` if
+    /// there are any, or else in the deck after the `kept` notes.
+    fn decks(
+        &self,
+        cards: &[VocabularyCard],
+        order: &[usize],
+        subdecks: Option<&[String]>,
+        kept: &[&PackageNote],
+    ) -> Result<Vec<Deck>> {
+        let tags = self.note_tags();
+        if let Some(subdecks) = subdecks {
+            let mut decks: Vec<(String, Deck)> = Vec::new();
+            for &index in order {
+                let (card, subdeck) = (&cards[index], subdecks[index].clone());
+                let index = match decks.iter().position(|(name, _)| *name == subdeck) {
+                    Some(index) => index,
+                    None => {
+                        let deck = Deck::new(
+                            subdeck_id(self.full_deck_id(), &subdeck),
+                            &format!("{}::{}", self.full_deck_name(), subdeck),
+                            "Vocabulary imported from Duocards",
+                        );
+                        decks.push((subdeck, deck));
+                        decks.len() - 1
+                    }
+                };
+                decks[index].1.add_note(self.note(card, &tags)?);
+            }
+            return Ok(decks.into_iter().map(|(_, deck)| deck).collect());
+        }
+
+        let mut deck = Deck::new(
+            self.full_deck_id(),
+            &self.full_deck_name(),
             "Vocabulary imported from Duocards",
         );
 
-        Self {
-            deck,
-            model,
-            existing_words: HashSet::new(),
+        for note in kept {
+            deck.add_note(self.existing_note(note)?);
+        }
+        for &index in order {
+            deck.add_note(self.note(&cards[index], &tags)?);
+        }
+
+        Ok(vec![deck])
+    }
+
+    /// Calls `f` with all collected cards, a batch at a time if they are
+    /// kept in a [`CardStore`].
+    fn for_each_batch(&self, mut f: impl FnMut(&[VocabularyCard]) -> Result<()>) -> Result<()> {
+        match &self.store {
+            Some(store) => {
+                store.for_each_batch(SortOrder::Original, self.batch_size, |cards| f(&cards))
+            }
+            None => f(&self.cards),
+        }
+    }
+
+    /// Notes of the existing package that stay in the package.
+    ///
+    /// A note with the GUID of an exported card is that card before it
+    /// changed, and is replaced by it.
+    fn kept_notes(&self) -> Result<Vec<&PackageNote>> {
+        let Some(package) = &self.existing else {
+            return Ok(Vec::new());
+        };
+        let mut guids = HashSet::new();
+        self.for_each_batch(|cards| {
+            guids.extend(
+                cards
+                    .iter()
+                    .filter_map(|card| card.source_id.as_deref())
+                    .map(|card_id| note_guid(&self.source_deck_id, card_id)),
+            );
+            Ok(())
+        })?;
+        Ok(package
+            .notes
+            .iter()
+            .filter(|note| !guids.contains(&note.guid))
+            .collect())
+    }
+
+    /// GUID of the note of `card` in a collection package.
+    fn collection_guid(&self, card: &VocabularyCard) -> String {
+        match &card.source_id {
+            Some(card_id) => note_guid(&self.source_deck_id, card_id),
+            None => note_guid(&card.word, &card.translation),
+        }
+    }
+
+    /// Schedules of `cards` by note GUID in a collection package; none in a
+    /// deck package.
+    fn schedules(&self, cards: &[VocabularyCard]) -> HashMap<String, CardSchedule> {
+        if !self.collection {
+            return HashMap::new();
+        }
+        cards
+            .iter()
+            .filter_map(|card| Some((self.collection_guid(card), CardSchedule::from_card(card)?)))
+            .collect()
+    }
+
+    fn note(&self, card: &VocabularyCard, tags: &[String]) -> Result<Note> {
+        let mut note = VocabularyNote::new(card.clone(), &self.tag_scheme)
+            .with_tags(tags.iter().cloned())
+            .with_stable_guid(&self.source_deck_id)
+            .with_html_escaping(self.escape_html);
+        if self.collection {
+            note.guid = Some(self.collection_guid(card));
+        }
+        if let Some(url) = &card.image
+            && self.media_file(url, MediaKind::Image).is_some()
+        {
+            note.image = Some(MediaStore::file_name(url, MediaKind::Image));
+        }
+        if let Some(url) = &card.audio
+            && self.media_file(url, MediaKind::Audio).is_some()
+        {
+            note.audio = Some(MediaStore::file_name(url, MediaKind::Audio));
+        }
+        Ok(note.to_anki_note_with_fields(&self.model, self.extra_fields)?)
+    }
+
+    fn existing_note(&self, note: &PackageNote) -> Result<Note> {
+        let mut fields: Vec<&str> = note
+            .fields
+            .iter()
+            .map(String::as_str)
+            .take(self.field_count())
+            .collect();
+        fields.resize(self.field_count(), "");
+        let note = Note::new(self.model.clone(), fields)
+            .map_err(|e| anyhow::anyhow!("Failed to keep note {:?}: {}", note.word(), e))?
+            .guid(&note.guid)
+            .tags(note.tags.clone());
+        Ok(note)
+    }
+
+    fn media_file(&self, url: &str, kind: MediaKind) -> Option<PathBuf> {
+        self.media.as_ref()?.get(url, kind)
+    }
+
+    fn write_file(&self, path: &Path) -> Result<()> {
+        let Some(store) = &self.store else {
+            let mut package = BatchedPackage::new(&self.package_options)?;
+            package.add_decks(self.build_decks()?)?;
+            package.schedule_cards(&self.schedules(&self.cards))?;
+            return package.write(&self.media_files()?, path, &self.package_options);
+        };
+
+        let kept = self.kept_notes()?;
+        let mut package = BatchedPackage::new(&self.package_options)?;
+        let mut batches = 0;
+        store.for_each_batch(self.sort, self.batch_size, |cards| {
+            // The batch is sorted already; kept notes go into the first one
+            let order: Vec<usize> = (0..cards.len()).collect();
+            let kept = if batches == 0 { &kept[..] } else { &[] };
+            let decks = self.decks(
+                &self.annotated(&cards),
+                &order,
+                self.subdecks(&cards).as_deref(),
+                kept,
+            )?;
+            batches += 1;
+            package.add_decks(decks)?;
+            package.schedule_cards(&self.schedules(&cards))
+        })?;
+        if batches == 0 {
+            package.add_decks(self.decks(&[], &[], self.subdecks(&[]).as_deref(), &kept)?)?;
         }
+        package.write(&self.media_files()?, path, &self.package_options)
+    }
+
+    /// Paths of the media of a merged package and of the downloaded pictures
+    /// and pronunciations of all cards, each file name listed once.
+    fn media_files(&self) -> Result<Vec<PathBuf>> {
+        let mut files: Vec<PathBuf> = self
+            .existing
+            .iter()
+            .flat_map(|package| package.media_files.iter().cloned())
+            .collect();
+        let mut names: HashSet<OsString> = files
+            .iter()
+            .filter_map(|file| file.file_name().map(OsStr::to_os_string))
+            .collect();
+        self.for_each_batch(|cards| {
+            let media = cards.iter().flat_map(|card| {
+                let image = card.image.as_deref().map(|url| (url, MediaKind::Image));
+                let audio = card.audio.as_deref().map(|url| (url, MediaKind::Audio));
+                image.into_iter().chain(audio)
+            });
+            for (url, kind) in media {
+                if let Some(file) = self.media_file(url, kind)
+                    && let Some(name) = file.file_name()
+                    && names.insert(name.to_os_string())
+                {
+                    files.push(file);
+                }
+            }
+            Ok(())
+        })?;
+        Ok(files)
+    }
+}
+
+/// Name of the subdeck for cards with `status`.
+fn status_subdeck(status: &LearningStatus) -> &'static str {
+    match status {
+        LearningStatus::New => "New",
+        LearningStatus::Learning => "Learning",
+        LearningStatus::Known => "Known",
     }
 }
 
+/// Stable deck ID for a subdeck, derived from its name.
+fn subdeck_id(deck_id: i64, name: &str) -> i64 {
+    let hash = name
+        .bytes()
+        .fold(0i64, |hash, byte| (hash * 31 + byte as i64) % 1_000_000);
+    deck_id.wrapping_add(1 + hash)
+}
+
 impl OutputBuilder for AnkiPackageBuilder {
     fn add_note(&mut self, vocab_card: VocabularyCard) -> Result<bool> {
-        // Check for duplicates before moving the card
-        if self.existing_words.contains(&vocab_card.word) {
-            return Ok(false); // Duplicate
+        self.check_card(&vocab_card)?;
+        if self.disk_store && self.store.is_none() {
+            self.store = Some(CardStore::new()?);
+        }
+        match &mut self.store {
+            Some(store) => self.duplicates.add(store, vocab_card),
+            None => self.duplicates.add(&mut self.cards, vocab_card),
         }
+    }
+
+    /// Rejects cards that cannot become Anki notes, see [`invalid_card_reason`].
+    fn check_card(&self, card: &VocabularyCard) -> Result<()> {
+        match invalid_card_reason(card) {
+            Some(reason) => Err(DuoloadError::BadCard {
+                word: card.word.clone(),
+                reason,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        self.duplicates.set_policy(policy);
+    }
 
-        // Clone the word before moving the card
-        let word = vocab_card.word.clone();
+    fn set_dedupe_key(&mut self, key: DedupeKey) {
+        self.duplicates.set_key(key);
+    }
+
+    fn set_dedupe_store(&mut self, store: &DedupeStore) {
+        self.duplicates.set_store(store);
+    }
 
-        // Create and add the note
-        let note = VocabularyNote::from(vocab_card).to_anki_note(&self.model)?;
-        self.deck.add_note(note);
-        self.existing_words.insert(word);
-        Ok(true)
+    fn set_sort_order(&mut self, order: SortOrder) {
+        self.sort = order;
+    }
+
+    fn set_deck_metadata(&mut self, metadata: &DeckMetadata) {
+        self.source_deck_id = metadata.id.clone();
+        if let Some(name) = metadata.name.as_deref().map(str::trim)
+            && self.name_from_deck
+            && !name.is_empty()
+        {
+            self.deck_name = name.to_string();
+        }
     }
 
     fn write(&self, dest: OutputDestination<'_>) -> Result<()> {
         match dest {
-            OutputDestination::Writer(_) => {
-                // Anki packages can only be written to files
-                Err(DuoloadError::AnkiOutputNotSupported)
-            }
-            OutputDestination::File(path) => {
-                // Convert path to string and write the Anki package
-                let path_str = path
-                    .to_str()
-                    .ok_or_else(|| anyhow::anyhow!("Invalid file path"))?;
-                self.deck
-                    .write_to_file(path_str)
-                    .map_err(|e| anyhow::anyhow!("Failed to write Anki package: {}", e))?;
+            OutputDestination::Writer(writer) => {
+                // genanki can only write files, so the package is built in a
+                // temporary file and copied to the writer
+                let dir = tempfile::tempdir()?;
+                let path = dir.path().join("deck.apkg");
+                self.write_file(&path)?;
+                io::copy(&mut File::open(&path)?, writer)?;
+                writer.flush()?;
                 Ok(())
             }
+            OutputDestination::File(path) => self.write_file(path),
         }
     }
+
+    fn count_written_notes(&self, path: &Path) -> Result<Option<usize>> {
+        let count = PackageCollection::open(path)?.note_count()?;
+        // Notes kept from a merged package were not part of this export
+        Ok(Some(count.saturating_sub(self.kept_notes()?.len())))
+    }
 }
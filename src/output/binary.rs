@@ -0,0 +1,127 @@
+use crate::duocards::models::VocabularyCard;
+use crate::error::{DuoloadError, Result};
+use crate::output::sort::SortOrder;
+use crate::output::{OutputBuilder, OutputDestination};
+use crate::transfer::DuplicateHandler;
+use crate::transfer::duplicates::{DedupeKey, DuplicatePolicy};
+use crate::transfer::key_store::DedupeStore;
+use std::io::Write;
+use std::path::Path;
+use std::time::Instant;
+
+/// Binary serializations of the cards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryEncoding {
+    /// MessagePack (.msgpack)
+    MessagePack,
+    /// CBOR, RFC 8949 (.cbor)
+    Cbor,
+}
+
+impl BinaryEncoding {
+    fn name(self) -> &'static str {
+        match self {
+            BinaryEncoding::MessagePack => "MessagePack",
+            BinaryEncoding::Cbor => "CBOR",
+        }
+    }
+
+    fn error(self, e: impl std::fmt::Display) -> DuoloadError {
+        match self {
+            BinaryEncoding::MessagePack => DuoloadError::MessagePack(e.to_string()),
+            BinaryEncoding::Cbor => DuoloadError::Cbor(e.to_string()),
+        }
+    }
+}
+
+/// Builder for MessagePack or CBOR files of the vocabulary.
+///
+/// Writes an array of cards with the same fields as the JSON output, each a
+/// map keyed by field name, for applications that embed exports without
+/// parsing JSON.
+pub struct BinaryOutputBuilder {
+    encoding: BinaryEncoding,
+    cards: Vec<VocabularyCard>,
+    duplicates: DuplicateHandler,
+    sort: SortOrder,
+    start_time: Instant,
+}
+
+impl BinaryOutputBuilder {
+    pub fn new(encoding: BinaryEncoding) -> Self {
+        Self {
+            encoding,
+            cards: Vec::new(),
+            duplicates: DuplicateHandler::new(),
+            sort: SortOrder::default(),
+            start_time: Instant::now(),
+        }
+    }
+
+    fn write_document<W: Write>(&self, mut writer: W) -> Result<()> {
+        let cards = self.sort.sorted(&self.cards);
+        match self.encoding {
+            // Maps rather than arrays, as cards leave out empty fields
+            BinaryEncoding::MessagePack => rmp_serde::encode::write_named(&mut writer, &cards)
+                .map_err(|e| self.encoding.error(e))?,
+            BinaryEncoding::Cbor => {
+                ciborium::into_writer(&cards, &mut writer).map_err(|e| self.encoding.error(e))?
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl OutputBuilder for BinaryOutputBuilder {
+    fn add_note(&mut self, card: VocabularyCard) -> Result<bool> {
+        self.duplicates.add(&mut self.cards, card)
+    }
+
+    fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        self.duplicates.set_policy(policy);
+    }
+
+    fn set_dedupe_key(&mut self, key: DedupeKey) {
+        self.duplicates.set_key(key);
+    }
+
+    fn set_dedupe_store(&mut self, store: &DedupeStore) {
+        self.duplicates.set_store(store);
+    }
+
+    fn set_sort_order(&mut self, order: SortOrder) {
+        self.sort = order;
+    }
+
+    fn write(&self, dest: OutputDestination<'_>) -> Result<()> {
+        match dest {
+            OutputDestination::Writer(writer) => self.write_document(writer)?,
+            OutputDestination::File(path) => {
+                let file = std::fs::File::create(path)?;
+                self.write_document(std::io::BufWriter::new(file))?;
+            }
+        }
+
+        tracing::info!(
+            "{} written successfully at {:?}",
+            self.encoding.name(),
+            self.start_time.elapsed()
+        );
+
+        Ok(())
+    }
+
+    fn count_written_notes(&self, path: &Path) -> Result<Option<usize>> {
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+        let cards: Vec<serde::de::IgnoredAny> = match self.encoding {
+            BinaryEncoding::MessagePack => {
+                rmp_serde::from_read(file).map_err(|e| self.encoding.error(e))?
+            }
+            BinaryEncoding::Cbor => {
+                ciborium::from_reader(file).map_err(|e| self.encoding.error(e))?
+            }
+        };
+        Ok(Some(cards.len()))
+    }
+}
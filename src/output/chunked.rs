@@ -0,0 +1,284 @@
+//! Output split into files of a fixed number of cards.
+
+use crate::duocards::models::{DeckMetadata, VocabularyCard};
+use crate::error::{DuoloadError, Result};
+use crate::output::sort::SortOrder;
+use crate::output::{OutputBuilder, OutputDestination, write_atomically};
+use crate::transfer::DuplicateHandler;
+use crate::transfer::duplicates::{DedupeKey, DuplicatePolicy};
+use crate::transfer::key_store::DedupeStore;
+use std::path::{Path, PathBuf};
+
+/// Output builder that writes at most `size` cards per file.
+///
+/// `deck.apkg` becomes `deck-001.apkg`, `deck-002.apkg` and so on, each
+/// written by a fresh builder from `new_builder`. Duplicates are resolved and
+/// cards sorted across the whole deck before splitting, so the files hold
+/// consecutive runs of the output a single file would have.
+pub struct ChunkedOutputBuilder<B, F> {
+    new_builder: F,
+    /// Checks cards as they are added, before the chunks are built
+    probe: B,
+    size: usize,
+    cards: Vec<VocabularyCard>,
+    duplicates: DuplicateHandler,
+    sort: SortOrder,
+    metadata: Option<DeckMetadata>,
+}
+
+impl<B, F> ChunkedOutputBuilder<B, F>
+where
+    B: OutputBuilder,
+    F: Fn() -> B + Send + Sync,
+{
+    /// Splits the output every `size` cards; a size of 0 is taken as 1.
+    pub fn new(size: usize, new_builder: F) -> Self {
+        Self {
+            probe: new_builder(),
+            new_builder,
+            size: size.max(1),
+            cards: Vec::new(),
+            duplicates: DuplicateHandler::new(),
+            sort: SortOrder::default(),
+            metadata: None,
+        }
+    }
+
+    /// Number of files written; an empty deck still gets one.
+    fn chunk_count(&self) -> usize {
+        self.cards.len().div_ceil(self.size).max(1)
+    }
+}
+
+impl<B, F> OutputBuilder for ChunkedOutputBuilder<B, F>
+where
+    B: OutputBuilder,
+    F: Fn() -> B + Send + Sync,
+{
+    fn add_note(&mut self, card: VocabularyCard) -> Result<bool> {
+        self.probe.check_card(&card)?;
+        self.duplicates.add(&mut self.cards, card)
+    }
+
+    fn check_card(&self, card: &VocabularyCard) -> Result<()> {
+        self.probe.check_card(card)
+    }
+
+    fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        self.duplicates.set_policy(policy);
+    }
+
+    fn set_dedupe_key(&mut self, key: DedupeKey) {
+        self.duplicates.set_key(key);
+    }
+
+    fn set_dedupe_store(&mut self, store: &DedupeStore) {
+        self.duplicates.set_store(store);
+    }
+
+    fn set_sort_order(&mut self, order: SortOrder) {
+        self.sort = order;
+    }
+
+    fn set_deck_metadata(&mut self, metadata: &DeckMetadata) {
+        self.metadata = Some(metadata.clone());
+    }
+
+    fn writes_in_place(&self) -> bool {
+        // Each chunk is written by its own builder
+        true
+    }
+
+    fn write(&self, dest: OutputDestination<'_>) -> Result<()> {
+        let OutputDestination::File(path) = dest else {
+            return Err(DuoloadError::SplitOutputNotSupported);
+        };
+
+        let sorted = self.sort.sorted(&self.cards);
+        for index in 0..self.chunk_count() {
+            let start = index * self.size;
+            let chunk = &sorted[start.min(sorted.len())..(start + self.size).min(sorted.len())];
+            let mut builder = (self.new_builder)();
+            // Duplicates are resolved and cards sorted already
+            builder.set_duplicate_policy(DuplicatePolicy::KeepAll);
+            if let Some(metadata) = &self.metadata {
+                builder.set_deck_metadata(metadata);
+            }
+            for card in chunk {
+                builder.add_note((*card).clone())?;
+            }
+            let chunk_path = chunk_path(path, index + 1);
+            if builder.writes_in_place() {
+                builder.write(OutputDestination::File(&chunk_path))?;
+            } else {
+                write_atomically(&builder, &chunk_path)?;
+            }
+            tracing::info!("Wrote {} cards to {:?}", chunk.len(), chunk_path);
+        }
+        // Chunks of an earlier, larger export would pass for part of this one
+        for (number, stale) in existing_chunks(path)? {
+            if number > self.chunk_count() {
+                std::fs::remove_file(&stale)?;
+                tracing::info!("Removed {:?} of an earlier export", stale);
+            }
+        }
+        Ok(())
+    }
+
+    fn count_written_notes(&self, path: &Path) -> Result<Option<usize>> {
+        let mut total = 0;
+        for index in 0..self.chunk_count() {
+            match self
+                .probe
+                .count_written_notes(&chunk_path(path, index + 1))?
+            {
+                Some(count) => total += count,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(total))
+    }
+
+    fn written_files(&self, path: &Path) -> Vec<PathBuf> {
+        (1..=self.chunk_count())
+            .map(|number| chunk_path(path, number))
+            .collect()
+    }
+}
+
+/// Inserts the 1-based chunk number before the extension: `deck.apkg`
+/// becomes `deck-001.apkg`.
+pub fn chunk_path(path: &Path, number: usize) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut name = format!("{}-{:03}", stem, number);
+    if let Some(extension) = path.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+    path.with_file_name(name)
+}
+
+/// Chunks of `path` on disk, e.g. from an earlier export, with their numbers
+/// in ascending order.
+pub fn existing_chunks(path: &Path) -> Result<Vec<(usize, PathBuf)>> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let stem = path
+        .file_stem()
+        .map(|stem| format!("{}-", stem.to_string_lossy()))
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    let mut chunks = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let number = name
+            .strip_prefix(&stem)
+            .and_then(|rest| rest.strip_suffix(&extension))
+            .filter(|digits| digits.len() >= 3 && digits.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|digits| digits.parse().ok());
+        if let Some(number) = number {
+            chunks.push((number, path.with_file_name(name)));
+        }
+    }
+    chunks.sort();
+    Ok(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duocards::models::LearningStatus;
+    use crate::output::json::JsonOutputBuilder;
+    use tempfile::tempdir;
+
+    fn card(word: &str) -> VocabularyCard {
+        VocabularyCard {
+            word: word.to_string(),
+            translation: format!("{} translated", word),
+            status: LearningStatus::New,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_chunk_path() {
+        assert_eq!(
+            chunk_path(Path::new("out/deck.apkg"), 2),
+            PathBuf::from("out/deck-002.apkg")
+        );
+        assert_eq!(
+            chunk_path(Path::new("deck"), 1000),
+            PathBuf::from("deck-1000")
+        );
+    }
+
+    #[test]
+    fn test_split_json_every_two_cards() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("deck.json");
+        let mut builder = ChunkedOutputBuilder::new(2, JsonOutputBuilder::new);
+        builder.set_sort_order(SortOrder::Alphabetical);
+        for word in ["dog", "cat", "bird", "cat", "ant"] {
+            builder.add_note(card(word))?;
+        }
+
+        builder.write(OutputDestination::File(&path))?;
+
+        let read = |number| -> Result<Vec<serde_json::Value>> {
+            Ok(serde_json::from_slice(&std::fs::read(chunk_path(
+                &path, number,
+            ))?)?)
+        };
+        let first = read(1)?;
+        assert_eq!(first.len(), 2);
+        assert_eq!(first[0]["word"], "ant");
+        assert_eq!(first[1]["word"], "bird");
+        assert_eq!(read(2)?.len(), 2);
+        assert!(!chunk_path(&path, 3).exists());
+        assert!(!path.exists());
+        assert_eq!(builder.count_written_notes(&path)?, Some(4));
+        assert_eq!(
+            builder.written_files(&path),
+            [chunk_path(&path, 1), chunk_path(&path, 2)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_stale_chunks_are_removed() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("deck.json");
+        for number in 1..=3 {
+            std::fs::write(chunk_path(&path, number), "[]")?;
+        }
+        std::fs::write(dir.path().join("deck-notes.json"), "[]")?;
+        assert_eq!(existing_chunks(&path)?.len(), 3);
+
+        let mut builder = ChunkedOutputBuilder::new(2, JsonOutputBuilder::new);
+        for word in ["ant", "bird", "cat"] {
+            builder.add_note(card(word))?;
+        }
+        builder.write(OutputDestination::File(&path))?;
+
+        let numbers: Vec<usize> = existing_chunks(&path)?
+            .into_iter()
+            .map(|(number, _)| number)
+            .collect();
+        assert_eq!(numbers, [1, 2]);
+        assert!(dir.path().join("deck-notes.json").exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,275 @@
+use crate::duocards::models::VocabularyCard;
+use crate::error::{DuoloadError, Result};
+use crate::output::sort::SortOrder;
+use crate::output::{OutputBuilder, OutputDestination};
+use crate::transfer::DuplicateHandler;
+use crate::transfer::duplicates::{DedupeKey, DuplicatePolicy};
+use crate::transfer::key_store::DedupeStore;
+use clap::ValueEnum;
+use csv::QuoteStyle;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Instant;
+
+/// Column names of the header row.
+pub const CSV_HEADER: [&str; 8] = [
+    "word",
+    "translation",
+    "example",
+    "status",
+    "source_id",
+    "created_at",
+    "waiting_until",
+    "known_count",
+];
+
+/// Name of the column added by [`CsvOutputBuilder::with_image_column`].
+pub const CSV_IMAGE_HEADER: &str = "image";
+
+/// Name of the column added by [`CsvOutputBuilder::with_provenance_column`].
+pub const CSV_PROVENANCE_HEADER: &str = "provenance";
+
+/// Column names for [`CsvOutputBuilder::with_headers`], parsed from a
+/// comma-separated list of one name up to one per column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvHeaders(pub Vec<String>);
+
+impl FromStr for CsvHeaders {
+    type Err = DuoloadError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let headers: Vec<String> = s.split(',').map(|name| name.trim().to_string()).collect();
+        if s.trim().is_empty() || headers.len() > CSV_HEADER.len() {
+            return Err(DuoloadError::Format(format!(
+                "CSV headers must name 1 to {} columns ({}), got {:?}",
+                CSV_HEADER.len(),
+                CSV_HEADER.join(","),
+                s
+            )));
+        }
+        Ok(Self(headers))
+    }
+}
+
+/// Parses a field delimiter: a single ASCII character, or `tab`.
+pub fn parse_delimiter(s: &str) -> Result<u8> {
+    match s {
+        "tab" | "\\t" => Ok(b'\t'),
+        _ if s.len() == 1 && s.is_ascii() && s != "\"" => Ok(s.as_bytes()[0]),
+        _ => Err(DuoloadError::Format(format!(
+            "CSV delimiter must be a single ASCII character other than a quote, or tab, got {:?}",
+            s
+        ))),
+    }
+}
+
+/// When fields are put in quotes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum CsvQuoting {
+    /// Only fields with a delimiter, quote or line break
+    #[default]
+    Necessary,
+    /// Every field
+    Always,
+    /// Every field that is not a number
+    NonNumeric,
+    /// No field, even if it breaks the row
+    Never,
+}
+
+impl From<CsvQuoting> for QuoteStyle {
+    fn from(quoting: CsvQuoting) -> Self {
+        match quoting {
+            CsvQuoting::Necessary => QuoteStyle::Necessary,
+            CsvQuoting::Always => QuoteStyle::Always,
+            CsvQuoting::NonNumeric => QuoteStyle::NonNumeric,
+            CsvQuoting::Never => QuoteStyle::Never,
+        }
+    }
+}
+
+/// Builder for creating CSV files from vocabulary cards.
+///
+/// Writes a header row followed by one row per card, with the same fields as
+/// the JSON output. Cards without an example, or without the Duocards ID,
+/// timestamps and known count, get empty columns for them.
+/// The result opens in spreadsheets and can be imported with Anki's
+/// plain-text importer.
+///
+/// Spreadsheets in many locales expect semicolons between fields; the
+/// delimiter, quoting and header names can be changed to match.
+pub struct CsvOutputBuilder {
+    cards: Vec<VocabularyCard>,
+    duplicates: DuplicateHandler,
+    sort: SortOrder,
+    start_time: Instant,
+    headers: Option<Vec<String>>,
+    delimiter: u8,
+    quoting: CsvQuoting,
+    image_column: bool,
+    provenance_column: bool,
+}
+
+impl Default for CsvOutputBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CsvOutputBuilder {
+    pub fn new() -> Self {
+        Self {
+            cards: Vec::new(),
+            duplicates: DuplicateHandler::new(),
+            sort: SortOrder::default(),
+            start_time: Instant::now(),
+            headers: None,
+            delimiter: b',',
+            quoting: CsvQuoting::default(),
+            image_column: false,
+            provenance_column: false,
+        }
+    }
+
+    /// Names the columns `headers` instead of [`CSV_HEADER`], in the same
+    /// order, e.g. in the language of a spreadsheet. With fewer names than
+    /// columns, only the first columns are written: `Wort,Übersetzung`
+    /// writes just the word and translation. Names beyond the last column
+    /// are ignored.
+    pub fn with_headers(mut self, headers: Vec<String>) -> Self {
+        self.headers = Some(headers);
+        self
+    }
+
+    /// Separates fields with `delimiter`, a comma by default.
+    pub fn with_delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    pub fn with_quoting(mut self, quoting: CsvQuoting) -> Self {
+        self.quoting = quoting;
+        self
+    }
+
+    /// Adds an `image` column after the others with the picture of each
+    /// card: its URL, or the path of the downloaded file.
+    pub fn with_image_column(mut self, enabled: bool) -> Self {
+        self.image_column = enabled;
+        self
+    }
+
+    /// Adds a `provenance` column after the others with the deck, page and
+    /// time each card was fetched from, see [`Provenance`].
+    ///
+    /// [`Provenance`]: crate::duocards::models::Provenance
+    pub fn with_provenance_column(mut self, enabled: bool) -> Self {
+        self.provenance_column = enabled;
+        self
+    }
+
+    fn write_rows<W: Write>(&self, writer: W) -> Result<()> {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(self.delimiter)
+            .quote_style(self.quoting.into())
+            .from_writer(writer);
+        let columns = self.headers.as_ref().map_or(CSV_HEADER.len(), |headers| {
+            headers.len().min(CSV_HEADER.len())
+        });
+        let mut header: Vec<&str> = match &self.headers {
+            Some(headers) => headers[..columns].iter().map(String::as_str).collect(),
+            None => CSV_HEADER.to_vec(),
+        };
+        if self.image_column {
+            header.push(CSV_IMAGE_HEADER);
+        }
+        if self.provenance_column {
+            header.push(CSV_PROVENANCE_HEADER);
+        }
+        writer.write_record(&header)?;
+        for card in self.sort.sorted(&self.cards) {
+            let known_count = card
+                .known_count
+                .map_or_else(String::new, |count| count.to_string());
+            let waiting_until = card
+                .waiting_until
+                .map_or_else(String::new, |time| time.rfc3339());
+            let row = [
+                card.word.as_str(),
+                card.translation.as_str(),
+                card.example.as_deref().unwrap_or(""),
+                card.status.as_str(),
+                card.source_id.as_deref().unwrap_or(""),
+                card.created_at.as_deref().unwrap_or(""),
+                waiting_until.as_str(),
+                known_count.as_str(),
+            ];
+            let provenance = card
+                .provenance
+                .as_ref()
+                .map_or_else(String::new, |provenance| provenance.to_string());
+            let mut record = row[..columns].to_vec();
+            if self.image_column {
+                record.push(card.image.as_deref().unwrap_or(""));
+            }
+            if self.provenance_column {
+                record.push(provenance.as_str());
+            }
+            writer.write_record(&record)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl OutputBuilder for CsvOutputBuilder {
+    fn add_note(&mut self, card: VocabularyCard) -> Result<bool> {
+        self.duplicates.add(&mut self.cards, card)
+    }
+
+    fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        self.duplicates.set_policy(policy);
+    }
+
+    fn set_dedupe_key(&mut self, key: DedupeKey) {
+        self.duplicates.set_key(key);
+    }
+
+    fn set_dedupe_store(&mut self, store: &DedupeStore) {
+        self.duplicates.set_store(store);
+    }
+
+    fn set_sort_order(&mut self, order: SortOrder) {
+        self.sort = order;
+    }
+
+    fn write(&self, dest: OutputDestination<'_>) -> Result<()> {
+        match dest {
+            OutputDestination::Writer(writer) => self.write_rows(writer)?,
+            OutputDestination::File(path) => {
+                let file = std::fs::File::create(path)?;
+                self.write_rows(std::io::BufWriter::new(file))?;
+            }
+        }
+
+        tracing::info!(
+            "CSV written successfully at {:?}",
+            self.start_time.elapsed()
+        );
+
+        Ok(())
+    }
+
+    fn count_written_notes(&self, path: &Path) -> Result<Option<usize>> {
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .from_path(path)?;
+        let mut count = 0;
+        for record in reader.records() {
+            record?;
+            count += 1;
+        }
+        Ok(Some(count))
+    }
+}
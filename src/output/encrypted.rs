@@ -0,0 +1,213 @@
+//! Encryption of output before it is written.
+//!
+//! [`EncryptedOutputBuilder`] wraps another builder and encrypts whatever it
+//! writes, either with [age](https://age-encryption.org) or by piping it
+//! through `gpg`, so the plaintext never reaches the disk.
+
+use crate::duocards::models::{DeckMetadata, VocabularyCard};
+use crate::error::{DuoloadError, Result};
+use crate::output::sort::SortOrder;
+use crate::output::{OutputBuilder, OutputDestination};
+use crate::transfer::duplicates::{DedupeKey, DuplicatePolicy};
+use crate::transfer::key_store::DedupeStore;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+/// Who the output is encrypted for, as given to `--encrypt`.
+#[derive(Clone)]
+pub enum Encryption {
+    /// `age:<recipient>`, an age X25519 public key (`age1...`)
+    Age(age::x25519::Recipient),
+    /// `gpg:<recipient>`, a key ID, fingerprint or e-mail known to the local `gpg`
+    Gpg(String),
+}
+
+impl fmt::Debug for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Encryption::Age(recipient) => write!(f, "age:{}", recipient),
+            Encryption::Gpg(recipient) => write!(f, "gpg:{}", recipient),
+        }
+    }
+}
+
+impl FromStr for Encryption {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("age", recipient)) => recipient
+                .parse()
+                .map(Encryption::Age)
+                .map_err(|e| format!("Invalid age recipient: {}", e)),
+            Some(("gpg", recipient)) if !recipient.is_empty() => {
+                Ok(Encryption::Gpg(recipient.to_string()))
+            }
+            _ => Err("Expected age:<recipient> or gpg:<recipient>".to_string()),
+        }
+    }
+}
+
+/// Output builder that encrypts the output of another builder.
+pub struct EncryptedOutputBuilder<B> {
+    inner: B,
+    encryption: Encryption,
+}
+
+impl<B: OutputBuilder> EncryptedOutputBuilder<B> {
+    pub fn new(inner: B, encryption: Encryption) -> Self {
+        Self { inner, encryption }
+    }
+
+    fn write_age(&self, recipient: &age::x25519::Recipient, output: &mut dyn Write) -> Result<()> {
+        let encryptor =
+            age::Encryptor::with_recipients(std::iter::once(recipient as &dyn age::Recipient))
+                .map_err(|e| DuoloadError::Encryption(e.to_string()))?;
+        let mut writer = encryptor.wrap_output(output)?;
+        self.inner.write(OutputDestination::Writer(&mut writer))?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    fn write_gpg(&self, recipient: &str, path: &Path) -> Result<()> {
+        let mut gpg = Command::new("gpg")
+            .args(["--batch", "--yes", "--encrypt", "--recipient", recipient])
+            .arg("--output")
+            .arg(path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| DuoloadError::Encryption(format!("Failed to run gpg: {}", e)))?;
+
+        let mut stdin = gpg.stdin.take().expect("stdin is piped");
+        let written = self.inner.write(OutputDestination::Writer(&mut stdin));
+        // Closing stdin lets gpg finish even if writing failed half-way
+        drop(stdin);
+        let status = gpg.wait()?;
+        written?;
+
+        if !status.success() {
+            return Err(DuoloadError::Encryption(format!(
+                "gpg exited with {}",
+                status
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl<B: OutputBuilder> OutputBuilder for EncryptedOutputBuilder<B> {
+    fn add_note(&mut self, card: VocabularyCard) -> Result<bool> {
+        self.inner.add_note(card)
+    }
+
+    fn check_card(&self, card: &VocabularyCard) -> Result<()> {
+        self.inner.check_card(card)
+    }
+
+    fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        self.inner.set_duplicate_policy(policy);
+    }
+
+    fn set_dedupe_key(&mut self, key: DedupeKey) {
+        self.inner.set_dedupe_key(key);
+    }
+
+    fn set_dedupe_store(&mut self, store: &DedupeStore) {
+        self.inner.set_dedupe_store(store);
+    }
+
+    fn set_deck_metadata(&mut self, metadata: &DeckMetadata) {
+        self.inner.set_deck_metadata(metadata);
+    }
+
+    fn set_sort_order(&mut self, order: SortOrder) {
+        self.inner.set_sort_order(order);
+    }
+
+    fn writes_in_place(&self) -> bool {
+        self.inner.writes_in_place()
+    }
+
+    fn write(&self, dest: OutputDestination<'_>) -> Result<()> {
+        match (&self.encryption, dest) {
+            (Encryption::Age(recipient), OutputDestination::Writer(writer)) => {
+                self.write_age(recipient, writer)
+            }
+            (Encryption::Age(recipient), OutputDestination::File(path)) => {
+                let mut writer = BufWriter::new(File::create(path)?);
+                self.write_age(recipient, &mut writer)?;
+                writer.flush()?;
+                Ok(())
+            }
+            (Encryption::Gpg(recipient), OutputDestination::File(path)) => {
+                self.write_gpg(recipient, path)
+            }
+            (Encryption::Gpg(recipient), OutputDestination::Writer(writer)) => {
+                // gpg cannot write to our writer directly; go through a temporary file
+                let encrypted = tempfile::NamedTempFile::new()?;
+                self.write_gpg(recipient, encrypted.path())?;
+                io::copy(&mut File::open(encrypted.path())?, writer)?;
+                Ok(())
+            }
+        }
+    }
+
+    // Encrypted output cannot be read back, so it is never verified
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duocards::models::LearningStatus;
+    use crate::output::json::JsonOutputBuilder;
+
+    fn card(word: &str) -> VocabularyCard {
+        VocabularyCard {
+            word: word.to_string(),
+            translation: "translation".to_string(),
+            status: LearningStatus::New,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_encryption() {
+        let identity = age::x25519::Identity::generate();
+        let recipient = identity.to_public().to_string();
+
+        let parsed: Encryption = format!("age:{}", recipient).parse().unwrap();
+        assert_eq!(format!("{:?}", parsed), format!("age:{}", recipient));
+        assert!(matches!(
+            "gpg:me@example.com".parse::<Encryption>(),
+            Ok(Encryption::Gpg(r)) if r == "me@example.com"
+        ));
+
+        assert!("age:nonsense".parse::<Encryption>().is_err());
+        assert!("gpg:".parse::<Encryption>().is_err());
+        assert!("rot13:me".parse::<Encryption>().is_err());
+    }
+
+    #[test]
+    fn test_age_round_trip() -> Result<()> {
+        let identity = age::x25519::Identity::generate();
+        let mut json = JsonOutputBuilder::new();
+        json.add_note(card("hello"))?;
+        let builder = EncryptedOutputBuilder::new(json, Encryption::Age(identity.to_public()));
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("deck.json.age");
+        builder.write(OutputDestination::File(&path))?;
+
+        let ciphertext = std::fs::read(&path)?;
+        assert!(!String::from_utf8_lossy(&ciphertext).contains("hello"));
+        let plaintext = age::decrypt(&identity, &ciphertext).unwrap();
+        let cards: Vec<serde_json::Value> = serde_json::from_slice(&plaintext)?;
+        assert_eq!(cards[0]["word"], "hello");
+        assert_eq!(builder.count_written_notes(&path)?, None);
+        Ok(())
+    }
+}
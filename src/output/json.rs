@@ -1,20 +1,139 @@
-use crate::duocards::models::VocabularyCard;
-use crate::error::Result;
-use crate::output::{OutputBuilder, OutputDestination};
+use crate::duocards::models::{DeckMetadata, VocabularyCard};
+use crate::error::{DuoloadError, Result};
+use crate::output::schema;
+use crate::output::sort::SortOrder;
+use crate::output::timestamp::UtcDateTime;
+use crate::output::{self, OutputBuilder, OutputDestination};
+use crate::transfer::DuplicateHandler;
+use crate::transfer::duplicates::{DedupeKey, DuplicatePolicy};
+use crate::transfer::key_store::{DedupeStore, KeyStore, SeenKey};
+use serde::Serialize;
 use serde_json;
-use std::collections::HashSet;
-use std::io::Write;
-use std::time::Instant;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime};
+use tempfile::TempPath;
 
 /// Builder for creating JSON files from vocabulary cards.
 ///
 /// This struct manages the creation of a JSON file containing vocabulary cards, handling:
-/// - Card collection and duplicate detection
+/// - Card collection and duplicate resolution
 /// - JSON file generation with pretty printing
+///
+/// Created with [`JsonOutputBuilder::ndjson`], it instead streams JSON Lines:
+/// every card is written as one line as soon as it is added, so memory use
+/// does not grow with the deck.
+///
+/// With [`JsonOutputBuilder::with_metadata_header`], the deck name and
+/// languages come first: the array becomes `{"deck": {...}, "cards": [...]}`
+/// and JSON Lines start with a `{"deck": {...}}` line.
+/// [`JsonOutputBuilder::with_envelope`] adds the export statistics:
+/// `{"deck": {...}, "stats": {...}, "cards": [...]}`.
+///
+/// The output follows the JSON Schema in [`schema`](crate::output::schema);
+/// [`JsonOutputBuilder::with_validation`] checks it before writing.
 pub struct JsonOutputBuilder {
     cards: Vec<VocabularyCard>,
-    existing_words: HashSet<String>,
+    duplicates: DuplicateHandler,
+    sort: SortOrder,
     start_time: Instant,
+    /// Export time for the envelope, left out of deterministic output
+    exported: Option<SystemTime>,
+    stream: Option<LineStream>,
+    metadata_header: bool,
+    envelope: bool,
+    pretty: bool,
+    validate: bool,
+    /// Cards skipped or merged as duplicates
+    skipped: usize,
+    deck: Option<DeckMetadata>,
+    #[cfg(feature = "lang")]
+    languages: bool,
+}
+
+/// The JSON document with a metadata header.
+#[derive(Serialize)]
+struct Document<'a> {
+    deck: Option<&'a DeckMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stats: Option<DocumentStats>,
+    cards: Vec<&'a VocabularyCard>,
+}
+
+/// Statistics of the export in an envelope.
+#[derive(Serialize)]
+struct DocumentStats {
+    cards: usize,
+    duplicates: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exported_at: Option<String>,
+}
+
+/// The cards of a written [`Document`], read back for verification.
+#[derive(serde::Deserialize)]
+struct DocumentCards {
+    cards: Vec<serde_json::Value>,
+}
+
+/// JSON Lines output, written card by card.
+struct LineStream {
+    writer: Box<dyn Write + Send + Sync>,
+    policy: DuplicatePolicy,
+    key: DedupeKey,
+    seen: Box<dyn KeyStore>,
+    header: bool,
+    validate: bool,
+    /// Temporary file the lines go to, moved to the output path by `write`
+    temp: Mutex<Option<TempPath>>,
+}
+
+impl LineStream {
+    /// Writes the `{"deck": {...}}` line, unless cards were written already.
+    fn add_header(&mut self, deck: &DeckMetadata) -> Result<()> {
+        if self.header || !self.seen.is_empty() {
+            return Ok(());
+        }
+        let line = serde_json::json!({ "deck": deck });
+        if self.validate {
+            schema::validate_line(&line)?;
+        }
+        serde_json::to_writer(&mut self.writer, &line)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        self.header = true;
+        Ok(())
+    }
+
+    fn add(&mut self, card: &VocabularyCard) -> Result<bool> {
+        let key = self.key.of(card);
+        match self.seen.get(&key)? {
+            None => {
+                let index = self.seen.len();
+                self.seen.insert(key, index)?;
+            }
+            Some(SeenKey::EarlierRun) => return Ok(false),
+            Some(SeenKey::At(_)) => match self.policy {
+                DuplicatePolicy::First => return Ok(false),
+                DuplicatePolicy::KeepAll => {}
+                DuplicatePolicy::Error => {
+                    return Err(DuoloadError::DuplicateWord(card.word.clone()));
+                }
+                policy => return Err(DuoloadError::StreamingDuplicatePolicy(policy)),
+            },
+        }
+
+        if self.validate {
+            schema::validate_line(&serde_json::to_value(card)?)?;
+        }
+        serde_json::to_writer(&mut self.writer, card)?;
+        self.writer.write_all(b"\n")?;
+        // Readers such as jq see each card right away
+        self.writer.flush()?;
+        Ok(true)
+    }
 }
 
 impl Default for JsonOutputBuilder {
@@ -32,50 +151,271 @@ impl JsonOutputBuilder {
     pub fn new() -> Self {
         Self {
             cards: Vec::new(),
-            existing_words: HashSet::new(),
+            duplicates: DuplicateHandler::new(),
+            sort: SortOrder::default(),
             start_time: Instant::now(),
+            exported: Some(SystemTime::now()),
+            stream: None,
+            metadata_header: false,
+            envelope: false,
+            pretty: true,
+            validate: false,
+            skipped: 0,
+            deck: None,
+            #[cfg(feature = "lang")]
+            languages: false,
+        }
+    }
+
+    /// Puts the deck name and languages in front of the cards.
+    pub fn with_metadata_header(mut self, enabled: bool) -> Self {
+        self.metadata_header = enabled;
+        self
+    }
+
+    /// Puts the deck name and languages, and the number of cards and
+    /// duplicates, in front of the cards. Not available for JSON Lines.
+    pub fn with_envelope(mut self, enabled: bool) -> Self {
+        self.envelope = enabled;
+        self
+    }
+
+    /// Leaves the export time out of the envelope, so that identical cards
+    /// are written as identical files.
+    pub fn with_deterministic(mut self, enabled: bool) -> Self {
+        if enabled {
+            self.exported = None;
+        }
+        self
+    }
+
+    /// Indents the JSON, as by default, or writes it on a single line.
+    /// JSON Lines are always written one card per line.
+    pub fn with_pretty(mut self, pretty: bool) -> Self {
+        self.pretty = pretty;
+        self
+    }
+
+    /// Checks the output against the JSON Schema before writing it, and
+    /// fails with [`DuoloadError::InvalidOutput`] instead of writing output
+    /// that does not match. JSON Lines are checked line by line.
+    pub fn with_validation(mut self, enabled: bool) -> Self {
+        self.validate = enabled;
+        if let Some(stream) = &mut self.stream {
+            stream.validate = enabled;
         }
+        self
+    }
+
+    /// Adds the detected languages of the word and translation of each card
+    /// as `"language": {"word": "de", "translation": "en"}`. Not available
+    /// for JSON Lines.
+    #[cfg(feature = "lang")]
+    pub fn with_languages(mut self, enabled: bool) -> Self {
+        self.languages = enabled;
+        self
+    }
+
+    /// The added cards, with their languages if enabled.
+    fn annotated_cards(&self) -> Cow<'_, [VocabularyCard]> {
+        #[cfg(feature = "lang")]
+        if self.languages {
+            let mut cards = self.cards.clone();
+            crate::lang::annotate_languages(&mut cards);
+            return Cow::Owned(cards);
+        }
+        Cow::Borrowed(&self.cards)
+    }
+
+    /// Whether the cards are wrapped in a document instead of an array.
+    fn is_document(&self) -> bool {
+        self.metadata_header || self.envelope
+    }
+
+    fn write_document<W: Write>(&self, writer: W) -> Result<()> {
+        let annotated = self.annotated_cards();
+        let cards = self.sort.sorted(&annotated);
+        let result = if self.is_document() {
+            let stats = self.envelope.then(|| DocumentStats {
+                cards: self.cards.len(),
+                duplicates: self.skipped,
+                exported_at: self
+                    .exported
+                    .map(|time| UtcDateTime::from_system_time(time).rfc3339()),
+            });
+            let document = Document {
+                deck: self.deck.as_ref(),
+                stats,
+                cards,
+            };
+            self.check(&document)?;
+            self.serialize(writer, &document)
+        } else {
+            self.check(&cards)?;
+            self.serialize(writer, &cards)
+        };
+        result.map_err(|e| anyhow::anyhow!("Failed to write JSON: {}", e))?;
+        Ok(())
+    }
+
+    /// Checks `value` against the JSON Schema, if enabled.
+    fn check<T: Serialize>(&self, value: &T) -> Result<()> {
+        if self.validate {
+            schema::validate_document(&serde_json::to_value(value)?)?;
+        }
+        Ok(())
+    }
+
+    fn serialize<W: Write, T: Serialize>(&self, writer: W, value: &T) -> serde_json::Result<()> {
+        if self.pretty {
+            serde_json::to_writer_pretty(writer, value)
+        } else {
+            serde_json::to_writer(writer, value)
+        }
+    }
+
+    /// Creates a builder streaming JSON Lines to `writer` as cards are added.
+    ///
+    /// Duplicates can only be skipped, kept or rejected, since earlier lines
+    /// are already written when a repeated word shows up.
+    pub fn ndjson<W: Write + Send + Sync + 'static>(writer: W) -> Self {
+        Self {
+            stream: Some(LineStream {
+                writer: Box::new(writer),
+                policy: DuplicatePolicy::default(),
+                key: DedupeKey::default(),
+                seen: Box::new(HashMap::<String, usize>::new()),
+                header: false,
+                validate: false,
+                temp: Mutex::new(None),
+            }),
+            ..Self::new()
+        }
+    }
+
+    /// Creates a builder streaming JSON Lines into a temporary file next to
+    /// `path`. Writing the builder moves the file to `path`, so an export
+    /// that fails or is interrupted leaves no partial file there.
+    pub fn ndjson_file(path: &Path) -> Result<Self> {
+        let temp = output::temp_file_next_to(path)?;
+        let mut builder = Self::ndjson(BufWriter::new(temp.reopen()?));
+        if let Some(stream) = &mut builder.stream {
+            stream.temp = Mutex::new(Some(temp.into_temp_path()));
+        }
+        Ok(builder)
     }
 }
 
 impl OutputBuilder for JsonOutputBuilder {
     fn add_note(&mut self, card: VocabularyCard) -> Result<bool> {
-        // Check for duplicates
-        if self.existing_words.contains(&card.word) {
-            return Ok(false); // Duplicate
+        match &mut self.stream {
+            Some(stream) => stream.add(&card),
+            None => {
+                let added = self.duplicates.add(&mut self.cards, card)?;
+                if !added {
+                    self.skipped += 1;
+                }
+                Ok(added)
+            }
         }
+    }
 
-        // Clone the word before moving the card
-        let word = card.word.clone();
+    fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        if let Some(stream) = &mut self.stream {
+            stream.policy = policy;
+        }
+        self.duplicates.set_policy(policy);
+    }
 
-        // Add the card
-        self.cards.push(card);
-        self.existing_words.insert(word);
-        Ok(true)
+    fn set_dedupe_key(&mut self, key: DedupeKey) {
+        if let Some(stream) = &mut self.stream {
+            stream.key = key;
+        }
+        self.duplicates.set_key(key);
+    }
+
+    fn set_dedupe_store(&mut self, store: &DedupeStore) {
+        if let Some(stream) = &mut self.stream {
+            stream.seen = Box::new(store.key_store());
+        }
+        self.duplicates.set_store(store);
+    }
+
+    fn set_sort_order(&mut self, order: SortOrder) {
+        self.sort = order;
+    }
+
+    fn set_deck_metadata(&mut self, metadata: &DeckMetadata) {
+        if !self.is_document() {
+            return;
+        }
+        if let Some(stream) = &mut self.stream
+            && let Err(e) = stream.add_header(metadata)
+        {
+            tracing::warn!("Failed to write the deck metadata line: {}", e);
+        }
+        self.deck = Some(metadata.clone());
+    }
+
+    fn writes_in_place(&self) -> bool {
+        // Streamed lines go straight to their writer
+        self.stream.is_some()
     }
 
     fn write(&self, dest: OutputDestination<'_>) -> Result<()> {
+        if let Some(stream) = &self.stream {
+            // Every line went out as it was added
+            let temp = stream.temp.lock().unwrap().take();
+            if let (Some(temp), OutputDestination::File(path)) = (temp, dest) {
+                output::persist_temp_file(temp, path)?;
+            }
+            tracing::info!(
+                "JSON Lines written successfully at {:?}",
+                self.start_time.elapsed()
+            );
+            return Ok(());
+        }
+
         match dest {
             OutputDestination::Writer(writer) => {
                 // Write directly to the writer
-                serde_json::to_writer_pretty(writer, &self.cards)
-                    .map_err(|e| anyhow::anyhow!("Failed to write JSON: {}", e))?;
+                self.write_document(writer)?;
             }
             OutputDestination::File(path) => {
                 // Create a file and write to it
                 let file = std::fs::File::create(path)?;
                 let mut writer = std::io::BufWriter::new(file);
-                serde_json::to_writer_pretty(&mut writer, &self.cards)
-                    .map_err(|e| anyhow::anyhow!("Failed to write JSON: {}", e))?;
+                self.write_document(&mut writer)?;
                 writer.flush()?;
             }
         }
 
-        println!(
+        tracing::info!(
             "JSON written successfully at {:?}",
             self.start_time.elapsed()
         );
 
         Ok(())
     }
+
+    fn count_written_notes(&self, path: &Path) -> Result<Option<usize>> {
+        if let Some(stream) = &self.stream {
+            let mut count: usize = 0;
+            for line in BufReader::new(std::fs::File::open(path)?).lines() {
+                if !line?.trim().is_empty() {
+                    count += 1;
+                }
+            }
+            // The metadata line is not a card
+            return Ok(Some(count.saturating_sub(usize::from(stream.header))));
+        }
+
+        let content = std::fs::read(path)?;
+        if self.is_document() {
+            let document: DocumentCards = serde_json::from_slice(&content)?;
+            return Ok(Some(document.cards.len()));
+        }
+        let cards: Vec<serde_json::Value> = serde_json::from_slice(&content)?;
+        Ok(Some(cards.len()))
+    }
 }
@@ -0,0 +1,212 @@
+//! Per-language output files for decks mixing several languages.
+
+use crate::duocards::models::{DeckMetadata, VocabularyCard};
+use crate::error::{DuoloadError, Result};
+use crate::lang::detect_languages;
+use crate::output::sort::SortOrder;
+use crate::output::{OutputBuilder, OutputDestination, write_atomically};
+use crate::transfer::DuplicateHandler;
+use crate::transfer::duplicates::{DedupeKey, DuplicatePolicy};
+use crate::transfer::key_store::DedupeStore;
+use std::path::{Path, PathBuf};
+
+/// Output builder that writes one file per detected language.
+///
+/// `deck.json` becomes `deck.de.json`, `deck.es.json` and so on, each written
+/// by a fresh builder from `new_builder`. Duplicates are resolved across the
+/// whole deck before splitting.
+pub struct LanguageSplitBuilder<B, F> {
+    new_builder: F,
+    /// Checks cards as they are added, before the files are built
+    probe: B,
+    cards: Vec<VocabularyCard>,
+    duplicates: DuplicateHandler,
+    sort: SortOrder,
+    metadata: Option<DeckMetadata>,
+}
+
+impl<B, F> LanguageSplitBuilder<B, F>
+where
+    B: OutputBuilder,
+    F: Fn() -> B + Send + Sync,
+{
+    pub fn new(new_builder: F) -> Self {
+        Self {
+            probe: new_builder(),
+            new_builder,
+            cards: Vec::new(),
+            duplicates: DuplicateHandler::new(),
+            sort: SortOrder::default(),
+            metadata: None,
+        }
+    }
+
+    /// Cards grouped by language, in order of first appearance.
+    fn groups(&self) -> Vec<(String, Vec<VocabularyCard>)> {
+        let mut groups: Vec<(String, Vec<VocabularyCard>)> = Vec::new();
+        for (card, language) in self.cards.iter().zip(detect_languages(&self.cards)) {
+            match groups.iter_mut().find(|(lang, _)| *lang == language) {
+                Some((_, cards)) => cards.push(card.clone()),
+                None => groups.push((language, vec![card.clone()])),
+            }
+        }
+        groups
+    }
+}
+
+impl<B, F> OutputBuilder for LanguageSplitBuilder<B, F>
+where
+    B: OutputBuilder,
+    F: Fn() -> B + Send + Sync,
+{
+    fn add_note(&mut self, card: VocabularyCard) -> Result<bool> {
+        self.probe.check_card(&card)?;
+        self.duplicates.add(&mut self.cards, card)
+    }
+
+    fn check_card(&self, card: &VocabularyCard) -> Result<()> {
+        self.probe.check_card(card)
+    }
+
+    fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        self.duplicates.set_policy(policy);
+    }
+
+    fn set_dedupe_key(&mut self, key: DedupeKey) {
+        self.duplicates.set_key(key);
+    }
+
+    fn set_dedupe_store(&mut self, store: &DedupeStore) {
+        self.duplicates.set_store(store);
+    }
+
+    fn set_sort_order(&mut self, order: SortOrder) {
+        self.sort = order;
+    }
+
+    fn set_deck_metadata(&mut self, metadata: &DeckMetadata) {
+        self.metadata = Some(metadata.clone());
+    }
+
+    fn writes_in_place(&self) -> bool {
+        // Each language file is written by its own builder
+        true
+    }
+
+    fn write(&self, dest: OutputDestination<'_>) -> Result<()> {
+        let OutputDestination::File(path) = dest else {
+            return Err(DuoloadError::SplitOutputNotSupported);
+        };
+
+        for (language, cards) in self.groups() {
+            let count = cards.len();
+            let mut builder = (self.new_builder)();
+            // Duplicates are resolved already
+            builder.set_duplicate_policy(DuplicatePolicy::KeepAll);
+            builder.set_sort_order(self.sort);
+            if let Some(metadata) = &self.metadata {
+                builder.set_deck_metadata(metadata);
+            }
+            for card in cards {
+                builder.add_note(card)?;
+            }
+            let language_path = language_path(path, &language);
+            if builder.writes_in_place() {
+                builder.write(OutputDestination::File(&language_path))?;
+            } else {
+                write_atomically(&builder, &language_path)?;
+            }
+            tracing::info!("Wrote {} {} cards to {:?}", count, language, language_path);
+        }
+        Ok(())
+    }
+
+    fn count_written_notes(&self, path: &Path) -> Result<Option<usize>> {
+        let mut total = 0;
+        for (language, _) in self.groups() {
+            match self
+                .probe
+                .count_written_notes(&language_path(path, &language))?
+            {
+                Some(count) => total += count,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(total))
+    }
+
+    fn written_files(&self, path: &Path) -> Vec<PathBuf> {
+        self.groups()
+            .into_iter()
+            .map(|(language, _)| language_path(path, &language))
+            .collect()
+    }
+}
+
+/// Inserts the language before the extension: `deck.json` becomes `deck.de.json`.
+pub fn language_path(path: &Path, language: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut name = format!("{}.{}", stem, language);
+    if let Some(extension) = path.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duocards::models::LearningStatus;
+    use crate::output::json::JsonOutputBuilder;
+    use tempfile::tempdir;
+
+    fn card(word: &str, example: &str) -> VocabularyCard {
+        VocabularyCard {
+            word: word.to_string(),
+            example: Some(example.to_string()),
+            status: LearningStatus::New,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_language_path() {
+        assert_eq!(
+            language_path(Path::new("out/deck.json"), "de"),
+            PathBuf::from("out/deck.de.json")
+        );
+    }
+
+    #[test]
+    fn test_split_json_by_language() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("deck.json");
+        let mut builder = LanguageSplitBuilder::new(JsonOutputBuilder::new);
+        builder.add_note(card(
+            "Haus",
+            "Das Haus ist sehr groß und hat einen schönen Garten.",
+        ))?;
+        builder.add_note(card(
+            "perro",
+            "El perro duerme todos los días debajo de la mesa de la cocina.",
+        ))?;
+        builder.add_note(card(
+            "Hund",
+            "Der Hund schläft jeden Tag unter dem Tisch in der Küche.",
+        ))?;
+
+        builder.write(OutputDestination::File(&path))?;
+
+        let german: Vec<serde_json::Value> =
+            serde_json::from_slice(&std::fs::read(dir.path().join("deck.de.json"))?)?;
+        assert_eq!(german.len(), 2);
+        assert!(dir.path().join("deck.es.json").exists());
+        assert!(!path.exists());
+        assert_eq!(builder.count_written_notes(&path)?, Some(3));
+        Ok(())
+    }
+}
@@ -0,0 +1,160 @@
+//! Sidecar manifests describing written exports.
+//!
+//! A manifest records the SHA-256 hash and size of every output file along
+//! with the export statistics, so backup tooling can check integrity and
+//! detect changes without parsing the exports themselves.
+
+use crate::error::Result;
+use crate::output::timestamp::UtcDateTime;
+use crate::transfer::processor::TransferStats;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Suffix appended to an output path to name its manifest.
+pub const MANIFEST_SUFFIX: &str = ".manifest.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub tool: String,
+    pub version: String,
+    pub created_at: String,
+    pub deck_id: String,
+    pub total_cards: usize,
+    pub duplicates: usize,
+    pub files: Vec<ManifestFile>,
+}
+
+/// An output file listed in a manifest.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestFile {
+    /// File name, relative to the manifest
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl Manifest {
+    pub fn new(deck_id: &str, stats: &TransferStats) -> Self {
+        Self {
+            tool: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            created_at: UtcDateTime::from_system_time(SystemTime::now()).rfc3339(),
+            deck_id: deck_id.to_string(),
+            total_cards: stats.total_cards,
+            duplicates: stats.duplicates,
+            files: Vec::new(),
+        }
+    }
+
+    /// Hashes `path` and lists it in the manifest.
+    pub fn add_file(&mut self, path: &Path) -> Result<()> {
+        let (size, sha256) = hash_file(path)?;
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        self.files.push(ManifestFile {
+            path: name,
+            size,
+            sha256,
+        });
+        Ok(())
+    }
+
+    /// Lists in-memory content, such as an archive entry, in the manifest.
+    pub fn add_entry(&mut self, name: &str, content: &[u8]) {
+        self.files.push(ManifestFile {
+            path: name.to_string(),
+            size: content.len() as u64,
+            sha256: hex_digest(Sha256::digest(content).as_slice()),
+        });
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, content + "\n")?;
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let file = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    /// Files that are missing or differ from the manifest, resolved next to `manifest_path`.
+    pub fn changed_files(&self, manifest_path: &Path) -> Result<Vec<PathBuf>> {
+        let dir = manifest_path.parent().unwrap_or(Path::new(""));
+        let mut changed = Vec::new();
+        for file in &self.files {
+            let path = dir.join(&file.path);
+            let matches = match hash_file(&path) {
+                Ok((size, sha256)) => size == file.size && sha256 == file.sha256,
+                Err(_) => false,
+            };
+            if !matches {
+                changed.push(path);
+            }
+        }
+        Ok(changed)
+    }
+}
+
+/// The manifest path for an output file: `deck.apkg` becomes `deck.apkg.manifest.json`.
+pub fn manifest_path(output: &Path) -> PathBuf {
+    let mut name = output.as_os_str().to_owned();
+    name.push(MANIFEST_SUFFIX);
+    PathBuf::from(name)
+}
+
+/// Returns the size and hex-encoded SHA-256 hash of a file.
+pub fn hash_file(path: &Path) -> Result<(u64, String)> {
+    let mut hasher = Sha256::new();
+    let size = io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok((size, hex_digest(hasher.finalize().as_slice())))
+}
+
+pub(crate) fn hex_digest(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_manifest_round_trip() -> Result<()> {
+        let dir = tempdir()?;
+        let output = dir.path().join("deck.json");
+        std::fs::write(&output, "[]")?;
+
+        let stats = TransferStats {
+            total_cards: 0,
+            duplicates: 2,
+            ..TransferStats::default()
+        };
+        let mut manifest = Manifest::new("deck", &stats);
+        manifest.add_file(&output)?;
+        let path = manifest_path(&output);
+        manifest.write(&path)?;
+
+        assert_eq!(path, dir.path().join("deck.json.manifest.json"));
+        let read = Manifest::read(&path)?;
+        assert_eq!(read, manifest);
+        assert_eq!(read.files[0].path, "deck.json");
+        assert_eq!(read.files[0].size, 2);
+        assert_eq!(
+            read.files[0].sha256,
+            "4f53cda18c2baa0c0354bb5f9a3ecbe5ed12ab4d8e11ba873c2f11161202b945"
+        );
+        assert!(read.changed_files(&path)?.is_empty());
+
+        std::fs::write(&output, "[{}]")?;
+        assert_eq!(read.changed_files(&path)?, vec![output]);
+        Ok(())
+    }
+}
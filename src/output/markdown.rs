@@ -0,0 +1,183 @@
+//! Markdown output for note-taking apps.
+
+use crate::duocards::models::{DeckMetadata, VocabularyCard};
+use crate::error::Result;
+use crate::output::sort::SortOrder;
+use crate::output::{OutputBuilder, OutputDestination};
+use crate::transfer::DuplicateHandler;
+use crate::transfer::duplicates::{DedupeKey, DuplicatePolicy};
+use crate::transfer::key_store::DedupeStore;
+use clap::ValueEnum;
+use std::io::Write;
+use std::path::Path;
+use std::time::Instant;
+
+/// Tag marking a note as a flashcard deck for Obsidian's spaced repetition plugin.
+pub const FLASHCARDS_TAG: &str = "#flashcards";
+
+/// How the cards are laid out in the Markdown file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum MarkdownStyle {
+    /// A table with word, translation and example columns
+    #[default]
+    Table,
+    /// Flashcards for Obsidian's spaced repetition plugin: `word::translation`,
+    /// or `word`, `?` and the translation with the example on separate lines
+    Obsidian,
+}
+
+/// Builder for Markdown files listing the vocabulary.
+///
+/// The file starts with the deck name as heading, if the deck has one.
+pub struct MarkdownOutputBuilder {
+    cards: Vec<VocabularyCard>,
+    duplicates: DuplicateHandler,
+    sort: SortOrder,
+    style: MarkdownStyle,
+    title: Option<String>,
+    start_time: Instant,
+}
+
+impl Default for MarkdownOutputBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MarkdownOutputBuilder {
+    pub fn new() -> Self {
+        Self {
+            cards: Vec::new(),
+            duplicates: DuplicateHandler::new(),
+            sort: SortOrder::default(),
+            style: MarkdownStyle::default(),
+            title: None,
+            start_time: Instant::now(),
+        }
+    }
+
+    pub fn with_style(mut self, style: MarkdownStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    fn write_document<W: Write>(&self, mut writer: W) -> Result<()> {
+        if let Some(title) = &self.title {
+            writeln!(writer, "# {}", title)?;
+            writeln!(writer)?;
+        }
+        match self.style {
+            MarkdownStyle::Table => {
+                writeln!(writer, "| Word | Translation | Example |")?;
+                writeln!(writer, "| --- | --- | --- |")?;
+                for card in self.sort.sorted(&self.cards) {
+                    writeln!(
+                        writer,
+                        "| {} | {} | {} |",
+                        table_cell(&card.word),
+                        table_cell(&card.translation),
+                        table_cell(card.example.as_deref().unwrap_or(""))
+                    )?;
+                }
+            }
+            MarkdownStyle::Obsidian => {
+                writeln!(writer, "{}", FLASHCARDS_TAG)?;
+                for card in self.sort.sorted(&self.cards) {
+                    writeln!(writer)?;
+                    let word = single_line(&card.word);
+                    let translation = single_line(&card.translation);
+                    match &card.example {
+                        Some(example) => {
+                            writeln!(writer, "{}\n?\n{}", word, translation)?;
+                            // A blank line would end the card
+                            for line in example.lines().filter(|line| !line.trim().is_empty()) {
+                                writeln!(writer, "*{}*", line.trim())?;
+                            }
+                        }
+                        None => writeln!(writer, "{}::{}", word, translation)?,
+                    }
+                }
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Escapes pipes, which end a cell, and turns line breaks into `<br>`.
+fn table_cell(text: &str) -> String {
+    text.replace('|', "\\|")
+        .lines()
+        .collect::<Vec<_>>()
+        .join("<br>")
+}
+
+/// Joins the lines of `text`, as line breaks separate the parts of a flashcard.
+fn single_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+impl OutputBuilder for MarkdownOutputBuilder {
+    fn add_note(&mut self, card: VocabularyCard) -> Result<bool> {
+        self.duplicates.add(&mut self.cards, card)
+    }
+
+    fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        self.duplicates.set_policy(policy);
+    }
+
+    fn set_dedupe_key(&mut self, key: DedupeKey) {
+        self.duplicates.set_key(key);
+    }
+
+    fn set_dedupe_store(&mut self, store: &DedupeStore) {
+        self.duplicates.set_store(store);
+    }
+
+    fn set_sort_order(&mut self, order: SortOrder) {
+        self.sort = order;
+    }
+
+    fn set_deck_metadata(&mut self, metadata: &DeckMetadata) {
+        self.title = metadata.name.clone();
+    }
+
+    fn write(&self, dest: OutputDestination<'_>) -> Result<()> {
+        match dest {
+            OutputDestination::Writer(writer) => self.write_document(writer)?,
+            OutputDestination::File(path) => {
+                let file = std::fs::File::create(path)?;
+                self.write_document(std::io::BufWriter::new(file))?;
+            }
+        }
+
+        tracing::info!(
+            "Markdown written successfully at {:?}",
+            self.start_time.elapsed()
+        );
+
+        Ok(())
+    }
+
+    fn count_written_notes(&self, path: &Path) -> Result<Option<usize>> {
+        let content = std::fs::read_to_string(path)?;
+        let count = match self.style {
+            // Every row after the header and the separator
+            MarkdownStyle::Table => content
+                .lines()
+                .filter(|line| line.starts_with('|'))
+                .count()
+                .saturating_sub(2),
+            // Every block after a blank line, except the tag below the title
+            MarkdownStyle::Obsidian => {
+                let blocks = content
+                    .lines()
+                    .zip(content.lines().skip(1))
+                    .filter(|(previous, line)| previous.is_empty() && !line.is_empty())
+                    .count();
+                blocks.saturating_sub(usize::from(self.title.is_some()))
+            }
+        };
+        Ok(Some(count))
+    }
+}
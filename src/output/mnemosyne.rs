@@ -0,0 +1,184 @@
+//! Mnemosyne XML output.
+
+use crate::duocards::models::{DeckMetadata, VocabularyCard};
+use crate::error::Result;
+use crate::output::anki::DEFAULT_DECK_NAME;
+use crate::output::sort::SortOrder;
+use crate::output::{OutputBuilder, OutputDestination};
+use crate::transfer::DuplicateHandler;
+use crate::transfer::duplicates::{DedupeKey, DuplicatePolicy};
+use crate::transfer::key_store::DedupeStore;
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Builder for Mnemosyne XML files, as imported by Mnemosyne with
+/// "File > Import > Mnemosyne 1.x XML".
+///
+/// Each card becomes an item with the word as question and the translation,
+/// followed by the example, as answer. All items go into one category named
+/// after the deck. Learning progress is not carried over, so Mnemosyne
+/// schedules every imported item as new.
+pub struct MnemosyneOutputBuilder {
+    cards: Vec<VocabularyCard>,
+    duplicates: DuplicateHandler,
+    sort: SortOrder,
+    category: String,
+    /// Whether the category is taken from the deck metadata
+    category_from_deck: bool,
+    exported: SystemTime,
+    start_time: Instant,
+}
+
+impl Default for MnemosyneOutputBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MnemosyneOutputBuilder {
+    pub fn new() -> Self {
+        Self {
+            cards: Vec::new(),
+            duplicates: DuplicateHandler::new(),
+            sort: SortOrder::default(),
+            category: DEFAULT_DECK_NAME.to_string(),
+            category_from_deck: true,
+            exported: SystemTime::now(),
+            start_time: Instant::now(),
+        }
+    }
+
+    /// Sets the category of the items; by default the name of the Duocards deck.
+    pub fn with_category(mut self, category: impl Into<String>) -> Self {
+        self.category = category.into();
+        self.category_from_deck = false;
+        self
+    }
+
+    /// Sets the time written as the start of the collection, now by default.
+    pub fn with_export_time(mut self, time: SystemTime) -> Self {
+        self.exported = time;
+        self
+    }
+
+    fn write_document<W: Write>(&self, mut writer: W) -> Result<()> {
+        let start = self
+            .exported
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let category = escape(&self.category);
+
+        writeln!(writer, r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+        writeln!(
+            writer,
+            r#"<mnemosyne core_version="1" time_of_start="{}">"#,
+            start
+        )?;
+        writeln!(
+            writer,
+            r#"<category active="1"><name>{}</name></category>"#,
+            category
+        )?;
+        for card in self.sort.sorted(&self.cards) {
+            let mut answer = escape(&card.translation);
+            if let Some(example) = &card.example {
+                answer.push('\n');
+                answer.push_str(&escape(example));
+            }
+            writeln!(writer, r#"<item id="{}">"#, item_id(card))?;
+            writeln!(writer, "<cat>{}</cat>", category)?;
+            writeln!(writer, "<Q>{}</Q>", escape(&card.word))?;
+            writeln!(writer, "<A>{}</A>", answer)?;
+            writeln!(writer, "</item>")?;
+        }
+        writeln!(writer, "</mnemosyne>")?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// A stable ID, so importing a later export again finds the same items.
+fn item_id(card: &VocabularyCard) -> String {
+    let mut hasher = Sha256::new();
+    match &card.source_id {
+        Some(id) => hasher.update(id.as_bytes()),
+        None => {
+            hasher.update(card.word.as_bytes());
+            hasher.update([0x1f]);
+            hasher.update(card.translation.as_bytes());
+        }
+    }
+    hasher.finalize()[..4]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Escapes the characters with a meaning in XML text and attributes.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl OutputBuilder for MnemosyneOutputBuilder {
+    fn add_note(&mut self, card: VocabularyCard) -> Result<bool> {
+        self.duplicates.add(&mut self.cards, card)
+    }
+
+    fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        self.duplicates.set_policy(policy);
+    }
+
+    fn set_dedupe_key(&mut self, key: DedupeKey) {
+        self.duplicates.set_key(key);
+    }
+
+    fn set_dedupe_store(&mut self, store: &DedupeStore) {
+        self.duplicates.set_store(store);
+    }
+
+    fn set_sort_order(&mut self, order: SortOrder) {
+        self.sort = order;
+    }
+
+    fn set_deck_metadata(&mut self, metadata: &DeckMetadata) {
+        if let Some(name) = &metadata.name
+            && self.category_from_deck
+        {
+            self.category = name.clone();
+        }
+    }
+
+    fn write(&self, dest: OutputDestination<'_>) -> Result<()> {
+        match dest {
+            OutputDestination::Writer(writer) => self.write_document(writer)?,
+            OutputDestination::File(path) => {
+                let file = std::fs::File::create(path)?;
+                self.write_document(std::io::BufWriter::new(file))?;
+            }
+        }
+
+        tracing::info!(
+            "Mnemosyne XML written successfully at {:?}",
+            self.start_time.elapsed()
+        );
+
+        Ok(())
+    }
+
+    fn count_written_notes(&self, path: &Path) -> Result<Option<usize>> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(Some(content.matches("<item ").count()))
+    }
+}
@@ -1,10 +1,47 @@
-use crate::duocards::models::VocabularyCard;
+use crate::duocards::models::{DeckMetadata, VocabularyCard};
 use crate::error::Result;
+use crate::transfer::duplicates::{DedupeKey, DuplicatePolicy};
+use crate::transfer::key_store::DedupeStore;
+use clap::ValueEnum;
+use sort::SortOrder;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub mod anki;
+pub mod binary;
+pub mod chunked;
+pub mod csv;
+pub mod encrypted;
 pub mod json;
+#[cfg(feature = "lang")]
+pub mod languages;
+pub mod manifest;
+pub mod markdown;
+pub mod mnemosyne;
+pub mod multi;
+pub mod raw;
+pub mod registry;
+pub mod retention;
+pub mod schema;
+pub mod sort;
+pub mod sqlite;
+pub mod store;
+pub mod timestamp;
+pub mod yaml;
+
+/// The file formats cards can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Anki,
+    Json,
+    Csv,
+    Sqlite,
+    Mnemosyne,
+    Markdown,
+    Yaml,
+    Msgpack,
+    Cbor,
+}
 
 /// Output destination for builders
 pub enum OutputDestination<'a> {
@@ -15,6 +52,132 @@ pub enum OutputDestination<'a> {
 }
 
 pub trait OutputBuilder: Send + Sync {
+    /// Adds a card, returning `false` if it repeats a card already in the output.
     fn add_note(&mut self, card: VocabularyCard) -> Result<bool>;
+    /// Fails with [`DuoloadError::BadCard`] if the format cannot hold `card`;
+    /// `add_note` rejects such cards too.
+    fn check_card(&self, _card: &VocabularyCard) -> Result<()> {
+        Ok(())
+    }
+    /// Sets how repeated words are resolved by `add_note`.
+    fn set_duplicate_policy(&mut self, policy: DuplicatePolicy);
+    /// Sets what makes two cards duplicates for `add_note`.
+    fn set_dedupe_key(&mut self, key: DedupeKey);
+    /// Keeps the keys of the cards `add_note` has seen in `store` instead
+    /// of in memory.
+    fn set_dedupe_store(&mut self, store: &DedupeStore);
+    /// Receives the name and languages of the deck before its first card.
+    fn set_deck_metadata(&mut self, _metadata: &DeckMetadata) {}
+    /// Sets the order `write` puts the cards in; outputs without an order,
+    /// such as databases, ignore it.
+    fn set_sort_order(&mut self, _order: SortOrder) {}
     fn write(&self, dest: OutputDestination<'_>) -> Result<()>;
+    /// Whether `write` updates the file at the given path itself, e.g. a
+    /// database, instead of writing it from scratch. Other outputs are
+    /// written with [`write_atomically`].
+    fn writes_in_place(&self) -> bool {
+        false
+    }
+    /// Re-reads a written file and returns how many notes it contains,
+    /// or `None` if the format cannot be read back.
+    fn count_written_notes(&self, _path: &Path) -> Result<Option<usize>> {
+        Ok(None)
+    }
+    /// Files `write` produced for the given path, e.g. to list them in a
+    /// manifest; outputs that are split up return each of their files.
+    fn written_files(&self, path: &Path) -> Vec<PathBuf> {
+        vec![path.to_path_buf()]
+    }
+}
+
+/// Builders chosen at runtime, e.g. from the [`registry`].
+impl<B: OutputBuilder + ?Sized> OutputBuilder for Box<B> {
+    fn add_note(&mut self, card: VocabularyCard) -> Result<bool> {
+        (**self).add_note(card)
+    }
+
+    fn check_card(&self, card: &VocabularyCard) -> Result<()> {
+        (**self).check_card(card)
+    }
+
+    fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        (**self).set_duplicate_policy(policy);
+    }
+
+    fn set_dedupe_key(&mut self, key: DedupeKey) {
+        (**self).set_dedupe_key(key);
+    }
+
+    fn set_dedupe_store(&mut self, store: &DedupeStore) {
+        (**self).set_dedupe_store(store);
+    }
+
+    fn set_deck_metadata(&mut self, metadata: &DeckMetadata) {
+        (**self).set_deck_metadata(metadata);
+    }
+
+    fn set_sort_order(&mut self, order: SortOrder) {
+        (**self).set_sort_order(order);
+    }
+
+    fn write(&self, dest: OutputDestination<'_>) -> Result<()> {
+        (**self).write(dest)
+    }
+
+    fn writes_in_place(&self) -> bool {
+        (**self).writes_in_place()
+    }
+
+    fn count_written_notes(&self, path: &Path) -> Result<Option<usize>> {
+        (**self).count_written_notes(path)
+    }
+
+    fn written_files(&self, path: &Path) -> Vec<PathBuf> {
+        (**self).written_files(path)
+    }
+}
+
+/// Writes `builder` to a temporary file in the directory of `path` and
+/// renames it to `path` once complete, so a failed write never leaves a
+/// truncated file behind or destroys an earlier export.
+pub fn write_atomically<B: OutputBuilder + ?Sized>(builder: &B, path: &Path) -> Result<()> {
+    write_file_atomically(path, |temp| builder.write(OutputDestination::File(temp)))
+}
+
+/// Runs `write` on a temporary file next to `path` and moves the file to
+/// `path` if it succeeds, like [`write_atomically`] for outputs that are not
+/// [`OutputBuilder`]s.
+pub fn write_file_atomically(path: &Path, write: impl FnOnce(&Path) -> Result<()>) -> Result<()> {
+    let temp = temp_file_next_to(path)?.into_temp_path();
+    write(&temp)?;
+    persist_temp_file(temp, path)
+}
+
+/// Creates a temporary file in the directory of `path`, to be moved there
+/// with [`persist_temp_file`] once it is complete. It is removed if it is
+/// dropped before.
+pub fn temp_file_next_to(path: &Path) -> Result<tempfile::NamedTempFile> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    Ok(tempfile::Builder::new()
+        .prefix(".duoload-")
+        .tempfile_in(dir)?)
+}
+
+/// Moves a complete temporary file from [`temp_file_next_to`] to `path`.
+pub fn persist_temp_file(temp: tempfile::TempPath, path: &Path) -> Result<()> {
+    // Temporary files are only readable by their owner
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let permissions = match std::fs::metadata(path) {
+            Ok(metadata) => metadata.permissions(),
+            Err(_) => std::fs::Permissions::from_mode(0o644),
+        };
+        std::fs::set_permissions(&temp, permissions)?;
+    }
+    temp.persist(path).map_err(|e| e.error)?;
+    Ok(())
 }
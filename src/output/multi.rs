@@ -0,0 +1,382 @@
+//! Several outputs filled from a single fetch of the deck.
+
+use crate::duocards::models::{DeckMetadata, LearningStatus, VocabularyCard};
+use crate::error::{DuoloadError, Result};
+use crate::output::retention;
+use crate::output::sort::SortOrder;
+use crate::output::{OutputBuilder, OutputDestination, write_atomically};
+use crate::transfer::duplicates::{DedupeKey, DuplicatePolicy};
+use crate::transfer::key_store::DedupeStore;
+use crate::transform::CardTransform;
+use std::path::{Path, PathBuf};
+
+/// An output of a [`MultiOutputBuilder`] and the file it is written to.
+struct Output {
+    builder: Box<dyn OutputBuilder>,
+    path: PathBuf,
+    /// Changes the cards of this output only, e.g. into sentence cards
+    transform: Option<Box<dyn CardTransform>>,
+    /// Whether the cards of this output count as added to the export
+    counted: bool,
+    /// Cards the builder took as new entries
+    added: usize,
+}
+
+/// Output builder that hands every card to several builders, each written
+/// to its own path, e.g. an Anki package and a JSON file of the same export.
+///
+/// The path passed to [`OutputBuilder::write`] is ignored. A path of `-`
+/// writes that output to stdout.
+#[derive(Default)]
+pub struct MultiOutputBuilder {
+    outputs: Vec<Output>,
+    /// Cards any output took as a new entry
+    added: usize,
+}
+
+impl MultiOutputBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an output written to `path`.
+    pub fn with_output(mut self, builder: Box<dyn OutputBuilder>, path: impl AsRef<Path>) -> Self {
+        self.outputs.push(Output {
+            builder,
+            path: path.as_ref().to_path_buf(),
+            transform: None,
+            counted: true,
+            added: 0,
+        });
+        self
+    }
+
+    /// Adds an output written to `path` that gets the cards changed by
+    /// `transform`, leaving out those it drops. Its cards do not count as
+    /// added to the export.
+    pub fn with_transformed_output<T: CardTransform + 'static>(
+        mut self,
+        builder: Box<dyn OutputBuilder>,
+        path: impl AsRef<Path>,
+        transform: T,
+    ) -> Self {
+        self.outputs.push(Output {
+            builder,
+            path: path.as_ref().to_path_buf(),
+            transform: Some(Box::new(transform)),
+            counted: false,
+            added: 0,
+        });
+        self
+    }
+
+    /// Adds an output written to `path` that only gets the cards `filter`
+    /// keeps, e.g. one of the files of each learning status. Unlike with
+    /// [`MultiOutputBuilder::with_transformed_output`], its cards count as
+    /// added to the export.
+    pub fn with_filtered_output<T: CardTransform + 'static>(
+        mut self,
+        builder: Box<dyn OutputBuilder>,
+        path: impl AsRef<Path>,
+        filter: T,
+    ) -> Self {
+        self.outputs.push(Output {
+            builder,
+            path: path.as_ref().to_path_buf(),
+            transform: Some(Box::new(filter)),
+            counted: true,
+            added: 0,
+        });
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.outputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+}
+
+impl OutputBuilder for MultiOutputBuilder {
+    /// Adds `card` to every output; it counts as added if any output without
+    /// a transform, or with a filter, took it, e.g. when only a merged
+    /// package already had the word.
+    fn add_note(&mut self, card: VocabularyCard) -> Result<bool> {
+        // A card one output rejects is left out of all of them
+        self.check_card(&card)?;
+        let mut added = false;
+        for output in &mut self.outputs {
+            let card = match &output.transform {
+                Some(transform) => match transform.transform(card.clone()) {
+                    Some(card) => card,
+                    None => continue,
+                },
+                None => card.clone(),
+            };
+            if output.builder.add_note(card)? {
+                output.added += 1;
+                added |= output.counted;
+            }
+        }
+        if added {
+            self.added += 1;
+        }
+        Ok(added)
+    }
+
+    fn check_card(&self, card: &VocabularyCard) -> Result<()> {
+        self.outputs
+            .iter()
+            .try_for_each(|output| match &output.transform {
+                Some(transform) => match transform.transform(card.clone()) {
+                    Some(card) => output.builder.check_card(&card),
+                    None => Ok(()),
+                },
+                None => output.builder.check_card(card),
+            })
+    }
+
+    fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        for output in &mut self.outputs {
+            output.builder.set_duplicate_policy(policy);
+        }
+    }
+
+    fn set_dedupe_key(&mut self, key: DedupeKey) {
+        for output in &mut self.outputs {
+            output.builder.set_dedupe_key(key);
+        }
+    }
+
+    fn set_dedupe_store(&mut self, store: &DedupeStore) {
+        // Each output skips the duplicates among its own cards, also in
+        // later runs writing it under another dated name
+        for output in &mut self.outputs {
+            let base = retention::undated_path(&output.path);
+            let namespace = base.to_string_lossy();
+            output
+                .builder
+                .set_dedupe_store(&store.namespaced(&namespace));
+        }
+    }
+
+    fn set_deck_metadata(&mut self, metadata: &DeckMetadata) {
+        for output in &mut self.outputs {
+            output.builder.set_deck_metadata(metadata);
+        }
+    }
+
+    fn set_sort_order(&mut self, order: SortOrder) {
+        for output in &mut self.outputs {
+            output.builder.set_sort_order(order);
+        }
+    }
+
+    fn writes_in_place(&self) -> bool {
+        // Every output is written to its own path
+        true
+    }
+
+    fn write(&self, _dest: OutputDestination<'_>) -> Result<()> {
+        for output in &self.outputs {
+            if output.path.as_os_str() == "-" {
+                let stdout = std::io::stdout();
+                let mut writer = stdout.lock();
+                output
+                    .builder
+                    .write(OutputDestination::Writer(&mut writer))?;
+            } else if output.builder.writes_in_place() {
+                output
+                    .builder
+                    .write(OutputDestination::File(&output.path))?;
+            } else {
+                write_atomically(&output.builder, &output.path)?;
+            }
+            tracing::info!("Wrote {:?}", output.path);
+        }
+        Ok(())
+    }
+
+    /// Checks every output written to a file against the cards it took, and
+    /// returns the number of cards any output took if they all match.
+    fn count_written_notes(&self, _path: &Path) -> Result<Option<usize>> {
+        let mut verified = false;
+        for output in &self.outputs {
+            if output.path.as_os_str() == "-" {
+                continue;
+            }
+            match output.builder.count_written_notes(&output.path)? {
+                Some(found) if found == output.added => verified = true,
+                Some(found) => {
+                    return Err(DuoloadError::VerificationFailed {
+                        expected: output.added,
+                        found,
+                    });
+                }
+                None => {}
+            }
+        }
+        Ok(verified.then_some(self.added))
+    }
+
+    /// The files of every output not written to stdout.
+    fn written_files(&self, _path: &Path) -> Vec<PathBuf> {
+        self.outputs
+            .iter()
+            .filter(|output| output.path.as_os_str() != "-")
+            .flat_map(|output| output.builder.written_files(&output.path))
+            .collect()
+    }
+}
+
+/// Inserts the status before the extension: `deck.json` becomes
+/// `deck-known.json`.
+pub fn status_path(path: &Path, status: &LearningStatus) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut name = format!("{}-{}", stem, status.as_str());
+    if let Some(extension) = path.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::csv::CsvOutputBuilder;
+    use crate::output::json::JsonOutputBuilder;
+    use crate::transform::filter::CardFilter;
+    use crate::transform::sentences::SentenceExtractor;
+    use std::time::{Duration, UNIX_EPOCH};
+    use tempfile::tempdir;
+
+    fn card(word: &str) -> VocabularyCard {
+        VocabularyCard {
+            word: word.to_string(),
+            translation: format!("{} translated", word),
+            status: LearningStatus::New,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_every_output_gets_every_card() -> Result<()> {
+        let dir = tempdir()?;
+        let json = dir.path().join("deck.json");
+        let csv = dir.path().join("deck.csv");
+        let mut builder = MultiOutputBuilder::new()
+            .with_output(Box::new(JsonOutputBuilder::new()), &json)
+            .with_output(Box::new(CsvOutputBuilder::new()), &csv);
+        assert!(builder.add_note(card("Haus"))?);
+        assert!(builder.add_note(card("Baum"))?);
+        assert!(!builder.add_note(card("Haus"))?);
+
+        builder.write(OutputDestination::File(Path::new("ignored")))?;
+
+        let cards: Vec<serde_json::Value> = serde_json::from_slice(&std::fs::read(&json)?)?;
+        assert_eq!(cards.len(), 2);
+        assert_eq!(std::fs::read_to_string(&csv)?.lines().count(), 3);
+        assert_eq!(builder.count_written_notes(Path::new("ignored"))?, Some(2));
+
+        // A damaged output fails the verification of the whole export
+        std::fs::write(&json, "[]")?;
+        assert!(matches!(
+            builder.count_written_notes(Path::new("ignored")),
+            Err(DuoloadError::VerificationFailed {
+                expected: 2,
+                found: 0
+            })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_dedupe_store_outlives_dated_names() -> Result<()> {
+        let dir = tempdir()?;
+        let state = dir.path().join("seen.db");
+        let base = dir.path().join("deck.json");
+        let run = |time: u64| -> Result<bool> {
+            let store = DedupeStore::open(&state)?;
+            let path = retention::dated_path(&base, UNIX_EPOCH + Duration::from_secs(time));
+            let mut builder =
+                MultiOutputBuilder::new().with_output(Box::new(JsonOutputBuilder::new()), path);
+            builder.set_dedupe_store(&store);
+            let added = builder.add_note(card("Haus"))?;
+            store.commit()?;
+            Ok(added)
+        };
+        assert!(run(0)?);
+        // The next day's file has another name, but the word was exported
+        assert!(!run(86400)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_transformed_output_gets_changed_cards() -> Result<()> {
+        let dir = tempdir()?;
+        let words = dir.path().join("words.json");
+        let sentences = dir.path().join("sentences.json");
+        let mut builder = MultiOutputBuilder::new()
+            .with_output(Box::new(JsonOutputBuilder::new()), &words)
+            .with_transformed_output(
+                Box::new(JsonOutputBuilder::new()),
+                &sentences,
+                SentenceExtractor::new(),
+            );
+        let mut with_example = card("Haus");
+        with_example.example = Some("Das Haus ist alt.".to_string());
+        assert!(builder.add_note(with_example)?);
+        assert!(builder.add_note(card("Baum"))?);
+
+        builder.write(OutputDestination::File(Path::new("ignored")))?;
+
+        let cards: Vec<serde_json::Value> = serde_json::from_slice(&std::fs::read(&words)?)?;
+        assert_eq!(cards.len(), 2);
+        let cards: Vec<serde_json::Value> = serde_json::from_slice(&std::fs::read(&sentences)?)?;
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0]["word"], "Das <b>Haus</b> ist alt.");
+        assert_eq!(builder.count_written_notes(Path::new("ignored"))?, Some(2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_filtered_outputs_split_the_cards() -> Result<()> {
+        let dir = tempdir()?;
+        let base = dir.path().join("deck.json");
+        let mut builder = MultiOutputBuilder::new();
+        for status in LearningStatus::ALL {
+            builder = builder.with_filtered_output(
+                Box::new(JsonOutputBuilder::new()),
+                status_path(&base, &status),
+                CardFilter::new().with_status(status),
+            );
+        }
+        let mut known = card("Baum");
+        known.status = LearningStatus::Known;
+        assert!(builder.add_note(card("Haus"))?);
+        assert!(builder.add_note(known)?);
+
+        builder.write(OutputDestination::File(Path::new("ignored")))?;
+
+        let count = |name: &str| -> Result<usize> {
+            let cards: Vec<serde_json::Value> =
+                serde_json::from_slice(&std::fs::read(dir.path().join(name))?)?;
+            Ok(cards.len())
+        };
+        assert_eq!(count("deck-new.json")?, 1);
+        assert_eq!(count("deck-learning.json")?, 0);
+        assert_eq!(count("deck-known.json")?, 1);
+        assert_eq!(builder.count_written_notes(Path::new("ignored"))?, Some(2));
+        assert_eq!(
+            builder.written_files(Path::new("ignored")),
+            LearningStatus::ALL.map(|status| status_path(&base, &status))
+        );
+        Ok(())
+    }
+}
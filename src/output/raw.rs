@@ -0,0 +1,67 @@
+//! Cards exactly as the Duocards API returns them.
+//!
+//! Unlike the other outputs, which write [`VocabularyCard`]s, the raw export
+//! keeps every field of [`Card`], such as `knownCount`, `waiting` and the
+//! image, along with the pagination cursor of each card.
+//!
+//! [`VocabularyCard`]: crate::duocards::models::VocabularyCard
+
+use crate::duocards::models::{Card, CardEdge, DuocardsResponse};
+use crate::error::Result;
+use crate::output::OutputDestination;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::Write;
+
+/// An unconverted card with the cursor of its position in the deck.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawCard {
+    #[serde(flatten)]
+    pub card: Card,
+    pub cursor: String,
+}
+
+impl From<CardEdge> for RawCard {
+    fn from(edge: CardEdge) -> Self {
+        Self {
+            card: edge.node,
+            cursor: edge.cursor,
+        }
+    }
+}
+
+/// The cards of raw API pages, such as those of a snapshot, in deck order.
+pub fn raw_cards(pages: &[Value]) -> Result<Vec<RawCard>> {
+    let mut cards = Vec::new();
+    for page in pages {
+        let response = DuocardsResponse::deserialize(page)?;
+        cards.extend(
+            response
+                .data
+                .node
+                .cards
+                .edges
+                .into_iter()
+                .map(RawCard::from),
+        );
+    }
+    Ok(cards)
+}
+
+/// Writes `cards` as a pretty-printed JSON array.
+pub fn write_raw_cards(cards: &[RawCard], dest: OutputDestination<'_>) -> Result<()> {
+    match dest {
+        OutputDestination::Writer(writer) => {
+            serde_json::to_writer_pretty(&mut *writer, cards)?;
+            writeln!(writer)?;
+            writer.flush()?;
+        }
+        OutputDestination::File(path) => {
+            let mut writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+            serde_json::to_writer_pretty(&mut writer, cards)?;
+            writeln!(writer)?;
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,465 @@
+//! Output formats looked up by name.
+//!
+//! The [`OutputRegistry`] maps names like `csv` to a function creating the
+//! builder for that format. It starts out with the built-in formats, and
+//! applications embedding duoload can [`register`](OutputRegistry::register)
+//! their own, which `--format NAME` then accepts like the built-in ones.
+
+use crate::anki::note::{CardTemplates, QuestionField, TagScheme};
+use crate::anki::package::ExistingPackage;
+use crate::anki::writer::ApkgOptions;
+use crate::error::{DuoloadError, Result};
+use crate::media::MediaStore;
+use crate::output::OutputBuilder;
+use crate::output::OutputFormat;
+use crate::output::anki::{AnkiPackageBuilder, DECK_ID, DEFAULT_DECK_NAME};
+use crate::output::binary::{BinaryEncoding, BinaryOutputBuilder};
+use crate::output::csv::{CsvOutputBuilder, CsvQuoting};
+use crate::output::json::JsonOutputBuilder;
+use crate::output::markdown::{MarkdownOutputBuilder, MarkdownStyle};
+use crate::output::mnemosyne::MnemosyneOutputBuilder;
+use crate::output::sqlite::SqliteOutputBuilder;
+use crate::output::yaml::YamlOutputBuilder;
+use clap::ValueEnum;
+use std::fmt;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+/// What a format may need to know to create its builder: the exported deck
+/// and the settings of every built-in format.
+#[derive(Debug, Clone, Default)]
+pub struct FormatContext {
+    /// ID of the exported Duocards deck
+    pub deck_id: String,
+    /// Name given to the exported deck, if any
+    pub deck_name: Option<String>,
+    /// Leaves out export times and uses fixed IDs, see `--deterministic`
+    pub deterministic: bool,
+    /// Adds the provenance of the cards to the formats with a column for it
+    pub provenance: bool,
+    pub anki: AnkiSettings,
+    pub json: JsonSettings,
+    pub csv: CsvSettings,
+    pub markdown_style: MarkdownStyle,
+}
+
+/// Settings of Anki packages, see [`AnkiPackageBuilder`].
+#[derive(Debug, Clone)]
+pub struct AnkiSettings {
+    pub deck_id: i64,
+    /// ID of the note type, or `None` for one derived from the extra fields
+    pub model_id: Option<i64>,
+    /// Custom templates, or `None` for the default ones
+    pub templates: Option<CardTemplates>,
+    pub tags: Vec<String>,
+    pub tag_scheme: TagScheme,
+    pub status_subdecks: bool,
+    pub source_fields: bool,
+    pub status_fields: bool,
+    pub hint_field: bool,
+    pub known_count_field: bool,
+    pub question_field: QuestionField,
+    pub escape_html: bool,
+    /// Options of the written file; whether it is deterministic comes from
+    /// the [`FormatContext`]
+    pub package_options: ApkgOptions,
+    pub disk_store: bool,
+    pub collection: bool,
+    /// Store of the downloaded pictures and pronunciations to add
+    pub media: Option<MediaStore>,
+    /// Package whose notes are kept, see [`AnkiPackageBuilder::with_existing`]
+    pub existing: Option<Arc<ExistingPackage>>,
+    /// Appended to the deck name, see [`AnkiPackageBuilder::with_name_suffix`]
+    pub name_suffix: String,
+    #[cfg(feature = "lang")]
+    pub language_subdecks: bool,
+    #[cfg(feature = "lang")]
+    pub language_tags: bool,
+}
+
+impl Default for AnkiSettings {
+    fn default() -> Self {
+        Self {
+            deck_id: DECK_ID,
+            model_id: None,
+            templates: None,
+            tags: Vec::new(),
+            tag_scheme: TagScheme::default(),
+            status_subdecks: false,
+            source_fields: false,
+            status_fields: false,
+            hint_field: false,
+            known_count_field: false,
+            question_field: QuestionField::None,
+            escape_html: true,
+            package_options: ApkgOptions::default(),
+            disk_store: false,
+            collection: false,
+            media: None,
+            existing: None,
+            name_suffix: String::new(),
+            #[cfg(feature = "lang")]
+            language_subdecks: false,
+            #[cfg(feature = "lang")]
+            language_tags: false,
+        }
+    }
+}
+
+/// Settings of JSON arrays, see [`JsonOutputBuilder`].
+#[derive(Debug, Clone)]
+pub struct JsonSettings {
+    pub metadata_header: bool,
+    pub envelope: bool,
+    pub pretty: bool,
+    pub validate: bool,
+    #[cfg(feature = "lang")]
+    pub languages: bool,
+}
+
+impl Default for JsonSettings {
+    fn default() -> Self {
+        Self {
+            metadata_header: false,
+            envelope: false,
+            pretty: true,
+            validate: false,
+            #[cfg(feature = "lang")]
+            languages: false,
+        }
+    }
+}
+
+/// Settings of CSV files, see [`CsvOutputBuilder`].
+#[derive(Debug, Clone)]
+pub struct CsvSettings {
+    /// Names of the columns, or `None` for the default header row
+    pub headers: Option<Vec<String>>,
+    pub delimiter: u8,
+    pub quoting: CsvQuoting,
+    pub image_column: bool,
+}
+
+impl Default for CsvSettings {
+    fn default() -> Self {
+        Self {
+            headers: None,
+            delimiter: b',',
+            quoting: CsvQuoting::default(),
+            image_column: false,
+        }
+    }
+}
+
+type NewBuilder = dyn Fn(&FormatContext) -> Box<dyn OutputBuilder> + Send + Sync;
+
+/// A named output format.
+#[derive(Clone)]
+pub struct RegisteredFormat {
+    name: String,
+    description: String,
+    new_builder: Arc<NewBuilder>,
+}
+
+impl RegisteredFormat {
+    /// A format called `name` whose builders are created by `new_builder`.
+    pub fn new<F, B>(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        new_builder: F,
+    ) -> Self
+    where
+        F: Fn(&FormatContext) -> B + Send + Sync + 'static,
+        B: OutputBuilder + 'static,
+    {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            new_builder: Arc::new(move |context| Box::new(new_builder(context))),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// The built-in format of this name, if it is one.
+    pub fn builtin(&self) -> Option<OutputFormat> {
+        OutputFormat::from_str(&self.name, false).ok()
+    }
+
+    /// Creates a builder for one export.
+    pub fn new_builder(&self, context: &FormatContext) -> Box<dyn OutputBuilder> {
+        (self.new_builder)(context)
+    }
+}
+
+impl fmt::Debug for RegisteredFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RegisteredFormat")
+            .field("name", &self.name)
+            .field("description", &self.description)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The output formats available by name.
+#[derive(Debug, Clone)]
+pub struct OutputRegistry {
+    formats: Vec<RegisteredFormat>,
+}
+
+impl Default for OutputRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OutputRegistry {
+    /// A registry with the built-in formats, configured by the
+    /// [`FormatContext`] their builders are created with.
+    pub fn new() -> Self {
+        let formats = OutputFormat::value_variants()
+            .iter()
+            .map(|format| builtin_format(*format))
+            .collect();
+        Self { formats }
+    }
+
+    /// Adds a format, failing if one of the same name is registered already.
+    pub fn register(&mut self, format: RegisteredFormat) -> Result<()> {
+        if self.get(format.name()).is_some() {
+            return Err(DuoloadError::Format(format!(
+                "Output format {:?} is already registered",
+                format.name()
+            )));
+        }
+        self.formats.push(format);
+        Ok(())
+    }
+
+    /// The format called `name`, ignoring case.
+    pub fn get(&self, name: &str) -> Option<&RegisteredFormat> {
+        self.formats
+            .iter()
+            .find(|format| format.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The registered built-in `format`.
+    ///
+    /// # Panics
+    ///
+    /// Never for a registry created by [`OutputRegistry::new`], which
+    /// registers all of them first.
+    pub fn builtin(&self, format: OutputFormat) -> &RegisteredFormat {
+        self.formats
+            .iter()
+            .find(|registered| registered.builtin() == Some(format))
+            .expect("built-in formats are always registered")
+    }
+
+    /// Like [`OutputRegistry::get`], with an error listing the known formats.
+    pub fn find(&self, name: &str) -> Result<&RegisteredFormat> {
+        self.get(name).ok_or_else(|| {
+            DuoloadError::Format(format!(
+                "Unknown output format {:?}; known formats: {}",
+                name,
+                self.names().collect::<Vec<_>>().join(", ")
+            ))
+        })
+    }
+
+    /// Names of the formats in the order they were registered.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.formats.iter().map(RegisteredFormat::name)
+    }
+
+    pub fn formats(&self) -> &[RegisteredFormat] {
+        &self.formats
+    }
+}
+
+fn builtin_format(format: OutputFormat) -> RegisteredFormat {
+    let name = format
+        .to_possible_value()
+        .expect("output formats are not skipped")
+        .get_name()
+        .to_string();
+    match format {
+        OutputFormat::Anki => RegisteredFormat::new(name, "Anki package (.apkg)", anki_builder),
+        OutputFormat::Json => RegisteredFormat::new(name, "JSON array of cards", |context| {
+            let settings = &context.json;
+            #[allow(unused_mut)]
+            let mut builder = JsonOutputBuilder::new()
+                .with_metadata_header(settings.metadata_header)
+                .with_envelope(settings.envelope)
+                .with_pretty(settings.pretty)
+                .with_validation(settings.validate)
+                .with_deterministic(context.deterministic);
+            #[cfg(feature = "lang")]
+            {
+                builder = builder.with_languages(settings.languages);
+            }
+            builder
+        }),
+        OutputFormat::Csv => RegisteredFormat::new(name, "CSV with a header row", |context| {
+            let settings = &context.csv;
+            let mut builder = CsvOutputBuilder::new()
+                .with_delimiter(settings.delimiter)
+                .with_quoting(settings.quoting)
+                .with_image_column(settings.image_column)
+                .with_provenance_column(context.provenance);
+            if let Some(headers) = &settings.headers {
+                builder = builder.with_headers(headers.clone());
+            }
+            builder
+        }),
+        OutputFormat::Sqlite => RegisteredFormat::new(name, "SQLite database", |context| {
+            SqliteOutputBuilder::new(&context.deck_id)
+        }),
+        OutputFormat::Mnemosyne => RegisteredFormat::new(name, "Mnemosyne 1.x XML", |context| {
+            let mut builder = match &context.deck_name {
+                Some(name) => MnemosyneOutputBuilder::new().with_category(name),
+                None => MnemosyneOutputBuilder::new(),
+            };
+            if context.deterministic {
+                builder = builder.with_export_time(UNIX_EPOCH);
+            }
+            builder
+        }),
+        OutputFormat::Markdown => RegisteredFormat::new(name, "Markdown table", |context| {
+            MarkdownOutputBuilder::new().with_style(context.markdown_style)
+        }),
+        OutputFormat::Yaml => {
+            RegisteredFormat::new(name, "YAML list of cards", |_| YamlOutputBuilder::new())
+        }
+        OutputFormat::Msgpack => RegisteredFormat::new(name, "MessagePack array of cards", |_| {
+            BinaryOutputBuilder::new(BinaryEncoding::MessagePack)
+        }),
+        OutputFormat::Cbor => RegisteredFormat::new(name, "CBOR array of cards", |_| {
+            BinaryOutputBuilder::new(BinaryEncoding::Cbor)
+        }),
+    }
+}
+
+fn anki_builder(context: &FormatContext) -> AnkiPackageBuilder {
+    let settings = &context.anki;
+    let deck_name = context.deck_name.as_deref().unwrap_or(DEFAULT_DECK_NAME);
+    let mut builder = AnkiPackageBuilder::new(deck_name)
+        .with_name_from_deck(context.deck_name.is_none())
+        .with_name_suffix(&settings.name_suffix)
+        .with_deck_id(settings.deck_id)
+        .with_tags(settings.tags.clone())
+        .with_tag_scheme(settings.tag_scheme.clone())
+        .with_status_subdecks(settings.status_subdecks)
+        .with_source_fields(settings.source_fields)
+        .with_status_fields(settings.status_fields)
+        .with_hint_field(settings.hint_field)
+        .with_provenance_field(context.provenance)
+        .with_known_count_field(settings.known_count_field)
+        .with_question_field(settings.question_field)
+        .with_package_options(ApkgOptions {
+            deterministic: context.deterministic,
+            ..settings.package_options
+        })
+        .with_disk_store(settings.disk_store)
+        .with_collection(settings.collection)
+        .with_html_escaping(settings.escape_html);
+    if let Some(model_id) = settings.model_id {
+        builder = builder.with_model_id(model_id);
+    }
+    if let Some(templates) = &settings.templates {
+        builder = builder.with_templates(templates.clone());
+    }
+    if let Some(store) = &settings.media {
+        builder = builder.with_media(store.clone());
+    }
+    if let Some(existing) = &settings.existing {
+        builder = builder.with_existing(existing.clone());
+    }
+    #[cfg(feature = "lang")]
+    {
+        builder = builder
+            .with_language_subdecks(settings.language_subdecks)
+            .with_language_tags(settings.language_tags);
+    }
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duocards::models::{LearningStatus, VocabularyCard};
+    use crate::output::OutputDestination;
+
+    fn card() -> VocabularyCard {
+        VocabularyCard {
+            word: "Haus".to_string(),
+            translation: "house".to_string(),
+            status: LearningStatus::New,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_builtin_formats() {
+        let registry = OutputRegistry::new();
+        assert_eq!(
+            registry.names().collect::<Vec<_>>(),
+            [
+                "anki",
+                "json",
+                "csv",
+                "sqlite",
+                "mnemosyne",
+                "markdown",
+                "yaml",
+                "msgpack",
+                "cbor"
+            ]
+        );
+        assert_eq!(
+            registry.find("CSV").unwrap().builtin(),
+            Some(OutputFormat::Csv)
+        );
+        assert!(matches!(
+            registry.find("toml"),
+            Err(DuoloadError::Format(message)) if message.contains("anki, json")
+        ));
+    }
+
+    #[test]
+    fn test_register_format() {
+        let mut registry = OutputRegistry::new();
+        registry
+            .register(RegisteredFormat::new("words", "Plain word list", |_| {
+                MarkdownOutputBuilder::new()
+            }))
+            .unwrap();
+        assert!(
+            registry
+                .register(RegisteredFormat::new("json", "Another JSON", |_| {
+                    JsonOutputBuilder::new()
+                }))
+                .is_err()
+        );
+
+        let format = registry.find("words").unwrap();
+        assert_eq!(format.builtin(), None);
+        let mut builder = format.new_builder(&FormatContext::default());
+        assert!(builder.add_note(card()).unwrap());
+        let mut output = Vec::new();
+        builder
+            .write(OutputDestination::Writer(&mut output))
+            .unwrap();
+        assert!(
+            String::from_utf8(output)
+                .unwrap()
+                .contains("| Haus | house |")
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! Dated output names and pruning of old exports.
+//!
+//! Scheduled exports write to `deck-20250601-031500.apkg` instead of
+//! overwriting `deck.apkg`; after a successful run, older files with the same
+//! base name are removed according to a [`RetentionPolicy`].
+
+use crate::error::Result;
+use crate::output::manifest;
+use crate::output::timestamp::UtcDateTime;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Length of the `YYYYMMDD-HHMMSS` stamp added to dated file names.
+const STAMP_LEN: usize = 15;
+
+/// Which dated exports to keep. Files matching neither limit are removed;
+/// without any limit nothing is removed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// Keep at most this many of the newest exports
+    pub keep_last: Option<usize>,
+    /// Remove exports older than this
+    pub keep_for: Option<Duration>,
+}
+
+impl RetentionPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.keep_last.is_none() && self.keep_for.is_none()
+    }
+}
+
+/// Adds a UTC timestamp to the file name: `deck.apkg` becomes `deck-20250601-031500.apkg`.
+pub fn dated_path(path: &Path, time: SystemTime) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut name = format!("{}-{}", stem, UtcDateTime::from_system_time(time).stamp());
+    if let Some(extension) = path.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+    path.with_file_name(name)
+}
+
+/// The undated path a dated path was derived from: `deck-20250601-031500.apkg`
+/// becomes `deck.apkg`. Other paths are returned as they are.
+pub fn undated_path(path: &Path) -> PathBuf {
+    let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+        return path.to_path_buf();
+    };
+    let Some(base) = stem
+        .len()
+        .checked_sub(STAMP_LEN + 1)
+        .and_then(|end| stem.get(..end))
+    else {
+        return path.to_path_buf();
+    };
+    let dated = stem[base.len()..].strip_prefix('-').is_some_and(is_stamp);
+    if !dated || base.is_empty() {
+        return path.to_path_buf();
+    }
+    let mut name = base.to_string();
+    if let Some(extension) = path.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+    path.with_file_name(name)
+}
+
+/// Removes dated exports of `path` that fall outside `policy`.
+///
+/// `path` is the undated output path the dated names were derived from.
+/// Returns the removed files, oldest first.
+pub fn prune(path: &Path, policy: &RetentionPolicy, now: SystemTime) -> Result<Vec<PathBuf>> {
+    if policy.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut exports: Vec<(String, PathBuf)> = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if let Some(stamp) = dated_stamp(path, &name) {
+            exports.push((stamp.to_string(), entry.path()));
+        }
+    }
+    // Stamps sort chronologically, newest first after the reverse
+    exports.sort();
+    exports.reverse();
+
+    let mut removed = Vec::new();
+    for (index, (stamp, export)) in exports.iter().enumerate() {
+        let too_many = policy.keep_last.is_some_and(|keep| index >= keep);
+        let too_old = match policy.keep_for {
+            Some(keep_for) => {
+                now.duration_since(export_time(stamp, export)?)
+                    .unwrap_or_default()
+                    > keep_for
+            }
+            None => false,
+        };
+        if too_many || too_old {
+            fs::remove_file(export)?;
+            // Drop the manifest along with the export it describes
+            let manifest = manifest::manifest_path(export);
+            if manifest.exists() {
+                fs::remove_file(manifest)?;
+            }
+            removed.push(export.clone());
+        }
+    }
+    removed.reverse();
+    Ok(removed)
+}
+
+/// When an export was written: the time in its name, which copying or
+/// syncing the file keeps, or its modification time if that is no valid date.
+fn export_time(stamp: &str, export: &Path) -> Result<SystemTime> {
+    match UtcDateTime::parse_stamp(stamp) {
+        Some(time) => Ok(UNIX_EPOCH + Duration::from_secs(time.unix_secs().max(0) as u64)),
+        None => Ok(fs::metadata(export)?.modified()?),
+    }
+}
+
+/// Returns the stamp if `name` is a dated file name derived from `path`.
+fn dated_stamp<'a>(path: &Path, name: &'a str) -> Option<&'a str> {
+    let stem = path.file_stem()?.to_str()?;
+    let rest = name.strip_prefix(stem)?.strip_prefix('-')?;
+    let stamp = rest.get(..STAMP_LEN)?;
+    let suffix = &rest[STAMP_LEN..];
+    let expected_suffix = match path.extension() {
+        Some(extension) => format!(".{}", extension.to_str()?),
+        None => String::new(),
+    };
+
+    (is_stamp(stamp) && suffix == expected_suffix).then_some(stamp)
+}
+
+/// Whether `stamp` has the `YYYYMMDD-HHMMSS` form of dated file names.
+fn is_stamp(stamp: &str) -> bool {
+    stamp.len() == STAMP_LEN
+        && stamp
+            .char_indices()
+            .all(|(i, c)| if i == 8 { c == '-' } else { c.is_ascii_digit() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_dated_path() {
+        // 2025-06-01 03:15:00 UTC
+        let time = UNIX_EPOCH + Duration::from_secs(1748747700);
+        assert_eq!(
+            dated_path(Path::new("backups/deck.apkg"), time),
+            PathBuf::from("backups/deck-20250601-031500.apkg")
+        );
+        assert_eq!(
+            dated_path(Path::new("deck"), UNIX_EPOCH),
+            PathBuf::from("deck-19700101-000000")
+        );
+    }
+
+    #[test]
+    fn test_undated_path() {
+        let time = UNIX_EPOCH + Duration::from_secs(1748747700);
+        for path in ["backups/deck.apkg", "deck", "deck-known.json"] {
+            let path = Path::new(path);
+            assert_eq!(undated_path(&dated_path(path, time)), path);
+            assert_eq!(undated_path(path), path);
+        }
+        assert_eq!(
+            undated_path(Path::new("deck-2025-06.json")),
+            Path::new("deck-2025-06.json")
+        );
+    }
+
+    #[test]
+    fn test_dated_stamp_matches_only_own_exports() {
+        let path = Path::new("deck.apkg");
+        assert_eq!(
+            dated_stamp(path, "deck-20250601-031500.apkg"),
+            Some("20250601-031500")
+        );
+        assert_eq!(dated_stamp(path, "deck.apkg"), None);
+        assert_eq!(dated_stamp(path, "deck-20250601-031500.json"), None);
+        assert_eq!(dated_stamp(path, "deck-old-20250601-031500.apkg"), None);
+        assert_eq!(dated_stamp(path, "other-20250601-031500.apkg"), None);
+    }
+
+    #[test]
+    fn test_prune_keep_last() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("deck.apkg");
+        for day in 1..=4 {
+            fs::write(
+                dir.path().join(format!("deck-2025060{}-000000.apkg", day)),
+                "",
+            )?;
+        }
+        fs::write(
+            dir.path().join("deck-20250601-000000.apkg.manifest.json"),
+            "",
+        )?;
+        fs::write(dir.path().join("deck.apkg"), "")?;
+        fs::write(dir.path().join("notes-20250601-000000.apkg"), "")?;
+
+        let policy = RetentionPolicy {
+            keep_last: Some(2),
+            keep_for: None,
+        };
+        let removed = prune(&path, &policy, SystemTime::now())?;
+
+        assert_eq!(
+            removed,
+            vec![
+                dir.path().join("deck-20250601-000000.apkg"),
+                dir.path().join("deck-20250602-000000.apkg"),
+            ]
+        );
+        assert!(dir.path().join("deck-20250604-000000.apkg").exists());
+        assert!(
+            !dir.path()
+                .join("deck-20250601-000000.apkg.manifest.json")
+                .exists()
+        );
+        assert!(dir.path().join("deck.apkg").exists());
+        assert!(dir.path().join("notes-20250601-000000.apkg").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune_keep_days() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("deck.json");
+        // Freshly written, but named after 2025-06-01 00:00:00 UTC
+        fs::write(dir.path().join("deck-20250601-000000.json"), "")?;
+        fs::write(dir.path().join("deck-20250602-120000.json"), "")?;
+
+        let policy = RetentionPolicy {
+            keep_last: None,
+            keep_for: Some(Duration::from_secs(86400)),
+        };
+        // 2025-06-02 06:00:00 UTC
+        let now = UNIX_EPOCH + Duration::from_secs(1748844000);
+        assert_eq!(
+            prune(&path, &policy, now)?,
+            vec![dir.path().join("deck-20250601-000000.json")]
+        );
+        assert!(dir.path().join("deck-20250602-120000.json").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune_keep_days_falls_back_to_mtime() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("deck.json");
+        // Matches the pattern, but is no valid date
+        fs::write(dir.path().join("deck-20251399-000000.json"), "")?;
+
+        let policy = RetentionPolicy {
+            keep_last: None,
+            keep_for: Some(Duration::from_secs(86400)),
+        };
+        assert!(prune(&path, &policy, SystemTime::now())?.is_empty());
+
+        let later = SystemTime::now() + Duration::from_secs(2 * 86400);
+        assert_eq!(prune(&path, &policy, later)?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_policy_keeps_everything() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("deck.json");
+        fs::write(dir.path().join("deck-20250601-000000.json"), "")?;
+        assert!(prune(&path, &RetentionPolicy::default(), SystemTime::now())?.is_empty());
+        Ok(())
+    }
+}
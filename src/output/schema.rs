@@ -0,0 +1,107 @@
+//! The JSON Schema of the JSON output.
+//!
+//! The schema is published in the repository and printed by `duoload schema`,
+//! so integrators can check exports against a stable contract. With
+//! [`JsonOutputBuilder::with_validation`], duoload checks its own output
+//! against it before writing.
+//!
+//! [`JsonOutputBuilder::with_validation`]: crate::output::json::JsonOutputBuilder::with_validation
+
+use crate::error::{DuoloadError, Result};
+use jsonschema::{Draft, JSONSchema};
+use serde_json::Value;
+use std::sync::LazyLock;
+
+/// The schema of JSON documents and arrays written by duoload.
+pub const JSON_SCHEMA: &str = include_str!("../../schema/output.schema.json");
+
+/// Most schema violations listed in an error.
+const MAX_REPORTED_ERRORS: usize = 5;
+
+static SCHEMA: LazyLock<Value> =
+    LazyLock::new(|| serde_json::from_str(JSON_SCHEMA).expect("the JSON schema is valid JSON"));
+
+static DOCUMENT: LazyLock<JSONSchema> = LazyLock::new(|| compile(SCHEMA.clone()));
+
+/// The schema of a line of JSON Lines: the root schema, checking against
+/// `#/definitions/line` instead.
+static LINE: LazyLock<JSONSchema> = LazyLock::new(|| {
+    let mut schema = SCHEMA.clone();
+    let root = schema
+        .as_object_mut()
+        .expect("the JSON schema is an object");
+    for keyword in ["if", "then", "else"] {
+        root.remove(keyword);
+    }
+    root.insert("$ref".to_string(), "#/definitions/line".into());
+    compile(schema)
+});
+
+fn compile(schema: Value) -> JSONSchema {
+    JSONSchema::options()
+        .with_draft(Draft::Draft7)
+        .compile(&schema)
+        .expect("the JSON schema compiles")
+}
+
+/// Checks a JSON array of cards or a document against the schema.
+pub fn validate_document(document: &Value) -> Result<()> {
+    validate(&DOCUMENT, document)
+}
+
+/// Checks a line of JSON Lines output against the schema.
+pub fn validate_line(line: &Value) -> Result<()> {
+    validate(&LINE, line)
+}
+
+fn validate(schema: &JSONSchema, instance: &Value) -> Result<()> {
+    let Err(errors) = schema.validate(instance) else {
+        return Ok(());
+    };
+    let errors: Vec<String> = errors
+        .take(MAX_REPORTED_ERRORS)
+        .map(|error| {
+            let path = error.instance_path.to_string();
+            let path = if path.is_empty() { "/" } else { &path };
+            format!("{}: {}", path, error)
+        })
+        .collect();
+    Err(DuoloadError::InvalidOutput(errors.join("; ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn card() -> Value {
+        json!({"word": "hello", "translation": "hola", "example": null, "status": "new"})
+    }
+
+    #[test]
+    fn test_valid_outputs() -> Result<()> {
+        validate_document(&json!([card()]))?;
+        validate_document(&json!({
+            "deck": {"id": "deck", "name": null, "source_language": "en", "target_language": "es"},
+            "stats": {"cards": 1, "duplicates": 0},
+            "cards": [card()]
+        }))?;
+        validate_line(&card())?;
+        validate_line(&json!({"deck": {"id": "deck"}}))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_outputs() {
+        let mut unknown = card();
+        unknown["status"] = json!("forgotten");
+        match validate_document(&json!([card(), unknown])) {
+            Err(DuoloadError::InvalidOutput(message)) => {
+                assert!(message.starts_with("/1/status"), "{}", message)
+            }
+            result => panic!("Expected InvalidOutput, got {:?}", result),
+        }
+        assert!(validate_line(&json!({"word": "hello"})).is_err());
+        assert!(validate_document(&json!({"cards": []})).is_err());
+    }
+}
@@ -0,0 +1,108 @@
+//! Order of the cards in the written output.
+
+use crate::duocards::models::VocabularyCard;
+use clap::ValueEnum;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::BuildHasher;
+
+/// How the output builders order the cards they write.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SortOrder {
+    /// The order of the Duocards deck
+    #[default]
+    Original,
+    /// By word, ignoring case
+    Alphabetical,
+    /// New cards first, then learning and known ones, each in deck order
+    Status,
+    /// Shuffled anew on every export
+    Random,
+}
+
+impl SortOrder {
+    /// Indices of `cards` in the order they are written.
+    pub fn order(&self, cards: &[VocabularyCard]) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..cards.len()).collect();
+        match self {
+            SortOrder::Original => {}
+            SortOrder::Alphabetical => {
+                // Words differing only in case keep a fixed order
+                order.sort_by_cached_key(|&index| {
+                    let word = &cards[index].word;
+                    (word.to_lowercase(), word.clone())
+                });
+            }
+            SortOrder::Status => order.sort_by(|&a, &b| cards[a].status.cmp(&cards[b].status)),
+            SortOrder::Random => {
+                let state = RandomState::new();
+                order.sort_by_cached_key(|&index| state.hash_one(index));
+            }
+        }
+        order
+    }
+
+    /// `cards` in the order they are written.
+    pub fn sorted<'a>(&self, cards: &'a [VocabularyCard]) -> Vec<&'a VocabularyCard> {
+        self.order(cards)
+            .into_iter()
+            .map(|index| &cards[index])
+            .collect()
+    }
+}
+
+impl fmt::Display for SortOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.to_possible_value().expect("no order is skipped");
+        f.write_str(value.get_name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duocards::models::LearningStatus;
+
+    fn card(word: &str, status: LearningStatus) -> VocabularyCard {
+        VocabularyCard {
+            word: word.to_string(),
+            status,
+            ..Default::default()
+        }
+    }
+
+    fn words(order: SortOrder, cards: &[VocabularyCard]) -> Vec<&str> {
+        order
+            .sorted(cards)
+            .into_iter()
+            .map(|card| card.word.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_orders() {
+        let cards = [
+            card("zwei", LearningStatus::Known),
+            card("Apfel", LearningStatus::New),
+            card("baum", LearningStatus::Learning),
+            card("acht", LearningStatus::New),
+        ];
+
+        assert_eq!(
+            words(SortOrder::Original, &cards),
+            ["zwei", "Apfel", "baum", "acht"]
+        );
+        assert_eq!(
+            words(SortOrder::Alphabetical, &cards),
+            ["acht", "Apfel", "baum", "zwei"]
+        );
+        assert_eq!(
+            words(SortOrder::Status, &cards),
+            ["Apfel", "acht", "baum", "zwei"]
+        );
+
+        let mut shuffled = words(SortOrder::Random, &cards);
+        shuffled.sort();
+        assert_eq!(shuffled, ["Apfel", "acht", "baum", "zwei"]);
+    }
+}
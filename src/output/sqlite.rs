@@ -0,0 +1,129 @@
+use crate::duocards::models::VocabularyCard;
+use crate::error::{DuoloadError, Result};
+use crate::output::timestamp::UtcDateTime;
+use crate::output::{OutputBuilder, OutputDestination};
+use crate::transfer::DuplicateHandler;
+use crate::transfer::duplicates::{DedupeKey, DuplicatePolicy};
+use crate::transfer::key_store::DedupeStore;
+use rusqlite::{Connection, params};
+use std::path::Path;
+use std::time::{Instant, SystemTime};
+
+/// Schema of the exported database; existing databases are left as they are.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS cards (
+        word TEXT NOT NULL,
+        translation TEXT NOT NULL,
+        example TEXT,
+        status TEXT NOT NULL,
+        deck_id TEXT NOT NULL,
+        fetched_at TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS cards_deck_id ON cards (deck_id);
+";
+
+/// Builder for writing vocabulary cards into an SQLite database.
+///
+/// Cards go into a single `cards` table, tagged with the deck they came from
+/// and the time of the export. Writing replaces the rows of the exported deck
+/// and keeps those of other decks, so one database can be synced with
+/// several decks over time and queried with plain SQL.
+pub struct SqliteOutputBuilder {
+    deck_id: String,
+    fetched_at: String,
+    cards: Vec<VocabularyCard>,
+    duplicates: DuplicateHandler,
+    start_time: Instant,
+}
+
+impl SqliteOutputBuilder {
+    pub fn new(deck_id: &str) -> Self {
+        Self {
+            deck_id: deck_id.to_string(),
+            fetched_at: UtcDateTime::from_system_time(SystemTime::now()).rfc3339(),
+            cards: Vec::new(),
+            duplicates: DuplicateHandler::new(),
+            start_time: Instant::now(),
+        }
+    }
+
+    fn write_database(&self, path: &Path) -> rusqlite::Result<()> {
+        let mut connection = Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "DELETE FROM cards WHERE deck_id = ?1",
+            params![self.deck_id],
+        )?;
+        {
+            let mut insert = transaction.prepare(
+                "INSERT INTO cards (word, translation, example, status, deck_id, fetched_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for card in &self.cards {
+                insert.execute(params![
+                    card.word,
+                    card.translation,
+                    card.example,
+                    card.status.as_str(),
+                    self.deck_id,
+                    self.fetched_at
+                ])?;
+            }
+        }
+        transaction.commit()
+    }
+}
+
+impl OutputBuilder for SqliteOutputBuilder {
+    fn add_note(&mut self, card: VocabularyCard) -> Result<bool> {
+        self.duplicates.add(&mut self.cards, card)
+    }
+
+    fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        self.duplicates.set_policy(policy);
+    }
+
+    fn set_dedupe_key(&mut self, key: DedupeKey) {
+        self.duplicates.set_key(key);
+    }
+
+    fn set_dedupe_store(&mut self, store: &DedupeStore) {
+        self.duplicates.set_store(store);
+    }
+
+    fn writes_in_place(&self) -> bool {
+        // Rows of other decks are kept, and the transaction replaces the
+        // rows of this deck all at once
+        true
+    }
+
+    fn write(&self, dest: OutputDestination<'_>) -> Result<()> {
+        let OutputDestination::File(path) = dest else {
+            // An SQLite database cannot be streamed to a writer
+            return Err(DuoloadError::SqliteOutputNotSupported);
+        };
+
+        self.write_database(path)
+            .map_err(|e| anyhow::anyhow!("Failed to write SQLite database: {}", e))?;
+        tracing::info!(
+            "SQLite database written successfully at {:?}",
+            self.start_time.elapsed()
+        );
+        Ok(())
+    }
+
+    fn count_written_notes(&self, path: &Path) -> Result<Option<usize>> {
+        let connection = Connection::open(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open SQLite database: {}", e))?;
+        let count: i64 = connection
+            .query_row(
+                "SELECT count(*) FROM cards WHERE deck_id = ?1",
+                params![self.deck_id],
+                |row| row.get(0),
+            )
+            .map_err(|e| anyhow::anyhow!("Failed to count cards: {}", e))?;
+        Ok(Some(count as usize))
+    }
+}
@@ -0,0 +1,212 @@
+//! Cards kept in a temporary database on disk instead of in memory.
+
+use crate::duocards::models::VocabularyCard;
+use crate::error::Result;
+use crate::output::sort::SortOrder;
+use crate::transfer::duplicates::CardList;
+use rusqlite::{Connection, params};
+use std::path::Path;
+use std::sync::Mutex;
+use tempfile::TempDir;
+
+/// Schema of the store; `position` is the index of the card.
+const SCHEMA: &str = "
+    PRAGMA journal_mode = OFF;
+    PRAGMA synchronous = OFF;
+    CREATE TABLE cards (
+        position INTEGER PRIMARY KEY,
+        word_key TEXT NOT NULL,
+        word TEXT NOT NULL,
+        status INTEGER NOT NULL,
+        card TEXT NOT NULL
+    );
+";
+
+/// Cards of an export spilled to an SQLite database in a temporary
+/// directory, which is removed with the store.
+///
+/// Only the cards being read are held in memory, so outputs that need the
+/// whole deck before writing, like Anki packages, can export very large
+/// decks with roughly constant memory.
+pub struct CardStore {
+    // SQLite connections cannot be shared between threads
+    connection: Mutex<Connection>,
+    len: usize,
+    _dir: TempDir,
+}
+
+impl CardStore {
+    /// Creates an empty store in the system's temporary directory.
+    pub fn new() -> Result<Self> {
+        Self::create(tempfile::tempdir()?)
+    }
+
+    /// Creates an empty store in `dir`, e.g. on a disk with more free space.
+    pub fn new_in(dir: &Path) -> Result<Self> {
+        Self::create(tempfile::tempdir_in(dir)?)
+    }
+
+    fn create(dir: TempDir) -> Result<Self> {
+        let connection = Connection::open(dir.path().join("cards.db")).map_err(store_error)?;
+        connection.execute_batch(SCHEMA).map_err(store_error)?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+            len: 0,
+            _dir: dir,
+        })
+    }
+
+    /// Calls `f` with the cards in `order`, at most `size` at a time.
+    pub fn for_each_batch(
+        &self,
+        order: SortOrder,
+        size: usize,
+        mut f: impl FnMut(Vec<VocabularyCard>) -> Result<()>,
+    ) -> Result<()> {
+        // Ties keep the order the cards were added in, like SortOrder::order
+        let order_by = match order {
+            SortOrder::Original => "position",
+            SortOrder::Alphabetical => "word_key, word, position",
+            SortOrder::Status => "status, position",
+            SortOrder::Random => "random()",
+        };
+        let connection = self.connection.lock().expect("store lock poisoned");
+        let mut statement = connection
+            .prepare(&format!("SELECT card FROM cards ORDER BY {}", order_by))
+            .map_err(store_error)?;
+        let mut rows = statement.query([]).map_err(store_error)?;
+        let mut batch = Vec::with_capacity(size.min(self.len));
+        while let Some(row) = rows.next().map_err(store_error)? {
+            let card: String = row.get(0).map_err(store_error)?;
+            batch.push(serde_json::from_str(&card)?);
+            if batch.len() >= size.max(1) {
+                f(std::mem::take(&mut batch))?;
+            }
+        }
+        if !batch.is_empty() {
+            f(batch)?;
+        }
+        Ok(())
+    }
+}
+
+impl CardList for CardStore {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn push(&mut self, card: VocabularyCard) -> Result<()> {
+        let connection = self.connection.get_mut().expect("store lock poisoned");
+        connection
+            .execute(
+                "INSERT INTO cards (position, word_key, word, status, card) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    self.len as i64,
+                    card.word.to_lowercase(),
+                    card.word,
+                    card.status.clone() as i64,
+                    serde_json::to_string(&card)?
+                ],
+            )
+            .map_err(store_error)?;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn get(&self, index: usize) -> Result<VocabularyCard> {
+        let connection = self.connection.lock().expect("store lock poisoned");
+        let card: String = connection
+            .query_row(
+                "SELECT card FROM cards WHERE position = ?1",
+                params![index as i64],
+                |row| row.get(0),
+            )
+            .map_err(store_error)?;
+        Ok(serde_json::from_str(&card)?)
+    }
+
+    fn set(&mut self, index: usize, card: VocabularyCard) -> Result<()> {
+        let connection = self.connection.get_mut().expect("store lock poisoned");
+        connection
+            .execute(
+                "UPDATE cards SET word_key = ?2, word = ?3, status = ?4, card = ?5 WHERE position = ?1",
+                params![
+                    index as i64,
+                    card.word.to_lowercase(),
+                    card.word,
+                    card.status.clone() as i64,
+                    serde_json::to_string(&card)?
+                ],
+            )
+            .map_err(store_error)?;
+        Ok(())
+    }
+}
+
+fn store_error(e: rusqlite::Error) -> anyhow::Error {
+    anyhow::anyhow!("Failed to store cards on disk: {}", e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duocards::models::LearningStatus;
+    use crate::transfer::DuplicateHandler;
+    use crate::transfer::duplicates::DuplicatePolicy;
+
+    fn card(word: &str, status: LearningStatus) -> VocabularyCard {
+        VocabularyCard {
+            word: word.to_string(),
+            translation: format!("{} translated", word),
+            status,
+            ..Default::default()
+        }
+    }
+
+    fn words(store: &CardStore, order: SortOrder) -> Result<Vec<Vec<String>>> {
+        let mut batches = Vec::new();
+        store.for_each_batch(order, 2, |batch| {
+            batches.push(batch.into_iter().map(|card| card.word).collect());
+            Ok(())
+        })?;
+        Ok(batches)
+    }
+
+    #[test]
+    fn test_batches_in_sort_order() -> Result<()> {
+        let mut store = CardStore::new()?;
+        store.push(card("dog", LearningStatus::Known))?;
+        store.push(card("Cat", LearningStatus::New))?;
+        store.push(card("bird", LearningStatus::Known))?;
+
+        assert_eq!(
+            words(&store, SortOrder::Original)?,
+            vec![vec!["dog", "Cat"], vec!["bird"]]
+        );
+        assert_eq!(
+            words(&store, SortOrder::Alphabetical)?,
+            vec![vec!["bird", "Cat"], vec!["dog"]]
+        );
+        assert_eq!(
+            words(&store, SortOrder::Status)?,
+            vec![vec!["Cat", "dog"], vec!["bird"]]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicates_resolved_on_disk() -> Result<()> {
+        let mut store = CardStore::new()?;
+        let mut duplicates = DuplicateHandler::with_policy(DuplicatePolicy::Merge);
+        assert!(duplicates.add(&mut store, card("Haus", LearningStatus::New))?);
+        let mut other = card("Haus", LearningStatus::Known);
+        other.translation = "home".to_string();
+        assert!(!duplicates.add(&mut store, other)?);
+
+        assert_eq!(store.len(), 1);
+        let merged = store.get(0)?;
+        assert_eq!(merged.translation, "Haus translated; home");
+        assert_eq!(merged.status, LearningStatus::Known);
+        Ok(())
+    }
+}
@@ -0,0 +1,254 @@
+//! UTC timestamps for file names and metadata.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A point in time broken down into UTC calendar fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UtcDateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u64,
+    pub minute: u64,
+    pub second: u64,
+}
+
+impl UtcDateTime {
+    pub fn from_system_time(time: SystemTime) -> Self {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let (year, month, day) = civil_from_days((secs / 86400) as i64);
+        let secs_of_day = secs % 86400;
+        Self {
+            year,
+            month,
+            day,
+            hour: secs_of_day / 3600,
+            minute: secs_of_day % 3600 / 60,
+            second: secs_of_day % 60,
+        }
+    }
+
+    /// The time `secs` seconds after 1970-01-01, or 1970-01-01 for earlier times.
+    pub fn from_unix_secs(secs: i64) -> Self {
+        Self::from_system_time(UNIX_EPOCH + Duration::from_secs(secs.max(0) as u64))
+    }
+
+    /// Seconds since 1970-01-01.
+    pub fn unix_secs(&self) -> i64 {
+        self.unix_days() * 86400 + (self.hour * 3600 + self.minute * 60 + self.second) as i64
+    }
+
+    /// Compact form for file names: `20250601-031500`.
+    pub fn stamp(&self) -> String {
+        format!(
+            "{:04}{:02}{:02}-{:02}{:02}{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+
+    /// Parses the compact form written by [`UtcDateTime::stamp`].
+    pub fn parse_stamp(s: &str) -> Option<Self> {
+        let (date, time) = s.split_once('-')?;
+        let digits =
+            |part: &str, len| part.len() == len && part.bytes().all(|b| b.is_ascii_digit());
+        if !digits(date, 8) || !digits(time, 6) {
+            return None;
+        }
+        Self::parse(&format!(
+            "{}-{}-{}T{}:{}:{}Z",
+            &date[..4],
+            &date[4..6],
+            &date[6..],
+            &time[..2],
+            &time[2..4],
+            &time[4..]
+        ))
+    }
+
+    /// Parses the UTC RFC 3339 form written by [`UtcDateTime::rfc3339`].
+    pub fn parse_rfc3339(s: &str) -> Option<Self> {
+        fn field<T: std::str::FromStr>(s: &str, range: std::ops::Range<usize>) -> Option<T> {
+            s.get(range)?.parse().ok()
+        }
+        let bytes = s.as_bytes();
+        if s.len() != 20 || bytes[4] != b'-' || bytes[7] != b'-' || bytes[10] != b'T' {
+            return None;
+        }
+        if bytes[13] != b':' || bytes[16] != b':' || bytes[19] != b'Z' {
+            return None;
+        }
+        Some(Self {
+            year: field(s, 0..4)?,
+            month: field(s, 5..7)?,
+            day: field(s, 8..10)?,
+            hour: field(s, 11..13)?,
+            minute: field(s, 14..16)?,
+            second: field(s, 17..19)?,
+        })
+    }
+
+    /// Parses an RFC 3339 timestamp as sent by the Duocards API, such as
+    /// `2025-06-01T05:15:00.250+02:00`, converting it to UTC. Fractions of
+    /// seconds are dropped.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let bytes = s.as_bytes();
+        if s.len() < 20 || !s.is_char_boundary(19) || !matches!(bytes[10], b'T' | b't' | b' ') {
+            return None;
+        }
+        let (main, rest) = s.split_at(19);
+        let time = Self::parse_rfc3339(&format!("{}T{}Z", &main[..10], &main[11..]))?;
+        if time.month == 0 || time.month > 12 || time.day == 0 || time.day > 31 {
+            return None;
+        }
+        if time.hour > 23 || time.minute > 59 || time.second > 60 {
+            return None;
+        }
+        let zone = match rest.strip_prefix('.') {
+            Some(fraction) => fraction.trim_start_matches(|c: char| c.is_ascii_digit()),
+            None => rest,
+        };
+        let offset = match zone.as_bytes() {
+            [b'Z' | b'z'] => 0,
+            [sign @ (b'+' | b'-'), ..] if zone.len() == 6 && zone.as_bytes()[3] == b':' => {
+                let hours: i64 = zone.get(1..3)?.parse().ok()?;
+                let minutes: i64 = zone.get(4..6)?.parse().ok()?;
+                let offset = hours * 3600 + minutes * 60;
+                if *sign == b'-' { -offset } else { offset }
+            }
+            _ => return None,
+        };
+        Some(Self::from_unix_secs(time.unix_secs() - offset))
+    }
+
+    /// Days since 1970-01-01.
+    pub fn unix_days(&self) -> i64 {
+        days_from_civil(self.year, self.month, self.day)
+    }
+
+    /// The calendar date, as `2025-06-01`.
+    pub fn date(&self) -> String {
+        format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+
+    /// The Monday of this date's week, as `2025-06-02`.
+    pub fn week_start(&self) -> String {
+        let days = days_from_civil(self.year, self.month, self.day);
+        // 1970-01-01 was a Thursday
+        let (year, month, day) = civil_from_days(days - (days + 3).rem_euclid(7));
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    }
+
+    /// RFC 3339 form: `2025-06-01T03:15:00Z`.
+    pub fn rfc3339(&self) -> String {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// Written in RFC 3339 form, as `2025-06-01T03:15:00Z`.
+impl Serialize for UtcDateTime {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.rfc3339())
+    }
+}
+
+/// Read from any RFC 3339 form accepted by [`UtcDateTime::parse`].
+impl<'de> Deserialize<'de> for UtcDateTime {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Self::parse(&text)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid timestamp {:?}", text)))
+    }
+}
+
+/// Converts days since 1970-01-01 to a (year, month, day) date.
+///
+/// Howard Hinnant's `civil_from_days` algorithm.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Converts a (year, month, day) date to days since 1970-01-01.
+///
+/// Howard Hinnant's `days_from_civil` algorithm.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_formats() {
+        // 2025-06-01 03:15:00 UTC
+        let time = UtcDateTime::from_system_time(UNIX_EPOCH + Duration::from_secs(1748747700));
+        assert_eq!(time.stamp(), "20250601-031500");
+        assert_eq!(UtcDateTime::parse_stamp("20250601-031500"), Some(time));
+        assert_eq!(UtcDateTime::parse_stamp("20251301-031500"), None);
+        assert_eq!(UtcDateTime::parse_stamp("2025061-0315000"), None);
+        assert_eq!(time.rfc3339(), "2025-06-01T03:15:00Z");
+
+        // Leap day
+        let time = UtcDateTime::from_system_time(UNIX_EPOCH + Duration::from_secs(951782400));
+        assert_eq!(time.rfc3339(), "2000-02-29T00:00:00Z");
+    }
+
+    #[test]
+    fn test_parse_and_week_start() {
+        let time = UtcDateTime::parse_rfc3339("2025-06-01T03:15:00Z").unwrap();
+        assert_eq!(time.rfc3339(), "2025-06-01T03:15:00Z");
+        // A Sunday belongs to the week starting the Monday before
+        assert_eq!(time.week_start(), "2025-05-26");
+        assert_eq!(
+            UtcDateTime::parse_rfc3339("2025-06-02T00:00:00Z")
+                .unwrap()
+                .week_start(),
+            "2025-06-02"
+        );
+        assert_eq!(UtcDateTime::parse_rfc3339("2025-06-01"), None);
+    }
+
+    #[test]
+    fn test_parse_api_forms() {
+        let expected = UtcDateTime::parse_rfc3339("2025-06-01T03:15:00Z");
+        assert_eq!(UtcDateTime::parse("2025-06-01T03:15:00Z"), expected);
+        assert_eq!(UtcDateTime::parse("2025-06-01T03:15:00.250Z"), expected);
+        assert_eq!(UtcDateTime::parse("2025-06-01T05:15:00+02:00"), expected);
+        // The offset moves the time to the previous day
+        assert_eq!(UtcDateTime::parse("2025-05-31T23:15:00.5-04:00"), expected);
+        assert_eq!(UtcDateTime::parse("2025-06-01T03:15:00"), None);
+        assert_eq!(UtcDateTime::parse("2025-13-01T03:15:00Z"), None);
+        assert_eq!(UtcDateTime::parse("soon"), None);
+
+        let time = expected.unwrap();
+        assert_eq!(UtcDateTime::from_unix_secs(time.unix_secs()), time);
+        assert_eq!(
+            serde_json::to_string(&time).unwrap(),
+            "\"2025-06-01T03:15:00Z\""
+        );
+    }
+}
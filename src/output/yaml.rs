@@ -0,0 +1,89 @@
+use crate::duocards::models::VocabularyCard;
+use crate::error::Result;
+use crate::output::sort::SortOrder;
+use crate::output::{OutputBuilder, OutputDestination};
+use crate::transfer::DuplicateHandler;
+use crate::transfer::duplicates::{DedupeKey, DuplicatePolicy};
+use crate::transfer::key_store::DedupeStore;
+use std::io::Write;
+use std::path::Path;
+use std::time::Instant;
+
+/// Builder for YAML files listing the vocabulary.
+///
+/// Writes a sequence of cards with the same fields as the JSON output, for
+/// config-driven tools and static site generators that read YAML data files.
+pub struct YamlOutputBuilder {
+    cards: Vec<VocabularyCard>,
+    duplicates: DuplicateHandler,
+    sort: SortOrder,
+    start_time: Instant,
+}
+
+impl Default for YamlOutputBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl YamlOutputBuilder {
+    pub fn new() -> Self {
+        Self {
+            cards: Vec::new(),
+            duplicates: DuplicateHandler::new(),
+            sort: SortOrder::default(),
+            start_time: Instant::now(),
+        }
+    }
+
+    fn write_document<W: Write>(&self, mut writer: W) -> Result<()> {
+        serde_norway::to_writer(&mut writer, &self.sort.sorted(&self.cards))?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl OutputBuilder for YamlOutputBuilder {
+    fn add_note(&mut self, card: VocabularyCard) -> Result<bool> {
+        self.duplicates.add(&mut self.cards, card)
+    }
+
+    fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        self.duplicates.set_policy(policy);
+    }
+
+    fn set_dedupe_key(&mut self, key: DedupeKey) {
+        self.duplicates.set_key(key);
+    }
+
+    fn set_dedupe_store(&mut self, store: &DedupeStore) {
+        self.duplicates.set_store(store);
+    }
+
+    fn set_sort_order(&mut self, order: SortOrder) {
+        self.sort = order;
+    }
+
+    fn write(&self, dest: OutputDestination<'_>) -> Result<()> {
+        match dest {
+            OutputDestination::Writer(writer) => self.write_document(writer)?,
+            OutputDestination::File(path) => {
+                let file = std::fs::File::create(path)?;
+                self.write_document(std::io::BufWriter::new(file))?;
+            }
+        }
+
+        tracing::info!(
+            "YAML written successfully at {:?}",
+            self.start_time.elapsed()
+        );
+
+        Ok(())
+    }
+
+    fn count_written_notes(&self, path: &Path) -> Result<Option<usize>> {
+        let file = std::fs::File::open(path)?;
+        let cards: Vec<serde_norway::Value> = serde_norway::from_reader(file)?;
+        Ok(Some(cards.len()))
+    }
+}
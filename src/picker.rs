@@ -0,0 +1,90 @@
+//! Choosing a deck to export when no deck ID was given.
+//!
+//! [`prompt_deck`] lists the decks of the account as a numbered menu and
+//! reads the number of the chosen one. With the `tui` feature the binary
+//! uses [`crate::tui::pick_deck`] instead, which also takes arrow keys.
+
+use crate::duocards::models::DeckSummary;
+use crate::error::{DuoloadError, Result};
+use std::io::{BufRead, Write};
+
+/// Label of a deck in the menu: its name and languages.
+pub fn deck_label(deck: &DeckSummary) -> String {
+    match (&deck.front, &deck.back) {
+        (Some(front), Some(back)) => format!("{} ({} -> {})", deck.name, front, back),
+        _ => deck.name.clone(),
+    }
+}
+
+/// Lists `decks` on `output` and asks for the number of one of them.
+///
+/// Asks again after an invalid answer. An empty answer or the end of `input`
+/// cancels the choice with [`DuoloadError::Aborted`].
+pub fn prompt_deck<R: BufRead, W: Write>(
+    decks: &[DeckSummary],
+    mut input: R,
+    mut output: W,
+) -> Result<&DeckSummary> {
+    for (number, deck) in decks.iter().enumerate() {
+        writeln!(output, "{:>3}) {}", number + 1, deck_label(deck))?;
+    }
+    loop {
+        write!(output, "Deck to export [1-{}]: ", decks.len())?;
+        output.flush()?;
+
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 || answer.trim().is_empty() {
+            return Err(DuoloadError::Aborted);
+        }
+        match answer.trim().parse::<usize>() {
+            Ok(number) if (1..=decks.len()).contains(&number) => return Ok(&decks[number - 1]),
+            _ => writeln!(output, "Please enter a number from 1 to {}", decks.len())?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decks() -> Vec<DeckSummary> {
+        ["German", "Spanish"]
+            .iter()
+            .map(|name| DeckSummary {
+                id: format!("{}-id", name),
+                name: name.to_string(),
+                front: Some("en".to_string()),
+                back: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_prompt_deck_retries_invalid_answers() {
+        let decks = decks();
+        let mut output = Vec::new();
+
+        let deck = prompt_deck(&decks, "x\n3\n2\n".as_bytes(), &mut output).unwrap();
+
+        assert_eq!(deck.id, "Spanish-id");
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("  1) German\n  2) Spanish\n"));
+        assert_eq!(
+            output.matches("Please enter a number from 1 to 2").count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_prompt_deck_cancelled() {
+        let decks = decks();
+        assert!(matches!(
+            prompt_deck(&decks, "\n".as_bytes(), Vec::new()),
+            Err(DuoloadError::Aborted)
+        ));
+        assert!(matches!(
+            prompt_deck(&decks, "".as_bytes(), Vec::new()),
+            Err(DuoloadError::Aborted)
+        ));
+    }
+}
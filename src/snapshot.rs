@@ -0,0 +1,544 @@
+//! Snapshot bundles: lossless point-in-time backups of a deck.
+//!
+//! A `.duoload` bundle is a zip archive holding:
+//!
+//! - `metadata.json`: deck ID, capture time and bundle format version
+//! - `pages/0001.json`, ...: the API responses exactly as they were received
+//! - `cards.json`: the cards converted to duoload's own representation
+//! - `manifest.json`: sizes and SHA-256 hashes of the entries above
+//!
+//! Because the raw responses are kept, later duoload versions can re-process
+//! a snapshot with whatever they extract from the API.
+
+use crate::duocards::DuocardsClient;
+use crate::duocards::models::{DuocardsResponse, VocabularyCard};
+use crate::error::{DuoloadError, Result};
+use crate::output::manifest::Manifest;
+use crate::output::timestamp::UtcDateTime;
+use crate::output::{self, OutputBuilder};
+use crate::transfer::CursorGuard;
+use crate::transfer::processor::TransferStats;
+use crate::transfer::progress::{ProgressEvent, ProgressReporter};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use tokio::time::sleep;
+
+/// Version of the bundle layout written by this duoload.
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 1;
+
+const METADATA_ENTRY: &str = "metadata.json";
+const CARDS_ENTRY: &str = "cards.json";
+const MANIFEST_ENTRY: &str = "manifest.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotMetadata {
+    pub format_version: u32,
+    pub deck_id: String,
+    pub created_at: String,
+    pub tool_version: String,
+    pub pages: u32,
+    /// `false` if capturing stopped at the page limit before the last page
+    pub complete: bool,
+}
+
+/// A captured deck.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub metadata: SnapshotMetadata,
+    /// Raw API responses, one per page
+    pub pages: Vec<Value>,
+    /// Every card of every page, duplicates included
+    pub cards: Vec<VocabularyCard>,
+}
+
+impl Snapshot {
+    /// Fetches every page of a deck, honoring the client's page limit.
+    ///
+    /// Pages are `page_delay` apart, or further if the server asks to slow
+    /// down.
+    pub async fn capture(
+        client: &DuocardsClient,
+        deck_id: &str,
+        page_delay: Duration,
+        reporter: &dyn ProgressReporter,
+    ) -> Result<Self> {
+        let mut pages = Vec::new();
+        let mut cards = Vec::new();
+        let mut cursor = None;
+        let mut cursor_guard = CursorGuard::new(None);
+        let mut complete = false;
+
+        loop {
+            let page = pages.len() as u32 + 1;
+            if !client.should_continue(page) {
+                reporter.report(&ProgressEvent::PageLimitReached { pages: page - 1 });
+                break;
+            }
+
+            reporter.report(&ProgressEvent::FetchingPage { page });
+            // Same pacing as regular exports
+            if page > 1 {
+                let hint = client.throttle_delay().filter(|hint| *hint > page_delay);
+                sleep(hint.unwrap_or(page_delay)).await;
+            }
+
+            let raw = client.fetch_page_raw(deck_id, cursor).await?;
+            let response: DuocardsResponse = serde_json::from_value(raw.clone())?;
+            let page_cards = client.convert_to_vocabulary_cards(&response);
+            reporter.report(&ProgressEvent::PageFetched {
+                page,
+                cards: page_cards.len(),
+            });
+            cursor_guard.check(page, &response)?;
+
+            pages.push(raw);
+            cards.extend(page_cards);
+
+            let page_info = response.data.node.cards.page_info;
+            if !page_info.has_next_page {
+                reporter.report(&ProgressEvent::LastPageFetched);
+                complete = true;
+                break;
+            }
+            cursor = page_info.end_cursor;
+        }
+
+        Ok(Self {
+            metadata: SnapshotMetadata {
+                format_version: SNAPSHOT_FORMAT_VERSION,
+                deck_id: deck_id.to_string(),
+                created_at: UtcDateTime::from_system_time(SystemTime::now()).rfc3339(),
+                tool_version: env!("CARGO_PKG_VERSION").to_string(),
+                pages: pages.len() as u32,
+                complete,
+            },
+            pages,
+            cards,
+        })
+    }
+
+    /// Writes the bundle to `path` and returns its manifest.
+    pub fn write(&self, path: &Path) -> Result<Manifest> {
+        let mut entries = vec![(
+            METADATA_ENTRY.to_string(),
+            serde_json::to_vec_pretty(&self.metadata)?,
+        )];
+        for (index, page) in self.pages.iter().enumerate() {
+            entries.push((page_entry(index + 1), serde_json::to_vec_pretty(page)?));
+        }
+        entries.push((
+            CARDS_ENTRY.to_string(),
+            serde_json::to_vec_pretty(&self.cards)?,
+        ));
+
+        let stats = TransferStats {
+            total_cards: self.cards.len(),
+            ..TransferStats::default()
+        };
+        let mut manifest = Manifest::new(&self.metadata.deck_id, &stats);
+        for (name, content) in &entries {
+            manifest.add_entry(name, content);
+        }
+        entries.push((
+            MANIFEST_ENTRY.to_string(),
+            serde_json::to_vec_pretty(&manifest)?,
+        ));
+
+        // An interrupted write must not leave a corrupt bundle behind
+        output::write_file_atomically(path, |temp| {
+            let mut archive = zip::ZipWriter::new(File::create(temp)?);
+            let options = zip::write::FileOptions::default();
+            for (name, content) in &entries {
+                archive.start_file(name.as_str(), options).map_err(|e| {
+                    DuoloadError::Snapshot(format!("Failed to write {}: {}", name, e))
+                })?;
+                archive.write_all(content)?;
+            }
+            archive
+                .finish()
+                .map_err(|e| DuoloadError::Snapshot(e.to_string()))?;
+            Ok(())
+        })?;
+
+        Ok(manifest)
+    }
+
+    /// Reads a bundle, checking every entry against its manifest.
+    pub fn read(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .map_err(|e| DuoloadError::Snapshot(format!("Failed to open {:?}: {}", path, e)))?;
+        let mut archive = zip::ZipArchive::new(file)
+            .map_err(|e| DuoloadError::Snapshot(format!("Failed to open {:?}: {}", path, e)))?;
+
+        let manifest: Manifest =
+            serde_json::from_slice(&read_entry(&mut archive, MANIFEST_ENTRY)?)?;
+        let mut entries = HashMap::new();
+        for file in &manifest.files {
+            let content = read_entry(&mut archive, &file.path)?;
+            let mut check = manifest.clone();
+            check.files.clear();
+            check.add_entry(&file.path, &content);
+            if check.files[0] != *file {
+                return Err(DuoloadError::Snapshot(format!(
+                    "Entry {} is corrupted: expected SHA-256 {}, found {}",
+                    file.path, file.sha256, check.files[0].sha256
+                )));
+            }
+            entries.insert(file.path.clone(), content);
+        }
+        let entry = |name: &str| {
+            entries.get(name).ok_or_else(|| {
+                DuoloadError::Snapshot(format!("Entry {} is not listed in the manifest", name))
+            })
+        };
+
+        let metadata: SnapshotMetadata = serde_json::from_slice(entry(METADATA_ENTRY)?)?;
+        if metadata.format_version > SNAPSHOT_FORMAT_VERSION {
+            return Err(DuoloadError::Snapshot(format!(
+                "Format version {} is newer than supported version {}",
+                metadata.format_version, SNAPSHOT_FORMAT_VERSION
+            )));
+        }
+
+        let mut pages = Vec::new();
+        for page in 1..=metadata.pages as usize {
+            pages.push(serde_json::from_slice(entry(&page_entry(page))?)?);
+        }
+        let cards = serde_json::from_slice(entry(CARDS_ENTRY)?)?;
+
+        Ok(Self {
+            metadata,
+            pages,
+            cards,
+        })
+    }
+}
+
+impl Snapshot {
+    /// Converts the raw API responses into cards with the current conversion rules.
+    ///
+    /// Unlike [`Snapshot::cards`], which reflects the duoload version that took
+    /// the snapshot, this picks up improvements made since.
+    pub fn convert_pages(&self) -> Result<Vec<VocabularyCard>> {
+        let mut cards = Vec::new();
+        for page in &self.pages {
+            let response: DuocardsResponse = serde_json::from_value(page.clone())?;
+            cards.extend(
+                response
+                    .data
+                    .node
+                    .cards
+                    .edges
+                    .into_iter()
+                    .map(|edge| VocabularyCard::from(edge.node)),
+            );
+        }
+        Ok(cards)
+    }
+
+    /// Feeds the snapshot's cards into `builder`, as an export would.
+    ///
+    /// The deck metadata of the first page is passed on too, so the notes get
+    /// the same GUIDs as in a live export of the deck.
+    pub fn restore<B: OutputBuilder>(&self, builder: &mut B) -> Result<TransferStats> {
+        if let Some(page) = self.pages.first() {
+            let response: DuocardsResponse = serde_json::from_value(page.clone())?;
+            builder.set_deck_metadata(&response.deck_metadata());
+        }
+        let mut stats = TransferStats::default();
+        for card in self.convert_pages()? {
+            let (word, status) = (card.word.clone(), card.status.clone());
+            if builder.add_note(card)? {
+                stats.count_saved(&word, status);
+            } else {
+                stats.duplicates += 1;
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Compares the cards of this snapshot with a later one, word by word.
+    ///
+    /// Repeated words are compared by their first card.
+    pub fn diff(&self, later: &Snapshot) -> Result<SnapshotDiff> {
+        let before = first_cards(self.convert_pages()?);
+        let after = first_cards(later.convert_pages()?);
+        let before_words: HashMap<&str, &VocabularyCard> = before
+            .iter()
+            .map(|card| (card.word.as_str(), card))
+            .collect();
+        let after_words: HashMap<&str, &VocabularyCard> = after
+            .iter()
+            .map(|card| (card.word.as_str(), card))
+            .collect();
+
+        let mut diff = SnapshotDiff::default();
+        for card in &after {
+            match before_words.get(card.word.as_str()) {
+                None => diff.added.push(card.clone()),
+                Some(old) if !same_card(old, card) => {
+                    diff.changed.push(((*old).clone(), card.clone()))
+                }
+                Some(_) => {}
+            }
+        }
+        diff.removed = before
+            .iter()
+            .filter(|card| !after_words.contains_key(card.word.as_str()))
+            .cloned()
+            .collect();
+        Ok(diff)
+    }
+}
+
+/// Differences between the cards of two snapshots.
+#[derive(Debug, Default)]
+pub struct SnapshotDiff {
+    pub added: Vec<VocabularyCard>,
+    pub removed: Vec<VocabularyCard>,
+    /// Pairs of (before, after) for words whose card changed
+    pub changed: Vec<(VocabularyCard, VocabularyCard)>,
+}
+
+impl SnapshotDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl fmt::Display for SnapshotDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for card in &self.removed {
+            writeln!(f, "- {}: {}", card.word, card.translation)?;
+        }
+        for card in &self.added {
+            writeln!(f, "+ {}: {}", card.word, card.translation)?;
+        }
+        for (old, new) in &self.changed {
+            writeln!(f, "~ {}", new.word)?;
+            if old.translation != new.translation {
+                writeln!(
+                    f,
+                    "    translation: {} -> {}",
+                    old.translation, new.translation
+                )?;
+            }
+            if old.example != new.example {
+                writeln!(
+                    f,
+                    "    example: {} -> {}",
+                    old.example.as_deref().unwrap_or("(none)"),
+                    new.example.as_deref().unwrap_or("(none)")
+                )?;
+            }
+            if old.status != new.status {
+                writeln!(f, "    status: {:?} -> {:?}", old.status, new.status)?;
+            }
+        }
+        write!(
+            f,
+            "{} added, {} removed, {} changed",
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len()
+        )
+    }
+}
+
+/// Keeps the first card of every word, in order.
+pub(crate) fn first_cards(cards: Vec<VocabularyCard>) -> Vec<VocabularyCard> {
+    let mut seen = HashSet::new();
+    cards
+        .into_iter()
+        .filter(|card| seen.insert(card.word.clone()))
+        .collect()
+}
+
+fn same_card(a: &VocabularyCard, b: &VocabularyCard) -> bool {
+    a.translation == b.translation && a.example == b.example && a.status == b.status
+}
+
+fn page_entry(page: usize) -> String {
+    format!("pages/{:04}.json", page)
+}
+
+fn read_entry(archive: &mut zip::ZipArchive<File>, name: &str) -> Result<Vec<u8>> {
+    let mut entry = archive
+        .by_name(name)
+        .map_err(|e| DuoloadError::Snapshot(format!("Missing entry {}: {}", name, e)))?;
+    let mut content = Vec::new();
+    entry.read_to_end(&mut content)?;
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duocards::models::LearningStatus;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            metadata: SnapshotMetadata {
+                format_version: SNAPSHOT_FORMAT_VERSION,
+                deck_id: "deck".to_string(),
+                created_at: "2025-06-01T03:15:00Z".to_string(),
+                tool_version: "0.1.2".to_string(),
+                pages: 2,
+                complete: true,
+            },
+            pages: vec![json!({"page": 1, "unknownField": true}), json!({"page": 2})],
+            cards: vec![VocabularyCard {
+                word: "hello".to_string(),
+                translation: "hola".to_string(),
+                status: LearningStatus::Known,
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("deck.duoload");
+        let snapshot = snapshot();
+
+        let manifest = snapshot.write(&path)?;
+        let names: Vec<&str> = manifest.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "metadata.json",
+                "pages/0001.json",
+                "pages/0002.json",
+                "cards.json"
+            ]
+        );
+        assert_eq!(manifest.total_cards, 1);
+
+        let read = Snapshot::read(&path)?;
+        assert_eq!(read.metadata, snapshot.metadata);
+        assert_eq!(read.pages, snapshot.pages);
+        assert_eq!(read.cards[0].word, "hello");
+        // The temporary file was renamed to the bundle
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_corrupted_entry() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("deck.duoload");
+        let manifest = snapshot().write(&path)?;
+
+        // Rewrite the bundle with tampered cards
+        let mut original = zip::ZipArchive::new(File::open(&path)?).unwrap();
+        let mut entries = Vec::new();
+        for file in &manifest.files {
+            entries.push((file.path.clone(), read_entry(&mut original, &file.path)?));
+        }
+        entries.push((
+            MANIFEST_ENTRY.to_string(),
+            read_entry(&mut original, MANIFEST_ENTRY)?,
+        ));
+        entries[3].1 = b"[]".to_vec();
+
+        let mut archive = zip::ZipWriter::new(File::create(&path)?);
+        for (name, content) in &entries {
+            archive
+                .start_file(name.as_str(), zip::write::FileOptions::default())
+                .unwrap();
+            archive.write_all(content)?;
+        }
+        archive.finish().unwrap();
+
+        let err = Snapshot::read(&path).unwrap_err();
+        assert!(err.to_string().contains("corrupted"), "{}", err);
+        Ok(())
+    }
+
+    /// A raw `cardsQuery` page with (front, back, knownCount) cards.
+    fn page(cards: &[(&str, &str, i32)]) -> Value {
+        let edges: Vec<Value> = cards
+            .iter()
+            .enumerate()
+            .map(|(index, (front, back, known_count))| {
+                json!({
+                    "node": {
+                        "id": format!("card{}", index),
+                        "front": front,
+                        "back": back,
+                        "hint": null,
+                        "waiting": null,
+                        "knownCount": known_count,
+                        "svg": null,
+                        "__typename": "Card"
+                    },
+                    "cursor": index.to_string()
+                })
+            })
+            .collect();
+        json!({
+            "data": {"node": {
+                "__typename": "Deck",
+                "cards": {"edges": edges, "pageInfo": {"endCursor": null, "hasNextPage": false}},
+                "id": "deck"
+            }},
+            "extensions": {"releaseId": null}
+        })
+    }
+
+    fn snapshot_of(cards: &[(&str, &str, i32)]) -> Snapshot {
+        Snapshot {
+            pages: vec![page(cards)],
+            ..snapshot()
+        }
+    }
+
+    #[test]
+    fn test_restore_into_builder() -> Result<()> {
+        let snapshot = snapshot_of(&[
+            ("hello", "hola", 0),
+            ("world", "mundo", 5),
+            ("hello", "hi", 0),
+        ]);
+        let mut builder = crate::output::json::JsonOutputBuilder::new();
+
+        let stats = snapshot.restore(&mut builder)?;
+        assert_eq!(stats.total_cards, 2);
+        assert_eq!(stats.duplicates, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff() -> Result<()> {
+        let before = snapshot_of(&[
+            ("hello", "hola", 0),
+            ("world", "mundo", 0),
+            ("cat", "gato", 0),
+        ]);
+        let after = snapshot_of(&[
+            ("hello", "hola", 0),
+            ("world", "mundo", 5),
+            ("dog", "perro", 0),
+        ]);
+
+        let diff = before.diff(&after)?;
+        assert_eq!(diff.added[0].word, "dog");
+        assert_eq!(diff.removed[0].word, "cat");
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(
+            diff.to_string(),
+            "- cat: gato\n+ dog: perro\n~ world\n    status: New -> Known\n1 added, 1 removed, 1 changed"
+        );
+        assert!(before.diff(&before)?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,171 @@
+//! Folding inflected forms of a word for duplicate detection.
+//!
+//! With [`DedupeKey::Stem`], cards are duplicates when their words reduce to
+//! the same Snowball stem, so "run", "runs" and "running" are one card, and
+//! the merge duplicate policy folds their translations and examples together.
+//! Stems are computed per word of the card; they only serve as keys and never
+//! show up in the output.
+//!
+//! Only available with the `stem` feature.
+//!
+//! [`DedupeKey::Stem`]: crate::transfer::duplicates::DedupeKey::Stem
+
+use rust_stemmers::{Algorithm, Stemmer};
+
+/// Language whose inflection rules fold the words of a deck.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StemLanguage {
+    Arabic,
+    Danish,
+    Dutch,
+    English,
+    Finnish,
+    French,
+    German,
+    Greek,
+    Hungarian,
+    Italian,
+    Norwegian,
+    Portuguese,
+    Romanian,
+    Russian,
+    Spanish,
+    Swedish,
+    Tamil,
+    Turkish,
+}
+
+impl StemLanguage {
+    pub const ALL: [StemLanguage; 18] = [
+        StemLanguage::Arabic,
+        StemLanguage::Danish,
+        StemLanguage::Dutch,
+        StemLanguage::English,
+        StemLanguage::Finnish,
+        StemLanguage::French,
+        StemLanguage::German,
+        StemLanguage::Greek,
+        StemLanguage::Hungarian,
+        StemLanguage::Italian,
+        StemLanguage::Norwegian,
+        StemLanguage::Portuguese,
+        StemLanguage::Romanian,
+        StemLanguage::Russian,
+        StemLanguage::Spanish,
+        StemLanguage::Swedish,
+        StemLanguage::Tamil,
+        StemLanguage::Turkish,
+    ];
+
+    /// ISO 639-1 code of the language, e.g. `de`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            StemLanguage::Arabic => "ar",
+            StemLanguage::Danish => "da",
+            StemLanguage::Dutch => "nl",
+            StemLanguage::English => "en",
+            StemLanguage::Finnish => "fi",
+            StemLanguage::French => "fr",
+            StemLanguage::German => "de",
+            StemLanguage::Greek => "el",
+            StemLanguage::Hungarian => "hu",
+            StemLanguage::Italian => "it",
+            StemLanguage::Norwegian => "no",
+            StemLanguage::Portuguese => "pt",
+            StemLanguage::Romanian => "ro",
+            StemLanguage::Russian => "ru",
+            StemLanguage::Spanish => "es",
+            StemLanguage::Swedish => "sv",
+            StemLanguage::Tamil => "ta",
+            StemLanguage::Turkish => "tr",
+        }
+    }
+
+    /// The language with the ISO 639-1 `code`.
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|language| language.code().eq_ignore_ascii_case(code))
+    }
+
+    /// Name of the duplicate key folding words of this language, e.g. `stem-de`.
+    pub fn key_name(&self) -> &'static str {
+        match self {
+            StemLanguage::Arabic => "stem-ar",
+            StemLanguage::Danish => "stem-da",
+            StemLanguage::Dutch => "stem-nl",
+            StemLanguage::English => "stem-en",
+            StemLanguage::Finnish => "stem-fi",
+            StemLanguage::French => "stem-fr",
+            StemLanguage::German => "stem-de",
+            StemLanguage::Greek => "stem-el",
+            StemLanguage::Hungarian => "stem-hu",
+            StemLanguage::Italian => "stem-it",
+            StemLanguage::Norwegian => "stem-no",
+            StemLanguage::Portuguese => "stem-pt",
+            StemLanguage::Romanian => "stem-ro",
+            StemLanguage::Russian => "stem-ru",
+            StemLanguage::Spanish => "stem-es",
+            StemLanguage::Swedish => "stem-sv",
+            StemLanguage::Tamil => "stem-ta",
+            StemLanguage::Turkish => "stem-tr",
+        }
+    }
+
+    fn algorithm(&self) -> Algorithm {
+        match self {
+            StemLanguage::Arabic => Algorithm::Arabic,
+            StemLanguage::Danish => Algorithm::Danish,
+            StemLanguage::Dutch => Algorithm::Dutch,
+            StemLanguage::English => Algorithm::English,
+            StemLanguage::Finnish => Algorithm::Finnish,
+            StemLanguage::French => Algorithm::French,
+            StemLanguage::German => Algorithm::German,
+            StemLanguage::Greek => Algorithm::Greek,
+            StemLanguage::Hungarian => Algorithm::Hungarian,
+            StemLanguage::Italian => Algorithm::Italian,
+            StemLanguage::Norwegian => Algorithm::Norwegian,
+            StemLanguage::Portuguese => Algorithm::Portuguese,
+            StemLanguage::Romanian => Algorithm::Romanian,
+            StemLanguage::Russian => Algorithm::Russian,
+            StemLanguage::Spanish => Algorithm::Spanish,
+            StemLanguage::Swedish => Algorithm::Swedish,
+            StemLanguage::Tamil => Algorithm::Tamil,
+            StemLanguage::Turkish => Algorithm::Turkish,
+        }
+    }
+
+    /// The stems of the lowercased words of `text`, separated by spaces.
+    pub fn fold(&self, text: &str) -> String {
+        let stemmer = Stemmer::create(self.algorithm());
+        text.split_whitespace()
+            .map(|word| stemmer.stem(&word.to_lowercase()).into_owned())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inflected_forms_fold_together() {
+        let english = StemLanguage::English;
+        assert_eq!(english.fold("run"), english.fold("runs"));
+        assert_eq!(english.fold("run"), english.fold("Running"));
+        assert_ne!(english.fold("run"), english.fold("ran out"));
+
+        let german = StemLanguage::German;
+        assert_eq!(german.fold("Häuser"), german.fold("Haus"));
+    }
+
+    #[test]
+    fn test_codes() {
+        assert_eq!(StemLanguage::from_code("DE"), Some(StemLanguage::German));
+        assert_eq!(StemLanguage::from_code("xx"), None);
+        for language in StemLanguage::ALL {
+            assert_eq!(language.key_name(), format!("stem-{}", language.code()));
+        }
+    }
+}
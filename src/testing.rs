@@ -0,0 +1,755 @@
+//! Test helpers for exercising duoload without the real Duocards API.
+//!
+//! [`MockDuocardsServer`] is an in-process HTTP server that answers
+//! `cardsQuery` requests the way Duocards does, paginating over
+//! [`FixtureDeck`]s. Point a [`DuocardsClient`] at it with
+//! [`MockDuocardsServer::client`] and the whole export pipeline runs locally.
+//!
+//! ```no_run
+//! # async fn example() -> duoload::error::Result<()> {
+//! use duoload::testing::{FixtureDeck, MockDuocardsServer};
+//!
+//! let deck = FixtureDeck::generate(250);
+//! let server = MockDuocardsServer::start(vec![deck.clone()]).await?;
+//! let response = server.client()?.fetch_page(&deck.id, None).await?;
+//! assert_eq!(response.data.node.cards.edges.len(), 100);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Fixture files, such as the pages printed by `fetch_cards` or written by
+//! `--raw-json-file`, load with [`FixtureDeck::load`]. The
+//! `duoload-mock-server` binary serves them on a fixed port, so that the
+//! `duoload` CLI can export them with `--api-url`.
+//!
+//! [`DuocardsClient::with_faults`] makes some of a client's requests time
+//! out, fail with a server error or return a malformed body, to exercise
+//! retries and resumed exports.
+//!
+//! Only available with the `testing` feature.
+
+use crate::duocards::DuocardsClient;
+use crate::duocards::deck::validate_deck_id;
+use crate::duocards::graphql::query_hash;
+use crate::duocards::models::{
+    Card, CardConnection, CardEdge, Deck, DeckSummary, DeckSummaryData, DeckSummaryResponse,
+    DeckTotals, DecksData, DecksResponse, DuocardsResponse, Extensions, PageInfo, ResponseData,
+    Viewer,
+};
+use crate::error::{DuoloadError, Result};
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use serde_json::{Value, json};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+/// Page size used when a request does not say how many cards it wants.
+const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// Release ID the mock server reports in answer to `pingQuery`.
+pub const MOCK_RELEASE_ID: &str = "mock";
+
+/// Returns a valid, random deck ID (base64 of `Deck:<UUID4>`).
+pub fn random_deck_id() -> String {
+    BASE64.encode(format!("Deck:{}", Uuid::new_v4()))
+}
+
+/// Builds a card as the Duocards API returns it.
+pub fn fixture_card(front: &str, back: &str, hint: Option<&str>, known_count: i32) -> Card {
+    Card {
+        id: Uuid::new_v4().to_string(),
+        front: front.to_string(),
+        back: back.to_string(),
+        hint: hint.map(|s| s.to_string()),
+        created_at: None,
+        waiting: None,
+        known_count,
+        svg: None,
+        audio: None,
+        source: None,
+        s_card: None,
+        typename: "Card".to_string(),
+    }
+}
+
+/// A deck served by [`MockDuocardsServer`].
+#[derive(Debug, Clone)]
+pub struct FixtureDeck {
+    pub id: String,
+    pub name: Option<String>,
+    pub cards: Vec<Card>,
+}
+
+impl FixtureDeck {
+    /// An empty deck with a random ID.
+    pub fn new() -> Self {
+        Self::with_id(random_deck_id())
+    }
+
+    /// An empty deck with the given ID.
+    pub fn with_id(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            name: None,
+            cards: Vec::new(),
+        }
+    }
+
+    /// A deck of `count` distinct cards (`word0`/`translation0`, ...).
+    ///
+    /// Every other card has an example, and the known count cycles through
+    /// 0..=5 so that all learning statuses are represented.
+    pub fn generate(count: usize) -> Self {
+        let mut deck = Self::new();
+        for index in 0..count {
+            let example = format!("Example for word{}", index);
+            deck = deck.with_card(fixture_card(
+                &format!("word{}", index),
+                &format!("translation{}", index),
+                (index % 2 == 0).then_some(example.as_str()),
+                (index % 6) as i32,
+            ));
+        }
+        deck
+    }
+
+    /// Names the deck, as returned with every page.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn with_card(mut self, card: Card) -> Self {
+        self.cards.push(card);
+        self
+    }
+
+    /// Appends another card for an existing word, with a different translation.
+    pub fn with_duplicate(self, word: &str) -> Self {
+        let translation = format!("another translation of {}", word);
+        self.with_card(fixture_card(word, &translation, None, 0))
+    }
+
+    /// A deck from a fixture: a `cardsQuery` response, an array of them, or
+    /// an array of cards. A response gives the deck its ID and name; an array
+    /// of cards makes a deck with a random ID.
+    pub fn from_json(fixture: Value) -> Result<Self> {
+        let invalid = |e: serde_json::Error| {
+            DuoloadError::Format(format!(
+                "Fixture is neither a cardsQuery response nor an array of them or of cards: {}",
+                e
+            ))
+        };
+        let pages = match fixture {
+            Value::Array(items) if items.iter().all(|item| item.get("data").is_none()) => {
+                let cards = items
+                    .into_iter()
+                    .map(serde_json::from_value)
+                    .collect::<serde_json::Result<Vec<Card>>>()
+                    .map_err(invalid)?;
+                return Ok(Self {
+                    cards,
+                    ..Self::new()
+                });
+            }
+            Value::Array(pages) => pages,
+            page => vec![page],
+        };
+
+        let mut deck: Option<Self> = None;
+        for page in pages {
+            let node = serde_json::from_value::<DuocardsResponse>(page)
+                .map_err(invalid)?
+                .data
+                .node;
+            let deck = deck.get_or_insert_with(|| Self {
+                name: node.name.clone(),
+                ..Self::with_id(node.id.clone())
+            });
+            deck.cards
+                .extend(node.cards.edges.into_iter().map(|edge| edge.node));
+        }
+        deck.ok_or_else(|| DuoloadError::Format("Fixture has no pages".to_string()))
+    }
+
+    /// Reads a deck from a fixture file, see [`FixtureDeck::from_json`]. A
+    /// file named after a deck ID, like `<deck ID>.json`, serves its cards
+    /// under that ID.
+    pub fn load(path: &Path) -> Result<Self> {
+        let fixture = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        let mut deck = Self::from_json(fixture)?;
+        if let Some(stem) = path.file_stem().and_then(|stem| stem.to_str())
+            && validate_deck_id(stem).is_ok()
+        {
+            deck.id = stem.to_string();
+        }
+        Ok(deck)
+    }
+
+    /// The page of `count` cards following `cursor`, as `cardsQuery` returns it.
+    ///
+    /// Cursors are the index of the card as a string, like the real API uses.
+    pub fn page(&self, cursor: Option<&str>, count: usize) -> DuocardsResponse {
+        let start = cursor
+            .and_then(|cursor| cursor.parse::<usize>().ok())
+            .map_or(0, |index| index + 1)
+            .min(self.cards.len());
+        let end = (start + count).min(self.cards.len());
+
+        let edges: Vec<CardEdge> = self.cards[start..end]
+            .iter()
+            .enumerate()
+            .map(|(offset, card)| CardEdge {
+                node: card.clone(),
+                cursor: (start + offset).to_string(),
+            })
+            .collect();
+
+        DuocardsResponse {
+            data: ResponseData {
+                node: Deck {
+                    __typename: "Deck".to_string(),
+                    cards: CardConnection {
+                        page_info: PageInfo {
+                            end_cursor: edges.last().map(|edge| edge.cursor.clone()),
+                            has_next_page: end < self.cards.len(),
+                        },
+                        edges,
+                    },
+                    id: self.id.clone(),
+                    name: self.name.clone(),
+                    front: None,
+                    back: None,
+                },
+            },
+            extensions: Extensions { release_id: None },
+        }
+    }
+}
+
+impl Default for FixtureDeck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// State shared between the server handle and its connection tasks.
+#[derive(Default)]
+struct ServerState {
+    decks: HashMap<String, FixtureDeck>,
+    persisted_queries: HashSet<String>,
+    requests: Vec<Value>,
+}
+
+/// In-process mock of the Duocards GraphQL endpoint.
+///
+/// The server stops when the handle is dropped.
+pub struct MockDuocardsServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<ServerState>>,
+    task: JoinHandle<()>,
+}
+
+impl MockDuocardsServer {
+    /// Starts serving the given decks on a random local port.
+    pub async fn start(decks: Vec<FixtureDeck>) -> Result<Self> {
+        Self::bind("127.0.0.1:0", decks).await
+    }
+
+    /// Starts serving the given decks on `addr`.
+    pub async fn bind(addr: impl ToSocketAddrs, decks: Vec<FixtureDeck>) -> Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(ServerState {
+            decks: decks
+                .into_iter()
+                .map(|deck| (deck.id.clone(), deck))
+                .collect(),
+            ..ServerState::default()
+        }));
+
+        let server_state = state.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve_connection(stream, server_state.clone()));
+            }
+        });
+
+        Ok(Self { addr, state, task })
+    }
+
+    /// The GraphQL endpoint URL.
+    pub fn url(&self) -> String {
+        format!("http://{}/graphql", self.addr)
+    }
+
+    /// A client talking to this server.
+    pub fn client(&self) -> Result<DuocardsClient> {
+        let mut client = DuocardsClient::new()?;
+        client.base_url = self.url();
+        Ok(client)
+    }
+
+    /// Request bodies received so far, in order.
+    pub fn requests(&self) -> Vec<Value> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    pub fn request_count(&self) -> usize {
+        self.state.lock().unwrap().requests.len()
+    }
+}
+
+impl Drop for MockDuocardsServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn serve_connection(stream: TcpStream, state: Arc<Mutex<ServerState>>) {
+    let mut stream = BufReader::new(stream);
+    // Serve requests until the client closes the keep-alive connection
+    while let Ok(Some(body)) = read_request(&mut stream).await {
+        let (status, response) = match serde_json::from_slice::<Value>(&body) {
+            Ok(request) => ("200 OK", handle_request(&state, request)),
+            Err(e) => ("400 Bad Request", json!({ "error": e.to_string() })),
+        };
+        let response = response.to_string();
+        let head = format!(
+            "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n",
+            status,
+            response.len()
+        );
+        let stream = stream.get_mut();
+        if stream.write_all(head.as_bytes()).await.is_err()
+            || stream.write_all(response.as_bytes()).await.is_err()
+        {
+            return;
+        }
+    }
+}
+
+/// Reads one HTTP request and returns its body, or `None` once the peer hangs up.
+async fn read_request(stream: &mut BufReader<TcpStream>) -> std::io::Result<Option<Vec<u8>>> {
+    let mut content_length = 0;
+    let mut line = String::new();
+    let mut request_line = true;
+    loop {
+        line.clear();
+        if stream.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            if request_line {
+                continue;
+            }
+            break;
+        }
+        request_line = false;
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse().unwrap_or(0);
+        }
+    }
+
+    let mut body = vec![0; content_length];
+    stream.read_exact(&mut body).await?;
+    Ok(Some(body))
+}
+
+fn handle_request(state: &Mutex<ServerState>, request: Value) -> Value {
+    let mut state = state.lock().unwrap();
+    state.requests.push(request.clone());
+
+    let hash = request["extensions"]["persistedQuery"]["sha256Hash"].as_str();
+    match (request["query"].as_str(), hash) {
+        (Some(query), Some(hash)) => {
+            if query_hash(query) != hash {
+                return graphql_error("provided sha does not match query");
+            }
+            state.persisted_queries.insert(hash.to_string());
+        }
+        (None, Some(hash)) if !state.persisted_queries.contains(hash) => {
+            return graphql_error("PersistedQueryNotFound");
+        }
+        (None, None) => return graphql_error("Must provide query string."),
+        _ => {}
+    }
+
+    if request["operationName"] == "pingQuery" {
+        // Nobody is logged in to the mock
+        return json!({
+            "data": { "__typename": "Query", "viewer": null },
+            "extensions": { "releaseId": MOCK_RELEASE_ID }
+        });
+    }
+    if request["operationName"] == "decksQuery" {
+        // Every deck belongs to the account the decks are listed for
+        let mut decks: Vec<DeckSummary> = state
+            .decks
+            .values()
+            .map(|deck| DeckSummary {
+                id: deck.id.clone(),
+                name: deck.name.clone().unwrap_or_default(),
+                front: None,
+                back: None,
+            })
+            .collect();
+        decks.sort_by(|a, b| a.id.cmp(&b.id));
+        let response = DecksResponse {
+            data: DecksData {
+                viewer: Some(Viewer { decks }),
+            },
+        };
+        return serde_json::to_value(response).expect("responses are always serializable");
+    }
+
+    let operation = request["operationName"].as_str().unwrap_or_default();
+    if operation != "cardsQuery" && operation != "deckSummaryQuery" {
+        return graphql_error(&format!("Unknown operation {}", request["operationName"]));
+    }
+
+    let variables = &request["variables"];
+    let Some(deck) = variables["deckId"]
+        .as_str()
+        .and_then(|id| state.decks.get(id))
+    else {
+        return graphql_error("Deck not found");
+    };
+
+    let response = if operation == "cardsQuery" {
+        let count = variables["count"]
+            .as_u64()
+            .map_or(DEFAULT_PAGE_SIZE, |count| count as usize);
+        serde_json::to_value(deck.page(variables["cursor"].as_str(), count))
+    } else {
+        serde_json::to_value(DeckSummaryResponse {
+            data: DeckSummaryData {
+                node: DeckTotals {
+                    __typename: "Deck".to_string(),
+                    id: deck.id.clone(),
+                    name: deck.name.clone(),
+                    front: None,
+                    back: None,
+                    cards_count: deck.cards.len() as u64,
+                },
+            },
+        })
+    };
+    response.expect("responses are always serializable")
+}
+
+fn graphql_error(message: &str) -> Value {
+    json!({ "errors": [{ "message": message }] })
+}
+
+/// Default wait of an injected timeout, as long as the client waits for a response.
+pub const DEFAULT_FAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often [`DuocardsClient::with_faults`] makes a request fail, each as a
+/// fraction of the requests from 0 to 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Faults {
+    /// Requests that hang for `timeout_after`, then fail
+    pub timeout: f64,
+    /// Requests answered with 500 Internal Server Error
+    pub server_error: f64,
+    /// Requests answered with a truncated body
+    pub malformed: f64,
+    /// How long an injected timeout hangs before it fails
+    pub timeout_after: Duration,
+    /// Seed of the random choice of failing requests, to fail the same
+    /// requests in every run; random without one
+    pub seed: Option<u64>,
+}
+
+impl Default for Faults {
+    fn default() -> Self {
+        Self {
+            timeout: 0.0,
+            server_error: 0.0,
+            malformed: 0.0,
+            timeout_after: DEFAULT_FAULT_TIMEOUT,
+            seed: None,
+        }
+    }
+}
+
+impl FromStr for Faults {
+    type Err = String;
+
+    /// Parses comma-separated `kind=rate` pairs, e.g.
+    /// `timeout=0.1,server-error=0.05,malformed=0.01,seed=42`, with
+    /// `timeout-after=SECONDS` for how long injected timeouts hang.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut faults = Faults::default();
+        for pair in s.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let (kind, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("Expected kind=rate, got {:?}", pair))?;
+            let (kind, value) = (kind.trim(), value.trim());
+            match kind {
+                "seed" => {
+                    faults.seed = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("Invalid seed {:?}", value))?,
+                    );
+                    continue;
+                }
+                "timeout-after" => {
+                    faults.timeout_after = value
+                        .parse()
+                        .ok()
+                        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                        .ok_or_else(|| format!("Invalid number of seconds {:?}", value))?;
+                    continue;
+                }
+                _ => {}
+            }
+            let rate: f64 = value
+                .parse()
+                .ok()
+                .filter(|rate| (0.0..=1.0).contains(rate))
+                .ok_or_else(|| format!("Expected a rate from 0 to 1, got {:?}", value))?;
+            match kind {
+                "timeout" => faults.timeout = rate,
+                "server-error" => faults.server_error = rate,
+                "malformed" => faults.malformed = rate,
+                _ => {
+                    return Err(format!(
+                        "Unknown fault {:?}; expected timeout, server-error, malformed, \
+                         timeout-after or seed",
+                        kind
+                    ));
+                }
+            }
+        }
+        if faults.timeout + faults.server_error + faults.malformed > 1.0 {
+            return Err("The fault rates add up to more than 1".to_string());
+        }
+        Ok(faults)
+    }
+}
+
+impl fmt::Display for Faults {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "timeout={},server-error={},malformed={},timeout-after={}",
+            self.timeout,
+            self.server_error,
+            self.malformed,
+            self.timeout_after.as_secs_f64()
+        )?;
+        if let Some(seed) = self.seed {
+            write!(f, ",seed={}", seed)?;
+        }
+        Ok(())
+    }
+}
+
+/// A fault a [`FaultInjector`] injects into a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// No response within [`Faults::timeout_after`]
+    Timeout,
+    /// 500 Internal Server Error
+    ServerError,
+    /// The request is sent, but its response body is cut off
+    Malformed,
+}
+
+/// Picks the requests of a [`DuocardsClient`] that fail at the rates of its
+/// [`Faults`], e.g. to check that an export with retries, `--auto-retry` or
+/// a checkpoint still gets every card. See [`DuocardsClient::with_faults`].
+#[derive(Debug)]
+pub struct FaultInjector {
+    faults: Faults,
+    /// State of the random number generator
+    state: AtomicU64,
+    injected: Arc<AtomicU64>,
+}
+
+impl FaultInjector {
+    pub fn new(faults: Faults) -> Self {
+        let seed = faults.seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_nanos() as u64)
+        });
+        Self {
+            faults,
+            state: AtomicU64::new(seed),
+            injected: Arc::default(),
+        }
+    }
+
+    pub fn faults(&self) -> &Faults {
+        &self.faults
+    }
+
+    /// Number of requests that failed because of an injected fault, still
+    /// readable once the injector is handed to a client.
+    pub fn injected(&self) -> Arc<AtomicU64> {
+        self.injected.clone()
+    }
+
+    /// The next number in `[0, 1)` of a SplitMix64 sequence.
+    fn random_fraction(&self) -> f64 {
+        let mut z = self
+            .state
+            .fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed)
+            .wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// The fault to inject into the next request, if any.
+    pub fn next_fault(&self) -> Option<Fault> {
+        let draw = self.random_fraction();
+        let Faults {
+            timeout,
+            server_error,
+            malformed,
+            ..
+        } = self.faults;
+        let fault = if draw < timeout {
+            Fault::Timeout
+        } else if draw < timeout + server_error {
+            Fault::ServerError
+        } else if draw < timeout + server_error + malformed {
+            Fault::Malformed
+        } else {
+            return None;
+        };
+        self.injected.fetch_add(1, Ordering::Relaxed);
+        Some(fault)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_faults() {
+        let faults: Faults = "timeout=0.1, server-error=0.2,seed=42".parse().unwrap();
+        assert_eq!(faults.timeout, 0.1);
+        assert_eq!(faults.server_error, 0.2);
+        assert_eq!(faults.malformed, 0.0);
+        assert_eq!(faults.seed, Some(42));
+        assert_eq!(
+            faults.to_string(),
+            "timeout=0.1,server-error=0.2,malformed=0,timeout-after=30,seed=42"
+        );
+        let faults: Faults = "timeout=1,timeout-after=0.5".parse().unwrap();
+        assert_eq!(faults.timeout_after, Duration::from_millis(500));
+
+        assert!("timeout=2".parse::<Faults>().is_err());
+        assert!("timeout=0.6,malformed=0.6".parse::<Faults>().is_err());
+        assert!("latency=0.1".parse::<Faults>().is_err());
+    }
+
+    #[test]
+    fn test_fixture_deck_pagination() {
+        let deck = FixtureDeck::generate(5);
+        assert!(validate_deck_id(&deck.id).is_ok());
+
+        let first = deck.page(None, 2);
+        let cards = &first.data.node.cards;
+        assert_eq!(cards.edges.len(), 2);
+        assert_eq!(cards.page_info.end_cursor.as_deref(), Some("1"));
+        assert!(cards.page_info.has_next_page);
+
+        let last = deck.page(Some("3"), 2);
+        let cards = &last.data.node.cards;
+        assert_eq!(cards.edges.len(), 1);
+        assert_eq!(cards.edges[0].node.front, "word4");
+        assert!(!cards.page_info.has_next_page);
+    }
+
+    #[test]
+    fn test_fixture_deck_from_files() -> Result<()> {
+        let deck = FixtureDeck::generate(5).with_name("Deutsch");
+        let pages = vec![deck.page(None, 3), deck.page(Some("2"), 3)];
+        let loaded = FixtureDeck::from_json(serde_json::to_value(&pages)?)?;
+        assert_eq!(loaded.id, deck.id);
+        assert_eq!(loaded.name.as_deref(), Some("Deutsch"));
+        assert_eq!(loaded.cards.len(), 5);
+
+        // Cards take the ID from the file name when it is a deck ID
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(format!("{}.json", deck.id));
+        std::fs::write(&path, serde_json::to_string(&deck.cards)?)?;
+        let loaded = FixtureDeck::load(&path)?;
+        assert_eq!(loaded.id, deck.id);
+        assert_eq!(loaded.cards[4].front, "word4");
+
+        assert!(matches!(
+            FixtureDeck::from_json(json!({"cards": []})),
+            Err(DuoloadError::Format(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_server_paginates_deck() -> Result<()> {
+        let deck = FixtureDeck::generate(150);
+        let server = MockDuocardsServer::start(vec![deck.clone()]).await?;
+        let client = server.client()?;
+
+        let first = client.fetch_page(&deck.id, None).await?;
+        assert_eq!(first.data.node.cards.edges.len(), 100);
+        let cursor = first.data.node.cards.page_info.end_cursor.clone();
+        let second = client.fetch_page(&deck.id, cursor).await?;
+        assert_eq!(second.data.node.cards.edges.len(), 50);
+        assert!(!second.data.node.cards.page_info.has_next_page);
+
+        assert_eq!(server.request_count(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_server_rejects_unknown_deck() -> Result<()> {
+        let server = MockDuocardsServer::start(Vec::new()).await?;
+        let err = server
+            .client()?
+            .fetch_page(&random_deck_id(), None)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Deck not found"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_server_supports_persisted_queries() -> Result<()> {
+        let deck = FixtureDeck::generate(3);
+        let server = MockDuocardsServer::start(vec![deck.clone()]).await?;
+        let client = server.client()?.with_persisted_queries(true);
+
+        client.fetch_page(&deck.id, None).await?;
+        client.fetch_page(&deck.id, None).await?;
+
+        // Hash miss, registration, then a hash hit
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[0].get("query").is_none());
+        assert!(requests[1].get("query").is_some());
+        assert!(requests[2].get("query").is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+//! Checkpoints for resuming interrupted exports.
+//!
+//! After every page that reaches the output builder, the processor saves the
+//! cursor of the next page, the number of pages done and the cards received
+//! so far. A later run with the same checkpoint file replays those cards into
+//! its builder, which restores the set of seen words and the duplicate
+//! counts, and continues fetching from the saved cursor.
+
+use crate::duocards::models::VocabularyCard;
+use crate::error::{DuoloadError, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub deck_id: String,
+    /// Cursor to fetch the next page from; `None` before the first page
+    pub cursor: Option<String>,
+    /// Pages that reached the output builder
+    pub pages: u32,
+    /// Whether the last page has been received
+    pub complete: bool,
+    /// Every card received so far, duplicates included, in deck order
+    pub cards: Vec<VocabularyCard>,
+}
+
+impl Checkpoint {
+    pub fn new(deck_id: &str) -> Self {
+        Self {
+            deck_id: deck_id.to_string(),
+            cursor: None,
+            pages: 0,
+            complete: false,
+            cards: Vec::new(),
+        }
+    }
+
+    /// Reads the checkpoint at `path`, or returns `None` if there is none yet.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let checkpoint = serde_json::from_reader(BufReader::new(file)).map_err(|e| {
+            DuoloadError::Checkpoint(format!("Cannot read checkpoint {:?}: {}", path, e))
+        })?;
+        Ok(Some(checkpoint))
+    }
+
+    /// Reads the checkpoint at `path` for `deck_id`, starting a new one if there is none.
+    pub fn load_for(path: &Path, deck_id: &str) -> Result<Self> {
+        match Self::load(path)? {
+            Some(checkpoint) if checkpoint.deck_id != deck_id => {
+                Err(DuoloadError::Checkpoint(format!(
+                    "Checkpoint {:?} belongs to deck {}, not {}",
+                    path, checkpoint.deck_id, deck_id
+                )))
+            }
+            Some(checkpoint) => Ok(checkpoint),
+            None => Ok(Self::new(deck_id)),
+        }
+    }
+
+    /// Saves the checkpoint, replacing the previous one only once fully written.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut temp_name = path.as_os_str().to_owned();
+        temp_name.push(".tmp");
+        let temp_path = Path::new(&temp_name);
+
+        let mut writer = BufWriter::new(File::create(temp_path)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        drop(writer);
+        std::fs::rename(temp_path, path)?;
+        Ok(())
+    }
+
+    /// Removes the checkpoint at `path` once the export it tracks has finished.
+    pub fn remove(path: &Path) -> Result<()> {
+        match std::fs::remove_file(path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duocards::models::LearningStatus;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_save_and_load() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("state.json");
+        assert_eq!(Checkpoint::load(&path)?, None);
+
+        let mut checkpoint = Checkpoint::load_for(&path, "deck")?;
+        checkpoint.cursor = Some("cursor1".to_string());
+        checkpoint.pages = 1;
+        checkpoint.cards.push(VocabularyCard {
+            word: "hello".to_string(),
+            translation: "hola".to_string(),
+            status: LearningStatus::New,
+            ..Default::default()
+        });
+        checkpoint.save(&path)?;
+
+        assert_eq!(Checkpoint::load_for(&path, "deck")?, checkpoint);
+        assert!(matches!(
+            Checkpoint::load_for(&path, "other"),
+            Err(DuoloadError::Checkpoint(_))
+        ));
+
+        Checkpoint::remove(&path)?;
+        assert!(!path.exists());
+        Checkpoint::remove(&path)?;
+        Ok(())
+    }
+}
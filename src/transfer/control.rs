@@ -0,0 +1,69 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Shared handle for steering a running export from the outside.
+///
+/// The fetcher checks it between pages: a paused export waits, a stopped
+/// export writes what it has fetched so far, and an aborted export fails
+/// without writing anything.
+#[derive(Debug, Clone, Default)]
+pub struct TransferControl {
+    paused: Arc<AtomicBool>,
+    stopped: Arc<AtomicBool>,
+    aborted: Arc<AtomicBool>,
+}
+
+impl TransferControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn toggle_pause(&self) {
+        self.paused.fetch_xor(true, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Stops fetching further pages; the cards collected so far are still written.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    /// Abandons the export without writing any output.
+    pub fn abort(&self) {
+        self.aborted.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_control_is_shared_between_clones() {
+        let control = TransferControl::new();
+        let handle = control.clone();
+
+        handle.toggle_pause();
+        assert!(control.is_paused());
+        handle.toggle_pause();
+        assert!(!control.is_paused());
+
+        handle.stop();
+        assert!(control.is_stopped());
+        assert!(!control.is_aborted());
+
+        handle.abort();
+        assert!(control.is_aborted());
+    }
+}
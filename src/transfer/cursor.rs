@@ -0,0 +1,126 @@
+use crate::duocards::models::DuocardsResponse;
+use crate::error::{DuoloadError, Result};
+use crate::output::manifest::hex_digest;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+
+/// Guards the pagination loop against cursors that never advance.
+///
+/// Every cursor handed out by the API must be new; a missing or repeated
+/// cursor on a page that claims to have a successor would otherwise make
+/// the export loop forever.
+pub struct CursorGuard {
+    seen_cursors: HashSet<String>,
+}
+
+impl CursorGuard {
+    /// Creates a guard, treating `start` (the cursor the export resumes from) as seen.
+    pub fn new(start: Option<&str>) -> Self {
+        Self {
+            seen_cursors: start.map(str::to_string).into_iter().collect(),
+        }
+    }
+
+    /// Checks the cursor of a fetched page before it is used for the next request.
+    pub fn check(&mut self, page: u32, response: &DuocardsResponse) -> Result<()> {
+        let page_info = &response.data.node.cards.page_info;
+        if !page_info.has_next_page {
+            return Ok(());
+        }
+
+        match &page_info.end_cursor {
+            Some(cursor) if self.seen_cursors.insert(cursor.clone()) => Ok(()),
+            cursor => Err(DuoloadError::PaginationStalled {
+                page,
+                cursor: cursor.clone(),
+                payload_hash: payload_hash(response),
+            }),
+        }
+    }
+}
+
+/// Hashes the page payload so a stalled page can be recognized in bug reports.
+///
+/// SHA-256 rather than the std hasher, whose output may change between Rust
+/// releases and would not match across duoload builds.
+fn payload_hash(response: &DuocardsResponse) -> String {
+    let payload = serde_json::to_vec(response).unwrap_or_default();
+    hex_digest(Sha256::digest(&payload).as_slice())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duocards::models::{CardConnection, Deck, Extensions, PageInfo, ResponseData};
+
+    fn response(end_cursor: Option<&str>, has_next_page: bool) -> DuocardsResponse {
+        DuocardsResponse {
+            data: ResponseData {
+                node: Deck {
+                    __typename: "Deck".to_string(),
+                    cards: CardConnection {
+                        edges: Vec::new(),
+                        page_info: PageInfo {
+                            end_cursor: end_cursor.map(str::to_string),
+                            has_next_page,
+                        },
+                    },
+                    id: "test-deck".to_string(),
+                    name: None,
+                    front: None,
+                    back: None,
+                },
+            },
+            extensions: Extensions { release_id: None },
+        }
+    }
+
+    #[test]
+    fn test_advancing_cursors() {
+        let mut guard = CursorGuard::new(None);
+        assert!(guard.check(1, &response(Some("a"), true)).is_ok());
+        assert!(guard.check(2, &response(Some("b"), true)).is_ok());
+        assert!(guard.check(3, &response(None, false)).is_ok());
+    }
+
+    #[test]
+    fn test_repeated_cursor() {
+        let mut guard = CursorGuard::new(None);
+        assert!(guard.check(1, &response(Some("a"), true)).is_ok());
+        assert!(guard.check(2, &response(Some("b"), true)).is_ok());
+
+        match guard.check(3, &response(Some("a"), true)) {
+            Err(DuoloadError::PaginationStalled { page, cursor, .. }) => {
+                assert_eq!(page, 3);
+                assert_eq!(cursor, Some("a".to_string()));
+            }
+            _ => panic!("Expected PaginationStalled error"),
+        }
+    }
+
+    #[test]
+    fn test_cursor_equal_to_start() {
+        let mut guard = CursorGuard::new(Some("a"));
+        assert!(guard.check(5, &response(Some("a"), true)).is_err());
+    }
+
+    #[test]
+    fn test_missing_cursor_with_next_page() {
+        let mut guard = CursorGuard::new(None);
+        match guard.check(1, &response(None, true)) {
+            Err(DuoloadError::PaginationStalled { cursor, .. }) => assert_eq!(cursor, None),
+            _ => panic!("Expected PaginationStalled error"),
+        }
+    }
+
+    #[test]
+    fn test_payload_hash_is_stable() {
+        let page = response(Some("a"), true);
+        assert_eq!(payload_hash(&page), payload_hash(&page.clone()));
+        assert_eq!(payload_hash(&page).len(), 64);
+        assert_ne!(
+            payload_hash(&page),
+            payload_hash(&response(Some("b"), true))
+        );
+    }
+}
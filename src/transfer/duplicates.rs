@@ -1,7 +1,173 @@
-use std::collections::HashSet;
+use crate::duocards::models::VocabularyCard;
+use crate::error::{DuoloadError, Result};
+#[cfg(feature = "stem")]
+use crate::stem::StemLanguage;
+use crate::transfer::key_store::{DedupeStore, KeyStore, SeenKey};
+use clap::ValueEnum;
+use clap::builder::PossibleValue;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::LazyLock;
 
+/// What to do when a word shows up more than once in a deck.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum DuplicatePolicy {
+    /// Keep the first card and skip later ones
+    #[default]
+    #[value(alias = "skip")]
+    First,
+    /// Keep the last card, in the position of the first one
+    Last,
+    /// Merge translations and examples into the first card
+    #[value(alias = "merge-examples")]
+    Merge,
+    /// Keep the card furthest along in learning, the first one on a tie
+    PreferKnown,
+    /// Keep every card, even if the word repeats
+    KeepAll,
+    /// Abort the export
+    Error,
+}
+
+impl DuplicatePolicy {
+    /// Whether the policy can be applied without revisiting earlier cards,
+    /// as required by outputs that write cards as they arrive.
+    pub fn is_streamable(&self) -> bool {
+        matches!(
+            self,
+            DuplicatePolicy::First | DuplicatePolicy::KeepAll | DuplicatePolicy::Error
+        )
+    }
+}
+
+impl fmt::Display for DuplicatePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.to_possible_value().expect("no policy is skipped");
+        f.write_str(value.get_name())
+    }
+}
+
+/// What makes two cards duplicates of each other.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DedupeKey {
+    /// Cards with the same word
+    #[default]
+    Word,
+    /// Cards with the same word and translation, so homographs such as
+    /// "bank" are all kept
+    WordTranslation,
+    /// Cards with the same Duocards ID; cards without one, e.g. read from an
+    /// older export, fall back to the word
+    CardId,
+    /// Cards whose words are inflected forms of each other in the language,
+    /// such as "run", "runs" and "running"
+    #[cfg(feature = "stem")]
+    Stem(StemLanguage),
+}
+
+impl DedupeKey {
+    /// The key `card` is tracked under.
+    pub fn of(&self, card: &VocabularyCard) -> String {
+        match self {
+            DedupeKey::Word => card.word.clone(),
+            // A control character cannot appear in either field
+            DedupeKey::WordTranslation => format!("{}\u{1f}{}", card.word, card.translation),
+            DedupeKey::CardId => match &card.source_id {
+                Some(id) => format!("id:{}", id),
+                None => card.word.clone(),
+            },
+            #[cfg(feature = "stem")]
+            DedupeKey::Stem(language) => language.fold(&card.word),
+        }
+    }
+}
+
+/// Every key, for the command line.
+static DEDUPE_KEYS: LazyLock<Vec<DedupeKey>> = LazyLock::new(|| {
+    let keys = vec![
+        DedupeKey::Word,
+        DedupeKey::WordTranslation,
+        DedupeKey::CardId,
+    ];
+    #[cfg(feature = "stem")]
+    let keys = keys
+        .into_iter()
+        .chain(StemLanguage::ALL.into_iter().map(DedupeKey::Stem))
+        .collect();
+    keys
+});
+
+impl ValueEnum for DedupeKey {
+    fn value_variants<'a>() -> &'a [Self] {
+        &DEDUPE_KEYS
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(match self {
+            DedupeKey::Word => PossibleValue::new("word").help("Cards with the same word"),
+            DedupeKey::WordTranslation => PossibleValue::new("word+translation")
+                .help("Cards with the same word and translation"),
+            DedupeKey::CardId => PossibleValue::new("card-id")
+                .help("Cards with the same Duocards ID, or the same word without one"),
+            #[cfg(feature = "stem")]
+            DedupeKey::Stem(language) => PossibleValue::new(language.key_name()).help(format!(
+                "Cards with inflected forms of a word in {:?}",
+                language
+            )),
+        })
+    }
+}
+
+impl fmt::Display for DedupeKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.to_possible_value().expect("no key is skipped");
+        f.write_str(value.get_name())
+    }
+}
+
+/// Cards collected by an output, by their index in the order they were added.
+///
+/// Outputs usually keep them in a `Vec`; a [`CardStore`] keeps them on disk.
+///
+/// [`CardStore`]: crate::output::store::CardStore
+pub trait CardList {
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn push(&mut self, card: VocabularyCard) -> Result<()>;
+    fn get(&self, index: usize) -> Result<VocabularyCard>;
+    fn set(&mut self, index: usize, card: VocabularyCard) -> Result<()>;
+}
+
+impl CardList for Vec<VocabularyCard> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn push(&mut self, card: VocabularyCard) -> Result<()> {
+        Vec::push(self, card);
+        Ok(())
+    }
+
+    fn get(&self, index: usize) -> Result<VocabularyCard> {
+        Ok(self[index].clone())
+    }
+
+    fn set(&mut self, index: usize, card: VocabularyCard) -> Result<()> {
+        self[index] = card;
+        Ok(())
+    }
+}
+
+/// Tracks words already added to an output and applies the duplicate policy.
 pub struct DuplicateHandler {
-    processed_words: HashSet<String>,
+    policy: DuplicatePolicy,
+    key: DedupeKey,
+    /// Index of the card added for each key
+    processed_words: Box<dyn KeyStore>,
+    /// Words already present in the output before the export started
+    seeded_words: HashSet<String>,
 }
 
 impl Default for DuplicateHandler {
@@ -12,48 +178,374 @@ impl Default for DuplicateHandler {
 
 impl DuplicateHandler {
     pub fn new() -> Self {
+        Self::with_policy(DuplicatePolicy::default())
+    }
+
+    pub fn with_policy(policy: DuplicatePolicy) -> Self {
         Self {
-            processed_words: HashSet::new(),
+            policy,
+            key: DedupeKey::default(),
+            processed_words: Box::new(HashMap::<String, usize>::new()),
+            seeded_words: HashSet::new(),
         }
     }
 
-    pub fn try_remember(&mut self, word: &str) -> bool {
-        !self.processed_words.insert(word.to_string())
+    /// Marks `word` as already present in the output, e.g. in a package that
+    /// is being merged into. Later cards with that word are always skipped,
+    /// whatever the policy.
+    pub fn seed(&mut self, word: &str) {
+        self.seeded_words.insert(word.to_string());
+    }
+
+    pub fn set_policy(&mut self, policy: DuplicatePolicy) {
+        self.policy = policy;
+    }
+
+    /// Sets what makes two cards duplicates; seeded words are still matched by word.
+    pub fn set_key(&mut self, key: DedupeKey) {
+        self.key = key;
+    }
+
+    /// Keeps the keys of seen cards in `store` instead of in memory. Cards
+    /// whose key an earlier export committed to the store are always
+    /// skipped, whatever the policy.
+    pub fn set_store(&mut self, store: &DedupeStore) {
+        self.processed_words = Box::new(store.key_store());
+    }
+
+    /// Adds `card` to `cards`, resolving a repeated key according to the policy.
+    ///
+    /// Returns `true` if the card was added as a new entry and `false` if it
+    /// was a duplicate that has been skipped or folded into an earlier card.
+    /// With [`DuplicatePolicy::KeepAll`] every card is a new entry.
+    pub fn add<L: CardList + ?Sized>(
+        &mut self,
+        cards: &mut L,
+        card: VocabularyCard,
+    ) -> Result<bool> {
+        if self.seeded_words.contains(&card.word) {
+            return Ok(false);
+        }
+        let key = self.key.of(&card);
+        let index = match self.processed_words.get(&key)? {
+            Some(SeenKey::At(index)) => index,
+            Some(SeenKey::EarlierRun) => return Ok(false),
+            None => {
+                self.processed_words.insert(key, cards.len())?;
+                cards.push(card)?;
+                return Ok(true);
+            }
+        };
+
+        match self.policy {
+            DuplicatePolicy::First => {}
+            DuplicatePolicy::Last => cards.set(index, card)?,
+            DuplicatePolicy::Merge => {
+                let mut existing = cards.get(index)?;
+                merge_cards(&mut existing, card);
+                cards.set(index, existing)?;
+            }
+            DuplicatePolicy::PreferKnown => {
+                if card.status > cards.get(index)?.status {
+                    cards.set(index, card)?;
+                }
+            }
+            DuplicatePolicy::KeepAll => {
+                cards.push(card)?;
+                return Ok(true);
+            }
+            DuplicatePolicy::Error => return Err(DuoloadError::DuplicateWord(card.word)),
+        }
+        Ok(false)
+    }
+}
+
+/// Folds a duplicate into an existing card, keeping every distinct
+/// translation and example, the first hint and the most advanced learning
+/// status.
+fn merge_cards(existing: &mut VocabularyCard, card: VocabularyCard) {
+    append_distinct(&mut existing.translation, &card.translation, "; ");
+
+    match (&mut existing.example, card.example) {
+        (Some(example), Some(other)) => append_distinct(example, &other, "\n"),
+        (example @ None, other) => *example = other,
+        (Some(_), None) => {}
+    }
+    if existing.hint.is_none() {
+        existing.hint = card.hint;
+    }
+
+    if card.status > existing.status {
+        existing.status = card.status;
+    }
+}
+
+fn append_distinct(value: &mut String, other: &str, separator: &str) {
+    if other.is_empty() || value.split(separator).any(|part| part == other) {
+        return;
+    }
+    if !value.is_empty() {
+        value.push_str(separator);
     }
+    value.push_str(other);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::duocards::models::LearningStatus;
+
+    fn card(word: &str, translation: &str, example: Option<&str>) -> VocabularyCard {
+        VocabularyCard {
+            word: word.to_string(),
+            translation: translation.to_string(),
+            example: example.map(|s| s.to_string()),
+            status: LearningStatus::New,
+            ..Default::default()
+        }
+    }
 
     #[test]
     fn test_duplicate_handler_new() {
         let handler = DuplicateHandler::new();
         assert!(handler.processed_words.is_empty());
+        assert_eq!(handler.policy, DuplicatePolicy::First);
     }
 
     #[test]
-    fn test_duplicate_handler_basic() {
+    fn test_duplicate_handler_basic() -> Result<()> {
         let mut handler = DuplicateHandler::new();
+        let mut cards = Vec::new();
 
         // First time seeing a word
-        assert!(!handler.try_remember("hello"));
-        assert!(handler.processed_words.contains("hello"));
+        assert!(handler.add(&mut cards, card("hello", "hola", None))?);
+        assert!(handler.processed_words.get("hello")?.is_some());
 
         // Second time seeing the same word
-        assert!(handler.try_remember("hello"));
+        assert!(!handler.add(&mut cards, card("hello", "bonjour", None))?);
 
         // Different word
-        assert!(!handler.try_remember("world"));
-        assert!(handler.processed_words.contains("world"));
+        assert!(handler.add(&mut cards, card("world", "mundo", None))?);
+        assert!(handler.processed_words.get("world")?.is_some());
+
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].translation, "hola");
+        Ok(())
     }
 
     #[test]
-    fn test_duplicate_handler_case_sensitive() {
+    fn test_duplicate_handler_case_sensitive() -> Result<()> {
         let mut handler = DuplicateHandler::new();
+        let mut cards = Vec::new();
+
+        assert!(handler.add(&mut cards, card("Hello", "hola", None))?);
+        assert!(handler.add(&mut cards, card("hello", "hola", None))?); // Different due to case
+        assert!(!handler.add(&mut cards, card("Hello", "hola", None))?); // Duplicate
+        Ok(())
+    }
+
+    #[test]
+    fn test_policy_last() -> Result<()> {
+        let mut handler = DuplicateHandler::with_policy(DuplicatePolicy::Last);
+        let mut cards = Vec::new();
+
+        handler.add(&mut cards, card("hello", "hola", None))?;
+        handler.add(&mut cards, card("world", "mundo", None))?;
+        assert!(!handler.add(&mut cards, card("hello", "bonjour", None))?);
+
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].word, "hello");
+        assert_eq!(cards[0].translation, "bonjour");
+        Ok(())
+    }
+
+    #[test]
+    fn test_policy_merge() -> Result<()> {
+        let mut handler = DuplicateHandler::with_policy(DuplicatePolicy::Merge);
+        let mut cards = Vec::new();
+
+        handler.add(
+            &mut cards,
+            card("bank", "Bank", Some("I went to the bank.")),
+        )?;
+        let mut known = card("bank", "Ufer", Some("The river bank."));
+        known.status = LearningStatus::Known;
+        assert!(!handler.add(&mut cards, known)?);
+        assert!(!handler.add(&mut cards, card("bank", "Bank", None))?);
+
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].translation, "Bank; Ufer");
+        assert_eq!(
+            cards[0].example.as_deref(),
+            Some("I went to the bank.\nThe river bank.")
+        );
+        assert_eq!(cards[0].status, LearningStatus::Known);
+        Ok(())
+    }
+
+    #[test]
+    fn test_policy_prefer_known() -> Result<()> {
+        let mut handler = DuplicateHandler::with_policy(DuplicatePolicy::PreferKnown);
+        let mut cards = Vec::new();
+
+        handler.add(&mut cards, card("hello", "hola", None))?;
+        let mut learning = card("hello", "salut", None);
+        learning.status = LearningStatus::Learning;
+        assert!(!handler.add(&mut cards, learning)?);
+        // A tie keeps the card already there
+        let mut tie = card("hello", "bonjour", None);
+        tie.status = LearningStatus::Learning;
+        assert!(!handler.add(&mut cards, tie)?);
+
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].translation, "salut");
+        Ok(())
+    }
+
+    #[test]
+    fn test_policy_keep_all() -> Result<()> {
+        let mut handler = DuplicateHandler::with_policy(DuplicatePolicy::KeepAll);
+        let mut cards = Vec::new();
+
+        assert!(handler.add(&mut cards, card("hello", "hola", None))?);
+        assert!(handler.add(&mut cards, card("hello", "bonjour", None))?);
+
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[1].translation, "bonjour");
+        Ok(())
+    }
+
+    #[test]
+    fn test_seeded_words_are_skipped() -> Result<()> {
+        let mut handler = DuplicateHandler::with_policy(DuplicatePolicy::Error);
+        handler.seed("hello");
+        let mut cards = Vec::new();
+
+        assert!(!handler.add(&mut cards, card("hello", "hola", None))?);
+        assert!(handler.add(&mut cards, card("world", "mundo", None))?);
+        assert_eq!(cards.len(), 1);
+        Ok(())
+    }
 
-        assert!(!handler.try_remember("Hello"));
-        assert!(!handler.try_remember("hello")); // Different due to case
-        assert!(handler.try_remember("Hello")); // Duplicate
+    #[test]
+    fn test_disk_store_skips_words_of_earlier_runs() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("keys.db");
+
+        let store = DedupeStore::open(&path)?;
+        let mut handler = DuplicateHandler::with_policy(DuplicatePolicy::Last);
+        handler.set_store(&store);
+        let mut cards = Vec::new();
+        assert!(handler.add(&mut cards, card("hello", "hola", None))?);
+        assert!(!handler.add(&mut cards, card("hello", "salut", None))?);
+        assert_eq!(cards[0].translation, "salut");
+        store.commit()?;
+        drop(store);
+
+        let store = DedupeStore::open(&path)?;
+        let mut handler = DuplicateHandler::with_policy(DuplicatePolicy::Last);
+        handler.set_store(&store);
+        let mut cards = Vec::new();
+        assert!(!handler.add(&mut cards, card("hello", "bonjour", None))?);
+        assert!(handler.add(&mut cards, card("world", "mundo", None))?);
+        assert_eq!(cards.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_word_translation_key_keeps_homographs() -> Result<()> {
+        let mut handler = DuplicateHandler::new();
+        handler.set_key(DedupeKey::WordTranslation);
+        let mut cards = Vec::new();
+
+        assert!(handler.add(&mut cards, card("bank", "Bank", None))?);
+        assert!(handler.add(&mut cards, card("bank", "Ufer", None))?);
+        assert!(!handler.add(&mut cards, card("bank", "Bank", Some("Again")))?);
+        assert_eq!(cards.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_card_id_key() -> Result<()> {
+        let mut handler = DuplicateHandler::with_policy(DuplicatePolicy::Last);
+        handler.set_key(DedupeKey::CardId);
+        let mut cards = Vec::new();
+        let with_id = |id: &str, translation: &str| VocabularyCard {
+            source_id: Some(id.to_string()),
+            ..card("bank", translation, None)
+        };
+
+        assert!(handler.add(&mut cards, with_id("1", "Bank"))?);
+        assert!(handler.add(&mut cards, with_id("2", "Ufer"))?);
+        assert!(!handler.add(&mut cards, with_id("1", "Geldinstitut"))?);
+        // Without an ID the word is the key
+        assert!(handler.add(&mut cards, card("bank", "Bank", None))?);
+        assert!(!handler.add(&mut cards, card("bank", "Bank", None))?);
+
+        assert_eq!(cards.len(), 3);
+        assert_eq!(cards[0].translation, "Geldinstitut");
+        Ok(())
+    }
+
+    #[cfg(feature = "stem")]
+    #[test]
+    fn test_stem_key_merges_inflected_forms() -> Result<()> {
+        let mut handler = DuplicateHandler::with_policy(DuplicatePolicy::Merge);
+        handler.set_key(DedupeKey::Stem(StemLanguage::English));
+        let mut cards = Vec::new();
+
+        assert!(handler.add(&mut cards, card("run", "laufen", Some("I run.")))?);
+        assert!(!handler.add(
+            &mut cards,
+            card("running", "laufend", Some("Running late."))
+        )?);
+        assert!(handler.add(&mut cards, card("ran out", "ausgehen", None))?);
+
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].word, "run");
+        assert_eq!(cards[0].translation, "laufen; laufend");
+        assert_eq!(cards[0].example.as_deref(), Some("I run.\nRunning late."));
+        assert_eq!(
+            DedupeKey::from_str("stem-en", false),
+            Ok(DedupeKey::Stem(StemLanguage::English))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_dedupe_key_names() {
+        assert_eq!(
+            DedupeKey::from_str("word+translation", false),
+            Ok(DedupeKey::WordTranslation)
+        );
+        assert_eq!(DedupeKey::CardId.to_string(), "card-id");
+    }
+
+    #[test]
+    fn test_policy_aliases() {
+        assert_eq!(
+            DuplicatePolicy::from_str("skip", false),
+            Ok(DuplicatePolicy::First)
+        );
+        assert_eq!(
+            DuplicatePolicy::from_str("merge-examples", false),
+            Ok(DuplicatePolicy::Merge)
+        );
+        assert_eq!(
+            DuplicatePolicy::from_str("keep-all", false),
+            Ok(DuplicatePolicy::KeepAll)
+        );
+    }
+
+    #[test]
+    fn test_policy_error() {
+        let mut handler = DuplicateHandler::with_policy(DuplicatePolicy::Error);
+        let mut cards = Vec::new();
+
+        assert!(handler.add(&mut cards, card("hello", "hola", None)).is_ok());
+        match handler.add(&mut cards, card("hello", "hola", None)) {
+            Err(DuoloadError::DuplicateWord(word)) => assert_eq!(word, "hello"),
+            _ => panic!("Expected DuplicateWord error"),
+        }
     }
 }
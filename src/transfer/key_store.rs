@@ -0,0 +1,266 @@
+//! Where a [`DuplicateHandler`] keeps the keys of the cards it has seen.
+//!
+//! Keys are kept in a `HashMap` by default. Merging millions of cards, a
+//! [`DedupeStore`] keeps them in an SQLite database on disk instead, which
+//! can also outlive the export so a later run skips every card an earlier
+//! one exported.
+//!
+//! [`DuplicateHandler`]: crate::transfer::DuplicateHandler
+
+use crate::error::{DuoloadError, Result};
+use clap::ValueEnum;
+use rusqlite::{Connection, OptionalExtension, params};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+/// Schema of the store; `position` is the index of the card in its output
+/// and `run` the export that added it.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS keys (
+        namespace TEXT NOT NULL,
+        key TEXT NOT NULL,
+        run INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        PRIMARY KEY (namespace, key)
+    ) WITHOUT ROWID;
+";
+
+/// Where the keys of seen cards are kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum KeyStoreKind {
+    /// In memory
+    #[default]
+    Memory,
+    /// In a temporary database on disk
+    Disk,
+}
+
+/// Where a key was seen before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeenKey {
+    /// At this index of the cards of the output
+    At(usize),
+    /// In an earlier export with the same [`DedupeStore`]
+    EarlierRun,
+}
+
+/// Keys of the cards a duplicate handler has seen.
+pub trait KeyStore: Send + Sync {
+    fn get(&self, key: &str) -> Result<Option<SeenKey>>;
+    /// Records that the card with `key` is at `index` of the output.
+    fn insert(&mut self, key: String, index: usize) -> Result<()>;
+    /// Number of keys added in this export.
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl KeyStore for HashMap<String, usize> {
+    fn get(&self, key: &str) -> Result<Option<SeenKey>> {
+        Ok(HashMap::get(self, key).map(|&index| SeenKey::At(index)))
+    }
+
+    fn insert(&mut self, key: String, index: usize) -> Result<()> {
+        HashMap::insert(self, key, index);
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        HashMap::len(self)
+    }
+}
+
+/// An SQLite database of seen keys, shared by the duplicate handlers of an
+/// export.
+///
+/// A temporary store is removed with its last handle. A store opened from a
+/// file keeps the keys of an export once it is [committed], and later
+/// exports with the file skip those cards, whatever the duplicate policy.
+/// Keys of an export that fails before the commit are discarded.
+///
+/// [committed]: DedupeStore::commit
+#[derive(Clone)]
+pub struct DedupeStore {
+    // SQLite connections cannot be shared between threads
+    connection: Arc<Mutex<Connection>>,
+    run: i64,
+    /// Keeps the keys of several outputs apart
+    namespace: String,
+    _dir: Option<Arc<TempDir>>,
+}
+
+impl DedupeStore {
+    /// Creates an empty store in the system's temporary directory.
+    pub fn temporary() -> Result<Self> {
+        let dir = tempfile::tempdir()?;
+        let connection = Connection::open(dir.path().join("keys.db")).map_err(store_error)?;
+        connection
+            .execute_batch("PRAGMA journal_mode = OFF; PRAGMA synchronous = OFF;")
+            .map_err(store_error)?;
+        connection.execute_batch(SCHEMA).map_err(store_error)?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+            run: 1,
+            namespace: String::new(),
+            _dir: Some(Arc::new(dir)),
+        })
+    }
+
+    /// Opens the store at `path`, creating it if it does not exist, with the
+    /// keys committed by earlier exports.
+    pub fn open(path: &Path) -> Result<Self> {
+        let connection = Connection::open(path).map_err(store_error)?;
+        connection.execute_batch(SCHEMA).map_err(store_error)?;
+        let run: i64 = connection
+            .query_row("SELECT COALESCE(MAX(run), 0) + 1 FROM keys", [], |row| {
+                row.get(0)
+            })
+            .map_err(store_error)?;
+        // Keys stay invisible to other exports until committed
+        connection.execute_batch("BEGIN").map_err(store_error)?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+            run,
+            namespace: String::new(),
+            _dir: None,
+        })
+    }
+
+    /// The same store, with keys kept apart from those of other namespaces,
+    /// e.g. for each output of an export to several files.
+    pub fn namespaced(&self, namespace: &str) -> Self {
+        Self {
+            namespace: format!("{}/{}", self.namespace, namespace),
+            ..self.clone()
+        }
+    }
+
+    /// A key store for a duplicate handler, starting without keys of this
+    /// export.
+    pub fn key_store(&self) -> DiskKeyStore {
+        DiskKeyStore {
+            store: self.clone(),
+            len: 0,
+        }
+    }
+
+    /// Keeps the keys added so far for later exports.
+    pub fn commit(&self) -> Result<()> {
+        let connection = self.connection.lock().expect("dedupe store lock poisoned");
+        if connection.is_autocommit() {
+            return Ok(());
+        }
+        connection
+            .execute_batch("COMMIT; BEGIN")
+            .map_err(store_error)
+    }
+}
+
+/// The keys of one duplicate handler in a [`DedupeStore`].
+pub struct DiskKeyStore {
+    store: DedupeStore,
+    len: usize,
+}
+
+impl KeyStore for DiskKeyStore {
+    fn get(&self, key: &str) -> Result<Option<SeenKey>> {
+        let connection = self
+            .store
+            .connection
+            .lock()
+            .expect("dedupe store lock poisoned");
+        let seen: Option<(i64, i64)> = connection
+            .prepare_cached("SELECT run, position FROM keys WHERE namespace = ?1 AND key = ?2")
+            .and_then(|mut statement| {
+                statement
+                    .query_row(params![self.store.namespace, key], |row| {
+                        Ok((row.get(0)?, row.get(1)?))
+                    })
+                    .optional()
+            })
+            .map_err(store_error)?;
+        Ok(seen.map(|(run, position)| {
+            if run == self.store.run {
+                SeenKey::At(position as usize)
+            } else {
+                SeenKey::EarlierRun
+            }
+        }))
+    }
+
+    fn insert(&mut self, key: String, index: usize) -> Result<()> {
+        let connection = self
+            .store
+            .connection
+            .lock()
+            .expect("dedupe store lock poisoned");
+        connection
+            .prepare_cached(
+                "INSERT OR REPLACE INTO keys (namespace, key, run, position) VALUES (?1, ?2, ?3, ?4)",
+            )
+            .and_then(|mut statement| {
+                statement.execute(params![
+                    self.store.namespace,
+                    key,
+                    self.store.run,
+                    index as i64
+                ])
+            })
+            .map_err(store_error)?;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+fn store_error(e: rusqlite::Error) -> DuoloadError {
+    DuoloadError::DedupeStore(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_temporary_store() -> Result<()> {
+        let store = DedupeStore::temporary()?;
+        let mut keys = store.key_store();
+        keys.insert("hello".to_string(), 0)?;
+        keys.insert("world".to_string(), 1)?;
+        assert_eq!(keys.get("world")?, Some(SeenKey::At(1)));
+        assert_eq!(keys.get("other")?, None);
+        assert_eq!(keys.len(), 2);
+        // Other namespaces do not see the keys
+        assert_eq!(store.namespaced("b").key_store().get("hello")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_committed_keys_are_earlier_runs() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("keys.db");
+
+        let store = DedupeStore::open(&path)?;
+        store.key_store().insert("hello".to_string(), 0)?;
+        store.commit()?;
+        drop(store);
+
+        // An export that fails before its commit leaves no keys behind
+        let store = DedupeStore::open(&path)?;
+        store.key_store().insert("lost".to_string(), 1)?;
+        drop(store);
+
+        let store = DedupeStore::open(&path)?;
+        let keys = store.key_store();
+        assert_eq!(keys.get("hello")?, Some(SeenKey::EarlierRun));
+        assert_eq!(keys.get("lost")?, None);
+        Ok(())
+    }
+}
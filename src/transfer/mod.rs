@@ -1,4 +1,12 @@
+pub mod checkpoint;
+pub mod control;
+pub mod cursor;
 pub mod duplicates;
+pub mod key_store;
 pub mod processor;
+pub mod progress;
+pub mod stats;
+pub mod webhook;
 
+pub use cursor::CursorGuard;
 pub use duplicates::DuplicateHandler;
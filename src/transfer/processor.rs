@@ -1,17 +1,106 @@
 use crate::duocards::DuocardsClientTrait;
-use crate::error::Result;
-use crate::output::{OutputBuilder, OutputDestination};
-use crate::transfer::DuplicateHandler;
+use crate::duocards::models::{DeckMetadata, LearningStatus, Provenance, VocabularyCard};
+use crate::error::{DuoloadError, Result};
+use crate::media::MediaFetcher;
+use crate::output::multi::MultiOutputBuilder;
+use crate::output::sort::SortOrder;
+use crate::output::timestamp::UtcDateTime;
+use crate::output::{OutputBuilder, OutputDestination, write_atomically};
+use crate::transfer::CursorGuard;
+use crate::transfer::checkpoint::Checkpoint;
+use crate::transfer::control::TransferControl;
+use crate::transfer::duplicates::{DedupeKey, DuplicatePolicy};
+use crate::transfer::key_store::DedupeStore;
+use crate::transfer::progress::{ConsoleReporter, ProgressEvent, ProgressReporter};
+use crate::transform::TransformPipeline;
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::io;
 use std::path::Path;
 use std::path::PathBuf;
-use std::time::{Duration, Instant};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::mpsc;
 use tokio::time::sleep;
+use tracing::Instrument;
 
-#[derive(Debug, Default, PartialEq)]
+/// Default number of fetched pages that may wait for the output writer.
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 4;
+
+/// Default wait between two page requests, or batches of concurrent requests.
+pub const DEFAULT_PAGE_DELAY: Duration = Duration::from_secs(1);
+
+/// Default wait before the first automatic retry of a failed export.
+pub const DEFAULT_AUTO_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// How often a paused export checks whether it may continue.
+const CONTROL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
 pub struct TransferStats {
     pub total_cards: usize,
     pub duplicates: usize,
+    /// Cards the output rejected, left out with [`TransferProcessorWithBuilder::with_skip_bad_cards`]
+    pub skipped: usize,
+    /// Saved cards by learning status
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub statuses: BTreeMap<LearningStatus, usize>,
+    /// Cards on each page fetched in this run, duplicates included
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub page_cards: Vec<usize>,
+    /// Saved cards by the number of characters of their word
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub word_lengths: BTreeMap<usize, usize>,
+    /// Saved cards by the first letter of their word, uppercased; words
+    /// without letters are counted under `#`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub first_letters: BTreeMap<char, usize>,
+}
+
+impl TransferStats {
+    /// Counts a saved card with `word` and `status`.
+    pub fn count_saved(&mut self, word: &str, status: LearningStatus) {
+        self.total_cards += 1;
+        *self.statuses.entry(status).or_default() += 1;
+        *self.word_lengths.entry(word.chars().count()).or_default() += 1;
+        let letter = word
+            .chars()
+            .find(|c| c.is_alphabetic())
+            .and_then(|c| c.to_uppercase().next())
+            .unwrap_or('#');
+        *self.first_letters.entry(letter).or_default() += 1;
+    }
+}
+
+/// A card left out of the export because the output rejected it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SkippedCard {
+    pub word: String,
+    pub source_id: Option<String>,
+    pub error: String,
+}
+
+/// Cards skipped so far and the report they are written to.
+struct BadCards {
+    path: PathBuf,
+    cards: Vec<SkippedCard>,
+}
+
+/// Pagination state of an export, advanced once a page has reached the builder.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TransferProgress {
+    pub cursor: Option<String>,
+    pub pages: u32,
+    pub processed_cards: usize,
+}
+
+/// A page of converted cards travelling from the fetcher to the writer.
+struct FetchedPage {
+    number: u32,
+    deck: DeckMetadata,
+    cards: Vec<VocabularyCard>,
+    next_cursor: Option<String>,
+    last: bool,
 }
 
 pub struct TransferProcessor<C>
@@ -28,14 +117,37 @@ where
     B: OutputBuilder,
 {
     client: C,
-    builder: B,
-    duplicates: DuplicateHandler,
+    /// Handed to the writer task while pages are transferred
+    builder: Option<B>,
     stats: TransferStats,
     deck_id: String,
     start_time: Instant,
     output_path: PathBuf,
+    channel_capacity: usize,
+    concurrency: usize,
+    page_delay: Duration,
+    progress: TransferProgress,
+    auto_retry: u32,
+    auto_retry_delay: Duration,
+    verify: bool,
+    reporter: Arc<dyn ProgressReporter>,
+    control: TransferControl,
+    checkpoint_path: Option<PathBuf>,
+    checkpoint: Option<Checkpoint>,
+    media: Option<MediaFetcher>,
+    transforms: TransformPipeline,
+    provenance: bool,
+    dedupe_store: Option<DedupeStore>,
+    max_cards: Option<usize>,
+    bad_cards: Option<BadCards>,
+    fallback_output: Option<PathBuf>,
+    fallback_prompt: Option<FallbackPrompt>,
 }
 
+/// Asks for another path to write the output to after it failed with the
+/// given error, or `None` to give up.
+pub type FallbackPrompt = Arc<dyn Fn(&DuoloadError) -> Option<PathBuf> + Send + Sync>;
+
 impl<C> TransferProcessor<C>
 where
     C: DuocardsClientTrait,
@@ -44,6 +156,25 @@ where
         Self { client, deck_id }
     }
 
+    pub fn client(&self) -> &C {
+        &self.client
+    }
+
+    /// Fans every card out to several outputs, each written to its own
+    /// path, so the deck is fetched only once for all of them.
+    pub fn outputs<P: AsRef<Path>>(
+        self,
+        outputs: Vec<(Box<dyn OutputBuilder>, P)>,
+    ) -> TransferProcessorWithBuilder<C, MultiOutputBuilder> {
+        let builder = outputs
+            .into_iter()
+            .fold(MultiOutputBuilder::new(), |multi, (builder, path)| {
+                multi.with_output(builder, path)
+            });
+        // Each output has its own path; the builder ignores this one
+        self.output(builder, "")
+    }
+
     pub fn output<B: OutputBuilder, P: AsRef<Path>>(
         self,
         builder: B,
@@ -51,12 +182,30 @@ where
     ) -> TransferProcessorWithBuilder<C, B> {
         TransferProcessorWithBuilder {
             client: self.client,
-            builder,
-            duplicates: DuplicateHandler::new(),
+            builder: Some(builder),
             stats: TransferStats::default(),
             deck_id: self.deck_id,
             start_time: Instant::now(),
             output_path: path.as_ref().to_path_buf(),
+            channel_capacity: DEFAULT_CHANNEL_CAPACITY,
+            concurrency: 1,
+            page_delay: DEFAULT_PAGE_DELAY,
+            progress: TransferProgress::default(),
+            auto_retry: 0,
+            auto_retry_delay: DEFAULT_AUTO_RETRY_DELAY,
+            verify: false,
+            reporter: Arc::new(ConsoleReporter),
+            control: TransferControl::new(),
+            checkpoint_path: None,
+            checkpoint: None,
+            media: None,
+            transforms: TransformPipeline::default(),
+            provenance: false,
+            dedupe_store: None,
+            max_cards: None,
+            bad_cards: None,
+            fallback_output: None,
+            fallback_prompt: None,
         }
     }
 }
@@ -64,140 +213,760 @@ where
 impl<C, B> TransferProcessorWithBuilder<C, B>
 where
     C: DuocardsClientTrait,
-    B: OutputBuilder,
+    B: OutputBuilder + 'static,
 {
-    pub async fn process(&mut self) -> Result<()> {
-        let mut cursor = None;
-        let mut page_count = 0;
-        let mut total_processed = 0;
+    /// Sets how many fetched pages may be buffered ahead of the output writer.
+    ///
+    /// Fetching pauses once the buffer is full, so a slow output builder
+    /// applies backpressure instead of letting pages pile up in memory.
+    pub fn with_channel_capacity(mut self, capacity: usize) -> Self {
+        self.channel_capacity = capacity.max(1);
+        self
+    }
 
-        // Print initial message with page limit info if set
-        if let Some(limit) = self.client.page_limit() {
-            eprintln!("Starting export (limited to {} pages)...", limit);
-        } else {
-            eprintln!("Starting export...");
+    /// Sets how many pages may be requested at once.
+    ///
+    /// Each page is requested with the cursor of the previous one, so only
+    /// the first page of a batch is certain. The API uses card indexes as
+    /// cursors, which lets the following pages be requested at predicted
+    /// cursors; a page whose prediction turns out wrong is discarded and
+    /// fetched again. Pages always reach the output builder in deck order.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Sets the wait between two page requests, or batches of concurrent requests.
+    pub fn with_page_delay(mut self, delay: Duration) -> Self {
+        self.page_delay = delay;
+        self
+    }
+
+    /// Sets how repeated words are resolved; the policy is applied by the output builder.
+    pub fn with_duplicate_policy(mut self, policy: DuplicatePolicy) -> Self {
+        self.builder_mut().set_duplicate_policy(policy);
+        self
+    }
+
+    /// Sets what makes two cards duplicates, the word by default.
+    pub fn with_dedupe_key(mut self, key: DedupeKey) -> Self {
+        self.builder_mut().set_dedupe_key(key);
+        self
+    }
+
+    /// Keeps the keys of seen cards in `store` instead of in memory, and
+    /// commits them once the output is written, so later exports with the
+    /// same store skip these cards.
+    pub fn with_dedupe_store(mut self, store: DedupeStore) -> Self {
+        self.builder_mut().set_dedupe_store(&store);
+        self.dedupe_store = Some(store);
+        self
+    }
+
+    /// Sets the order of the cards in the written output; applied by the output builder.
+    pub fn with_sort_order(mut self, order: SortOrder) -> Self {
+        self.builder_mut().set_sort_order(order);
+        self
+    }
+
+    /// Stops once `max_cards` cards have been added to the output, in the
+    /// middle of a page if need be. Duplicates do not count.
+    pub fn with_max_cards(mut self, max_cards: usize) -> Self {
+        self.max_cards = Some(max_cards);
+        self
+    }
+
+    /// Enables run-level retries: when fetching fails partway, the export waits
+    /// and resumes from the last page that reached the output builder.
+    ///
+    /// The wait starts at `delay` and doubles after every failed attempt.
+    pub fn with_auto_retry(mut self, max_retries: u32, delay: Duration) -> Self {
+        self.auto_retry = max_retries;
+        self.auto_retry_delay = delay;
+        self
+    }
+
+    /// Re-reads the written output and checks that it holds every saved card.
+    pub fn with_verification(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Replaces the default console output with another progress reporter.
+    pub fn with_progress_reporter(mut self, reporter: Arc<dyn ProgressReporter>) -> Self {
+        self.reporter = reporter;
+        self
+    }
+
+    /// Lets the export be paused, stopped or aborted through `control`.
+    pub fn with_control(mut self, control: TransferControl) -> Self {
+        self.control = control;
+        self
+    }
+
+    /// Saves progress to `path` after every page and resumes from it if it
+    /// already exists. The file is removed once the export has been written.
+    pub fn with_checkpoint<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.checkpoint_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Downloads the media of every card before it is handed to the builder.
+    ///
+    /// A failed download is reported and the card is kept without the file.
+    pub fn with_media_fetcher(mut self, fetcher: MediaFetcher) -> Self {
+        self.media = Some(fetcher);
+        self
+    }
+
+    /// Runs `transforms` on every fetched card, before duplicates are
+    /// detected and before it is saved to a checkpoint. Cards the pipeline
+    /// drops are not exported.
+    pub fn with_transforms(mut self, transforms: TransformPipeline) -> Self {
+        self.transforms = transforms;
+        self
+    }
+
+    /// Records on every fetched card the deck, page and time it was fetched
+    /// from and the version of duoload, before the transforms run.
+    pub fn with_provenance(mut self, provenance: bool) -> Self {
+        self.provenance = provenance;
+        self
+    }
+
+    /// Leaves out cards the output rejects, like cards with characters an
+    /// Anki package cannot hold, instead of failing the export. Skipped cards
+    /// are counted in the stats and listed in a JSON report at `path`, which
+    /// is written even if no card was skipped.
+    pub fn with_skip_bad_cards<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.bad_cards = Some(BadCards {
+            path: path.as_ref().to_path_buf(),
+            cards: Vec::new(),
+        });
+        self
+    }
+
+    fn report(&self, event: ProgressEvent) {
+        self.reporter.report(&event);
+    }
+
+    #[tracing::instrument(name = "export", skip_all, fields(deck_id = %self.deck_id))]
+    pub async fn process(&mut self) -> Result<()> {
+        self.report(ProgressEvent::Started {
+            deck_id: self.deck_id.clone(),
+            page_limit: self.client.page_limit(),
+        });
+
+        if let Some(path) = &self.checkpoint_path {
+            let checkpoint = Checkpoint::load_for(path, &self.deck_id)?;
+            self.restore_checkpoint(&checkpoint)?;
+            self.checkpoint = Some(checkpoint);
         }
 
+        let mut attempt = 0;
         loop {
-            page_count += 1;
-
-            // Check if we should continue based on page limit
-            if !self.client.should_continue(page_count) {
-                eprintln!("Page limit reached ({} pages)", page_count - 1);
-                break;
+            match self.transfer_pages().await {
+                Ok(()) => break,
+                Err(e) if attempt < self.auto_retry && e.is_retryable() => {
+                    attempt += 1;
+                    let delay = self
+                        .auto_retry_delay
+                        .saturating_mul(2u32.saturating_pow(attempt - 1));
+                    self.report(ProgressEvent::Retrying {
+                        pages: self.progress.pages,
+                        error: e.to_string(),
+                        delay,
+                        attempt,
+                        max_retries: self.auto_retry,
+                    });
+                    sleep(delay).await;
+                }
+                Err(e) => return Err(e),
             }
+        }
 
-            eprintln!("Fetching page {}...", page_count);
+        self.report(ProgressEvent::FetchFinished {
+            page_limit: self.client.page_limit(),
+            total_cards: self.stats.total_cards,
+            duplicates: self.stats.duplicates,
+            elapsed: self.start_time.elapsed(),
+        });
 
-            // Add a delay between page fetches (1 second)
-            if page_count > 1 {
-                sleep(Duration::from_secs(1)).await;
-            }
+        if let Some(bad_cards) = &self.bad_cards {
+            write_bad_cards(bad_cards)?;
+        }
 
-            // Fetch a page of cards
-            let response = self.client.fetch_page(&self.deck_id, cursor).await?;
-            let cards = self.client.convert_to_vocabulary_cards(&response);
-            let cards_len = cards.len();
-            eprintln!("Page {} fetched with {} cards", page_count, cards_len);
+        // Write the processed data to output
+        self.write_output_with_fallbacks()?;
 
-            // Process each card
-            for card in cards.into_iter() {
-                if self.duplicates.try_remember(&card.word) {
-                    self.stats.duplicates += 1;
-                    continue;
+        if self.verify {
+            self.verify_output()?;
+        }
+
+        if let Some(store) = &self.dedupe_store {
+            store.commit()?;
+        }
+
+        // A stopped export is incomplete, so its checkpoint is kept for a later run
+        if let Some(path) = &self.checkpoint_path
+            && !self.control.is_stopped()
+        {
+            Checkpoint::remove(path)?;
+        }
+
+        // Report final statistics
+        self.print_stats();
+
+        Ok(())
+    }
+
+    /// Feeds the cards saved in a checkpoint to the builder and continues after its last page.
+    fn restore_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<()> {
+        if checkpoint.pages == 0 {
+            return Ok(());
+        }
+        for card in checkpoint.cards.iter().cloned() {
+            add_card(
+                self.builder
+                    .as_mut()
+                    .expect("the writer hands the builder back"),
+                &mut self.stats,
+                card,
+                self.bad_cards.as_mut(),
+                &*self.reporter,
+            )?;
+        }
+        self.progress = TransferProgress {
+            cursor: checkpoint.cursor.clone(),
+            pages: checkpoint.pages,
+            processed_cards: checkpoint.cards.len(),
+        };
+        self.report(ProgressEvent::CheckpointLoaded {
+            pages: checkpoint.pages,
+            cards: checkpoint.cards.len(),
+        });
+        Ok(())
+    }
+
+    /// Fetches pages starting after the recorded progress and feeds them to the builder.
+    async fn transfer_pages(&mut self) -> Result<()> {
+        // Every page was fetched before the checkpointed run failed
+        if self.checkpoint.as_ref().is_some_and(|c| c.complete) {
+            return Ok(());
+        }
+
+        // Pages flow from the fetcher to the writer through a bounded channel,
+        // so the fetcher blocks once `channel_capacity` pages are waiting.
+        let (tx, rx) = mpsc::channel::<FetchedPage>(self.channel_capacity);
+
+        // The writer runs as a task of its own, so adding cards to the
+        // builder does not hold up fetching, and hands its state back when
+        // it is done
+        let state = PageWriter {
+            builder: self
+                .builder
+                .take()
+                .expect("the writer hands the builder back"),
+            stats: std::mem::take(&mut self.stats),
+            progress: self.progress.clone(),
+            checkpoint: self.checkpoint.take(),
+            media: self.media.take(),
+            bad_cards: self.bad_cards.take(),
+            reporter: self.reporter.clone(),
+            checkpoint_path: self.checkpoint_path.clone(),
+            start_time: self.start_time,
+            max_cards: self.max_cards,
+        };
+        let mut writer = tokio::spawn(state.run(rx));
+
+        let Self {
+            client,
+            deck_id,
+            concurrency,
+            page_delay,
+            progress,
+            reporter,
+            control,
+            transforms,
+            provenance,
+            ..
+        } = self;
+        let provenance = *provenance;
+        let reporter = &**reporter;
+
+        let mut cursor = progress.cursor.clone();
+        let mut page_count = progress.pages;
+        let mut cursor_guard = CursorGuard::new(cursor.as_deref());
+        // Cards on the last page received, used to predict the following cursors
+        let mut page_size = None;
+        // Whether the last wait was lengthened by a rate-limit hint
+        let mut throttled = false;
+
+        let fetcher = async move {
+            'batches: loop {
+                if control.is_paused() {
+                    reporter.report(&ProgressEvent::Paused);
+                    while control.is_paused() && !control.is_stopped() && !control.is_aborted() {
+                        sleep(CONTROL_POLL_INTERVAL).await;
+                    }
+                    reporter.report(&ProgressEvent::Resumed);
+                }
+                if control.is_aborted() {
+                    return Err(DuoloadError::Aborted);
+                }
+                if control.is_stopped() {
+                    reporter.report(&ProgressEvent::Stopped { pages: page_count });
+                    break;
                 }
 
-                if self.builder.add_note(card)? {
-                    self.stats.total_cards += 1;
+                // Check if we should continue based on page limit
+                if !client.should_continue(page_count + 1) {
+                    reporter.report(&ProgressEvent::PageLimitReached { pages: page_count });
+                    break;
                 }
 
-                total_processed += 1;
-                if total_processed % 100 == 0 {
-                    eprintln!(
-                        "Processed {} cards so far ({} added, {} duplicates) at {:?}",
-                        total_processed,
-                        self.stats.total_cards,
-                        self.stats.duplicates,
-                        self.start_time.elapsed()
+                let mut cursors = vec![cursor.clone()];
+                if let Some(size) = page_size {
+                    cursors.extend(
+                        predicted_cursors(cursor.as_deref(), size, *concurrency - 1)
+                            .into_iter()
+                            .zip(page_count + 2..)
+                            .take_while(|(_, page)| client.should_continue(*page))
+                            .map(|(cursor, _)| Some(cursor)),
                     );
                 }
-            }
+                for page in page_count + 1..=page_count + cursors.len() as u32 {
+                    reporter.report(&ProgressEvent::FetchingPage { page });
+                }
+
+                // Add a delay between page fetches, longer if the server
+                // asks to slow down
+                if page_count > 0 {
+                    let hint = client.throttle_delay().filter(|hint| hint > page_delay);
+                    match hint {
+                        Some(hint) if !throttled => {
+                            tracing::warn!(
+                                "The server's rate limit is running low, waiting {:.1}s between pages",
+                                hint.as_secs_f64()
+                            );
+                        }
+                        None if throttled => {
+                            tracing::info!("Rate limit recovered, back to the page delay");
+                        }
+                        _ => {}
+                    }
+                    throttled = hint.is_some();
+                    sleep(hint.unwrap_or(*page_delay)).await;
+                }
+                // The writer has all the cards it needs
+                if tx.is_closed() {
+                    break;
+                }
 
-            // Check if there are more pages
-            if !response.data.node.cards.page_info.has_next_page {
-                eprintln!("No more pages to process");
-                break;
+                // Fetch a batch of pages; failures only count for pages that are used
+                let responses = futures::future::join_all(
+                    cursors.iter().zip(page_count + 1..).map(|(cursor, page)| {
+                        // Retries and failures inside the client are logged within the span
+                        let span =
+                            tracing::info_span!("fetch_page", page, cursor = cursor.as_deref());
+                        client.fetch_page(deck_id, cursor.clone()).instrument(span)
+                    }),
+                )
+                .await;
+                let fetched_at = UtcDateTime::from_system_time(SystemTime::now());
+
+                for (response, requested) in responses.into_iter().zip(cursors) {
+                    // The previous page did not end where predicted; refetch from where it did
+                    if requested != cursor {
+                        continue 'batches;
+                    }
+                    let response = response?;
+
+                    page_count += 1;
+                    let origin =
+                        provenance.then(|| Provenance::new(deck_id, page_count, fetched_at));
+                    let cards: Vec<VocabularyCard> = client
+                        .convert_to_vocabulary_cards(&response)
+                        .into_iter()
+                        .map(|card| VocabularyCard {
+                            provenance: origin.clone().or(card.provenance),
+                            ..card
+                        })
+                        .filter_map(|card| transforms.apply(card))
+                        .collect();
+                    reporter.report(&ProgressEvent::PageFetched {
+                        page: page_count,
+                        cards: cards.len(),
+                    });
+                    if let Some(requests) = client.request_stats() {
+                        reporter.report(&ProgressEvent::Downloaded {
+                            requests: requests.requests(),
+                            bytes: requests.bytes(),
+                        });
+                    }
+                    cursor_guard.check(page_count, &response)?;
+
+                    page_size = Some(response.data.node.cards.edges.len());
+                    let deck = transforms.apply_metadata(response.deck_metadata());
+                    let page_info = response.data.node.cards.page_info;
+                    let page = FetchedPage {
+                        number: page_count,
+                        deck,
+                        cards,
+                        next_cursor: page_info.end_cursor.clone(),
+                        last: !page_info.has_next_page,
+                    };
+
+                    // The writer only hangs up after failing, and its error wins the join
+                    if tx.send(page).await.is_err() {
+                        break 'batches;
+                    }
+
+                    // Check if there are more pages
+                    if !page_info.has_next_page {
+                        reporter.report(&ProgressEvent::LastPageFetched);
+                        break 'batches;
+                    }
+
+                    cursor = page_info.end_cursor;
+                }
             }
 
-            cursor = response.data.node.cards.page_info.end_cursor;
-        }
+            Ok::<_, DuoloadError>(())
+        };
 
-        // Print completion message with appropriate context
-        if let Some(limit) = self.client.page_limit() {
-            eprintln!(
-                "Page limit reached ({} pages). Total cards: {}, Duplicates: {} in {:?}",
-                limit,
-                self.stats.total_cards,
-                self.stats.duplicates,
-                self.start_time.elapsed()
-            );
-        } else {
-            eprintln!(
-                "All pages processed. Total cards: {}, Duplicates: {} in {:?}",
-                self.stats.total_cards,
-                self.stats.duplicates,
-                self.start_time.elapsed()
-            );
-        }
+        // The writer only finishes first after failing or reaching the card
+        // limit, when the rest of the deck is not needed any more
+        let (fetched, joined) = tokio::select! {
+            biased;
+            fetched = fetcher => (fetched, writer.await),
+            joined = &mut writer => (Ok(()), joined),
+        };
+        let (state, written) = match joined {
+            Ok(joined) => joined,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        };
+        self.builder = Some(state.builder);
+        self.stats = state.stats;
+        self.progress = state.progress;
+        self.checkpoint = state.checkpoint;
+        self.media = state.media;
+        self.bad_cards = state.bad_cards;
+        // The fetcher stops once the writer fails, so the writer's error wins
+        written?;
+        fetched
+    }
 
-        // Write the processed data to output
-        self.write_output()?;
+    fn builder(&self) -> &B {
+        self.builder
+            .as_ref()
+            .expect("the writer hands the builder back")
+    }
 
-        // Print final statistics to stderr
-        self.print_stats();
+    fn builder_mut(&mut self) -> &mut B {
+        self.builder
+            .as_mut()
+            .expect("the writer hands the builder back")
+    }
 
-        Ok(())
+    pub fn deck_id(&self) -> &str {
+        &self.deck_id
     }
 
-    #[cfg(test)] // Used in tests
     pub fn stats(&self) -> &TransferStats {
         &self.stats
     }
 
     pub fn print_stats(&self) {
-        eprintln!("Export completed successfully!");
-        eprintln!("Total cards saved: {}", self.stats.total_cards);
-        eprintln!("Duplicates skipped: {}", self.stats.duplicates);
-        eprintln!("Total execution time: {:?}", self.start_time.elapsed());
+        self.report(ProgressEvent::Completed {
+            total_cards: self.stats.total_cards,
+            duplicates: self.stats.duplicates,
+            skipped: self.stats.skipped,
+            elapsed: self.start_time.elapsed(),
+        });
+    }
+
+    /// Checks the written file against the number of cards saved.
+    pub fn verify_output(&self) -> Result<()> {
+        if self.output_path.as_os_str() == "-" {
+            self.report(ProgressEvent::VerificationSkipped {
+                reason: "Skipping verification of stdout output".to_string(),
+            });
+            return Ok(());
+        }
+
+        match self.builder().count_written_notes(&self.output_path)? {
+            Some(found) if found == self.stats.total_cards => {
+                self.report(ProgressEvent::Verified { notes: found });
+                Ok(())
+            }
+            Some(found) => Err(DuoloadError::VerificationFailed {
+                expected: self.stats.total_cards,
+                found,
+            }),
+            None => {
+                self.report(ProgressEvent::VerificationSkipped {
+                    reason: "Output format does not support verification".to_string(),
+                });
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes the output to `path` if writing it to its own path fails, e.g.
+    /// because the disk is full, instead of losing the fetched cards.
+    pub fn with_fallback_output<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.fallback_output = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Asks `prompt` for another path each time writing the output fails,
+    /// after the fallback output if there is one, until a write succeeds or
+    /// the prompt gives up.
+    pub fn with_fallback_prompt(mut self, prompt: FallbackPrompt) -> Self {
+        self.fallback_prompt = Some(prompt);
+        self
+    }
+
+    /// Path the output was written to, which is the fallback output after
+    /// the first write failed.
+    pub fn output_path(&self) -> &Path {
+        &self.output_path
+    }
+
+    /// Files the output was written to, e.g. every chunk of a split output.
+    pub fn written_files(&self) -> Vec<PathBuf> {
+        self.builder().written_files(&self.output_path)
+    }
+
+    /// Writes the output, and after a failure the fallback outputs; the
+    /// error of the last attempt is returned if none succeeds.
+    fn write_output_with_fallbacks(&mut self) -> Result<()> {
+        let mut fallback = self.fallback_output.take();
+        let mut result = self.write_output();
+        // Output rejected by its schema is no better at another path
+        while let Err(e) = &result
+            && !matches!(e, DuoloadError::InvalidOutput(_))
+        {
+            let path = match fallback.take() {
+                Some(path) => path,
+                None => match self.fallback_prompt.as_ref().and_then(|prompt| prompt(e)) {
+                    Some(path) => path,
+                    None => break,
+                },
+            };
+            self.report(ProgressEvent::WritingFallback { path: path.clone() });
+            self.output_path = path;
+            result = self.write_output();
+        }
+        result
     }
 
     pub fn write_output(&self) -> Result<()> {
-        eprintln!("Writing deck to output...");
+        self.report(ProgressEvent::Writing);
 
         let result = if self.output_path.as_os_str() == "-" {
             // Write to stdout, ensure progress messages go to stderr
             let stdout = io::stdout();
             let mut writer = stdout.lock();
-            self.builder.write(OutputDestination::Writer(&mut writer))
-        } else {
-            // Write to file
-            self.builder
+            self.builder().write(OutputDestination::Writer(&mut writer))
+        } else if self.builder().writes_in_place() {
+            self.builder()
                 .write(OutputDestination::File(&self.output_path))
+        } else {
+            write_atomically(self.builder(), &self.output_path)
         };
 
         match result {
             Ok(_) => {
-                eprintln!("Deck written successfully");
+                self.report(ProgressEvent::Written);
                 Ok(())
             }
             Err(e) => {
-                eprintln!("Error writing deck: {}", e);
+                self.report(ProgressEvent::WriteFailed {
+                    error: e.to_string(),
+                });
                 Err(e)
             }
         }
     }
 }
 
+/// The writer task of [`TransferProcessorWithBuilder::process`]: adds the
+/// cards of each fetched page to the builder, which it owns until all pages
+/// are written.
+struct PageWriter<B> {
+    builder: B,
+    stats: TransferStats,
+    progress: TransferProgress,
+    checkpoint: Option<Checkpoint>,
+    media: Option<MediaFetcher>,
+    bad_cards: Option<BadCards>,
+    reporter: Arc<dyn ProgressReporter>,
+    checkpoint_path: Option<PathBuf>,
+    start_time: Instant,
+    max_cards: Option<usize>,
+}
+
+impl<B: OutputBuilder> PageWriter<B> {
+    /// Writes the pages received on `rx`, returning the state for the
+    /// processor with the result.
+    async fn run(mut self, mut rx: mpsc::Receiver<FetchedPage>) -> (Self, Result<()>) {
+        let result = self.write_pages(&mut rx).await;
+        (self, result)
+    }
+
+    async fn write_pages(&mut self, rx: &mut mpsc::Receiver<FetchedPage>) -> Result<()> {
+        let Self {
+            builder,
+            stats,
+            progress,
+            checkpoint,
+            media,
+            bad_cards,
+            reporter,
+            checkpoint_path,
+            start_time,
+            max_cards,
+        } = self;
+        let reporter = &**reporter;
+        let mut deck_known = false;
+        'pages: while let Some(page) = rx.recv().await {
+            if !deck_known {
+                builder.set_deck_metadata(&page.deck);
+                deck_known = true;
+            }
+            let checkpoint_cards = checkpoint.is_some().then(|| page.cards.clone());
+            stats.page_cards.push(page.cards.len());
+            for mut card in page.cards.into_iter() {
+                if let Some(media) = media.as_ref() {
+                    for (url, kind) in media.media_of(&card) {
+                        if let Err(e) = media.fetch(url, kind).await {
+                            reporter.report(&ProgressEvent::MediaFailed {
+                                word: card.word.clone(),
+                                error: e.to_string(),
+                            });
+                        }
+                    }
+                    card = media.link(card);
+                }
+                let word = card.word.clone();
+                let Some(added) = add_card(builder, stats, card, bad_cards.as_mut(), reporter)?
+                else {
+                    continue;
+                };
+
+                progress.processed_cards += 1;
+                reporter.report(&ProgressEvent::CardProcessed {
+                    word,
+                    added,
+                    processed: progress.processed_cards,
+                    total_cards: stats.total_cards,
+                    duplicates: stats.duplicates,
+                    elapsed: start_time.elapsed(),
+                });
+
+                if max_cards.is_some_and(|max| stats.total_cards >= max) {
+                    reporter.report(&ProgressEvent::CardLimitReached {
+                        cards: stats.total_cards,
+                    });
+                    // The fetcher sees the closed channel and stops
+                    rx.close();
+                    break 'pages;
+                }
+            }
+
+            // Only pages that fully reached the builder count as progress
+            progress.pages = page.number;
+            progress.cursor = page.next_cursor;
+
+            if let (Some(path), Some(checkpoint), Some(cards)) = (
+                checkpoint_path.as_deref(),
+                checkpoint.as_mut(),
+                checkpoint_cards,
+            ) {
+                checkpoint.cards.extend(cards);
+                checkpoint.pages = progress.pages;
+                checkpoint.cursor = progress.cursor.clone();
+                checkpoint.complete = page.last;
+                checkpoint.save(path)?;
+            }
+
+            // Let the fetcher make progress between pages
+            tokio::task::yield_now().await;
+        }
+
+        Ok(())
+    }
+}
+
+/// Adds `card` to `builder` and counts it in `stats`, returning whether it
+/// was added. With `bad_cards`, a card the builder rejects as bad is
+/// recorded there and `None` is returned instead of failing the export.
+fn add_card<B: OutputBuilder>(
+    builder: &mut B,
+    stats: &mut TransferStats,
+    card: VocabularyCard,
+    bad_cards: Option<&mut BadCards>,
+    reporter: &dyn ProgressReporter,
+) -> Result<Option<bool>> {
+    let word = card.word.clone();
+    let source_id = card.source_id.clone();
+    let status = card.status.clone();
+    match (builder.add_note(card), bad_cards) {
+        (Ok(true), _) => {
+            stats.count_saved(&word, status);
+            Ok(Some(true))
+        }
+        (Ok(false), _) => {
+            stats.duplicates += 1;
+            Ok(Some(false))
+        }
+        (Err(e @ DuoloadError::BadCard { .. }), Some(bad_cards)) => {
+            stats.skipped += 1;
+            let error = e.to_string();
+            reporter.report(&ProgressEvent::CardSkipped {
+                word: word.clone(),
+                error: error.clone(),
+            });
+            bad_cards.cards.push(SkippedCard {
+                word,
+                source_id,
+                error,
+            });
+            Ok(None)
+        }
+        (Err(e), _) => Err(e),
+    }
+}
+
+/// Writes the cards left out of the export to their JSON report.
+fn write_bad_cards(bad_cards: &BadCards) -> Result<()> {
+    std::fs::write(
+        &bad_cards.path,
+        serde_json::to_vec_pretty(&bad_cards.cards)?,
+    )?;
+    if !bad_cards.cards.is_empty() {
+        tracing::warn!(
+            "Skipped {} bad cards, listed in {:?}",
+            bad_cards.cards.len(),
+            bad_cards.path
+        );
+    }
+    Ok(())
+}
+
+/// Cursors of up to `count` pages following the one that starts after
+/// `cursor`, or none if the cursor is not a card index.
+fn predicted_cursors(cursor: Option<&str>, page_size: usize, count: usize) -> Vec<String> {
+    match cursor.and_then(|cursor| cursor.parse::<usize>().ok()) {
+        Some(index) if page_size > 0 => (1..=count)
+            .map(|page| (index + page * page_size).to_string())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -206,8 +975,9 @@ mod tests {
         PageInfo, ResponseData, VocabularyCard,
     };
     use crate::output::OutputBuilder;
+    use crate::transform::filter::CardFilter;
+    use crate::transform::mapping::FieldMapping;
     use std::io::{Cursor, Write};
-    use std::sync::Arc;
     use std::sync::Mutex;
 
     // Test-specific implementations
@@ -215,6 +985,8 @@ mod tests {
     struct TestDuocardsClient {
         responses: Arc<Mutex<Vec<DuocardsResponse>>>,
         page_limit: Option<u32>,
+        failures: Arc<Mutex<Vec<usize>>>,
+        requested_cursors: Arc<Mutex<Vec<Option<String>>>>,
     }
 
     impl TestDuocardsClient {
@@ -222,9 +994,21 @@ mod tests {
             Self {
                 responses: Arc::new(Mutex::new(responses)),
                 page_limit: None,
+                failures: Arc::new(Mutex::new(Vec::new())),
+                requested_cursors: Arc::new(Mutex::new(Vec::new())),
             }
         }
 
+        /// Makes the given (zero-based) fetch call fail with an API error
+        fn with_failure_at(self, call: usize) -> Self {
+            self.failures.lock().unwrap().push(call);
+            self
+        }
+
+        fn requested_cursors(&self) -> Vec<Option<String>> {
+            self.requested_cursors.lock().unwrap().clone()
+        }
+
         fn with_page_limit(mut self, limit: u32) -> Self {
             self.page_limit = Some(limit);
             self
@@ -236,8 +1020,19 @@ mod tests {
         async fn fetch_page(
             &self,
             _deck_id: &str,
-            _cursor: Option<String>,
+            cursor: Option<String>,
         ) -> Result<DuocardsResponse> {
+            let call = {
+                let mut requested = self.requested_cursors.lock().unwrap();
+                requested.push(cursor);
+                requested.len() - 1
+            };
+            if self.failures.lock().unwrap().contains(&call) {
+                return Err(crate::error::DuoloadError::TransientStatus {
+                    status: reqwest::StatusCode::SERVICE_UNAVAILABLE,
+                    body: "simulated failure".to_string(),
+                });
+            }
             let mut responses = self.responses.lock().unwrap();
             if responses.is_empty() {
                 panic!("No more test responses available");
@@ -256,13 +1051,8 @@ mod tests {
                     word: edge.node.front.clone(),
                     translation: edge.node.back.clone(),
                     example: edge.node.hint.clone(),
-                    status: if edge.node.known_count >= 5 {
-                        LearningStatus::Known
-                    } else if edge.node.known_count > 0 {
-                        LearningStatus::Learning
-                    } else {
-                        LearningStatus::New
-                    },
+                    status: LearningStatus::from_known_count(edge.node.known_count),
+                    ..Default::default()
                 })
                 .collect()
         }
@@ -307,6 +1097,12 @@ mod tests {
             }
         }
 
+        fn set_duplicate_policy(&mut self, _policy: DuplicatePolicy) {}
+
+        fn set_dedupe_key(&mut self, _key: DedupeKey) {}
+
+        fn set_dedupe_store(&mut self, _store: &DedupeStore) {}
+
         fn write(&self, dest: OutputDestination<'_>) -> Result<()> {
             match dest {
                 OutputDestination::Writer(writer) => {
@@ -337,6 +1133,7 @@ mod tests {
                     front: card.word,
                     back: card.translation,
                     hint: card.example,
+                    created_at: None,
                     waiting: None,
                     known_count: match card.status {
                         LearningStatus::Known => 5,
@@ -344,6 +1141,9 @@ mod tests {
                         LearningStatus::New => 0,
                     },
                     svg: None,
+                    audio: None,
+                    source: None,
+                    s_card: None,
                     typename: "Card".to_string(),
                 },
                 cursor: "0".to_string(),
@@ -362,6 +1162,9 @@ mod tests {
                         },
                     },
                     id: "test-deck".to_string(),
+                    name: None,
+                    front: None,
+                    back: None,
                 },
             },
             extensions: Extensions {
@@ -379,12 +1182,13 @@ mod tests {
                 translation: "hola".to_string(),
                 example: Some("Hello, world!".to_string()),
                 status: LearningStatus::New,
+                ..Default::default()
             },
             VocabularyCard {
                 word: "world".to_string(),
                 translation: "mundo".to_string(),
-                example: None,
                 status: LearningStatus::Known,
+                ..Default::default()
             },
         ];
 
@@ -408,7 +1212,7 @@ mod tests {
         assert_eq!(stats.duplicates, 0);
 
         // Verify cards were added
-        let added_cards = processor.builder.get_added_cards();
+        let added_cards = processor.builder().get_added_cards();
         assert_eq!(added_cards.len(), 2);
         assert_eq!(added_cards[0].word, "hello");
         assert_eq!(added_cards[1].word, "world");
@@ -424,13 +1228,14 @@ mod tests {
             translation: "hola".to_string(),
             example: Some("Hello, world!".to_string()),
             status: LearningStatus::New,
+            ..Default::default()
         }];
 
         let page2_cards = vec![VocabularyCard {
             word: "world".to_string(),
             translation: "mundo".to_string(),
-            example: None,
             status: LearningStatus::Known,
+            ..Default::default()
         }];
 
         // Create test responses
@@ -455,7 +1260,7 @@ mod tests {
         assert_eq!(stats.duplicates, 0);
 
         // Verify cards were added in correct order
-        let added_cards = processor.builder.get_added_cards();
+        let added_cards = processor.builder().get_added_cards();
         assert_eq!(added_cards.len(), 2);
         assert_eq!(added_cards[0].word, "hello");
         assert_eq!(added_cards[1].word, "world");
@@ -472,18 +1277,20 @@ mod tests {
                 translation: "hola".to_string(),
                 example: Some("Hello, world!".to_string()),
                 status: LearningStatus::New,
+                ..Default::default()
             },
             VocabularyCard {
                 word: "hello".to_string(), // duplicate
                 translation: "hola".to_string(),
                 example: Some("Hello again!".to_string()),
                 status: LearningStatus::Learning,
+                ..Default::default()
             },
             VocabularyCard {
                 word: "world".to_string(),
                 translation: "mundo".to_string(),
-                example: None,
                 status: LearningStatus::Known,
+                ..Default::default()
             },
         ];
 
@@ -505,9 +1312,16 @@ mod tests {
         let stats = processor.stats();
         assert_eq!(stats.total_cards, 2);
         assert_eq!(stats.duplicates, 1);
+        assert_eq!(
+            stats.statuses,
+            BTreeMap::from([(LearningStatus::New, 1), (LearningStatus::Known, 1)])
+        );
+        assert_eq!(stats.page_cards, vec![3]);
+        assert_eq!(stats.word_lengths, BTreeMap::from([(5, 2)]));
+        assert_eq!(stats.first_letters, BTreeMap::from([('H', 1), ('W', 1)]));
 
         // Verify cards were added correctly
-        let added_cards = processor.builder.get_added_cards();
+        let added_cards = processor.builder().get_added_cards();
         assert_eq!(added_cards.len(), 2);
         assert_eq!(added_cards[0].word, "hello");
         assert_eq!(added_cards[1].word, "world");
@@ -526,7 +1340,7 @@ mod tests {
         {
             let mut writer = Cursor::new(&mut output);
             processor
-                .builder
+                .builder()
                 .write(OutputDestination::Writer(&mut writer))?;
         }
         assert_eq!(output, b"TEST_OUTPUT");
@@ -555,20 +1369,21 @@ mod tests {
             translation: "hola".to_string(),
             example: Some("Hello, world!".to_string()),
             status: LearningStatus::New,
+            ..Default::default()
         }];
 
         let page2_cards = vec![VocabularyCard {
             word: "world".to_string(),
             translation: "mundo".to_string(),
-            example: None,
             status: LearningStatus::Known,
+            ..Default::default()
         }];
 
         let page3_cards = vec![VocabularyCard {
             word: "goodbye".to_string(),
             translation: "adiós".to_string(),
-            example: None,
             status: LearningStatus::New,
+            ..Default::default()
         }];
 
         // Create test responses
@@ -596,11 +1411,621 @@ mod tests {
         assert_eq!(stats.duplicates, 0);
 
         // Verify cards were added in correct order
-        let added_cards = processor.builder.get_added_cards();
+        let added_cards = processor.builder().get_added_cards();
         assert_eq!(added_cards.len(), 2);
         assert_eq!(added_cards[0].word, "hello");
         assert_eq!(added_cards[1].word, "world");
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_process_with_minimal_channel_capacity() -> Result<()> {
+        let page1_cards = vec![VocabularyCard {
+            word: "hello".to_string(),
+            translation: "hola".to_string(),
+            status: LearningStatus::New,
+            ..Default::default()
+        }];
+
+        let page2_cards = vec![VocabularyCard {
+            word: "world".to_string(),
+            translation: "mundo".to_string(),
+            status: LearningStatus::Known,
+            ..Default::default()
+        }];
+
+        let response1 = create_test_response(page1_cards, true, Some("cursor1".to_string()));
+        let response2 = create_test_response(page2_cards, false, None);
+
+        let client = TestDuocardsClient::new(vec![response1, response2]);
+        let builder = TestOutputBuilder::new();
+
+        // A single-page buffer forces the fetcher to wait for the writer
+        let mut processor = TransferProcessor::new(client, "test-deck".to_string())
+            .output(builder, Path::new("test_output.txt"))
+            .with_channel_capacity(1);
+
+        processor.process().await?;
+
+        let stats = processor.stats();
+        assert_eq!(stats.total_cards, 2);
+        assert_eq!(stats.duplicates, 0);
+
+        let added_cards = processor.builder().get_added_cards();
+        assert_eq!(added_cards.len(), 2);
+        assert_eq!(added_cards[0].word, "hello");
+        assert_eq!(added_cards[1].word, "world");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_process_propagates_writer_error() {
+        struct FailingOutputBuilder;
+
+        impl OutputBuilder for FailingOutputBuilder {
+            fn add_note(&mut self, _card: VocabularyCard) -> Result<bool> {
+                Err(crate::error::DuoloadError::Api(
+                    "builder failed".to_string(),
+                ))
+            }
+
+            fn set_duplicate_policy(&mut self, _policy: DuplicatePolicy) {}
+
+            fn set_dedupe_key(&mut self, _key: DedupeKey) {}
+
+            fn set_dedupe_store(&mut self, _store: &DedupeStore) {}
+
+            fn write(&self, _dest: OutputDestination<'_>) -> Result<()> {
+                Ok(())
+            }
+        }
+
+        let cards = vec![VocabularyCard {
+            word: "hello".to_string(),
+            translation: "hola".to_string(),
+            status: LearningStatus::New,
+            ..Default::default()
+        }];
+        let response = create_test_response(cards, false, None);
+
+        let client = TestDuocardsClient::new(vec![response]);
+        let mut processor = TransferProcessor::new(client, "test-deck".to_string())
+            .output(FailingOutputBuilder, Path::new("test_output.txt"));
+
+        let err = processor.process().await.unwrap_err();
+        assert!(err.to_string().contains("builder failed"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_write_keeps_existing_output() {
+        struct TruncatingOutputBuilder;
+
+        impl OutputBuilder for TruncatingOutputBuilder {
+            fn add_note(&mut self, _card: VocabularyCard) -> Result<bool> {
+                Ok(true)
+            }
+
+            fn set_duplicate_policy(&mut self, _policy: DuplicatePolicy) {}
+
+            fn set_dedupe_key(&mut self, _key: DedupeKey) {}
+
+            fn set_dedupe_store(&mut self, _store: &DedupeStore) {}
+
+            fn write(&self, dest: OutputDestination<'_>) -> Result<()> {
+                let OutputDestination::File(path) = dest else {
+                    unreachable!("the processor writes files");
+                };
+                std::fs::write(path, "[{\"word\"")?;
+                Err(crate::error::DuoloadError::Api("disk full".to_string()))
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("deck.json");
+        std::fs::write(&path, "[]").unwrap();
+
+        let response = create_test_response(Vec::new(), false, None);
+        let client = TestDuocardsClient::new(vec![response]);
+        let mut processor = TransferProcessor::new(client, "test-deck".to_string())
+            .output(TruncatingOutputBuilder, &path)
+            .with_page_delay(Duration::ZERO);
+
+        let err = processor.process().await.unwrap_err();
+        assert!(err.to_string().contains("disk full"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[]");
+        // The temporary file is removed
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failed_write_goes_to_fallback_output() {
+        let dir = tempfile::tempdir().unwrap();
+        // The directory of the output does not exist
+        let path = dir.path().join("missing").join("deck.json");
+        let fallback = dir.path().join("fallback.json");
+
+        let response = create_test_response(Vec::new(), false, None);
+        let client = TestDuocardsClient::new(vec![response]);
+        let mut processor = TransferProcessor::new(client, "test-deck".to_string())
+            .output(TestOutputBuilder::new(), &path)
+            .with_page_delay(Duration::ZERO)
+            .with_fallback_output(&fallback);
+
+        processor.process().await.unwrap();
+        assert_eq!(std::fs::read_to_string(&fallback).unwrap(), "TEST_OUTPUT");
+        assert_eq!(processor.output_path(), fallback);
+    }
+
+    #[tokio::test]
+    async fn test_fallback_prompt_is_asked_until_a_write_succeeds() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing");
+        let answers = Mutex::new(vec![
+            dir.path().join("deck.json"),
+            missing.join("other.json"),
+        ]);
+        let prompt: FallbackPrompt = Arc::new(move |_| answers.lock().unwrap().pop());
+
+        let response = create_test_response(Vec::new(), false, None);
+        let client = TestDuocardsClient::new(vec![response]);
+        let mut processor = TransferProcessor::new(client, "test-deck".to_string())
+            .output(TestOutputBuilder::new(), missing.join("deck.json"))
+            .with_page_delay(Duration::ZERO)
+            .with_fallback_prompt(prompt);
+
+        processor.process().await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("deck.json")).unwrap(),
+            "TEST_OUTPUT"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_process_resumes_after_failure() -> Result<()> {
+        let page1_cards = vec![VocabularyCard {
+            word: "hello".to_string(),
+            translation: "hola".to_string(),
+            status: LearningStatus::New,
+            ..Default::default()
+        }];
+
+        let page2_cards = vec![VocabularyCard {
+            word: "world".to_string(),
+            translation: "mundo".to_string(),
+            status: LearningStatus::Known,
+            ..Default::default()
+        }];
+
+        let response1 = create_test_response(page1_cards, true, Some("cursor1".to_string()));
+        let response2 = create_test_response(page2_cards, false, None);
+
+        // The second fetch fails once, then succeeds on retry
+        let client = TestDuocardsClient::new(vec![response1, response2]).with_failure_at(1);
+        let builder = TestOutputBuilder::new();
+
+        let mut processor = TransferProcessor::new(client.clone(), "test-deck".to_string())
+            .output(builder, Path::new("test_output.txt"))
+            .with_auto_retry(1, Duration::ZERO);
+
+        processor.process().await?;
+
+        let stats = processor.stats();
+        assert_eq!(stats.total_cards, 2);
+        assert_eq!(stats.duplicates, 0);
+
+        // The retry resumes from the cursor of the last written page
+        assert_eq!(
+            client.requested_cursors(),
+            vec![
+                None,
+                Some("cursor1".to_string()),
+                Some("cursor1".to_string())
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_process_resumes_from_checkpoint() -> Result<()> {
+        let card = |word: &str| VocabularyCard {
+            word: word.to_string(),
+            translation: "translation".to_string(),
+            status: LearningStatus::New,
+            ..Default::default()
+        };
+        let response1 = create_test_response(
+            vec![card("hello"), card("world")],
+            true,
+            Some("cursor1".to_string()),
+        );
+        let response2 = create_test_response(vec![card("hello"), card("again")], false, None);
+
+        let dir = tempfile::tempdir()?;
+        let state = dir.path().join("state.json");
+
+        // The first run fails on the second page, leaving the first one in the checkpoint
+        let client = TestDuocardsClient::new(vec![response1]).with_failure_at(1);
+        let mut processor = TransferProcessor::new(client, "test-deck".to_string())
+            .output(TestOutputBuilder::new(), dir.path().join("out.txt"))
+            .with_checkpoint(&state);
+        assert!(processor.process().await.is_err());
+        let checkpoint = Checkpoint::load(&state)?.unwrap();
+        assert_eq!(checkpoint.pages, 1);
+        assert_eq!(checkpoint.cursor.as_deref(), Some("cursor1"));
+        assert_eq!(checkpoint.cards.len(), 2);
+
+        // The second run only fetches the missing page
+        let client = TestDuocardsClient::new(vec![response2]);
+        let builder = TestOutputBuilder::new();
+        let mut processor = TransferProcessor::new(client.clone(), "test-deck".to_string())
+            .output(builder.clone(), dir.path().join("out.txt"))
+            .with_checkpoint(&state);
+        processor.process().await?;
+
+        assert_eq!(
+            client.requested_cursors(),
+            vec![Some("cursor1".to_string())]
+        );
+        let stats = processor.stats();
+        assert_eq!(
+            (stats.total_cards, stats.duplicates, stats.skipped),
+            (3, 1, 0)
+        );
+        let words: Vec<String> = builder
+            .get_added_cards()
+            .into_iter()
+            .map(|card| card.word)
+            .collect();
+        assert_eq!(words, vec!["hello", "world", "again"]);
+        assert!(!state.exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_max_cards_stops_mid_page() -> Result<()> {
+        let card = |word: &str| VocabularyCard {
+            word: word.to_string(),
+            translation: "translation".to_string(),
+            status: LearningStatus::New,
+            ..Default::default()
+        };
+        let page1 = vec![card("one"), card("one"), card("two"), card("three")];
+        let page2 = vec![card("four")];
+        let client = TestDuocardsClient::new(vec![
+            create_test_response(page1, true, Some("cursor1".to_string())),
+            create_test_response(page2, false, None),
+        ]);
+
+        let mut processor = TransferProcessor::new(client.clone(), "test-deck".to_string())
+            .output(TestOutputBuilder::new(), Path::new("test_output.txt"))
+            .with_page_delay(Duration::from_millis(50))
+            .with_max_cards(2);
+        processor.process().await?;
+
+        // Duplicates do not count towards the limit
+        assert_eq!(processor.stats().total_cards, 2);
+        assert_eq!(processor.stats().duplicates, 1);
+        let words: Vec<_> = processor
+            .builder()
+            .get_added_cards()
+            .into_iter()
+            .map(|card| card.word)
+            .collect();
+        assert_eq!(words, ["one", "two"]);
+        assert_eq!(client.requested_cursors(), vec![None]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_provenance_records_deck_and_page() -> Result<()> {
+        let card = |word: &str| VocabularyCard {
+            word: word.to_string(),
+            translation: "translation".to_string(),
+            status: LearningStatus::New,
+            ..Default::default()
+        };
+        let client = TestDuocardsClient::new(vec![
+            create_test_response(vec![card("one")], true, Some("cursor1".to_string())),
+            create_test_response(vec![card("two")], false, None),
+        ]);
+
+        let mut processor = TransferProcessor::new(client, "test-deck".to_string())
+            .output(TestOutputBuilder::new(), Path::new("test_output.txt"))
+            .with_page_delay(Duration::from_millis(10))
+            .with_provenance(true);
+        processor.process().await?;
+
+        let cards = processor.builder().get_added_cards();
+        let provenance: Vec<_> = cards
+            .iter()
+            .map(|card| card.provenance.clone().expect("provenance is recorded"))
+            .collect();
+        assert_eq!(provenance[0].deck_id, "test-deck");
+        assert_eq!((provenance[0].page, provenance[1].page), (1, 2));
+        assert_eq!(provenance[0].duoload_version, env!("CARGO_PKG_VERSION"));
+        assert!(provenance[0].fetched_at.unix_secs() <= provenance[1].fetched_at.unix_secs());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_filter_then_map_fields() -> Result<()> {
+        let card = |word: &str, translation: &str| VocabularyCard {
+            word: word.to_string(),
+            translation: translation.to_string(),
+            status: LearningStatus::New,
+            ..Default::default()
+        };
+        let page = vec![
+            card("Haus", "house"),
+            card("guten Tag", "hello"),
+            card("zwei", "2"),
+        ];
+        let client = TestDuocardsClient::new(vec![create_test_response(page, false, None)]);
+
+        let filter = CardFilter::new()
+            .with_exclude(regex::Regex::new(r"\d").unwrap())
+            .with_include(regex::Regex::new("^[a-z]").unwrap());
+        let mut processor = TransferProcessor::new(client, "test-deck".to_string())
+            .output(TestOutputBuilder::new(), Path::new("test_output.txt"))
+            .with_transforms(
+                TransformPipeline::new().then(filter).then(
+                    "front=translation,back=word"
+                        .parse::<FieldMapping>()
+                        .unwrap(),
+                ),
+            );
+        processor.process().await?;
+
+        // The filter sees the fields before they are mapped
+        let cards: Vec<_> = processor
+            .builder()
+            .get_added_cards()
+            .into_iter()
+            .map(|card| (card.word, card.translation))
+            .collect();
+        assert_eq!(
+            cards,
+            [
+                ("house".to_string(), "Haus".to_string()),
+                ("hello".to_string(), "guten Tag".to_string()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_process_gives_up_after_retries() {
+        let cards = vec![VocabularyCard {
+            word: "hello".to_string(),
+            translation: "hola".to_string(),
+            status: LearningStatus::New,
+            ..Default::default()
+        }];
+        let response = create_test_response(cards, false, None);
+
+        let client = TestDuocardsClient::new(vec![response])
+            .with_failure_at(0)
+            .with_failure_at(1);
+        let mut processor = TransferProcessor::new(client.clone(), "test-deck".to_string())
+            .output(TestOutputBuilder::new(), Path::new("test_output.txt"))
+            .with_auto_retry(1, Duration::ZERO);
+
+        let err = processor.process().await.unwrap_err();
+        assert!(err.to_string().contains("simulated failure"));
+        assert_eq!(client.requested_cursors().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_process_aborts_on_repeated_cursor() {
+        let cards = vec![VocabularyCard {
+            word: "hello".to_string(),
+            translation: "hola".to_string(),
+            status: LearningStatus::New,
+            ..Default::default()
+        }];
+
+        // Both pages claim a successor behind the same cursor
+        let response1 = create_test_response(cards.clone(), true, Some("cursor1".to_string()));
+        let response2 = create_test_response(cards, true, Some("cursor1".to_string()));
+
+        let client = TestDuocardsClient::new(vec![response1, response2]);
+        let mut processor = TransferProcessor::new(client, "test-deck".to_string())
+            .output(TestOutputBuilder::new(), Path::new("test_output.txt"));
+
+        match processor.process().await {
+            Err(crate::error::DuoloadError::PaginationStalled { page, cursor, .. }) => {
+                assert_eq!(page, 2);
+                assert_eq!(cursor, Some("cursor1".to_string()));
+            }
+            _ => panic!("Expected PaginationStalled error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_process_verifies_written_output() -> Result<()> {
+        let cards = vec![
+            VocabularyCard {
+                word: "hello".to_string(),
+                translation: "hola".to_string(),
+                status: LearningStatus::New,
+                ..Default::default()
+            },
+            VocabularyCard {
+                word: "hello".to_string(),
+                translation: "hola".to_string(),
+                status: LearningStatus::New,
+                ..Default::default()
+            },
+        ];
+        let response = create_test_response(cards, false, None);
+        let temp_file = tempfile::NamedTempFile::new()?;
+
+        let mut processor = TransferProcessor::new(
+            TestDuocardsClient::new(vec![response]),
+            "test-deck".to_string(),
+        )
+        .output(
+            crate::output::json::JsonOutputBuilder::new(),
+            temp_file.path(),
+        )
+        .with_verification(true);
+
+        processor.process().await?;
+        assert_eq!(processor.stats().total_cards, 1);
+
+        // Corrupt the file behind the processor's back
+        std::fs::write(temp_file.path(), "[]")?;
+        match processor.verify_output() {
+            Err(crate::error::DuoloadError::VerificationFailed { expected, found }) => {
+                assert_eq!(expected, 1);
+                assert_eq!(found, 0);
+            }
+            _ => panic!("Expected VerificationFailed error"),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_process_honors_control() -> Result<()> {
+        let cards = vec![VocabularyCard {
+            word: "hello".to_string(),
+            translation: "hola".to_string(),
+            status: LearningStatus::New,
+            ..Default::default()
+        }];
+
+        // A stopped export writes what it has, which is nothing yet
+        let control = TransferControl::new();
+        control.stop();
+        let client =
+            TestDuocardsClient::new(vec![create_test_response(cards.clone(), false, None)]);
+        let mut processor = TransferProcessor::new(client.clone(), "test-deck".to_string())
+            .output(TestOutputBuilder::new(), Path::new("test_output.txt"))
+            .with_control(control);
+        processor.process().await?;
+        assert_eq!(processor.stats().total_cards, 0);
+        assert!(client.requested_cursors().is_empty());
+
+        // An aborted export fails without fetching
+        let control = TransferControl::new();
+        control.abort();
+        let mut processor = TransferProcessor::new(client.clone(), "test-deck".to_string())
+            .output(TestOutputBuilder::new(), Path::new("test_output.txt"))
+            .with_control(control);
+        assert!(matches!(
+            processor.process().await,
+            Err(crate::error::DuoloadError::Aborted)
+        ));
+        assert!(client.requested_cursors().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_predicted_cursors() {
+        assert_eq!(predicted_cursors(Some("99"), 100, 2), vec!["199", "299"]);
+        assert!(predicted_cursors(None, 100, 2).is_empty());
+        assert!(predicted_cursors(Some("opaque"), 100, 2).is_empty());
+        assert!(predicted_cursors(Some("99"), 0, 2).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_process_skips_bad_cards() -> Result<()> {
+        let card = |word: &str| VocabularyCard {
+            word: word.to_string(),
+            translation: "translation".to_string(),
+            status: LearningStatus::New,
+            ..Default::default()
+        };
+        let dir = tempfile::tempdir()?;
+        let report = dir.path().join("errors.json");
+        let client = TestDuocardsClient::new(vec![create_test_response(
+            vec![card("hello"), card("bad\u{0}word"), card("world")],
+            false,
+            None,
+        )]);
+        let builder = crate::output::anki::AnkiPackageBuilder::new("Test Deck");
+        let mut processor = TransferProcessor::new(client, "test-deck".to_string())
+            .output(builder, dir.path().join("deck.apkg"))
+            .with_verification(true)
+            .with_skip_bad_cards(&report);
+
+        processor.process().await?;
+
+        let stats = processor.stats();
+        assert_eq!(
+            (stats.total_cards, stats.duplicates, stats.skipped),
+            (2, 0, 1)
+        );
+        let skipped: Vec<serde_json::Value> = serde_json::from_slice(&std::fs::read(&report)?)?;
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0]["word"], "bad\u{0}word");
+        assert!(skipped[0]["error"].as_str().unwrap().contains("U+0000"));
+
+        // Without skipping, the bad card fails the export
+        let client = TestDuocardsClient::new(vec![create_test_response(
+            vec![card("bad\u{0}word")],
+            false,
+            None,
+        )]);
+        let builder = crate::output::anki::AnkiPackageBuilder::new("Test Deck");
+        let mut processor = TransferProcessor::new(client, "test-deck".to_string())
+            .output(builder, dir.path().join("other.apkg"));
+        assert!(matches!(
+            processor.process().await,
+            Err(DuoloadError::BadCard { .. })
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_process_refetches_mispredicted_pages() -> Result<()> {
+        let card = |word: &str| VocabularyCard {
+            word: word.to_string(),
+            translation: "translation".to_string(),
+            status: LearningStatus::New,
+            ..Default::default()
+        };
+        // The second page ends earlier than its size predicts, so the third
+        // page fetched alongside it starts at the wrong card. The page
+        // predicted after the refetched one is never used.
+        let client = TestDuocardsClient::new(vec![
+            create_test_response(vec![card("a"), card("b")], true, Some("1".to_string())),
+            create_test_response(vec![card("c"), card("d")], true, Some("2".to_string())),
+            create_test_response(vec![card("wrong")], false, Some("5".to_string())),
+            create_test_response(vec![card("e")], false, Some("3".to_string())),
+            create_test_response(vec![card("unused")], false, Some("5".to_string())),
+        ]);
+        let builder = TestOutputBuilder::new();
+        let mut processor = TransferProcessor::new(client.clone(), "test-deck".to_string())
+            .output(builder, Path::new("test_output.txt"))
+            .with_concurrency(2)
+            .with_page_delay(Duration::ZERO);
+
+        processor.process().await?;
+
+        let words: Vec<String> = processor
+            .builder()
+            .get_added_cards()
+            .into_iter()
+            .map(|card| card.word)
+            .collect();
+        assert_eq!(words, vec!["a", "b", "c", "d", "e"]);
+        assert_eq!(
+            client.requested_cursors(),
+            vec![
+                None,
+                Some("1".to_string()),
+                Some("3".to_string()),
+                Some("2".to_string()),
+                Some("4".to_string()),
+            ]
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,297 @@
+//! Progress reporting for running exports.
+//!
+//! The transfer processor describes what it is doing as [`ProgressEvent`]s
+//! and hands them to a [`ProgressReporter`]. The default [`ConsoleReporter`]
+//! prints them to stderr; other reporters can render a dashboard or forward
+//! the events elsewhere.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Something that happened while exporting a deck.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgressEvent {
+    Started {
+        deck_id: String,
+        page_limit: Option<u32>,
+    },
+    CheckpointLoaded {
+        pages: u32,
+        cards: usize,
+    },
+    FetchingPage {
+        page: u32,
+    },
+    PageFetched {
+        page: u32,
+        cards: usize,
+    },
+    /// Totals of the requests sent so far, after each page of sources that
+    /// talk to the API.
+    Downloaded {
+        requests: u64,
+        bytes: u64,
+    },
+    CardProcessed {
+        word: String,
+        added: bool,
+        processed: usize,
+        total_cards: usize,
+        duplicates: usize,
+        elapsed: Duration,
+    },
+    MediaFailed {
+        word: String,
+        error: String,
+    },
+    /// A card the output rejected was left out of the export.
+    CardSkipped {
+        word: String,
+        error: String,
+    },
+    PageLimitReached {
+        pages: u32,
+    },
+    CardLimitReached {
+        cards: usize,
+    },
+    LastPageFetched,
+    Paused,
+    Resumed,
+    Stopped {
+        pages: u32,
+    },
+    Retrying {
+        pages: u32,
+        error: String,
+        delay: Duration,
+        attempt: u32,
+        max_retries: u32,
+    },
+    FetchFinished {
+        page_limit: Option<u32>,
+        total_cards: usize,
+        duplicates: usize,
+        elapsed: Duration,
+    },
+    Writing,
+    Written,
+    WriteFailed {
+        error: String,
+    },
+    /// Writing failed; the cards are written to another path instead.
+    WritingFallback {
+        path: PathBuf,
+    },
+    Verified {
+        notes: usize,
+    },
+    VerificationSkipped {
+        reason: String,
+    },
+    Completed {
+        total_cards: usize,
+        duplicates: usize,
+        skipped: usize,
+        elapsed: Duration,
+    },
+}
+
+impl ProgressEvent {
+    /// Human-readable log line for the event, if it deserves one.
+    ///
+    /// Processed cards are only summarized every 100 cards.
+    pub fn message(&self) -> Option<String> {
+        let message = match self {
+            ProgressEvent::Started {
+                page_limit: Some(limit),
+                ..
+            } => format!("Starting export (limited to {} pages)...", limit),
+            ProgressEvent::Started { .. } => "Starting export...".to_string(),
+            ProgressEvent::CheckpointLoaded { pages, cards } => format!(
+                "Resuming from checkpoint after {} pages ({} cards)",
+                pages, cards
+            ),
+            ProgressEvent::FetchingPage { page } => format!("Fetching page {}...", page),
+            ProgressEvent::PageFetched { page, cards } => {
+                format!("Page {} fetched with {} cards", page, cards)
+            }
+            ProgressEvent::CardProcessed {
+                processed,
+                total_cards,
+                duplicates,
+                elapsed,
+                ..
+            } if processed % 100 == 0 => format!(
+                "Processed {} cards so far ({} added, {} duplicates) at {:?}",
+                processed, total_cards, duplicates, elapsed
+            ),
+            ProgressEvent::CardProcessed { .. } => return None,
+            ProgressEvent::Downloaded { .. } => return None,
+            ProgressEvent::MediaFailed { word, error } => {
+                format!("Skipping media of '{}': {}", word, error)
+            }
+            ProgressEvent::CardSkipped { error, .. } => format!("Skipping card: {}", error),
+            ProgressEvent::PageLimitReached { pages } => {
+                format!("Page limit reached ({} pages)", pages)
+            }
+            ProgressEvent::CardLimitReached { cards } => {
+                format!("Card limit reached ({} cards)", cards)
+            }
+            ProgressEvent::LastPageFetched => "No more pages to process".to_string(),
+            ProgressEvent::Paused => "Export paused".to_string(),
+            ProgressEvent::Resumed => "Export resumed".to_string(),
+            ProgressEvent::Stopped { pages } => {
+                format!("Fetching stopped after {} pages", pages)
+            }
+            ProgressEvent::Retrying {
+                pages,
+                error,
+                delay,
+                attempt,
+                max_retries,
+            } => format!(
+                "Export failed after {} pages: {}. Resuming in {:?} (retry {}/{})...",
+                pages, error, delay, attempt, max_retries
+            ),
+            ProgressEvent::FetchFinished {
+                page_limit: Some(limit),
+                total_cards,
+                duplicates,
+                elapsed,
+            } => format!(
+                "Page limit reached ({} pages). Total cards: {}, Duplicates: {} in {:?}",
+                limit, total_cards, duplicates, elapsed
+            ),
+            ProgressEvent::FetchFinished {
+                total_cards,
+                duplicates,
+                elapsed,
+                ..
+            } => format!(
+                "All pages processed. Total cards: {}, Duplicates: {} in {:?}",
+                total_cards, duplicates, elapsed
+            ),
+            ProgressEvent::Writing => "Writing deck to output...".to_string(),
+            ProgressEvent::Written => "Deck written successfully".to_string(),
+            ProgressEvent::WriteFailed { error } => format!("Error writing deck: {}", error),
+            ProgressEvent::WritingFallback { path } => {
+                format!("Writing deck to fallback output {:?}...", path)
+            }
+            ProgressEvent::Verified { notes } => format!("Verified {} notes in output", notes),
+            ProgressEvent::VerificationSkipped { reason } => reason.clone(),
+            ProgressEvent::Completed {
+                total_cards,
+                duplicates,
+                skipped: 0,
+                elapsed,
+            } => format!(
+                "Export completed successfully!\n\
+                 Total cards saved: {}\n\
+                 Duplicates skipped: {}\n\
+                 Total execution time: {:?}",
+                total_cards, duplicates, elapsed
+            ),
+            ProgressEvent::Completed {
+                total_cards,
+                duplicates,
+                skipped,
+                elapsed,
+            } => format!(
+                "Export completed successfully!\n\
+                 Total cards saved: {}\n\
+                 Duplicates skipped: {}\n\
+                 Bad cards skipped: {}\n\
+                 Total execution time: {:?}",
+                total_cards, duplicates, skipped, elapsed
+            ),
+        };
+        Some(message)
+    }
+}
+
+/// Receives progress events from the transfer processor.
+pub trait ProgressReporter: Send + Sync {
+    fn report(&self, event: &ProgressEvent);
+}
+
+/// Logs progress messages through `tracing`; the binary writes them to
+/// stderr, keeping stdout free for piped output.
+#[derive(Debug, Default)]
+pub struct ConsoleReporter;
+
+impl ProgressReporter for ConsoleReporter {
+    fn report(&self, event: &ProgressEvent) {
+        let Some(message) = event.message() else {
+            return;
+        };
+        match event {
+            ProgressEvent::WriteFailed { .. } => tracing::error!("{}", message),
+            ProgressEvent::MediaFailed { .. }
+            | ProgressEvent::CardSkipped { .. }
+            | ProgressEvent::Retrying { .. }
+            | ProgressEvent::WritingFallback { .. }
+            | ProgressEvent::Stopped { .. } => tracing::warn!("{}", message),
+            _ => tracing::info!("{}", message),
+        }
+    }
+}
+
+/// Forwards every event to several reporters.
+#[derive(Default)]
+pub struct ProgressReporters(pub Vec<Arc<dyn ProgressReporter>>);
+
+impl ProgressReporter for ProgressReporters {
+    fn report(&self, event: &ProgressEvent) {
+        for reporter in &self.0 {
+            reporter.report(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    struct RecordingReporter(Mutex<Vec<ProgressEvent>>);
+
+    impl ProgressReporter for RecordingReporter {
+        fn report(&self, event: &ProgressEvent) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    fn card_processed(processed: usize) -> ProgressEvent {
+        ProgressEvent::CardProcessed {
+            word: "hello".to_string(),
+            added: true,
+            processed,
+            total_cards: processed,
+            duplicates: 0,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_card_messages_are_sampled() {
+        assert_eq!(card_processed(99).message(), None);
+        assert_eq!(
+            card_processed(100).message(),
+            Some("Processed 100 cards so far (100 added, 0 duplicates) at 0ns".to_string())
+        );
+    }
+
+    #[test]
+    fn test_reporters_fan_out() {
+        let first = Arc::new(RecordingReporter(Mutex::new(Vec::new())));
+        let second = Arc::new(RecordingReporter(Mutex::new(Vec::new())));
+        let reporters = ProgressReporters(vec![first.clone(), second.clone()]);
+
+        reporters.report(&ProgressEvent::Writing);
+
+        assert_eq!(*first.0.lock().unwrap(), vec![ProgressEvent::Writing]);
+        assert_eq!(*second.0.lock().unwrap(), vec![ProgressEvent::Writing]);
+    }
+}
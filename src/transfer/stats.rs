@@ -0,0 +1,277 @@
+//! Statistics of an export run.
+//!
+//! [`StatsRecorder`] is a progress reporter that collects page timings,
+//! repeated words and retries from the events of a run, so scripts can check
+//! the health of an export from a JSON file instead of parsing log lines.
+//! [`summary_table`] shows the cards of an export for people.
+
+use crate::duocards::client::RequestStats;
+use crate::transfer::processor::TransferStats;
+use crate::transfer::progress::{ProgressEvent, ProgressReporter};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Time taken to fetch one page.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PageTiming {
+    pub page: u32,
+    pub cards: usize,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RunStats {
+    pub deck_id: String,
+    pub success: bool,
+    pub error: Option<String>,
+    #[serde(flatten)]
+    pub transfer: TransferStats,
+    /// Words skipped or folded into an earlier card, in the order they were met
+    pub duplicate_words: Vec<String>,
+    /// Pages fetched in this run, not counting those restored from a checkpoint
+    pub pages: Vec<PageTiming>,
+    /// HTTP requests sent, retries included
+    pub requests: u64,
+    /// HTTP requests that repeated a failed one
+    pub request_retries: u64,
+    /// Bytes of the responses received, after decompression
+    pub bytes_downloaded: u64,
+    /// Times the export resumed after failing
+    pub export_retries: u32,
+    pub media_failures: usize,
+    /// Notes found in the written output, if it was verified
+    pub verified_notes: Option<usize>,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Default)]
+struct State {
+    stats: RunStats,
+    fetching: HashMap<u32, Instant>,
+}
+
+/// Collects [`RunStats`] from the progress events of an export.
+pub struct StatsRecorder {
+    state: Mutex<State>,
+    requests: Option<Arc<RequestStats>>,
+    start_time: Instant,
+}
+
+impl Default for StatsRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StatsRecorder {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::default(),
+            requests: None,
+            start_time: Instant::now(),
+        }
+    }
+
+    /// Includes the request counts of a client in the statistics.
+    pub fn with_request_stats(mut self, requests: Arc<RequestStats>) -> Self {
+        self.requests = Some(requests);
+        self
+    }
+
+    /// The statistics so far, with the outcome of the run.
+    pub fn finish(&self, error: Option<String>) -> RunStats {
+        let mut stats = self.state.lock().unwrap().stats.clone();
+        stats.success = error.is_none();
+        stats.error = error;
+        if let Some(requests) = &self.requests {
+            stats.requests = requests.requests();
+            stats.request_retries = requests.retries();
+            stats.bytes_downloaded = requests.bytes();
+        }
+        stats.elapsed_ms = self.start_time.elapsed().as_millis() as u64;
+        stats
+    }
+}
+
+impl ProgressReporter for StatsRecorder {
+    fn report(&self, event: &ProgressEvent) {
+        let mut state = self.state.lock().unwrap();
+        match event {
+            ProgressEvent::Started { deck_id, .. } => state.stats.deck_id = deck_id.clone(),
+            ProgressEvent::FetchingPage { page } => {
+                state.fetching.insert(*page, Instant::now());
+            }
+            ProgressEvent::PageFetched { page, cards } => {
+                let duration = state
+                    .fetching
+                    .remove(page)
+                    .map_or(0, |start| start.elapsed().as_millis() as u64);
+                state.stats.pages.push(PageTiming {
+                    page: *page,
+                    cards: *cards,
+                    duration_ms: duration,
+                });
+            }
+            ProgressEvent::CardProcessed {
+                word, added: false, ..
+            } => state.stats.duplicate_words.push(word.clone()),
+            ProgressEvent::MediaFailed { .. } => state.stats.media_failures += 1,
+            ProgressEvent::CardSkipped { .. } => state.stats.transfer.skipped += 1,
+            ProgressEvent::Retrying { .. } => state.stats.export_retries += 1,
+            ProgressEvent::FetchFinished {
+                total_cards,
+                duplicates,
+                ..
+            }
+            | ProgressEvent::Completed {
+                total_cards,
+                duplicates,
+                ..
+            } => {
+                state.stats.transfer.total_cards = *total_cards;
+                state.stats.transfer.duplicates = *duplicates;
+            }
+            ProgressEvent::Verified { notes } => state.stats.verified_notes = Some(*notes),
+            _ => {}
+        }
+    }
+}
+
+/// Width of the longest bar of a histogram in [`summary_table`].
+const BAR_WIDTH: usize = 40;
+
+/// A summary of `stats` for the terminal, as printed by `--stats`: saved
+/// cards by status, cards per page, and histograms of word lengths and first
+/// letters.
+pub fn summary_table(stats: &TransferStats) -> String {
+    let mut table = String::new();
+    let statuses = stats
+        .statuses
+        .iter()
+        .map(|(status, count)| (status.as_str(), *count));
+    let totals = [
+        ("total", stats.total_cards),
+        ("duplicates", stats.duplicates),
+        ("skipped", stats.skipped),
+    ];
+    table.push_str("Cards\n");
+    for (name, count) in statuses.chain(totals) {
+        let _ = writeln!(table, "  {:<12}{:>8}", name, count);
+    }
+
+    if let (Some(min), Some(max)) = (stats.page_cards.iter().min(), stats.page_cards.iter().max()) {
+        let sum: usize = stats.page_cards.iter().sum();
+        let _ = writeln!(
+            table,
+            "\nPages\n  {:<12}{:>8}\n  {:<12}{:>8}\n  {:<12}{:>8}\n  {:<12}{:>8.1}",
+            "fetched",
+            stats.page_cards.len(),
+            "min cards",
+            min,
+            "max cards",
+            max,
+            "avg cards",
+            sum as f64 / stats.page_cards.len() as f64
+        );
+    }
+
+    write_histogram(&mut table, "Word length", &stats.word_lengths);
+    write_histogram(&mut table, "First letter", &stats.first_letters);
+    table
+}
+
+fn write_histogram<K: Display>(table: &mut String, title: &str, counts: &BTreeMap<K, usize>) {
+    let Some(&most) = counts.values().max() else {
+        return;
+    };
+    let _ = writeln!(table, "\n{}", title);
+    for (key, &count) in counts {
+        let bar = "#".repeat((count * BAR_WIDTH).div_ceil(most));
+        let _ = writeln!(table, "  {:<12}{:>8} {}", key, count, bar);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duocards::models::LearningStatus;
+    use std::time::Duration;
+
+    #[test]
+    fn test_records_events() {
+        let recorder = StatsRecorder::new();
+        let events = [
+            ProgressEvent::Started {
+                deck_id: "deck".to_string(),
+                page_limit: None,
+            },
+            ProgressEvent::FetchingPage { page: 1 },
+            ProgressEvent::PageFetched { page: 1, cards: 2 },
+            ProgressEvent::CardProcessed {
+                word: "hello".to_string(),
+                added: true,
+                processed: 1,
+                total_cards: 1,
+                duplicates: 0,
+                elapsed: Duration::ZERO,
+            },
+            ProgressEvent::CardProcessed {
+                word: "hello".to_string(),
+                added: false,
+                processed: 2,
+                total_cards: 1,
+                duplicates: 1,
+                elapsed: Duration::ZERO,
+            },
+            ProgressEvent::FetchFinished {
+                page_limit: None,
+                total_cards: 1,
+                duplicates: 1,
+                elapsed: Duration::ZERO,
+            },
+            ProgressEvent::Verified { notes: 1 },
+        ];
+        for event in &events {
+            recorder.report(event);
+        }
+
+        let stats = recorder.finish(None);
+        assert!(stats.success);
+        assert_eq!(stats.deck_id, "deck");
+        assert_eq!(stats.transfer.total_cards, 1);
+        assert_eq!(stats.duplicate_words, vec!["hello"]);
+        assert_eq!(stats.pages.len(), 1);
+        assert_eq!(stats.pages[0].cards, 2);
+        assert_eq!(stats.verified_notes, Some(1));
+
+        let failed = recorder.finish(Some("boom".to_string()));
+        assert!(!failed.success);
+        let json = serde_json::to_value(&failed).unwrap();
+        assert_eq!(json["total_cards"], 1);
+        assert_eq!(json["error"], "boom");
+    }
+
+    #[test]
+    fn test_summary_table() {
+        let mut stats = TransferStats {
+            duplicates: 1,
+            page_cards: vec![2, 1],
+            ..TransferStats::default()
+        };
+        stats.count_saved("Haus", LearningStatus::Known);
+        stats.count_saved("hand", LearningStatus::New);
+
+        let table = summary_table(&stats);
+        assert!(table.contains("  new                1\n"));
+        assert!(table.contains("  known              1\n"));
+        assert!(table.contains("  total              2\n"));
+        assert!(table.contains("  avg cards        1.5\n"));
+        assert!(
+            table.contains("  4                  2 ########################################\n")
+        );
+        assert!(table.contains("  H                  2 "));
+    }
+}
@@ -0,0 +1,228 @@
+//! Progress events posted to a webhook.
+//!
+//! [`WebhookReporter`] sends every logged progress event as a JSON object to
+//! a URL, e.g. to follow a long export from CI or a phone. Requests are sent
+//! in order from a background task, so a slow endpoint never holds up the
+//! export; failed requests are logged and dropped.
+
+use crate::duocards::network::NetworkOptions;
+use crate::error::Result;
+use crate::transfer::progress::{ProgressEvent, ProgressReporter};
+use reqwest::{Client, Url};
+use serde_json::{Value, json};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Longest wait for the webhook to answer one event.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Posts progress events to a URL.
+pub struct WebhookReporter {
+    sender: Mutex<Option<mpsc::UnboundedSender<Value>>>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl WebhookReporter {
+    /// Starts posting to `url`. Must be called within a Tokio runtime.
+    pub fn new(url: Url, network: &NetworkOptions) -> Result<Self> {
+        let builder = Client::builder()
+            .user_agent(concat!("duoload/", env!("CARGO_PKG_VERSION")))
+            .timeout(WEBHOOK_TIMEOUT);
+        let client = network.apply(builder)?.build()?;
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Value>();
+        let task = tokio::spawn(async move {
+            let mut failed = false;
+            while let Some(payload) = receiver.recv().await {
+                let result = client
+                    .post(url.clone())
+                    .json(&payload)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status());
+                match result {
+                    // A webhook that is down would fail for every event
+                    Err(e) if !failed => {
+                        tracing::warn!("Cannot post progress to the webhook: {}", e);
+                        failed = true;
+                    }
+                    Err(e) => tracing::debug!("Cannot post progress to the webhook: {}", e),
+                    Ok(_) => {}
+                }
+            }
+        });
+        Ok(Self {
+            sender: Mutex::new(Some(sender)),
+            task: Mutex::new(Some(task)),
+        })
+    }
+
+    /// Posts a `failed` event with `error`, if the export failed, and waits
+    /// until every event has been sent. Later events are dropped.
+    pub async fn finish(&self, error: Option<String>) {
+        let sender = self.sender.lock().unwrap().take();
+        if let (Some(sender), Some(error)) = (&sender, error) {
+            let _ = sender.send(json!({
+                "event": "failed",
+                "error": error,
+                "message": format!("Export failed: {}", error),
+            }));
+        }
+        drop(sender);
+        let task = self.task.lock().unwrap().take();
+        if let Some(task) = task {
+            let _ = task.await;
+        }
+    }
+}
+
+impl ProgressReporter for WebhookReporter {
+    fn report(&self, event: &ProgressEvent) {
+        let Some(payload) = payload(event) else {
+            return;
+        };
+        if let Some(sender) = self.sender.lock().unwrap().as_ref() {
+            let _ = sender.send(payload);
+        }
+    }
+}
+
+/// The JSON object posted for `event`: its name as `event`, its fields and
+/// the log line as `message`. Events that are not logged, and the start of
+/// every page fetch, are not posted.
+pub fn payload(event: &ProgressEvent) -> Option<Value> {
+    if matches!(event, ProgressEvent::FetchingPage { .. }) {
+        return None;
+    }
+    let message = event.message()?;
+    let millis = |duration: &Duration| duration.as_millis() as u64;
+    let mut payload = match event {
+        ProgressEvent::Started {
+            deck_id,
+            page_limit,
+        } => json!({"event": "started", "deck_id": deck_id, "page_limit": page_limit}),
+        ProgressEvent::CheckpointLoaded { pages, cards } => {
+            json!({"event": "checkpoint_loaded", "pages": pages, "cards": cards})
+        }
+        ProgressEvent::FetchingPage { page } => json!({"event": "fetching_page", "page": page}),
+        ProgressEvent::PageFetched { page, cards } => {
+            json!({"event": "page_fetched", "page": page, "cards": cards})
+        }
+        ProgressEvent::Downloaded { requests, bytes } => {
+            json!({"event": "downloaded", "requests": requests, "bytes": bytes})
+        }
+        ProgressEvent::CardProcessed {
+            processed,
+            total_cards,
+            duplicates,
+            elapsed,
+            ..
+        } => json!({
+            "event": "cards_processed",
+            "processed": processed,
+            "total_cards": total_cards,
+            "duplicates": duplicates,
+            "elapsed_ms": millis(elapsed),
+        }),
+        ProgressEvent::MediaFailed { word, error } => {
+            json!({"event": "media_failed", "word": word, "error": error})
+        }
+        ProgressEvent::CardSkipped { word, error } => {
+            json!({"event": "card_skipped", "word": word, "error": error})
+        }
+        ProgressEvent::PageLimitReached { pages } => {
+            json!({"event": "page_limit_reached", "pages": pages})
+        }
+        ProgressEvent::CardLimitReached { cards } => {
+            json!({"event": "card_limit_reached", "cards": cards})
+        }
+        ProgressEvent::LastPageFetched => json!({"event": "last_page_fetched"}),
+        ProgressEvent::Paused => json!({"event": "paused"}),
+        ProgressEvent::Resumed => json!({"event": "resumed"}),
+        ProgressEvent::Stopped { pages } => json!({"event": "stopped", "pages": pages}),
+        ProgressEvent::Retrying {
+            pages,
+            error,
+            delay,
+            attempt,
+            max_retries,
+        } => json!({
+            "event": "retrying",
+            "pages": pages,
+            "error": error,
+            "delay_ms": millis(delay),
+            "attempt": attempt,
+            "max_retries": max_retries,
+        }),
+        ProgressEvent::FetchFinished {
+            page_limit,
+            total_cards,
+            duplicates,
+            elapsed,
+        } => json!({
+            "event": "fetch_finished",
+            "page_limit": page_limit,
+            "total_cards": total_cards,
+            "duplicates": duplicates,
+            "elapsed_ms": millis(elapsed),
+        }),
+        ProgressEvent::Writing => json!({"event": "writing"}),
+        ProgressEvent::Written => json!({"event": "written"}),
+        ProgressEvent::WriteFailed { error } => json!({"event": "write_failed", "error": error}),
+        ProgressEvent::WritingFallback { path } => {
+            json!({"event": "writing_fallback", "path": path})
+        }
+        ProgressEvent::Verified { notes } => json!({"event": "verified", "notes": notes}),
+        ProgressEvent::VerificationSkipped { reason } => {
+            json!({"event": "verification_skipped", "reason": reason})
+        }
+        ProgressEvent::Completed {
+            total_cards,
+            duplicates,
+            skipped,
+            elapsed,
+        } => json!({
+            "event": "completed",
+            "total_cards": total_cards,
+            "duplicates": duplicates,
+            "skipped": skipped,
+            "elapsed_ms": millis(elapsed),
+        }),
+    };
+    payload["message"] = Value::String(message);
+    Some(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload() {
+        assert_eq!(
+            payload(&ProgressEvent::PageFetched { page: 3, cards: 50 }),
+            Some(json!({
+                "event": "page_fetched",
+                "page": 3,
+                "cards": 50,
+                "message": "Page 3 fetched with 50 cards",
+            }))
+        );
+        assert_eq!(payload(&ProgressEvent::FetchingPage { page: 3 }), None);
+
+        // Processed cards are only posted when logged, every 100 cards
+        let processed = |processed| ProgressEvent::CardProcessed {
+            word: "Haus".to_string(),
+            added: true,
+            processed,
+            total_cards: processed,
+            duplicates: 0,
+            elapsed: Duration::from_millis(1500),
+        };
+        assert_eq!(payload(&processed(99)), None);
+        let posted = payload(&processed(100)).unwrap();
+        assert_eq!(posted["event"], "cards_processed");
+        assert_eq!(posted["elapsed_ms"], 1500);
+    }
+}
@@ -0,0 +1,170 @@
+//! Selection of cards by patterns matched against their word and translation,
+//! by how often they were answered correctly, by status and by their labels.
+
+use crate::duocards::models::{LearningStatus, VocabularyCard};
+use regex::Regex;
+
+/// Which cards to export. A card is kept if its word or translation matches
+/// the include pattern, neither matches the exclude pattern, its known count
+/// is within the bounds, and it has one of the statuses and labels, if any
+/// are given.
+#[derive(Debug, Clone, Default)]
+pub struct CardFilter {
+    include: Option<Regex>,
+    exclude: Option<Regex>,
+    min_known: Option<i32>,
+    max_known: Option<i32>,
+    statuses: Vec<LearningStatus>,
+    labels: Vec<String>,
+}
+
+impl CardFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps only cards whose word or translation matches `pattern`.
+    pub fn with_include(mut self, pattern: Regex) -> Self {
+        self.include = Some(pattern);
+        self
+    }
+
+    /// Drops cards whose word or translation matches `pattern`.
+    pub fn with_exclude(mut self, pattern: Regex) -> Self {
+        self.exclude = Some(pattern);
+        self
+    }
+
+    /// Keeps only cards answered correctly at least `count` times.
+    pub fn with_min_known(mut self, count: i32) -> Self {
+        self.min_known = Some(count);
+        self
+    }
+
+    /// Keeps only cards answered correctly at most `count` times.
+    pub fn with_max_known(mut self, count: i32) -> Self {
+        self.max_known = Some(count);
+        self
+    }
+
+    /// Keeps only cards with `status`; with several statuses, cards having
+    /// any of them.
+    pub fn with_status(mut self, status: LearningStatus) -> Self {
+        self.statuses.push(status);
+        self
+    }
+
+    /// Keeps only cards labelled `label`; with several labels, cards having
+    /// any of them.
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.labels.push(label.into());
+        self
+    }
+
+    /// Whether the filter keeps every card.
+    pub fn is_noop(&self) -> bool {
+        self.include.is_none()
+            && self.exclude.is_none()
+            && self.min_known.is_none()
+            && self.max_known.is_none()
+            && self.statuses.is_empty()
+            && self.labels.is_empty()
+    }
+
+    /// Whether `card` is exported. Cards without a known count, e.g. read
+    /// from an older export, count as the lowest count of their status.
+    pub fn matches(&self, card: &VocabularyCard) -> bool {
+        let found =
+            |pattern: &Regex| pattern.is_match(&card.word) || pattern.is_match(&card.translation);
+        let known_count = card
+            .known_count
+            .unwrap_or_else(|| card.status.min_known_count());
+        self.include.as_ref().is_none_or(found)
+            && !self.exclude.as_ref().is_some_and(found)
+            && self.min_known.is_none_or(|min| known_count >= min)
+            && self.max_known.is_none_or(|max| known_count <= max)
+            && (self.statuses.is_empty() || self.statuses.contains(&card.status))
+            && (self.labels.is_empty() || card.labels.iter().any(|l| self.labels.contains(l)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duocards::models::LearningStatus;
+
+    fn card(word: &str, translation: &str) -> VocabularyCard {
+        VocabularyCard {
+            word: word.to_string(),
+            translation: translation.to_string(),
+            status: LearningStatus::New,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(CardFilter::new().matches(&card("Haus", "house")));
+
+        let phrases = CardFilter::new().with_include(Regex::new(r"\s").unwrap());
+        assert!(phrases.matches(&card("guten Tag", "hello")));
+        assert!(phrases.matches(&card("Moin", "good morning")));
+        assert!(!phrases.matches(&card("Haus", "house")));
+
+        let no_numbers = CardFilter::new().with_exclude(Regex::new(r"\d").unwrap());
+        assert!(no_numbers.matches(&card("Haus", "house")));
+        assert!(!no_numbers.matches(&card("zwei", "2")));
+
+        let both = phrases.with_exclude(Regex::new("^der ").unwrap());
+        assert!(both.matches(&card("die Stadt", "the city")));
+        assert!(!both.matches(&card("der Hund", "the dog")));
+    }
+
+    #[test]
+    fn test_known_count_bounds() {
+        let counted = |known_count| VocabularyCard {
+            status: LearningStatus::from_known_count(known_count),
+            known_count: Some(known_count),
+            ..card("Haus", "house")
+        };
+        let filter = CardFilter::new().with_min_known(2).with_max_known(4);
+        assert!(!filter.matches(&counted(1)));
+        assert!(filter.matches(&counted(2)));
+        assert!(filter.matches(&counted(4)));
+        assert!(!filter.matches(&counted(5)));
+
+        // Without a count, the status decides
+        let known = VocabularyCard {
+            status: LearningStatus::Known,
+            ..card("Haus", "house")
+        };
+        assert!(CardFilter::new().with_min_known(5).matches(&known));
+        assert!(!CardFilter::new().with_max_known(4).matches(&known));
+    }
+
+    #[test]
+    fn test_labels() {
+        let labelled = VocabularyCard {
+            labels: vec!["course".to_string(), "A1".to_string()],
+            ..card("Haus", "house")
+        };
+        let filter = CardFilter::new().with_label("A1").with_label("A2");
+        assert!(filter.matches(&labelled));
+        assert!(!filter.matches(&card("Hund", "dog")));
+        assert!(!CardFilter::new().with_label("a1").matches(&labelled));
+    }
+
+    #[test]
+    fn test_statuses() {
+        let known = VocabularyCard {
+            status: LearningStatus::Known,
+            ..card("Haus", "house")
+        };
+        let filter = CardFilter::new().with_status(LearningStatus::Known);
+        assert!(!filter.is_noop());
+        assert!(filter.matches(&known));
+        assert!(!filter.matches(&card("Hund", "dog")));
+        let filter = filter.with_status(LearningStatus::New);
+        assert!(filter.matches(&card("Hund", "dog")));
+    }
+}
@@ -0,0 +1,241 @@
+//! Which card field ends up on the front, back and example of the output.
+
+use crate::duocards::models::{DeckMetadata, VocabularyCard};
+use std::fmt;
+use std::str::FromStr;
+
+/// A text field of a Duocards card.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CardField {
+    Word,
+    Translation,
+    Example,
+}
+
+impl CardField {
+    fn name(self) -> &'static str {
+        match self {
+            CardField::Word => "word",
+            CardField::Translation => "translation",
+            CardField::Example => "example",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "word" => Some(CardField::Word),
+            "translation" => Some(CardField::Translation),
+            "example" => Some(CardField::Example),
+            _ => None,
+        }
+    }
+
+    fn value(self, card: &VocabularyCard) -> Option<&str> {
+        match self {
+            CardField::Word => Some(&card.word),
+            CardField::Translation => Some(&card.translation),
+            CardField::Example => card.example.as_deref(),
+        }
+    }
+}
+
+/// Sources of the front (the word), back (the translation) and example of
+/// every output, e.g. `front=translation,back=word` for cards that ask for
+/// the word in the language being learned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldMapping {
+    pub front: CardField,
+    pub back: CardField,
+    /// `None` leaves the example out
+    pub example: Option<CardField>,
+}
+
+impl Default for FieldMapping {
+    fn default() -> Self {
+        Self {
+            front: CardField::Word,
+            back: CardField::Translation,
+            example: Some(CardField::Example),
+        }
+    }
+}
+
+impl FieldMapping {
+    /// Whether the mapping leaves every card unchanged.
+    pub fn is_noop(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Moves the fields of `card` to where the mapping puts them. A front or
+    /// back taken from a missing example is left empty.
+    pub fn apply(&self, mut card: VocabularyCard) -> VocabularyCard {
+        if self.is_noop() {
+            return card;
+        }
+        let front = self.front.value(&card).unwrap_or_default().to_string();
+        let back = self.back.value(&card).unwrap_or_default().to_string();
+        let example = self
+            .example
+            .and_then(|field| field.value(&card))
+            .map(str::to_string);
+        card.word = front;
+        card.translation = back;
+        card.example = example;
+        card
+    }
+}
+
+/// Exchanges the word and translation of every card, with their languages,
+/// for learning in the reverse direction of the Duocards deck.
+///
+/// Runs before duplicates are detected, so cards are told apart by their
+/// translation instead of their word.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Swap;
+
+impl Swap {
+    pub fn apply(&self, mut card: VocabularyCard) -> VocabularyCard {
+        std::mem::swap(&mut card.word, &mut card.translation);
+        if let Some(language) = &mut card.language {
+            std::mem::swap(&mut language.word, &mut language.translation);
+        }
+        card
+    }
+
+    /// `deck` with the languages of its fronts and backs exchanged.
+    pub fn apply_metadata(&self, mut deck: DeckMetadata) -> DeckMetadata {
+        std::mem::swap(&mut deck.source_language, &mut deck.target_language);
+        deck
+    }
+}
+
+impl fmt::Display for FieldMapping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "front={},back={},example={}",
+            self.front.name(),
+            self.back.name(),
+            self.example.map_or("none", CardField::name)
+        )
+    }
+}
+
+impl FromStr for FieldMapping {
+    type Err = String;
+
+    /// Parses comma-separated `target=source` pairs. Targets are `front`,
+    /// `back` and `example`, sources `word`, `translation` and `example`, or
+    /// `none` for the example; targets not given keep their default source.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut mapping = FieldMapping::default();
+        for pair in s.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let (target, source) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("Expected target=source, got {:?}", pair))?;
+            let (target, source) = (target.trim(), source.trim());
+            let field = CardField::parse(source);
+            match (target, field) {
+                ("front", Some(field)) => mapping.front = field,
+                ("back", Some(field)) => mapping.back = field,
+                ("example", Some(field)) => mapping.example = Some(field),
+                ("example", None) if source == "none" => mapping.example = None,
+                ("front" | "back" | "example", None) => {
+                    return Err(format!(
+                        "Unknown field {:?}; expected word, translation or example",
+                        source
+                    ));
+                }
+                _ => {
+                    return Err(format!(
+                        "Unknown target {:?}; expected front, back or example",
+                        target
+                    ));
+                }
+            }
+        }
+        Ok(mapping)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duocards::models::{CardLanguage, LearningStatus};
+
+    fn card() -> VocabularyCard {
+        VocabularyCard {
+            word: "Haus".to_string(),
+            translation: "house".to_string(),
+            example: Some("Das Haus ist groß".to_string()),
+            status: LearningStatus::New,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let mapping: FieldMapping = "front=translation, back=word".parse().unwrap();
+        assert_eq!(mapping.front, CardField::Translation);
+        assert_eq!(mapping.back, CardField::Word);
+        assert_eq!(mapping.example, Some(CardField::Example));
+        assert_eq!(
+            mapping.to_string(),
+            "front=translation,back=word,example=example"
+        );
+
+        let mapping: FieldMapping = "example=none".parse().unwrap();
+        assert_eq!(mapping.example, None);
+        assert!("".parse::<FieldMapping>().unwrap().is_noop());
+
+        assert!("front".parse::<FieldMapping>().is_err());
+        assert!("front=none".parse::<FieldMapping>().is_err());
+        assert!("side=word".parse::<FieldMapping>().is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mapping: FieldMapping = "front=translation,back=word".parse().unwrap();
+        let mapped = mapping.apply(card());
+        assert_eq!(mapped.word, "house");
+        assert_eq!(mapped.translation, "Haus");
+        assert_eq!(mapped.example.as_deref(), Some("Das Haus ist groß"));
+
+        let mapping: FieldMapping = "back=example,example=translation".parse().unwrap();
+        let mapped = mapping.apply(VocabularyCard {
+            example: None,
+            ..card()
+        });
+        assert_eq!(mapped.word, "Haus");
+        assert_eq!(mapped.translation, "");
+        assert_eq!(mapped.example.as_deref(), Some("house"));
+    }
+
+    #[test]
+    fn test_swap() {
+        let swapped = Swap.apply(VocabularyCard {
+            language: Some(CardLanguage {
+                word: "de".to_string(),
+                translation: "en".to_string(),
+            }),
+            ..card()
+        });
+        assert_eq!(swapped.word, "house");
+        assert_eq!(swapped.translation, "Haus");
+        assert_eq!(swapped.example.as_deref(), Some("Das Haus ist groß"));
+        let language = swapped.language.unwrap();
+        assert_eq!(
+            (language.word.as_str(), language.translation.as_str()),
+            ("en", "de")
+        );
+
+        let deck = Swap.apply_metadata(DeckMetadata {
+            id: "deck".to_string(),
+            name: None,
+            source_language: Some("de".to_string()),
+            target_language: None,
+        });
+        assert_eq!(deck.source_language, None);
+        assert_eq!(deck.target_language.as_deref(), Some("de"));
+    }
+}
@@ -0,0 +1,192 @@
+//! Changes applied to cards between fetching and output.
+//!
+//! Every change is a [`CardTransform`]; a [`TransformPipeline`] runs them in
+//! order on each fetched card.
+
+pub mod filter;
+pub mod mapping;
+pub mod normalize;
+pub mod sanitize;
+pub mod sentences;
+
+use crate::duocards::models::{DeckMetadata, VocabularyCard};
+use filter::CardFilter;
+use mapping::{FieldMapping, Swap};
+use normalize::NormalForm;
+use sanitize::SanitizeOptions;
+use sentences::SentenceExtractor;
+
+/// A change to every fetched card.
+pub trait CardTransform: Send + Sync {
+    /// The changed card, or `None` to leave it out of the export.
+    fn transform(&self, card: VocabularyCard) -> Option<VocabularyCard>;
+
+    /// The metadata of the deck of the changed cards, e.g. with the
+    /// languages of their fronts and backs.
+    fn deck_metadata(&self, deck: DeckMetadata) -> DeckMetadata {
+        deck
+    }
+
+    /// Whether the transform returns every card unchanged.
+    fn is_noop(&self) -> bool {
+        false
+    }
+}
+
+impl CardTransform for SanitizeOptions {
+    fn transform(&self, card: VocabularyCard) -> Option<VocabularyCard> {
+        Some(self.apply(card))
+    }
+
+    fn is_noop(&self) -> bool {
+        SanitizeOptions::is_noop(self)
+    }
+}
+
+impl CardTransform for NormalForm {
+    fn transform(&self, card: VocabularyCard) -> Option<VocabularyCard> {
+        Some(self.apply(card))
+    }
+}
+
+impl CardTransform for CardFilter {
+    fn transform(&self, card: VocabularyCard) -> Option<VocabularyCard> {
+        self.matches(&card).then_some(card)
+    }
+
+    fn is_noop(&self) -> bool {
+        CardFilter::is_noop(self)
+    }
+}
+
+impl CardTransform for FieldMapping {
+    fn transform(&self, card: VocabularyCard) -> Option<VocabularyCard> {
+        Some(self.apply(card))
+    }
+
+    fn is_noop(&self) -> bool {
+        FieldMapping::is_noop(self)
+    }
+}
+
+impl CardTransform for Swap {
+    fn transform(&self, card: VocabularyCard) -> Option<VocabularyCard> {
+        Some(self.apply(card))
+    }
+
+    fn deck_metadata(&self, deck: DeckMetadata) -> DeckMetadata {
+        self.apply_metadata(deck)
+    }
+}
+
+impl CardTransform for SentenceExtractor {
+    fn transform(&self, card: VocabularyCard) -> Option<VocabularyCard> {
+        self.extract(card)
+    }
+}
+
+/// Transforms run one after another on each card, e.g. sanitize, normalize,
+/// filter and map. A card dropped by one stage skips the ones after it.
+#[derive(Default)]
+pub struct TransformPipeline {
+    stages: Vec<Box<dyn CardTransform>>,
+}
+
+impl TransformPipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `stage` after the stages added so far. Stages that change nothing
+    /// are left out.
+    pub fn then<T: CardTransform + 'static>(mut self, stage: T) -> Self {
+        if !stage.is_noop() {
+            self.stages.push(Box::new(stage));
+        }
+        self
+    }
+
+    /// Whether the pipeline returns every card unchanged.
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    /// Runs every stage on `card`; `None` if a stage dropped it.
+    pub fn apply(&self, card: VocabularyCard) -> Option<VocabularyCard> {
+        self.stages
+            .iter()
+            .try_fold(card, |card, stage| stage.transform(card))
+    }
+
+    /// Runs every stage on the metadata of the deck.
+    pub fn apply_metadata(&self, deck: DeckMetadata) -> DeckMetadata {
+        self.stages
+            .iter()
+            .fold(deck, |deck, stage| stage.deck_metadata(deck))
+    }
+}
+
+impl std::fmt::Debug for TransformPipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TransformPipeline")
+            .field("stages", &self.stages.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duocards::models::LearningStatus;
+    use regex::Regex;
+
+    fn card(word: &str, translation: &str) -> VocabularyCard {
+        VocabularyCard {
+            word: word.to_string(),
+            translation: translation.to_string(),
+            status: LearningStatus::New,
+            ..Default::default()
+        }
+    }
+
+    /// Appends a mark to the word, to see the order of the stages.
+    struct Mark(&'static str);
+
+    impl CardTransform for Mark {
+        fn transform(&self, mut card: VocabularyCard) -> Option<VocabularyCard> {
+            card.word.push_str(self.0);
+            Some(card)
+        }
+    }
+
+    #[test]
+    fn test_stages_run_in_order() {
+        let pipeline = TransformPipeline::new().then(Mark("1")).then(Mark("2"));
+        assert_eq!(
+            pipeline.apply(card("Haus", "house")).unwrap().word,
+            "Haus12"
+        );
+    }
+
+    #[test]
+    fn test_noop_stages_are_left_out() {
+        let pipeline = TransformPipeline::new()
+            .then(SanitizeOptions::default())
+            .then(CardFilter::new())
+            .then(FieldMapping::default());
+        assert!(pipeline.is_empty());
+    }
+
+    #[test]
+    fn test_dropped_cards_skip_later_stages() {
+        let pipeline = TransformPipeline::new()
+            .then(SanitizeOptions {
+                strip_html: true,
+                ..Default::default()
+            })
+            .then(CardFilter::new().with_exclude(Regex::new("^Haus$").unwrap()))
+            .then(Mark("!"));
+        assert!(pipeline.apply(card("<b>Haus</b>", "house")).is_none());
+        assert_eq!(pipeline.apply(card("Hund", "dog")).unwrap().word, "Hund!");
+    }
+}
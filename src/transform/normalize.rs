@@ -0,0 +1,68 @@
+//! Unicode normalization of card fields.
+
+use crate::duocards::models::VocabularyCard;
+use clap::ValueEnum;
+use unicode_normalization::UnicodeNormalization;
+
+/// Unicode normal form the word, translation and example are converted to.
+///
+/// Words typed on different devices may spell `é` as one character or as `e`
+/// followed by a combining accent; after normalization they compare equal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum NormalForm {
+    /// Composed characters, as most keyboards produce them
+    Nfc,
+    /// Decomposed characters
+    Nfd,
+    /// Composed, with compatibility characters such as `ﬁ` replaced
+    Nfkc,
+    /// Decomposed, with compatibility characters replaced
+    Nfkd,
+}
+
+impl NormalForm {
+    /// Converts the text fields of `card` to the normal form.
+    pub fn apply(&self, mut card: VocabularyCard) -> VocabularyCard {
+        card.word = self.text(&card.word);
+        card.translation = self.text(&card.translation);
+        card.example = card.example.map(|example| self.text(&example));
+        card.hint = card.hint.map(|hint| self.text(&hint));
+        card
+    }
+
+    fn text(&self, text: &str) -> String {
+        match self {
+            NormalForm::Nfc => text.nfc().collect(),
+            NormalForm::Nfd => text.nfd().collect(),
+            NormalForm::Nfkc => text.nfkc().collect(),
+            NormalForm::Nfkd => text.nfkd().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duocards::models::LearningStatus;
+
+    #[test]
+    fn test_apply() {
+        let card = VocabularyCard {
+            word: "Cafe\u{301}".to_string(),
+            translation: "coffee".to_string(),
+            example: Some("\u{fb01}ne".to_string()),
+            status: LearningStatus::New,
+            ..Default::default()
+        };
+
+        let composed = NormalForm::Nfc.apply(card.clone());
+        assert_eq!(composed.word, "Café");
+        assert_eq!(composed.example.as_deref(), Some("\u{fb01}ne"));
+
+        let compatible = NormalForm::Nfkc.apply(card.clone());
+        assert_eq!(compatible.example.as_deref(), Some("fine"));
+
+        let decomposed = NormalForm::Nfd.apply(compatible);
+        assert_eq!(decomposed.word, card.word);
+    }
+}
@@ -0,0 +1,235 @@
+//! Cleanup of HTML markup, entities and whitespace in card fields, and
+//! escaping of text for outputs that show it as HTML.
+
+use crate::duocards::models::VocabularyCard;
+use std::borrow::Cow;
+
+/// Which cleanups to apply to the word, translation and example of a card.
+///
+/// Markup is stripped before entities are decoded, so an escaped `&lt;b&gt;`
+/// survives as the text `<b>`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SanitizeOptions {
+    /// Remove HTML tags; line breaks and block ends become spaces
+    pub strip_html: bool,
+    /// Replace entities such as `&amp;` and `&#233;` by their characters
+    pub decode_entities: bool,
+    /// Trim the fields and collapse runs of whitespace into one space
+    pub trim_whitespace: bool,
+}
+
+impl SanitizeOptions {
+    /// Whether the options leave every card unchanged.
+    pub fn is_noop(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Cleans the text fields of `card`; an example or hint left empty is
+    /// dropped.
+    pub fn apply(&self, mut card: VocabularyCard) -> VocabularyCard {
+        if self.is_noop() {
+            return card;
+        }
+        card.word = self.text(&card.word);
+        card.translation = self.text(&card.translation);
+        card.example = card
+            .example
+            .map(|example| self.text(&example))
+            .filter(|example| !example.is_empty());
+        card.hint = card
+            .hint
+            .map(|hint| self.text(&hint))
+            .filter(|hint| !hint.is_empty());
+        card
+    }
+
+    fn text(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        if self.strip_html {
+            text = strip_html(&text);
+        }
+        if self.decode_entities {
+            text = decode_entities(&text);
+        }
+        if self.trim_whitespace {
+            text = trim_whitespace(&text);
+        }
+        text
+    }
+}
+
+/// Tags whose end starts a new line when rendered.
+const BREAKING_TAGS: [&str; 6] = ["br", "p", "div", "li", "tr", "hr"];
+
+/// Removes HTML tags and comments. A `<` that does not start a tag is kept.
+pub fn strip_html(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        result.push_str(&rest[..start]);
+        let tag = &rest[start + 1..];
+        let starts_tag = tag
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '/' || c == '!');
+        match tag.find('>') {
+            Some(end) if starts_tag => {
+                let name = tag[..end]
+                    .trim_start_matches('/')
+                    .split(|c: char| c.is_whitespace() || c == '/')
+                    .next()
+                    .unwrap_or("")
+                    .to_ascii_lowercase();
+                if BREAKING_TAGS.contains(&name.as_str()) {
+                    result.push(' ');
+                }
+                rest = &tag[end + 1..];
+            }
+            _ => {
+                result.push('<');
+                rest = tag;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Decodes the common named entities and numeric character references.
+/// Unknown entities are kept as they are.
+pub fn decode_entities(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        let entity = &rest[start + 1..];
+        let decoded = entity
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| Some((decode_entity(&entity[..end])?, end)));
+        match decoded {
+            Some((c, end)) => {
+                result.push(c);
+                rest = &entity[end + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = entity;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Escapes the characters HTML gives a meaning to, and the `{{` that starts
+/// a field reference in Anki templates, so `text` is shown as it is.
+pub fn escape_html(text: &str) -> Cow<'_, str> {
+    if !text.contains(['&', '<', '>', '"', '{']) {
+        return Cow::Borrowed(text);
+    }
+    let mut result = String::with_capacity(text.len() + 8);
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '{' if chars.peek() == Some(&'{') => result.push_str("&#123;"),
+            c => result.push(c),
+        }
+    }
+    Cow::Owned(result)
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    let c = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "ndash" => '–',
+        "mdash" => '—',
+        "hellip" => '…',
+        "laquo" => '«',
+        "raquo" => '»',
+        _ => return None,
+    };
+    Some(c)
+}
+
+/// Trims `text` and collapses every run of whitespace into a single space.
+pub fn trim_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duocards::models::LearningStatus;
+
+    #[test]
+    fn test_strip_html() {
+        assert_eq!(strip_html("<b>Haus</b>"), "Haus");
+        assert_eq!(strip_html("eins<br>zwei<br/>drei"), "eins zwei drei");
+        assert_eq!(strip_html("<!-- note -->x"), "x");
+        assert_eq!(strip_html("a < b and 1<2"), "a < b and 1<2");
+        assert_eq!(strip_html("unclosed <b"), "unclosed <b");
+    }
+
+    #[test]
+    fn test_decode_entities() {
+        assert_eq!(decode_entities("Tom &amp; Jerry"), "Tom & Jerry");
+        assert_eq!(decode_entities("caf&#233; &#x263A;"), "café ☺");
+        assert_eq!(decode_entities("&lt;b&gt;"), "<b>");
+        assert_eq!(decode_entities("&unknown; & &amp"), "&unknown; & &amp");
+    }
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(escape_html("Haus"), "Haus");
+        assert!(matches!(escape_html("Haus"), Cow::Borrowed(_)));
+        assert_eq!(
+            escape_html("a < b && \"c\" > d"),
+            "a &lt; b &amp;&amp; &quot;c&quot; &gt; d"
+        );
+        assert_eq!(escape_html("{{Front}} {x}"), "&#123;{Front}} {x}");
+        assert_eq!(
+            decode_entities(&escape_html("<b>&amp;</b>")),
+            "<b>&amp;</b>"
+        );
+    }
+
+    #[test]
+    fn test_apply() {
+        let card = VocabularyCard {
+            word: "  <i>das</i>&nbsp;Haus ".to_string(),
+            translation: "house &amp; home".to_string(),
+            example: Some("<br>".to_string()),
+            status: LearningStatus::New,
+            ..Default::default()
+        };
+
+        let options = SanitizeOptions {
+            strip_html: true,
+            decode_entities: true,
+            trim_whitespace: true,
+        };
+        let sanitized = options.apply(card.clone());
+        assert_eq!(sanitized.word, "das Haus");
+        assert_eq!(sanitized.translation, "house & home");
+        assert_eq!(sanitized.example, None);
+
+        assert_eq!(SanitizeOptions::default().apply(card.clone()), card);
+    }
+}
@@ -0,0 +1,159 @@
+//! Cards for the example sentences of the fetched words, for a deck that
+//! practises words in context.
+
+use crate::duocards::models::VocabularyCard;
+use crate::transform::sanitize::escape_html;
+use regex::Regex;
+
+/// Appended to the source ID of a sentence card, so it is not taken for the
+/// card of its word, e.g. in the note GUIDs of an Anki package.
+pub const SENTENCE_ID_SUFFIX: &str = ":sentence";
+
+/// Turns each card with an example sentence into a card whose front is the
+/// sentence, with the word marked in bold, and whose back is the translation
+/// of the word. Cards without an example are left out.
+#[derive(Debug, Clone)]
+pub struct SentenceExtractor {
+    open: String,
+    close: String,
+    escape_html: bool,
+}
+
+impl Default for SentenceExtractor {
+    fn default() -> Self {
+        Self {
+            open: "<b>".to_string(),
+            close: "</b>".to_string(),
+            escape_html: false,
+        }
+    }
+}
+
+impl SentenceExtractor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Marks the word with `open` and `close` instead of `<b>` and `</b>`.
+    pub fn with_markers(mut self, open: impl Into<String>, close: impl Into<String>) -> Self {
+        self.open = open.into();
+        self.close = close.into();
+        self
+    }
+
+    /// Escapes HTML in the sentence and translation before the word is
+    /// marked, for outputs that show the cards as HTML without escaping
+    /// them, so only the markers are rendered.
+    pub fn with_html_escaping(mut self, enabled: bool) -> Self {
+        self.escape_html = enabled;
+        self
+    }
+
+    /// The sentence card of `card`, or `None` if it has no example.
+    pub fn extract(&self, card: VocabularyCard) -> Option<VocabularyCard> {
+        let sentence = card.example.as_deref().map(str::trim)?;
+        if sentence.is_empty() {
+            return None;
+        }
+        let (sentence, translation) = if self.escape_html {
+            let word = escape_html(card.word.trim());
+            (
+                self.highlight(&escape_html(sentence), &word),
+                escape_html(&card.translation).into_owned(),
+            )
+        } else {
+            (self.highlight(sentence, card.word.trim()), card.translation)
+        };
+        Some(VocabularyCard {
+            word: sentence,
+            translation,
+            example: None,
+            hint: None,
+            // Pronounces the word, not the sentence
+            audio: None,
+            source_id: card
+                .source_id
+                .map(|id| format!("{}{}", id, SENTENCE_ID_SUFFIX)),
+            ..card
+        })
+    }
+
+    /// `sentence` with every occurrence of `word` marked, ignoring case.
+    /// A sentence without the word, e.g. one using an inflected form, is
+    /// kept as it is.
+    fn highlight(&self, sentence: &str, word: &str) -> String {
+        if word.is_empty() {
+            return sentence.to_string();
+        }
+        let pattern = Regex::new(&format!("(?i){}", regex::escape(word)))
+            .expect("escaped word is a valid pattern");
+        pattern
+            .replace_all(sentence, |found: &regex::Captures| {
+                format!("{}{}{}", self.open, &found[0], self.close)
+            })
+            .into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duocards::models::LearningStatus;
+
+    fn card(word: &str, example: Option<&str>) -> VocabularyCard {
+        VocabularyCard {
+            word: word.to_string(),
+            translation: "dog".to_string(),
+            example: example.map(str::to_string),
+            status: LearningStatus::Learning,
+            audio: Some("https://example.com/hund.mp3".to_string()),
+            source_id: Some("card-1".to_string()),
+            known_count: Some(2),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sentence_card() {
+        let sentence = SentenceExtractor::new()
+            .extract(card("Hund", Some(" Der hund bellt, der Hund läuft. ")))
+            .unwrap();
+        assert_eq!(
+            sentence.word,
+            "Der <b>hund</b> bellt, der <b>Hund</b> läuft."
+        );
+        assert_eq!(sentence.translation, "dog");
+        assert_eq!(sentence.example, None);
+        assert_eq!(sentence.audio, None);
+        assert_eq!(sentence.source_id.as_deref(), Some("card-1:sentence"));
+        assert_eq!(sentence.known_count, Some(2));
+    }
+
+    #[test]
+    fn test_cards_without_example_are_dropped() {
+        let extractor = SentenceExtractor::new();
+        assert!(extractor.extract(card("Hund", None)).is_none());
+        assert!(extractor.extract(card("Hund", Some("  "))).is_none());
+    }
+
+    #[test]
+    fn test_escaped_sentence() {
+        let sentence = SentenceExtractor::new()
+            .with_html_escaping(true)
+            .extract(card("Hund", Some("<i>Der</i> Hund & die Katze")))
+            .unwrap();
+        assert_eq!(
+            sentence.word,
+            "&lt;i&gt;Der&lt;/i&gt; <b>Hund</b> &amp; die Katze"
+        );
+    }
+
+    #[test]
+    fn test_word_is_matched_literally() {
+        let sentence = SentenceExtractor::new()
+            .with_markers("**", "**")
+            .extract(card("a.b", Some("acb a.b $1")))
+            .unwrap();
+        assert_eq!(sentence.word, "acb **a.b** $1");
+    }
+}
@@ -0,0 +1,772 @@
+//! Full-screen dashboard for running exports.
+//!
+//! The dashboard runs on its own thread, drawing to stderr so that stdout
+//! stays usable for piped output. It is fed by a [`TuiReporter`] and steers
+//! the export through a [`TransferControl`]. Each run exports a single deck,
+//! so the dashboard follows one export: `s` stops it and writes the cards
+//! fetched so far.
+//!
+//! [`StatusLine`] is a lighter alternative that keeps the terminal: a few
+//! live lines below the log, which scrolls above them in color.
+//!
+//! [`pick_deck`] lets the user choose the deck to export before it starts.
+
+use crate::duocards::bandwidth::format_bytes;
+use crate::duocards::models::DeckSummary;
+use crate::picker::deck_label;
+use crate::transfer::control::TransferControl;
+use crate::transfer::progress::{ProgressEvent, ProgressReporter};
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{
+    EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode,
+};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{
+    Block, Borders, Cell, Gauge, List, ListItem, ListState, Paragraph, Row, Table, Widget,
+};
+use ratatui::{Frame, Terminal, TerminalOptions, Viewport};
+use std::collections::VecDeque;
+use std::io;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Number of log lines kept for the scrolling log panel.
+const LOG_CAPACITY: usize = 500;
+
+/// How long the dashboard waits for a key press before redrawing.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Number of recently processed words shown by the status line.
+const RECENT_WORDS: usize = 5;
+
+/// Height of the status line: a header, the counters and the recent words.
+const STATUS_HEIGHT: u16 = 3;
+
+/// Progress reporter that forwards events to the dashboard thread.
+pub struct TuiReporter {
+    events: Sender<ProgressEvent>,
+}
+
+impl ProgressReporter for TuiReporter {
+    fn report(&self, event: &ProgressEvent) {
+        // The dashboard may already be gone after an abort; nothing to do then
+        let _ = self.events.send(event.clone());
+    }
+}
+
+/// Handle to the running dashboard thread.
+pub struct Dashboard {
+    handle: JoinHandle<io::Result<()>>,
+}
+
+impl Dashboard {
+    /// Starts the dashboard and returns the reporter that feeds it.
+    ///
+    /// The dashboard closes once every clone of the reporter has been dropped.
+    pub fn start(control: TransferControl) -> (TuiReporter, Dashboard) {
+        let (tx, rx) = mpsc::channel();
+        let handle = thread::spawn(move || run(rx, control));
+        (TuiReporter { events: tx }, Dashboard { handle })
+    }
+
+    /// Waits for the dashboard to close and restores the terminal.
+    pub fn finish(self) -> io::Result<()> {
+        self.handle
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("dashboard thread panicked")))
+    }
+}
+
+fn run(events: Receiver<ProgressEvent>, control: TransferControl) -> io::Result<()> {
+    enable_raw_mode()?;
+    execute!(io::stderr(), EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stderr()))?;
+
+    let result = event_loop(&mut terminal, &events, &control);
+
+    disable_raw_mode()?;
+    execute!(io::stderr(), LeaveAlternateScreen)?;
+    result
+}
+
+fn event_loop(
+    terminal: &mut Terminal<CrosstermBackend<io::Stderr>>,
+    events: &Receiver<ProgressEvent>,
+    control: &TransferControl,
+) -> io::Result<()> {
+    let mut state = DashboardState::default();
+
+    loop {
+        let finished = loop {
+            match events.try_recv() {
+                Ok(event) => state.apply(event),
+                Err(TryRecvError::Empty) => break false,
+                Err(TryRecvError::Disconnected) => break true,
+            }
+        };
+
+        terminal.draw(|frame| render(frame, &state, control))?;
+        if finished {
+            return Ok(());
+        }
+
+        if event::poll(REFRESH_INTERVAL)?
+            && let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            match key.code {
+                KeyCode::Char('p') | KeyCode::Char(' ') => control.toggle_pause(),
+                KeyCode::Char('s') => control.stop(),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    control.abort()
+                }
+                KeyCode::Char('q') | KeyCode::Esc => control.abort(),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Handle to the thread drawing the status line.
+pub struct StatusLine {
+    handle: JoinHandle<io::Result<()>>,
+}
+
+impl StatusLine {
+    /// Starts drawing the status line and returns the reporter that feeds it.
+    ///
+    /// Fails if stderr is not a terminal that reports its cursor position.
+    /// The status line stops once every clone of the reporter has been
+    /// dropped, leaving its last state on the screen.
+    pub fn start() -> io::Result<(TuiReporter, StatusLine)> {
+        let terminal = Terminal::with_options(
+            CrosstermBackend::new(io::stderr()),
+            TerminalOptions {
+                viewport: Viewport::Inline(STATUS_HEIGHT),
+            },
+        )?;
+        let (tx, rx) = mpsc::channel();
+        let handle = thread::spawn(move || run_status(terminal, rx));
+        Ok((TuiReporter { events: tx }, StatusLine { handle }))
+    }
+
+    /// Waits for the status line to stop.
+    pub fn finish(self) -> io::Result<()> {
+        self.handle
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("status line thread panicked")))
+    }
+}
+
+fn run_status(
+    mut terminal: Terminal<CrosstermBackend<io::Stderr>>,
+    events: Receiver<ProgressEvent>,
+) -> io::Result<()> {
+    let mut state = StatusState::default();
+
+    loop {
+        let finished = match events.recv_timeout(REFRESH_INTERVAL) {
+            Ok(event) => {
+                state.apply(event);
+                // Take whatever else arrived, so busy exports redraw in batches
+                loop {
+                    match events.try_recv() {
+                        Ok(event) => state.apply(event),
+                        Err(TryRecvError::Empty) => break false,
+                        Err(TryRecvError::Disconnected) => break true,
+                    }
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => false,
+            Err(mpsc::RecvTimeoutError::Disconnected) => true,
+        };
+
+        for (line, color) in state.log.drain(..) {
+            terminal.insert_before(1, |buf| {
+                Paragraph::new(line)
+                    .style(Style::default().fg(color))
+                    .render(buf.area, buf)
+            })?;
+        }
+        terminal.draw(|frame| render_status(frame, &state))?;
+        if finished {
+            // Leave the cursor below the final status
+            eprintln!();
+            return Ok(());
+        }
+    }
+}
+
+/// What the status line shows, rebuilt from progress events.
+#[derive(Debug, Default)]
+struct StatusState {
+    deck: Option<DeckState>,
+    /// Recently processed words and whether they were added
+    recent: VecDeque<(String, bool)>,
+    /// Log lines not printed above the status line yet
+    log: Vec<(String, Color)>,
+}
+
+impl StatusState {
+    fn apply(&mut self, event: ProgressEvent) {
+        if let Some(message) = event.message() {
+            let color = match event {
+                ProgressEvent::WriteFailed { .. } => Color::Red,
+                ProgressEvent::MediaFailed { .. }
+                | ProgressEvent::CardSkipped { .. }
+                | ProgressEvent::Retrying { .. }
+                | ProgressEvent::Stopped { .. } => Color::Yellow,
+                ProgressEvent::Completed { .. } | ProgressEvent::Written => Color::Green,
+                _ => Color::Gray,
+            };
+            self.log
+                .extend(message.lines().map(|line| (line.to_string(), color)));
+        }
+
+        if let ProgressEvent::CardProcessed { word, added, .. } = &event {
+            if self.recent.len() == RECENT_WORDS {
+                self.recent.pop_front();
+            }
+            self.recent.push_back((word.clone(), *added));
+        }
+
+        // The status line shows the deck being exported
+        match (&event, &mut self.deck) {
+            (
+                ProgressEvent::Started {
+                    deck_id,
+                    page_limit,
+                },
+                _,
+            ) => self.deck = Some(DeckState::new(deck_id, *page_limit)),
+            (_, Some(deck)) => deck.apply(&event),
+            (_, None) => {}
+        }
+    }
+}
+
+fn render_status(frame: &mut Frame, state: &StatusState) {
+    let [table_area, words_area] =
+        Layout::vertical([Constraint::Length(2), Constraint::Length(1)]).areas(frame.area());
+
+    let Some(deck) = &state.deck else {
+        frame.render_widget(Paragraph::new("Starting export..."), frame.area());
+        return;
+    };
+    let pages = match deck.page_limit {
+        Some(limit) => format!("{}/{}", deck.pages, limit),
+        None => deck.pages.to_string(),
+    };
+    let status_color = match deck.status {
+        DeckStatus::Done => Color::Green,
+        DeckStatus::Failed => Color::Red,
+        DeckStatus::Paused | DeckStatus::Retrying => Color::Yellow,
+        _ => Color::Cyan,
+    };
+    let header = Row::new([
+        "Status",
+        "Pages",
+        "Cards",
+        "Cards/s",
+        "Duplicates",
+        "Errors",
+    ])
+    .style(Style::default().add_modifier(Modifier::BOLD));
+    let row = Row::new([
+        Cell::from(format!("{:?}", deck.status)).style(Style::default().fg(status_color)),
+        Cell::from(pages),
+        Cell::from(deck.added.to_string()),
+        Cell::from(format!("{:.1}", deck.throughput())),
+        Cell::from(format!("{:.1}%", deck.duplicate_rate() * 100.0)),
+        Cell::from(deck.errors.to_string()).style(Style::default().fg(if deck.errors > 0 {
+            Color::Yellow
+        } else {
+            Color::Reset
+        })),
+    ]);
+    let table = Table::new([row], [Constraint::Length(12); 6]).header(header);
+    frame.render_widget(table, table_area);
+
+    let mut words = vec![Span::styled(
+        "Recent: ",
+        Style::default().fg(Color::DarkGray),
+    )];
+    for (word, added) in &state.recent {
+        let color = if *added {
+            Color::Green
+        } else {
+            Color::DarkGray
+        };
+        words.push(Span::styled(word.clone(), Style::default().fg(color)));
+        words.push(Span::raw("  "));
+    }
+    frame.render_widget(Paragraph::new(Line::from(words)), words_area);
+}
+
+/// Where a deck export currently is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DeckStatus {
+    Fetching,
+    Paused,
+    Retrying,
+    Writing,
+    Done,
+    Failed,
+}
+
+/// Live counters for one exported deck.
+#[derive(Debug)]
+struct DeckState {
+    deck_id: String,
+    page_limit: Option<u32>,
+    pages: u32,
+    processed: usize,
+    added: usize,
+    duplicates: usize,
+    retries: u32,
+    errors: usize,
+    /// Bytes received from the API
+    bytes: u64,
+    status: DeckStatus,
+    started: Instant,
+}
+
+impl DeckState {
+    fn new(deck_id: &str, page_limit: Option<u32>) -> Self {
+        Self {
+            deck_id: deck_id.to_string(),
+            page_limit,
+            pages: 0,
+            processed: 0,
+            added: 0,
+            duplicates: 0,
+            retries: 0,
+            errors: 0,
+            bytes: 0,
+            status: DeckStatus::Fetching,
+            started: Instant::now(),
+        }
+    }
+
+    /// Updates the counters with an event of this deck's export.
+    fn apply(&mut self, event: &ProgressEvent) {
+        match *event {
+            ProgressEvent::CheckpointLoaded { pages, .. } => self.pages = pages,
+            ProgressEvent::PageFetched { page, .. } => {
+                self.pages = page;
+                self.status = DeckStatus::Fetching;
+            }
+            ProgressEvent::Downloaded { bytes, .. } => self.bytes = bytes,
+            ProgressEvent::CardProcessed {
+                processed,
+                total_cards,
+                duplicates,
+                ..
+            } => {
+                self.processed = processed;
+                self.added = total_cards;
+                self.duplicates = duplicates;
+            }
+            ProgressEvent::MediaFailed { .. } | ProgressEvent::CardSkipped { .. } => {
+                self.errors += 1
+            }
+            ProgressEvent::Paused => self.status = DeckStatus::Paused,
+            ProgressEvent::Resumed => self.status = DeckStatus::Fetching,
+            ProgressEvent::Retrying { .. } => {
+                self.retries += 1;
+                self.errors += 1;
+                self.status = DeckStatus::Retrying;
+            }
+            ProgressEvent::Writing => self.status = DeckStatus::Writing,
+            ProgressEvent::WriteFailed { .. } => {
+                self.errors += 1;
+                self.status = DeckStatus::Failed;
+            }
+            ProgressEvent::Completed { .. } => self.status = DeckStatus::Done,
+            _ => {}
+        }
+    }
+
+    fn throughput(&self) -> f64 {
+        let secs = self.started.elapsed().as_secs_f64();
+        if secs > 0.0 {
+            self.processed as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Share of the processed cards that repeated an earlier one.
+    fn duplicate_rate(&self) -> f64 {
+        if self.processed > 0 {
+            self.duplicates as f64 / self.processed as f64
+        } else {
+            0.0
+        }
+    }
+
+    fn ratio(&self) -> f64 {
+        match (self.status, self.page_limit) {
+            (DeckStatus::Done, _) => 1.0,
+            (_, Some(limit)) if limit > 0 => (self.pages as f64 / limit as f64).min(1.0),
+            _ => 0.0,
+        }
+    }
+}
+
+/// Everything the dashboard shows, rebuilt from progress events.
+#[derive(Debug, Default)]
+struct DashboardState {
+    decks: Vec<DeckState>,
+    log: VecDeque<String>,
+}
+
+impl DashboardState {
+    fn apply(&mut self, event: ProgressEvent) {
+        if let Some(message) = event.message() {
+            for line in message.lines() {
+                if self.log.len() == LOG_CAPACITY {
+                    self.log.pop_front();
+                }
+                self.log.push_back(line.to_string());
+            }
+        }
+
+        match &event {
+            ProgressEvent::Started {
+                deck_id,
+                page_limit,
+            } => self.decks.push(DeckState::new(deck_id, *page_limit)),
+            event => {
+                if let Some(deck) = self.decks.last_mut() {
+                    deck.apply(event);
+                }
+            }
+        }
+    }
+}
+
+fn render(frame: &mut Frame, state: &DashboardState, control: &TransferControl) {
+    let deck_rows = (state.decks.len().max(1) * 3) as u16;
+    let [decks_area, log_area, help_area] = Layout::vertical([
+        Constraint::Length(deck_rows),
+        Constraint::Min(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let rows =
+        Layout::vertical(vec![Constraint::Length(3); state.decks.len().max(1)]).split(decks_area);
+    for (deck, area) in state.decks.iter().zip(rows.iter()) {
+        let status = if control.is_paused() && deck.status == DeckStatus::Fetching {
+            DeckStatus::Paused
+        } else {
+            deck.status
+        };
+        let pages = match deck.page_limit {
+            Some(limit) => format!("{}/{}", deck.pages, limit),
+            None => deck.pages.to_string(),
+        };
+        let label = format!(
+            "{:?} | pages {} | added {} | duplicates {} | retries {} | errors {} | {:.1} cards/s | {}",
+            status,
+            pages,
+            deck.added,
+            deck.duplicates,
+            deck.retries,
+            deck.errors,
+            deck.throughput(),
+            format_bytes(deck.bytes)
+        );
+        let color = match status {
+            DeckStatus::Done => Color::Green,
+            DeckStatus::Failed => Color::Red,
+            DeckStatus::Paused | DeckStatus::Retrying => Color::Yellow,
+            _ => Color::Cyan,
+        };
+        let gauge = Gauge::default()
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!(" {} ", deck.deck_id)),
+            )
+            .gauge_style(Style::default().fg(color))
+            .ratio(deck.ratio())
+            .label(label);
+        frame.render_widget(gauge, *area);
+    }
+
+    let visible = log_area.height.saturating_sub(2) as usize;
+    let items: Vec<ListItem> = state
+        .log
+        .iter()
+        .skip(state.log.len().saturating_sub(visible))
+        .map(|line| ListItem::new(Line::from(line.as_str())))
+        .collect();
+    let log = List::new(items).block(Block::default().borders(Borders::ALL).title(" Log "));
+    frame.render_widget(log, log_area);
+
+    let help = Paragraph::new("p: pause/resume   s: stop and write what was fetched   q: abort")
+        .style(Style::default().fg(Color::DarkGray));
+    frame.render_widget(help, help_area);
+}
+
+/// Lets the user choose one of `decks` with the arrow keys, or by typing its
+/// number, and Enter.
+///
+/// Returns `None` if the user quits with Esc, `q` or Ctrl-C.
+pub fn pick_deck(decks: &[DeckSummary]) -> io::Result<Option<&DeckSummary>> {
+    enable_raw_mode()?;
+    execute!(io::stderr(), EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stderr()))?;
+
+    let result = picker_loop(&mut terminal, decks);
+
+    disable_raw_mode()?;
+    execute!(io::stderr(), LeaveAlternateScreen)?;
+    Ok(result?.map(|index| &decks[index]))
+}
+
+fn picker_loop(
+    terminal: &mut Terminal<CrosstermBackend<io::Stderr>>,
+    decks: &[DeckSummary],
+) -> io::Result<Option<usize>> {
+    let labels: Vec<String> = decks
+        .iter()
+        .enumerate()
+        .map(|(index, deck)| format!("{:>3}) {}", index + 1, deck_label(deck)))
+        .collect();
+    let mut picker = DeckPicker::new(decks.len());
+
+    loop {
+        terminal.draw(|frame| render_picker(frame, &labels, &picker))?;
+        if let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            let cancel = matches!(key.code, KeyCode::Esc | KeyCode::Char('q'))
+                || (key.code == KeyCode::Char('c')
+                    && key.modifiers.contains(KeyModifiers::CONTROL));
+            if cancel {
+                return Ok(None);
+            }
+            if let Some(index) = picker.key(key.code) {
+                return Ok(Some(index));
+            }
+        }
+    }
+}
+
+/// Selection state of the deck picker.
+#[derive(Debug)]
+struct DeckPicker {
+    len: usize,
+    selected: usize,
+    /// Digits typed so far, selecting the deck with that number
+    typed: String,
+}
+
+impl DeckPicker {
+    fn new(len: usize) -> Self {
+        Self {
+            len,
+            selected: 0,
+            typed: String::new(),
+        }
+    }
+
+    /// The typed deck number, if it is one.
+    fn number(&self) -> Option<usize> {
+        self.typed
+            .parse()
+            .ok()
+            .filter(|number| (1..=self.len).contains(number))
+    }
+
+    /// Applies a key press; returns the chosen index once Enter is pressed.
+    fn key(&mut self, code: KeyCode) -> Option<usize> {
+        match code {
+            KeyCode::Up => {
+                self.typed.clear();
+                self.selected = self.selected.saturating_sub(1);
+            }
+            KeyCode::Down => {
+                self.typed.clear();
+                self.selected = (self.selected + 1).min(self.len - 1);
+            }
+            KeyCode::Home => {
+                self.typed.clear();
+                self.selected = 0;
+            }
+            KeyCode::End => {
+                self.typed.clear();
+                self.selected = self.len - 1;
+            }
+            KeyCode::Char(digit) if digit.is_ascii_digit() => {
+                self.typed.push(digit);
+                // Start over with this digit once the number gets too large
+                if self.number().is_none() {
+                    self.typed = digit.to_string();
+                }
+                if let Some(number) = self.number() {
+                    self.selected = number - 1;
+                }
+            }
+            KeyCode::Backspace => {
+                self.typed.pop();
+            }
+            KeyCode::Enter => return Some(self.selected),
+            _ => {}
+        }
+        None
+    }
+}
+
+fn render_picker(frame: &mut Frame, labels: &[String], picker: &DeckPicker) {
+    let [list_area, help_area] =
+        Layout::vertical([Constraint::Min(3), Constraint::Length(1)]).areas(frame.area());
+
+    let items: Vec<ListItem> = labels
+        .iter()
+        .map(|label| ListItem::new(Line::from(label.as_str())))
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Deck to export "),
+        )
+        .highlight_style(Style::default().fg(Color::Black).bg(Color::Cyan))
+        .highlight_symbol("> ");
+    let mut state = ListState::default().with_selected(Some(picker.selected));
+    frame.render_stateful_widget(list, list_area, &mut state);
+
+    let help = Paragraph::new(format!(
+        "up/down or number: select {}  enter: export   q: quit",
+        picker.typed
+    ))
+    .style(Style::default().fg(Color::DarkGray));
+    frame.render_widget(help, help_area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deck_picker_keys() {
+        let mut picker = DeckPicker::new(12);
+        assert_eq!(picker.key(KeyCode::Up), None);
+        assert_eq!(picker.selected, 0);
+        picker.key(KeyCode::Down);
+        picker.key(KeyCode::Down);
+        assert_eq!(picker.selected, 2);
+        picker.key(KeyCode::End);
+        picker.key(KeyCode::Down);
+        assert_eq!(picker.selected, 11);
+
+        picker.key(KeyCode::Char('1'));
+        assert_eq!(picker.selected, 0);
+        picker.key(KeyCode::Char('0'));
+        assert_eq!(picker.selected, 9);
+        // 105 is out of range, so the 5 starts a new number
+        picker.key(KeyCode::Char('5'));
+        assert_eq!(picker.selected, 4);
+        assert_eq!(picker.key(KeyCode::Enter), Some(4));
+    }
+
+    #[test]
+    fn test_state_tracks_deck_progress() {
+        let mut state = DashboardState::default();
+        state.apply(ProgressEvent::Started {
+            deck_id: "deck".to_string(),
+            page_limit: Some(4),
+        });
+        state.apply(ProgressEvent::PageFetched {
+            page: 2,
+            cards: 100,
+        });
+        state.apply(ProgressEvent::Downloaded {
+            requests: 2,
+            bytes: 250_000,
+        });
+        state.apply(ProgressEvent::CardProcessed {
+            word: "hello".to_string(),
+            added: true,
+            processed: 150,
+            total_cards: 140,
+            duplicates: 10,
+            elapsed: Duration::ZERO,
+        });
+        state.apply(ProgressEvent::Retrying {
+            pages: 2,
+            error: "timeout".to_string(),
+            delay: Duration::ZERO,
+            attempt: 1,
+            max_retries: 3,
+        });
+
+        let deck = &state.decks[0];
+        assert_eq!(deck.pages, 2);
+        assert_eq!(deck.bytes, 250_000);
+        assert_eq!(deck.added, 140);
+        assert_eq!(deck.duplicates, 10);
+        assert_eq!(deck.retries, 1);
+        assert_eq!(deck.status, DeckStatus::Retrying);
+        assert_eq!(deck.ratio(), 0.5);
+        assert!(state.log.iter().any(|line| line.contains("timeout")));
+    }
+
+    #[test]
+    fn test_status_tracks_recent_words() {
+        let mut state = StatusState::default();
+        state.apply(ProgressEvent::Started {
+            deck_id: "deck".to_string(),
+            page_limit: None,
+        });
+        for (processed, word) in ["a", "b", "a", "c", "d", "e", "f"].iter().enumerate() {
+            state.apply(ProgressEvent::CardProcessed {
+                word: word.to_string(),
+                added: processed != 2,
+                processed: processed + 1,
+                total_cards: processed,
+                duplicates: 1,
+                elapsed: Duration::ZERO,
+            });
+        }
+        state.apply(ProgressEvent::Retrying {
+            pages: 1,
+            error: "timeout".to_string(),
+            delay: Duration::ZERO,
+            attempt: 1,
+            max_retries: 3,
+        });
+
+        let words: Vec<&str> = state.recent.iter().map(|(word, _)| word.as_str()).collect();
+        assert_eq!(words, vec!["a", "c", "d", "e", "f"]);
+        let deck = state.deck.as_ref().unwrap();
+        assert_eq!(deck.processed, 7);
+        assert_eq!(deck.duplicate_rate(), 1.0 / 7.0);
+        assert_eq!(deck.status, DeckStatus::Retrying);
+        assert!(
+            state
+                .log
+                .iter()
+                .any(|(line, color)| line.contains("timeout") && *color == Color::Yellow)
+        );
+    }
+
+    #[test]
+    fn test_log_is_bounded() {
+        let mut state = DashboardState::default();
+        for page in 0..(LOG_CAPACITY as u32 + 10) {
+            state.apply(ProgressEvent::FetchingPage { page });
+        }
+        assert_eq!(state.log.len(), LOG_CAPACITY);
+        assert_eq!(state.log.back().unwrap(), "Fetching page 509...");
+    }
+}
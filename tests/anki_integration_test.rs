@@ -14,6 +14,7 @@ fn create_test_card(
         translation: translation.to_string(),
         example: example.map(|s| s.to_string()),
         status,
+        ..Default::default()
     }
 }
 
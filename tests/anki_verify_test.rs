@@ -0,0 +1,95 @@
+use duoload::anki::note::{DEFAULT_TAG_PREFIX, TagScheme};
+use duoload::anki::verify::verify_package;
+use duoload::duocards::models::{LearningStatus, VocabularyCard};
+use duoload::output::anki::AnkiPackageBuilder;
+use duoload::output::{OutputBuilder, OutputDestination};
+use rusqlite::Connection;
+use std::io::Write;
+use tempfile::tempdir;
+
+fn create_test_card(word: &str, translation: &str, status: LearningStatus) -> VocabularyCard {
+    VocabularyCard {
+        word: word.to_string(),
+        translation: translation.to_string(),
+        status,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_verify_exported_package() {
+    let mut builder = AnkiPackageBuilder::new("German")
+        .with_status_fields(true)
+        .with_hint_field(true)
+        .with_status_subdecks(true);
+    builder
+        .add_note(create_test_card("Haus", "house", LearningStatus::New))
+        .unwrap();
+    builder
+        .add_note(create_test_card("Baum", "tree", LearningStatus::Known))
+        .unwrap();
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("deck.apkg");
+    builder.write(OutputDestination::File(&path)).unwrap();
+
+    let report = verify_package(&path, DEFAULT_TAG_PREFIX).unwrap();
+    assert!(report.is_valid(), "{}", report);
+    assert_eq!(report.notes, 2);
+    assert_eq!(report.cards, 2);
+    assert!(report.decks.contains(&"German::Known".to_string()));
+    assert_eq!(report.note_types, ["Duoload Vocabulary"]);
+}
+
+#[test]
+fn test_verify_reports_other_tags() {
+    let mut builder = AnkiPackageBuilder::new("German").with_tag_scheme(TagScheme {
+        prefix: "vocab".to_string(),
+        ..TagScheme::default()
+    });
+    builder
+        .add_note(create_test_card("Haus", "house", LearningStatus::New))
+        .unwrap();
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("deck.apkg");
+    builder.write(OutputDestination::File(&path)).unwrap();
+
+    let report = verify_package(&path, DEFAULT_TAG_PREFIX).unwrap();
+    assert_eq!(report.problems.len(), 1);
+    assert!(report.problems[0].contains("duoload_*"), "{}", report);
+    assert!(verify_package(&path, "vocab").unwrap().is_valid());
+    assert!(verify_package(&path, "").unwrap().is_valid());
+}
+
+#[test]
+fn test_verify_reports_incomplete_schema() {
+    let dir = tempdir().unwrap();
+    let database = dir.path().join("collection.anki2");
+    let connection = Connection::open(&database).unwrap();
+    connection
+        .execute_batch("CREATE TABLE notes (id INTEGER PRIMARY KEY, guid TEXT, flds TEXT);")
+        .unwrap();
+    drop(connection);
+
+    let path = dir.path().join("broken.apkg");
+    let mut archive = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+    archive
+        .start_file("collection.anki2", zip::write::FileOptions::default())
+        .unwrap();
+    archive
+        .write_all(&std::fs::read(&database).unwrap())
+        .unwrap();
+    archive.finish().unwrap();
+
+    let report = verify_package(&path, DEFAULT_TAG_PREFIX).unwrap();
+    assert!(!report.is_valid());
+    assert!(
+        report
+            .problems
+            .contains(&"The collection has no col table".to_string())
+    );
+    assert!(
+        report
+            .problems
+            .contains(&"The notes table has no mid column".to_string())
+    );
+}
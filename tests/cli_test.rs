@@ -0,0 +1,160 @@
+use duoload::duocards::models::VocabularyCard;
+use duoload::snapshot::{SNAPSHOT_FORMAT_VERSION, Snapshot, SnapshotMetadata};
+use duoload::testing::FixtureDeck;
+use std::path::Path;
+use std::process::{Command, Output};
+use tempfile::tempdir;
+
+/// Runs the duoload binary on the cards of a local JSON file.
+fn run_from_json(dir: &Path, args: &[&str]) -> Output {
+    let input = dir.join("cards.json");
+    let cards = vec![VocabularyCard {
+        word: "Haus".to_string(),
+        translation: "house".to_string(),
+        ..Default::default()
+    }];
+    std::fs::write(&input, serde_json::to_string(&cards).unwrap()).unwrap();
+
+    Command::new(env!("CARGO_BIN_EXE_duoload"))
+        .current_dir(dir)
+        .arg("--from-json")
+        .arg(&input)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// Writes a snapshot bundle of a small deck to `dir`.
+fn write_bundle(dir: &Path) -> std::path::PathBuf {
+    let deck = FixtureDeck::generate(3);
+    let page = serde_json::to_value(deck.page(None, 100)).unwrap();
+    let snapshot = Snapshot {
+        metadata: SnapshotMetadata {
+            format_version: SNAPSHOT_FORMAT_VERSION,
+            deck_id: deck.id.clone(),
+            created_at: "2025-06-01T00:00:00Z".to_string(),
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            pages: 1,
+            complete: true,
+        },
+        pages: vec![page],
+        cards: Vec::new(),
+    };
+    let path = dir.join("deck.duoload");
+    snapshot.write(&path).unwrap();
+    path
+}
+
+fn restore(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_duoload"))
+        .current_dir(dir)
+        .arg("restore")
+        .arg(write_bundle(dir))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_restore_keeps_existing_output() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("deck.json");
+    std::fs::write(&path, "earlier export").unwrap();
+
+    let output = restore(dir.path(), &["--to", "json", "--output", "deck.json"]);
+    assert!(!output.status.success());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "earlier export");
+
+    let output = restore(
+        dir.path(),
+        &["--to", "json", "--output", "deck.json", "--force"],
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let cards: Vec<VocabularyCard> =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(cards.len(), 3);
+    // Nothing but the restored file is left behind
+    let mut names: Vec<_> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["deck.duoload", "deck.json"]);
+}
+
+#[test]
+fn test_restore_to_apkg() {
+    let dir = tempdir().unwrap();
+    let output = restore(dir.path(), &["--to", "apkg", "--output", "deck.apkg"]);
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(dir.path().join("deck.apkg").exists());
+}
+
+#[test]
+fn test_encrypt_rejects_yaml_output() {
+    let dir = tempdir().unwrap();
+    let output = run_from_json(
+        dir.path(),
+        &["--yaml-file", "deck.yaml", "--encrypt", "gpg:alice"],
+    );
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("only supported for JSON and CSV"),
+        "{}",
+        stderr
+    );
+    assert!(!dir.path().join("deck.yaml").exists());
+}
+
+#[test]
+fn test_encrypt_json_output() {
+    let dir = tempdir().unwrap();
+    let recipient = age::x25519::Identity::generate().to_public();
+    let output = run_from_json(
+        dir.path(),
+        &[
+            "--json-file",
+            "deck.json",
+            "--encrypt",
+            &format!("age:{}", recipient),
+        ],
+    );
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let written = std::fs::read(dir.path().join("deck.json")).unwrap();
+    assert!(written.starts_with(b"age-encryption.org/v1"));
+}
+
+#[test]
+fn test_watch_requires_dated() {
+    let output = Command::new(env!("CARGO_BIN_EXE_duoload"))
+        .args([
+            "--deck-id",
+            "deck",
+            "--json-file",
+            "deck.json",
+            "--watch",
+            "60",
+        ])
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--dated"), "{}", stderr);
+}
@@ -1,7 +1,12 @@
+use duoload::duocards::auth::{AuthError, Credentials};
+use duoload::duocards::cache::PageCache;
 use duoload::duocards::client::DuocardsClient;
-use duoload::duocards::models::LearningStatus;
+use duoload::duocards::models::{Card, LearningStatus, VocabularyCard};
+use duoload::duocards::retry::RetryPolicy;
+use duoload::error::DuoloadError;
 use mockito::Server;
 use serde_json::json;
+use std::time::Duration;
 use tokio_test::block_on;
 
 // Valid test deck ID (base64 encoded "Deck:46f2b9ed-abf3-4bd8-a054-68dfa4a4203e")
@@ -20,7 +25,8 @@ fn create_mock_response() -> serde_json::Value {
                                 "front": "hello",
                                 "back": "hola",
                                 "hint": "Hello, world!",
-                                "waiting": null,
+                                "createdAt": "2025-05-30T18:02:11Z",
+                                "waiting": 1748851200000u64,
                                 "knownCount": 5,
                                 "svg": null,
                                 "__typename": "Card"
@@ -96,6 +102,111 @@ fn test_convert_to_vocabulary_cards() {
     assert_eq!(cards[0].translation, "hola");
     assert_eq!(cards[0].example, Some("Hello, world!".to_string()));
     assert!(matches!(cards[0].status, LearningStatus::Known));
+    assert_eq!(cards[0].source_id, Some("test-id".to_string()));
+    assert_eq!(
+        cards[0].created_at,
+        Some("2025-05-30T18:02:11Z".to_string())
+    );
+    assert_eq!(
+        cards[0].waiting_until.map(|time| time.rfc3339()),
+        Some("2025-06-02T08:00:00Z".to_string())
+    );
+}
+
+#[test]
+fn test_waiting_time_forms() {
+    let card = |waiting: serde_json::Value| -> Card {
+        serde_json::from_value(json!({
+            "id": "Q2FyZDox", "front": "hello", "back": "hola", "hint": null,
+            "waiting": waiting, "knownCount": 0, "svg": null, "__typename": "Card"
+        }))
+        .unwrap()
+    };
+    let expected = Some("2025-06-02T08:00:00Z".to_string());
+    for waiting in [
+        json!("2025-06-02T08:00:00.000Z"),
+        json!("2025-06-02T10:00:00+02:00"),
+        json!(1748851200),
+    ] {
+        let card = VocabularyCard::from(card(waiting));
+        assert_eq!(card.waiting_until.map(|time| time.rfc3339()), expected);
+        // Exported in RFC 3339 form
+        let json = serde_json::to_value(&card).unwrap();
+        assert_eq!(json["waiting_until"], "2025-06-02T08:00:00Z");
+    }
+    assert_eq!(card(json!(null)).waiting_until(), None);
+    assert_eq!(card(json!("later")).waiting_until(), None);
+}
+
+#[test]
+fn test_cached_pages_are_replayed_offline() {
+    let mut server = Server::new();
+    let mock = server
+        .mock("POST", "/graphql")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(create_mock_response().to_string())
+        .expect(1)
+        .create();
+    let dir = tempfile::tempdir().unwrap();
+
+    let mut client = DuocardsClient::new()
+        .unwrap()
+        .with_cache(PageCache::new(dir.path()));
+    client.base_url = server.url() + "/graphql";
+    let fetched = block_on(client.fetch_page(TEST_DECK_ID, None)).unwrap();
+
+    let mut offline = DuocardsClient::new()
+        .unwrap()
+        .with_cache(PageCache::new(dir.path()).with_offline(true));
+    offline.base_url = server.url() + "/graphql";
+    let replayed = block_on(offline.fetch_page(TEST_DECK_ID, None)).unwrap();
+
+    mock.assert();
+    assert_eq!(
+        serde_json::to_value(&replayed).unwrap(),
+        serde_json::to_value(&fetched).unwrap()
+    );
+    match block_on(offline.fetch_page(TEST_DECK_ID, Some("0".to_string()))) {
+        Err(DuoloadError::Cache(message)) => assert!(message.contains("\"0\""), "{}", message),
+        other => panic!("Expected a cache miss, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_cached_pages_are_revalidated() {
+    let mut server = Server::new();
+    let first = server
+        .mock("POST", "/graphql")
+        .match_header("if-none-match", mockito::Matcher::Missing)
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_header("etag", "\"v1\"")
+        .with_body(create_mock_response().to_string())
+        .expect(1)
+        .create();
+    let unchanged = server
+        .mock("POST", "/graphql")
+        .match_header("if-none-match", "\"v1\"")
+        .with_status(304)
+        .expect(1)
+        .create();
+    let dir = tempfile::tempdir().unwrap();
+
+    let mut client = DuocardsClient::new()
+        .unwrap()
+        .with_cache(PageCache::new(dir.path()).with_revalidation(true));
+    client.base_url = server.url() + "/graphql";
+    let fetched = block_on(client.fetch_page(TEST_DECK_ID, None)).unwrap();
+    let replayed = block_on(client.fetch_page(TEST_DECK_ID, None)).unwrap();
+
+    first.assert();
+    unchanged.assert();
+    assert_eq!(
+        serde_json::to_value(&replayed).unwrap(),
+        serde_json::to_value(&fetched).unwrap()
+    );
+    assert_eq!(client.request_stats().requests(), 2);
 }
 
 #[test]
@@ -140,3 +251,363 @@ fn test_page_limit_with_fetch() {
     mock.assert();
     assert_eq!(response.data.node.id, TEST_DECK_ID);
 }
+
+#[test]
+fn test_persisted_query_fallback() {
+    let mut server = Server::new();
+    let hash_only = server
+        .mock("POST", "/graphql")
+        .match_body(mockito::Matcher::Regex(r#"^\{"operationName""#.to_string()))
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(json!({"errors": [{"message": "PersistedQueryNotFound"}]}).to_string())
+        .expect(1)
+        .create();
+    let full = server
+        .mock("POST", "/graphql")
+        .match_body(mockito::Matcher::Regex(r#""query":"#.to_string()))
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(create_mock_response().to_string())
+        .expect(1)
+        .create();
+
+    let mut client = DuocardsClient::new().unwrap().with_persisted_queries(true);
+    client.base_url = server.url() + "/graphql";
+
+    let response = block_on(client.fetch_page(TEST_DECK_ID, None)).unwrap();
+
+    hash_only.assert();
+    full.assert();
+    assert_eq!(response.data.node.cards.edges.len(), 1);
+}
+
+#[test]
+fn test_graphql_errors_are_reported() {
+    let mut server = Server::new();
+    server
+        .mock("POST", "/graphql")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(json!({"errors": [{"message": "Deck not found"}]}).to_string())
+        .create();
+
+    let mut client = DuocardsClient::new().unwrap();
+    client.base_url = server.url() + "/graphql";
+
+    let err = block_on(client.fetch_page(TEST_DECK_ID, None)).unwrap_err();
+    assert!(err.to_string().contains("Deck not found"));
+}
+
+#[test]
+fn test_retries_transient_errors() {
+    let mut server = Server::new();
+    let unavailable = server
+        .mock("POST", "/graphql")
+        .with_status(503)
+        .with_body("Service Unavailable")
+        .expect(1)
+        .create();
+    let ok = server
+        .mock("POST", "/graphql")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(create_mock_response().to_string())
+        .expect(1)
+        .create();
+
+    let mut client = DuocardsClient::new()
+        .unwrap()
+        .with_retry_policy(RetryPolicy::new(2, Duration::ZERO));
+    client.base_url = server.url() + "/graphql";
+
+    let response = block_on(client.fetch_page(TEST_DECK_ID, None)).unwrap();
+    unavailable.assert();
+    ok.assert();
+    assert_eq!(response.data.node.id, TEST_DECK_ID);
+
+    let stats = client.request_stats();
+    assert_eq!(stats.requests(), 2);
+    assert_eq!(stats.retries(), 1);
+    assert_eq!(
+        stats.bytes(),
+        ("Service Unavailable".len() + create_mock_response().to_string().len()) as u64
+    );
+}
+
+#[test]
+fn test_byte_limit_stops_requests() {
+    let body = create_mock_response().to_string();
+    let mut server = Server::new();
+    let mock = server
+        .mock("POST", "/graphql")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(&body)
+        .expect(2)
+        .create();
+
+    let mut client = DuocardsClient::new()
+        .unwrap()
+        .with_max_bytes(body.len() as u64 + 1);
+    client.base_url = server.url() + "/graphql";
+
+    block_on(client.fetch_page(TEST_DECK_ID, None)).unwrap();
+    assert_eq!(client.request_stats().bytes(), body.len() as u64);
+    // The second response crosses the limit and is still used
+    block_on(client.fetch_page(TEST_DECK_ID, None)).unwrap();
+    let err = block_on(client.fetch_page(TEST_DECK_ID, None)).unwrap_err();
+    mock.assert();
+    match err {
+        DuoloadError::ByteLimit { limit, downloaded } => {
+            assert_eq!(limit, body.len() as u64 + 1);
+            assert_eq!(downloaded, 2 * body.len() as u64);
+        }
+        err => panic!("Expected ByteLimit, got {:?}", err),
+    }
+}
+
+#[test]
+fn test_gives_up_after_retries() {
+    let mut server = Server::new();
+    let mock = server
+        .mock("POST", "/graphql")
+        .with_status(429)
+        .with_body("Too Many Requests")
+        .expect(3)
+        .create();
+
+    let mut client = DuocardsClient::new()
+        .unwrap()
+        .with_retry_policy(RetryPolicy::new(2, Duration::ZERO));
+    client.base_url = server.url() + "/graphql";
+
+    let err = block_on(client.fetch_page(TEST_DECK_ID, None)).unwrap_err();
+    mock.assert();
+    assert!(err.to_string().contains("429"));
+}
+
+#[test]
+fn test_client_errors_are_not_retried() {
+    let mut server = Server::new();
+    let mock = server
+        .mock("POST", "/graphql")
+        .with_status(400)
+        .with_body("Bad Request")
+        .expect(1)
+        .create();
+
+    let mut client = DuocardsClient::new()
+        .unwrap()
+        .with_retry_policy(RetryPolicy::new(2, Duration::ZERO));
+    client.base_url = server.url() + "/graphql";
+
+    assert!(block_on(client.fetch_page(TEST_DECK_ID, None)).is_err());
+    mock.assert();
+}
+
+#[test]
+fn test_rate_limit_headers_slow_down() {
+    let mut server = Server::new();
+    let _mock = server
+        .mock("POST", "/graphql")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_header("x-ratelimit-limit", "100")
+        .with_header("x-ratelimit-remaining", "0")
+        .with_header("x-ratelimit-reset", "20")
+        .with_body(create_mock_response().to_string())
+        .create();
+
+    let mut client = DuocardsClient::new().unwrap();
+    client.base_url = server.url() + "/graphql";
+    assert_eq!(client.throttle_delay(), None);
+
+    block_on(client.fetch_page(TEST_DECK_ID, None)).unwrap();
+    let delay = client.throttle_delay().unwrap();
+    assert!(delay > Duration::from_secs(19) && delay <= Duration::from_secs(20));
+}
+
+#[test]
+fn test_sends_credentials() {
+    let mut server = Server::new();
+    let mock = server
+        .mock("POST", "/graphql")
+        .match_header("cookie", "session=abc")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(create_mock_response().to_string())
+        .create();
+
+    let mut client = DuocardsClient::new()
+        .unwrap()
+        .with_credentials(Credentials::cookie("session=abc").unwrap());
+    client.base_url = server.url() + "/graphql";
+
+    block_on(client.fetch_page(TEST_DECK_ID, None)).unwrap();
+    mock.assert();
+}
+
+#[test]
+fn test_sends_cookies_from_jar() {
+    let mut server = Server::new();
+    let mock = server
+        .mock("POST", "/graphql")
+        .match_header("cookie", "session=abc; theme=dark")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(create_mock_response().to_string())
+        .create();
+
+    let url = server.url() + "/graphql";
+    let jar = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(
+        jar.path(),
+        "# Netscape HTTP Cookie File\n\
+         127.0.0.1\tFALSE\t/\tFALSE\t0\tsession\tabc\n\
+         #HttpOnly_127.0.0.1\tFALSE\t/graphql\tFALSE\t0\ttheme\tdark\n\
+         127.0.0.1\tFALSE\t/other\tFALSE\t0\tunrelated\tx\n\
+         127.0.0.1\tFALSE\t/\tTRUE\t0\tsecure\ty\n",
+    )
+    .unwrap();
+    let mut client = DuocardsClient::new()
+        .unwrap()
+        .with_credentials(Credentials::cookie_file(jar.path(), &url).unwrap());
+    client.base_url = url;
+
+    block_on(client.fetch_page(TEST_DECK_ID, None)).unwrap();
+    mock.assert();
+
+    // No cookie of the jar is for the API
+    assert!(matches!(
+        Credentials::cookie_file(jar.path(), "https://api.duocards.com/graphql"),
+        Err(AuthError::NoCookies { .. })
+    ));
+}
+
+#[test]
+fn test_rejected_credentials() {
+    let mut server = Server::new();
+    let mock = server
+        .mock("POST", "/graphql")
+        .with_status(401)
+        .expect(2)
+        .create();
+
+    // Not retried, and reported according to the credentials used
+    let mut client = DuocardsClient::new()
+        .unwrap()
+        .with_credentials(Credentials::cookie("session=expired").unwrap());
+    client.base_url = server.url() + "/graphql";
+    let err = block_on(client.fetch_page(TEST_DECK_ID, None)).unwrap_err();
+    assert!(matches!(err, DuoloadError::Auth(AuthError::InvalidCookie)));
+
+    let mut client = DuocardsClient::new().unwrap();
+    client.base_url = server.url() + "/graphql";
+    let err = block_on(client.fetch_page(TEST_DECK_ID, None)).unwrap_err();
+    assert!(matches!(
+        err,
+        DuoloadError::Auth(AuthError::Unauthenticated)
+    ));
+    mock.assert();
+}
+
+#[test]
+fn test_ping_with_rejected_credentials() {
+    let mut server = Server::new();
+    let mock = server
+        .mock("POST", "/graphql")
+        .match_body(mockito::Matcher::Regex(
+            r#""operationName":"pingQuery""#.to_string(),
+        ))
+        .with_status(401)
+        .create();
+
+    let mut client = DuocardsClient::new()
+        .unwrap()
+        .with_credentials(Credentials::token("expired").unwrap());
+    client.base_url = server.url() + "/graphql";
+
+    let status = block_on(client.ping()).unwrap();
+    assert_eq!(status.authenticated, Some(false));
+    assert_eq!(status.release_id, None);
+    mock.assert();
+}
+
+#[test]
+fn test_list_decks() {
+    let mut server = Server::new();
+    let mock = server
+        .mock("POST", "/graphql")
+        .match_body(mockito::Matcher::Regex(r#""operationName":"decksQuery""#.to_string()))
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(
+            json!({"data": {"viewer": {"__typename": "User", "id": "user", "decks": [
+                {"id": TEST_DECK_ID, "name": "German", "front": "de", "back": "en", "__typename": "Deck"}
+            ]}}})
+            .to_string(),
+        )
+        .create();
+
+    let mut client = DuocardsClient::new()
+        .unwrap()
+        .with_credentials(Credentials::token("abc").unwrap());
+    client.base_url = server.url() + "/graphql";
+
+    let decks = block_on(client.list_decks()).unwrap();
+    mock.assert();
+    assert_eq!(decks.len(), 1);
+    assert_eq!(decks[0].id, TEST_DECK_ID);
+    assert_eq!(decks[0].name, "German");
+    assert_eq!(decks[0].front.as_deref(), Some("de"));
+}
+
+#[test]
+fn test_fetch_deck_summary() {
+    let mut server = Server::new();
+    let mock = server
+        .mock("POST", "/graphql")
+        .match_body(mockito::Matcher::Regex(
+            r#""operationName":"deckSummaryQuery""#.to_string(),
+        ))
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(
+            json!({"data": {"node": {
+                "__typename": "Deck", "id": TEST_DECK_ID, "name": "German",
+                "front": "de", "back": "en", "cardsCount": 250
+            }}})
+            .to_string(),
+        )
+        .create();
+
+    let mut client = DuocardsClient::new().unwrap();
+    client.base_url = server.url() + "/graphql";
+
+    let deck = block_on(client.fetch_deck_summary(TEST_DECK_ID)).unwrap();
+    mock.assert();
+    assert_eq!(deck.name.as_deref(), Some("German"));
+    assert_eq!(deck.cards_count, 250);
+    assert_eq!(deck.pages(), 3);
+}
+
+#[test]
+fn test_list_decks_requires_login() {
+    let mut server = Server::new();
+    server
+        .mock("POST", "/graphql")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(json!({"data": {"viewer": null}}).to_string())
+        .create();
+
+    let mut client = DuocardsClient::new().unwrap();
+    client.base_url = server.url() + "/graphql";
+
+    let err = block_on(client.list_decks()).unwrap_err();
+    assert!(matches!(
+        err,
+        DuoloadError::Auth(AuthError::Unauthenticated)
+    ));
+}
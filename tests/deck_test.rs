@@ -1,5 +1,5 @@
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
-use duoload::duocards::deck::validate_deck_id;
+use duoload::duocards::deck::{parse_deck_id, validate_deck_id};
 use duoload::error::{DeckIdError, DuoloadError};
 
 // Valid test deck ID (base64 encoded "Deck:46f2b9ed-abf3-4bd8-a054-68dfa4a4203e")
@@ -38,3 +38,37 @@ fn test_validate_deck_id() {
         _ => panic!("Expected NotUuidV4 error"),
     }
 }
+
+#[test]
+fn test_parse_deck_id() {
+    let uuid = "46f2b9ed-abf3-4bd8-a054-68dfa4a4203e";
+
+    assert_eq!(parse_deck_id(TEST_DECK_ID).unwrap(), TEST_DECK_ID);
+    assert_eq!(
+        parse_deck_id(&format!(" {} ", TEST_DECK_ID)).unwrap(),
+        TEST_DECK_ID
+    );
+    assert_eq!(parse_deck_id(uuid).unwrap(), TEST_DECK_ID);
+    assert_eq!(
+        parse_deck_id(&format!("https://app.duocards.com/deck/{}", uuid)).unwrap(),
+        TEST_DECK_ID
+    );
+    assert_eq!(
+        parse_deck_id(&format!(
+            "https://app.duocards.com/share?deckId={}&lang=en",
+            TEST_DECK_ID.replace('=', "%3D")
+        ))
+        .unwrap(),
+        TEST_DECK_ID
+    );
+
+    match parse_deck_id("https://app.duocards.com/deck/") {
+        Err(DuoloadError::DeckId(DeckIdError::InvalidFormat(_))) => (),
+        _ => panic!("Expected InvalidFormat error"),
+    }
+    // UUIDs other than v4 are rejected like their base64 form
+    match parse_deck_id("46f2b9ed-abf3-1bd8-a054-68dfa4a4203e") {
+        Err(DuoloadError::DeckId(DeckIdError::NotUuidV4(_))) => (),
+        _ => panic!("Expected NotUuidV4 error"),
+    }
+}
@@ -0,0 +1,116 @@
+use duoload::error::DuoloadError;
+use duoload::output::markdown::{MarkdownOutputBuilder, MarkdownStyle};
+use duoload::output::registry::{OutputRegistry, RegisteredFormat};
+use duoload::testing::{FixtureDeck, MockDuocardsServer};
+use duoload::transfer::progress::ProgressReporters;
+use duoload::{Duoload, Output};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::tempdir;
+
+#[tokio::test]
+async fn test_export_through_facade() {
+    let deck = FixtureDeck::generate(150).with_duplicate("word3");
+    let server = MockDuocardsServer::start(vec![deck.clone()]).await.unwrap();
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("deck.json");
+
+    let stats = Duoload::builder()
+        .client(server.client().unwrap())
+        .deck_id(deck.id.clone())
+        .output(Output::new("json", &path))
+        .page_delay(Duration::ZERO)
+        .reporter(Arc::new(ProgressReporters::default()))
+        .build()
+        .unwrap()
+        .run()
+        .await
+        .unwrap();
+
+    assert_eq!(stats.total_cards, 150);
+    assert_eq!(stats.duplicates, 1);
+    let cards: Vec<Value> = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(cards.len(), 150);
+    assert_eq!(cards[3]["word"], "word3");
+}
+
+#[tokio::test]
+async fn test_facade_page_limit_to_anki() {
+    let deck = FixtureDeck::generate(250);
+    let server = MockDuocardsServer::start(vec![deck.clone()]).await.unwrap();
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("deck.apkg");
+
+    let stats = Duoload::builder()
+        .client(server.client().unwrap())
+        .deck_id(deck.id.clone())
+        .output(Output::new("anki", &path))
+        .page_limit(1)
+        .reporter(Arc::new(ProgressReporters::default()))
+        .build()
+        .unwrap()
+        .run()
+        .await
+        .unwrap();
+
+    assert_eq!(stats.total_cards, 100);
+    assert_eq!(server.request_count(), 1);
+    assert!(path.exists());
+}
+
+#[test]
+fn test_facade_requires_deck_and_output() {
+    assert!(matches!(
+        Duoload::builder().output(Output::new("json", "-")).build(),
+        Err(DuoloadError::MissingSetting("deck ID"))
+    ));
+    assert!(matches!(
+        Duoload::builder().deck_id("not a deck").build(),
+        Err(DuoloadError::DeckId(_))
+    ));
+    assert!(matches!(
+        Duoload::builder()
+            .deck_id(duoload::testing::random_deck_id())
+            .build(),
+        Err(DuoloadError::MissingSetting("output"))
+    ));
+    assert!(matches!(
+        Duoload::builder()
+            .deck_id(duoload::testing::random_deck_id())
+            .output(Output::new("toml", "-"))
+            .build(),
+        Err(DuoloadError::Format(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_facade_registered_format() {
+    let deck = FixtureDeck::generate(20);
+    let server = MockDuocardsServer::start(vec![deck.clone()]).await.unwrap();
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("deck.md");
+    let mut registry = OutputRegistry::new();
+    registry
+        .register(RegisteredFormat::new("words", "Word list", |_| {
+            MarkdownOutputBuilder::new().with_style(MarkdownStyle::Obsidian)
+        }))
+        .unwrap();
+
+    Duoload::builder()
+        .client(server.client().unwrap())
+        .deck_id(deck.id.clone())
+        .output(Output::new("words", &path))
+        .registry(registry)
+        .page_delay(Duration::ZERO)
+        .reporter(Arc::new(ProgressReporters::default()))
+        .build()
+        .unwrap()
+        .run()
+        .await
+        .unwrap();
+
+    let written = std::fs::read_to_string(&path).unwrap();
+    assert!(written.contains("word0"), "{}", written);
+    assert!(!written.contains("| Word |"), "{}", written);
+}
@@ -0,0 +1,108 @@
+use duoload::duocards::FileSourceClient;
+use duoload::duocards::models::{LearningStatus, VocabularyCard};
+use duoload::output::anki::{AnkiPackageBuilder, DEFAULT_DECK_NAME};
+use duoload::output::csv::CsvOutputBuilder;
+use duoload::output::json::JsonOutputBuilder;
+use duoload::output::{OutputBuilder, OutputDestination};
+use duoload::transfer::processor::TransferProcessor;
+use duoload::transform::TransformPipeline;
+use duoload::transform::sanitize::SanitizeOptions;
+use std::time::Duration;
+use tempfile::tempdir;
+
+fn create_test_card(word: &str, translation: &str, status: LearningStatus) -> VocabularyCard {
+    VocabularyCard {
+        word: word.to_string(),
+        translation: translation.to_string(),
+        example: Some(format!("An example with {}", word)),
+        status,
+        ..Default::default()
+    }
+}
+
+fn test_cards() -> Vec<VocabularyCard> {
+    (0..250)
+        .map(|i| {
+            create_test_card(
+                &format!("word{}", i),
+                &format!("palabra{}", i),
+                LearningStatus::Learning,
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_json_dump_to_anki_package() {
+    let dir = tempdir().unwrap();
+    let dump = dir.path().join("dump.json");
+    let mut json = JsonOutputBuilder::new();
+    for card in test_cards() {
+        json.add_note(card).unwrap();
+    }
+    json.write(OutputDestination::File(&dump)).unwrap();
+
+    let client = FileSourceClient::from_json(&dump).unwrap();
+    assert_eq!(client.len(), 250);
+    let apkg = dir.path().join("deck.apkg");
+    let mut processor = TransferProcessor::new(client, "dump".to_string())
+        .output(AnkiPackageBuilder::new(DEFAULT_DECK_NAME), &apkg)
+        .with_page_delay(Duration::ZERO)
+        .with_verification(true);
+    processor.process().await.unwrap();
+
+    assert_eq!(processor.stats().total_cards, 250);
+}
+
+#[tokio::test]
+async fn test_csv_dump_round_trips_to_json() {
+    let dir = tempdir().unwrap();
+    let dump = dir.path().join("dump.csv");
+    let mut csv = CsvOutputBuilder::new();
+    for card in test_cards() {
+        csv.add_note(card).unwrap();
+    }
+    csv.write(OutputDestination::File(&dump)).unwrap();
+
+    let client = FileSourceClient::from_csv(&dump)
+        .unwrap()
+        .with_page_limit(2);
+    let json_path = dir.path().join("cards.json");
+    let mut processor = TransferProcessor::new(client, "dump".to_string())
+        .output(JsonOutputBuilder::new(), &json_path)
+        .with_page_delay(Duration::ZERO);
+    processor.process().await.unwrap();
+
+    let cards: Vec<VocabularyCard> =
+        serde_json::from_str(&std::fs::read_to_string(&json_path).unwrap()).unwrap();
+    assert_eq!(cards, test_cards()[..200]);
+}
+
+#[tokio::test]
+async fn test_sanitized_cards_are_deduplicated() {
+    let dir = tempdir().unwrap();
+    let mut marked_up = create_test_card("<b>Haus</b>", " house&nbsp;", LearningStatus::New);
+    marked_up.example = Some("<br>".to_string());
+    let client = FileSourceClient::new(vec![
+        marked_up,
+        create_test_card("Haus", "home", LearningStatus::New),
+    ]);
+
+    let json_path = dir.path().join("cards.json");
+    let mut processor = TransferProcessor::new(client, "dump".to_string())
+        .output(JsonOutputBuilder::new(), &json_path)
+        .with_page_delay(Duration::ZERO)
+        .with_transforms(TransformPipeline::new().then(SanitizeOptions {
+            strip_html: true,
+            decode_entities: true,
+            trim_whitespace: true,
+        }));
+    processor.process().await.unwrap();
+
+    let cards: Vec<VocabularyCard> =
+        serde_json::from_str(&std::fs::read_to_string(&json_path).unwrap()).unwrap();
+    assert_eq!(cards.len(), 1);
+    assert_eq!(cards[0].word, "Haus");
+    assert_eq!(cards[0].translation, "house");
+    assert_eq!(cards[0].example, None);
+}
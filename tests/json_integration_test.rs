@@ -1,7 +1,6 @@
 use duoload::duocards::models::{LearningStatus, VocabularyCard};
 use duoload::output::json::JsonOutputBuilder;
 use duoload::output::{OutputBuilder, OutputDestination};
-use serde_json;
 use std::fs::File;
 use std::io::BufWriter;
 use tempfile::NamedTempFile;
@@ -17,6 +16,7 @@ fn create_test_card(
         translation: translation.to_string(),
         example: example.map(|s| s.to_string()),
         status,
+        ..Default::default()
     }
 }
 
@@ -179,8 +179,8 @@ async fn test_large_json_deck_creation() {
     let content = std::fs::read_to_string(&temp_file).unwrap();
     let cards: Vec<VocabularyCard> = serde_json::from_str(&content).unwrap();
     assert_eq!(cards.len(), 100);
-    for i in 0..100 {
-        assert_eq!(cards[i].word, format!("word{}", i));
-        assert_eq!(cards[i].translation, format!("translation{}", i));
+    for (i, card) in cards.iter().enumerate() {
+        assert_eq!(card.word, format!("word{}", i));
+        assert_eq!(card.translation, format!("translation{}", i));
     }
 }
@@ -0,0 +1,111 @@
+use duoload::duocards::models::{LearningStatus, VocabularyCard};
+use duoload::media::{MediaFetcher, MediaKind, MediaStore};
+use mockito::Server;
+use tokio_test::block_on;
+
+#[test]
+fn test_fetch_downloads_once() {
+    let mut server = Server::new();
+    let mock = server
+        .mock("GET", "/pictures/house.svg")
+        .with_status(200)
+        .with_header("content-type", "image/svg+xml")
+        .with_body("<svg/>")
+        .expect(1)
+        .create();
+    let url = server.url() + "/pictures/house.svg";
+
+    let dir = tempfile::tempdir().unwrap();
+    let fetcher = MediaFetcher::new(MediaStore::new(dir.path().join("media"))).unwrap();
+
+    let path = block_on(fetcher.fetch(&url, MediaKind::Image)).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "<svg/>");
+    assert_eq!(
+        fetcher.store().get(&url, MediaKind::Image),
+        Some(path.clone())
+    );
+
+    // Already in the store, so not requested again
+    assert_eq!(
+        block_on(fetcher.fetch(&url, MediaKind::Image)).unwrap(),
+        path
+    );
+    mock.assert();
+}
+
+#[test]
+fn test_fetch_failure() {
+    let mut server = Server::new();
+    server
+        .mock("GET", "/pictures/missing.svg")
+        .with_status(404)
+        .create();
+    let url = server.url() + "/pictures/missing.svg";
+
+    let dir = tempfile::tempdir().unwrap();
+    let fetcher = MediaFetcher::new(MediaStore::new(dir.path())).unwrap();
+
+    let err = block_on(fetcher.fetch(&url, MediaKind::Image)).unwrap_err();
+    assert!(err.to_string().contains("404"));
+    assert_eq!(fetcher.store().get(&url, MediaKind::Image), None);
+}
+
+#[test]
+fn test_media_of_card() {
+    let card = VocabularyCard {
+        word: "Haus".to_string(),
+        translation: "house".to_string(),
+        status: LearningStatus::New,
+        image: Some("https://cdn.example.com/house.svg".to_string()),
+        audio: Some("https://cdn.example.com/haus.mp3".to_string()),
+        ..Default::default()
+    };
+    let dir = tempfile::tempdir().unwrap();
+    let fetcher = MediaFetcher::new(MediaStore::new(dir.path())).unwrap();
+
+    assert_eq!(
+        fetcher.media_of(&card),
+        vec![("https://cdn.example.com/house.svg", MediaKind::Image)]
+    );
+    assert_eq!(
+        fetcher.with_images(false).with_audio(true).media_of(&card),
+        vec![("https://cdn.example.com/haus.mp3", MediaKind::Audio)]
+    );
+}
+
+#[test]
+fn test_link_downloaded_picture() {
+    let mut server = Server::new();
+    server
+        .mock("GET", "/pictures/house.svg")
+        .with_status(200)
+        .with_body("<svg/>")
+        .create();
+    let url = server.url() + "/pictures/house.svg";
+    let card = VocabularyCard {
+        word: "Haus".to_string(),
+        translation: "house".to_string(),
+        status: LearningStatus::New,
+        image: Some(url.clone()),
+        ..Default::default()
+    };
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("data")).unwrap();
+    let store = MediaStore::new(dir.path().join("images"));
+
+    // Without links the URL stays, also once downloaded
+    let fetcher = MediaFetcher::new(store.clone()).unwrap();
+    assert_eq!(fetcher.link(card.clone()).image, Some(url.clone()));
+
+    let fetcher = fetcher.with_links(dir.path().join("data"));
+    // Not downloaded yet, so the URL stays
+    assert_eq!(fetcher.link(card.clone()).image, Some(url.clone()));
+    block_on(fetcher.fetch(&url, MediaKind::Image)).unwrap();
+    assert_eq!(
+        fetcher.link(card).image,
+        Some(format!(
+            "../images/{}",
+            MediaStore::file_name(&url, MediaKind::Image)
+        ))
+    );
+}
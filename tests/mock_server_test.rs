@@ -0,0 +1,288 @@
+use duoload::anki::package::PackageCollection;
+use duoload::duocards::models::LearningStatus;
+use duoload::duocards::retry::RetryPolicy;
+use duoload::output::anki::AnkiPackageBuilder;
+use duoload::output::json::JsonOutputBuilder;
+use duoload::output::{OutputBuilder, OutputDestination};
+use duoload::snapshot::Snapshot;
+use duoload::testing::{FaultInjector, Faults, FixtureDeck, MOCK_RELEASE_ID, MockDuocardsServer};
+use duoload::transfer::processor::TransferProcessor;
+use duoload::transfer::progress::ConsoleReporter;
+use serde_json::Value;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tempfile::tempdir;
+
+#[tokio::test]
+async fn test_export_against_mock_server() {
+    let deck = FixtureDeck::generate(120).with_duplicate("word7");
+    let server = MockDuocardsServer::start(vec![deck.clone()]).await.unwrap();
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("deck.json");
+
+    let mut processor = TransferProcessor::new(server.client().unwrap(), deck.id.clone())
+        .output(JsonOutputBuilder::new(), &path);
+    processor.process().await.unwrap();
+
+    assert_eq!(server.request_count(), 2);
+    let cards: Vec<Value> = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(cards.len(), 120);
+    assert_eq!(cards[7]["word"], "word7");
+    assert_eq!(cards[7]["translation"], "translation7");
+    assert_eq!(
+        cards[5]["status"],
+        serde_json::to_value(LearningStatus::Known).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_several_outputs_from_one_fetch() {
+    let deck = FixtureDeck::generate(120);
+    let server = MockDuocardsServer::start(vec![deck.clone()]).await.unwrap();
+    let dir = tempdir().unwrap();
+    let json = dir.path().join("deck.json");
+    let anki = dir.path().join("deck.apkg");
+
+    let outputs: Vec<(Box<dyn OutputBuilder>, _)> = vec![
+        (Box::new(JsonOutputBuilder::new()), json.clone()),
+        (Box::new(AnkiPackageBuilder::new("Deck")), anki.clone()),
+    ];
+    let mut processor =
+        TransferProcessor::new(server.client().unwrap(), deck.id.clone()).outputs(outputs);
+    processor.process().await.unwrap();
+
+    assert_eq!(server.request_count(), 2);
+    let cards: Vec<Value> = serde_json::from_str(&std::fs::read_to_string(&json).unwrap()).unwrap();
+    assert_eq!(cards.len(), 120);
+    let package = PackageCollection::open(&anki).unwrap();
+    assert_eq!(package.note_count().unwrap(), 120);
+}
+
+#[tokio::test]
+async fn test_deck_name_from_metadata() {
+    let deck = FixtureDeck::generate(3).with_name("Deutsch B1");
+    let server = MockDuocardsServer::start(vec![deck.clone()]).await.unwrap();
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("deck.apkg");
+
+    let builder = AnkiPackageBuilder::new("Fallback").with_name_from_deck(true);
+    let mut processor =
+        TransferProcessor::new(server.client().unwrap(), deck.id.clone()).output(builder, &path);
+    processor.process().await.unwrap();
+
+    let names = PackageCollection::open(&path)
+        .unwrap()
+        .deck_names()
+        .unwrap();
+    assert!(names.contains(&"Deutsch B1".to_string()), "{:?}", names);
+}
+
+#[tokio::test]
+async fn test_export_survives_injected_faults() {
+    let deck = FixtureDeck::generate(350);
+    let server = MockDuocardsServer::start(vec![deck.clone()]).await.unwrap();
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("deck.json");
+
+    let faults: Faults = "timeout=0.2,server-error=0.2,malformed=0.2,timeout-after=0.01,seed=7"
+        .parse()
+        .unwrap();
+    let injector = FaultInjector::new(faults);
+    let injected = injector.injected();
+    let client = server
+        .client()
+        .unwrap()
+        .with_retry_policy(RetryPolicy::new(2, Duration::ZERO))
+        .with_faults(injector);
+    let request_stats = client.request_stats();
+    let mut processor = TransferProcessor::new(client, deck.id.clone())
+        .output(JsonOutputBuilder::new(), &path)
+        .with_auto_retry(20, Duration::ZERO);
+    processor.process().await.unwrap();
+
+    let cards: Vec<Value> = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(cards.len(), 350);
+    let injected = injected.load(Ordering::Relaxed);
+    assert!(injected > 0);
+    // Every fault is retried by the client before resuming the export
+    assert!(request_stats.retries() > 0);
+    assert_eq!(request_stats.requests(), 4 + injected);
+    // Truncated pages were fetched from the server, the other faults never reach it
+    assert!(server.request_count() > 4);
+}
+
+#[tokio::test]
+async fn test_ping_mock_server() {
+    let server = MockDuocardsServer::start(vec![]).await.unwrap();
+
+    let status = server.client().unwrap().ping().await.unwrap();
+    assert_eq!(status.release_id.as_deref(), Some(MOCK_RELEASE_ID));
+    assert_eq!(status.authenticated, None);
+    assert_eq!(server.request_count(), 1);
+}
+
+#[tokio::test]
+async fn test_deck_summary_from_mock_server() {
+    let deck = FixtureDeck::generate(250).with_name("Deutsch B1");
+    let server = MockDuocardsServer::start(vec![deck.clone()]).await.unwrap();
+
+    let totals = server
+        .client()
+        .unwrap()
+        .fetch_deck_summary(&deck.id)
+        .await
+        .unwrap();
+    assert_eq!(totals.name.as_deref(), Some("Deutsch B1"));
+    assert_eq!(totals.cards_count, 250);
+    assert_eq!(totals.pages(), 3);
+}
+
+#[tokio::test]
+async fn test_list_decks_from_mock_server() {
+    let first = FixtureDeck::generate(1).with_name("Deutsch");
+    let second = FixtureDeck::generate(1).with_name("Español");
+    let server = MockDuocardsServer::start(vec![first.clone(), second.clone()])
+        .await
+        .unwrap();
+
+    let decks = server.client().unwrap().list_decks().await.unwrap();
+    let mut names: Vec<(String, String)> =
+        decks.into_iter().map(|deck| (deck.id, deck.name)).collect();
+    names.sort();
+    let mut expected = vec![
+        (first.id, "Deutsch".to_string()),
+        (second.id, "Español".to_string()),
+    ];
+    expected.sort();
+    assert_eq!(names, expected);
+}
+
+#[tokio::test]
+async fn test_snapshot_against_mock_server() {
+    let deck = FixtureDeck::generate(3).with_duplicate("word1");
+    let server = MockDuocardsServer::start(vec![deck.clone()]).await.unwrap();
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("deck.duoload");
+
+    let snapshot = Snapshot::capture(
+        &server.client().unwrap(),
+        &deck.id,
+        Duration::ZERO,
+        &ConsoleReporter,
+    )
+    .await
+    .unwrap();
+    snapshot.write(&path).unwrap();
+
+    let read = Snapshot::read(&path).unwrap();
+    assert_eq!(read.metadata.deck_id, deck.id);
+    assert!(read.metadata.complete);
+    assert_eq!(read.pages.len(), 1);
+    assert_eq!(
+        read.pages[0]["data"]["node"]["cards"]["edges"][3]["node"]["front"],
+        "word1"
+    );
+    // Snapshots keep every card, duplicates included
+    assert_eq!(read.cards.len(), 4);
+}
+
+#[tokio::test]
+async fn test_restore_snapshot_to_anki() {
+    let deck = FixtureDeck::generate(5).with_duplicate("word2");
+    let server = MockDuocardsServer::start(vec![deck.clone()]).await.unwrap();
+    let dir = tempdir().unwrap();
+    let bundle = dir.path().join("deck.duoload");
+    let apkg = dir.path().join("deck.apkg");
+
+    Snapshot::capture(
+        &server.client().unwrap(),
+        &deck.id,
+        Duration::ZERO,
+        &ConsoleReporter,
+    )
+    .await
+    .unwrap()
+    .write(&bundle)
+    .unwrap();
+
+    let mut builder = AnkiPackageBuilder::new("Restored");
+    let stats = Snapshot::read(&bundle)
+        .unwrap()
+        .restore(&mut builder)
+        .unwrap();
+    builder.write(OutputDestination::File(&apkg)).unwrap();
+
+    assert_eq!(stats.total_cards, 5);
+    assert_eq!(stats.duplicates, 1);
+    assert_eq!(builder.count_written_notes(&apkg).unwrap(), Some(5));
+}
+
+#[tokio::test]
+async fn test_restored_notes_keep_live_guids() {
+    let deck = FixtureDeck::generate(30);
+    let server = MockDuocardsServer::start(vec![deck.clone()]).await.unwrap();
+    let dir = tempdir().unwrap();
+    let bundle = dir.path().join("deck.duoload");
+    let live = dir.path().join("live.apkg");
+    let restored = dir.path().join("restored.apkg");
+
+    let mut processor = TransferProcessor::new(server.client().unwrap(), deck.id.clone())
+        .output(AnkiPackageBuilder::new("Deck"), &live);
+    processor.process().await.unwrap();
+
+    Snapshot::capture(
+        &server.client().unwrap(),
+        &deck.id,
+        Duration::ZERO,
+        &ConsoleReporter,
+    )
+    .await
+    .unwrap()
+    .write(&bundle)
+    .unwrap();
+    let mut builder = AnkiPackageBuilder::new("Deck");
+    Snapshot::read(&bundle)
+        .unwrap()
+        .restore(&mut builder)
+        .unwrap();
+    builder.write(OutputDestination::File(&restored)).unwrap();
+
+    let guids = |path| {
+        let mut guids: Vec<String> = PackageCollection::open(path)
+            .unwrap()
+            .notes()
+            .unwrap()
+            .into_iter()
+            .map(|note| note.guid)
+            .collect();
+        guids.sort();
+        guids
+    };
+    let live_guids = guids(&live);
+    assert_eq!(live_guids.len(), 30);
+    assert_eq!(guids(&restored), live_guids);
+}
+
+#[tokio::test]
+async fn test_concurrent_export_against_mock_server() {
+    let deck = FixtureDeck::generate(350);
+    let server = MockDuocardsServer::start(vec![deck.clone()]).await.unwrap();
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("deck.json");
+
+    let mut processor = TransferProcessor::new(server.client().unwrap(), deck.id.clone())
+        .output(JsonOutputBuilder::new(), &path)
+        .with_concurrency(3)
+        .with_page_delay(Duration::ZERO);
+    processor.process().await.unwrap();
+
+    // The first page alone, then the other three at once
+    assert_eq!(server.request_count(), 4);
+    let cards: Vec<Value> = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    let words: Vec<&str> = cards
+        .iter()
+        .map(|card| card["word"].as_str().unwrap())
+        .collect();
+    let expected: Vec<String> = (0..350).map(|i| format!("word{}", i)).collect();
+    assert_eq!(words, expected);
+}
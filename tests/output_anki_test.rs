@@ -1,6 +1,16 @@
-use duoload::duocards::models::{LearningStatus, VocabularyCard};
+use duoload::anki::note::{
+    CardTemplates, MODEL_ID, QuestionField, TagScheme, note_guid, parse_status_name,
+};
+use duoload::anki::package::{ExistingPackage, PackageCollection};
+use duoload::anki::writer::{ApkgOptions, ApkgScheme};
+use duoload::duocards::models::{DeckMetadata, LearningStatus, Provenance, VocabularyCard};
+use duoload::error::DuoloadError;
+use duoload::media::{MediaKind, MediaStore};
 use duoload::output::anki::AnkiPackageBuilder;
+use duoload::output::timestamp::UtcDateTime;
 use duoload::output::{OutputBuilder, OutputDestination};
+use std::io::Read;
+use std::time::{Duration, UNIX_EPOCH};
 use tempfile::NamedTempFile;
 
 fn create_test_card(
@@ -14,6 +24,7 @@ fn create_test_card(
         translation: translation.to_string(),
         example: example.map(|s| s.to_string()),
         status,
+        ..Default::default()
     }
 }
 
@@ -51,6 +62,27 @@ fn test_add_note() {
     assert!(builder.add_note(card3).unwrap());
 }
 
+#[test]
+fn test_rejects_bad_cards() {
+    let mut builder = AnkiPackageBuilder::new("Test Deck");
+
+    let separator = create_test_card("a\u{1f}b", "hola", None, LearningStatus::New);
+    let error = builder.add_note(separator).unwrap_err();
+    assert!(matches!(error, DuoloadError::BadCard { ref word, .. } if word == "a\u{1f}b"));
+    assert!(error.to_string().contains("U+001F"));
+
+    let empty = create_test_card("  ", "hola", None, LearningStatus::New);
+    assert!(matches!(
+        builder.add_note(empty),
+        Err(DuoloadError::BadCard { .. })
+    ));
+
+    // Line breaks and tabs are kept
+    let multiline = create_test_card("hello", "hola", Some("one\n\ttwo"), LearningStatus::New);
+    assert!(builder.check_card(&multiline).is_ok());
+    assert!(builder.add_note(multiline).unwrap());
+}
+
 #[test]
 fn test_write_to_file() {
     let mut builder = AnkiPackageBuilder::new("Test Deck");
@@ -80,14 +112,16 @@ fn test_write_to_buffer() {
     builder.add_note(card).unwrap();
 
     let mut buffer = Vec::new();
-    let result = builder.write(OutputDestination::Writer(&mut buffer));
-    assert!(result.is_err()); // Anki output only supports file output
-    assert!(
-        result
-            .unwrap_err()
-            .to_string()
-            .contains("Anki output is only supported for file output")
-    );
+    builder
+        .write(OutputDestination::Writer(&mut buffer))
+        .unwrap();
+    // The buffer holds the whole package, a zip archive
+    assert!(buffer.starts_with(b"PK"));
+
+    let temp_file = NamedTempFile::new().unwrap();
+    std::fs::write(temp_file.path(), &buffer).unwrap();
+    let count = builder.count_written_notes(temp_file.path()).unwrap();
+    assert_eq!(count, Some(1));
 }
 
 #[test]
@@ -97,3 +131,985 @@ fn test_empty_deck() {
     let result = builder.write(OutputDestination::File(temp_file.path()));
     assert!(result.is_ok()); // Should be able to write an empty deck
 }
+
+#[test]
+fn test_count_written_notes() {
+    let mut builder = AnkiPackageBuilder::new("Test Deck");
+    builder
+        .add_note(create_test_card("hello", "hola", None, LearningStatus::New))
+        .unwrap();
+    builder
+        .add_note(create_test_card(
+            "goodbye",
+            "adiós",
+            None,
+            LearningStatus::Known,
+        ))
+        .unwrap();
+
+    let temp_file = NamedTempFile::new().unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    let count = builder.count_written_notes(temp_file.path()).unwrap();
+    assert_eq!(count, Some(2));
+}
+
+#[cfg(feature = "lang")]
+#[test]
+fn test_language_subdecks() {
+    let mut builder = AnkiPackageBuilder::new("Vocabulary").with_language_subdecks(true);
+    let cards = [
+        (
+            "Haus",
+            "Das Haus ist sehr groß und hat einen schönen Garten.",
+        ),
+        (
+            "Hund",
+            "Der Hund schläft jeden Tag unter dem Tisch in der Küche.",
+        ),
+        (
+            "perro",
+            "El perro duerme todos los días debajo de la mesa de la cocina.",
+        ),
+    ];
+    for (word, example) in cards {
+        builder
+            .add_note(create_test_card(
+                word,
+                "",
+                Some(example),
+                LearningStatus::New,
+            ))
+            .unwrap();
+    }
+
+    let temp_file = NamedTempFile::new().unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    let collection = duoload::anki::package::PackageCollection::open(temp_file.path()).unwrap();
+    assert_eq!(collection.note_count().unwrap(), 3);
+    let names = collection.deck_names().unwrap();
+    assert!(names.contains(&"Vocabulary::de".to_string()), "{:?}", names);
+    assert!(names.contains(&"Vocabulary::es".to_string()), "{:?}", names);
+}
+
+#[cfg(feature = "lang")]
+#[test]
+fn test_language_tags() {
+    let mut builder = AnkiPackageBuilder::new("Vocabulary").with_language_tags(true);
+    builder
+        .add_note(create_test_card(
+            "Haus",
+            "The house is very big and has a beautiful garden.",
+            Some("Das Haus ist sehr groß und hat einen schönen Garten."),
+            LearningStatus::New,
+        ))
+        .unwrap();
+
+    let temp_file = NamedTempFile::new().unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    let package = ExistingPackage::open(temp_file.path()).unwrap();
+    let tags = &package.notes[0].tags;
+    assert!(tags.contains(&"lang::de".to_string()), "{:?}", tags);
+    assert!(tags.contains(&"lang::en".to_string()), "{:?}", tags);
+}
+
+#[test]
+fn test_status_subdecks() {
+    let mut builder = AnkiPackageBuilder::new("Vocabulary").with_status_subdecks(true);
+    for (word, status) in [
+        ("Haus", LearningStatus::Known),
+        ("Hund", LearningStatus::New),
+        ("Baum", LearningStatus::Known),
+    ] {
+        builder
+            .add_note(create_test_card(word, "", None, status))
+            .unwrap();
+    }
+
+    let temp_file = NamedTempFile::new().unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    let collection = PackageCollection::open(temp_file.path()).unwrap();
+    assert_eq!(collection.note_count().unwrap(), 3);
+    let names = collection.deck_names().unwrap();
+    assert!(
+        names.contains(&"Vocabulary::Known".to_string()),
+        "{:?}",
+        names
+    );
+    assert!(
+        names.contains(&"Vocabulary::New".to_string()),
+        "{:?}",
+        names
+    );
+    assert!(
+        !names.contains(&"Vocabulary::Learning".to_string()),
+        "{:?}",
+        names
+    );
+}
+
+#[test]
+fn test_source_fields() {
+    let mut card = create_test_card("Haus", "house", None, LearningStatus::New);
+    card.source_id = Some("Q2FyZDox".to_string());
+    card.waiting_until = UtcDateTime::parse_rfc3339("2025-06-02T08:00:00Z");
+    let mut builder = AnkiPackageBuilder::new("Vocabulary").with_source_fields(true);
+    builder.add_note(card).unwrap();
+
+    let temp_file = NamedTempFile::new().unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    let package = ExistingPackage::open(temp_file.path()).unwrap();
+    assert_eq!(
+        package.notes[0].fields,
+        [
+            "Haus",
+            "house",
+            "",
+            "",
+            "",
+            "Q2FyZDox",
+            "",
+            "2025-06-02T08:00:00Z"
+        ]
+    );
+}
+
+#[test]
+fn test_model_id_follows_extra_fields() {
+    let model_ids = |builder: AnkiPackageBuilder| {
+        let mut builder = builder;
+        builder
+            .add_note(create_test_card("Haus", "house", None, LearningStatus::New))
+            .unwrap();
+        let temp_file = NamedTempFile::new().unwrap();
+        builder
+            .write(OutputDestination::File(temp_file.path()))
+            .unwrap();
+        PackageCollection::open(temp_file.path())
+            .unwrap()
+            .model_ids()
+            .unwrap()
+    };
+
+    let plain = model_ids(AnkiPackageBuilder::new("Vocabulary"));
+    assert_eq!(plain, vec![MODEL_ID]);
+    let source = model_ids(AnkiPackageBuilder::new("Vocabulary").with_source_fields(true));
+    let both = model_ids(
+        AnkiPackageBuilder::new("Vocabulary")
+            .with_source_fields(true)
+            .with_provenance_field(true),
+    );
+    let hint =
+        model_ids(AnkiPackageBuilder::new("Vocabulary").with_question_field(QuestionField::Hint));
+    assert_ne!(source, plain);
+    assert_ne!(both, source);
+    assert_ne!(hint, source);
+    assert_eq!(
+        hint,
+        model_ids(AnkiPackageBuilder::new("Vocabulary").with_hint_field(true))
+    );
+    // A given ID is kept whatever the fields
+    assert_eq!(
+        model_ids(
+            AnkiPackageBuilder::new("Vocabulary")
+                .with_model_id(987654321)
+                .with_source_fields(true)
+        ),
+        vec![987654321]
+    );
+}
+
+#[test]
+fn test_status_fields() {
+    let mut learning = create_test_card("Haus", "house", None, LearningStatus::Learning);
+    learning.known_count = Some(3);
+    // Without a count, the lowest count of the status decides
+    let known = create_test_card("Hund", "dog", None, LearningStatus::Known);
+    let mut builder = AnkiPackageBuilder::new("Vocabulary")
+        .with_source_fields(true)
+        .with_status_fields(true);
+    builder.add_note(learning).unwrap();
+    builder.add_note(known).unwrap();
+
+    let temp_file = NamedTempFile::new().unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    let package = ExistingPackage::open(temp_file.path()).unwrap();
+    let status: Vec<&[String]> = package.notes.iter().map(|note| &note.fields[8..]).collect();
+    assert_eq!(status, [["learning", "2"], ["known", "0"]]);
+}
+
+#[test]
+fn test_provenance_field() {
+    let mut card = create_test_card("Haus", "house", None, LearningStatus::New);
+    card.hint = Some("a building to live in".to_string());
+    card.provenance = Some(Provenance::new(
+        "RGVjazox",
+        3,
+        UtcDateTime::parse_rfc3339("2025-06-01T10:00:00Z").unwrap(),
+    ));
+    let mut builder = AnkiPackageBuilder::new("Vocabulary")
+        .with_hint_field(true)
+        .with_provenance_field(true);
+    builder.add_note(card).unwrap();
+
+    let temp_file = NamedTempFile::new().unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    let package = ExistingPackage::open(temp_file.path()).unwrap();
+    assert_eq!(
+        package.notes[0].fields[5..],
+        [
+            "a building to live in".to_string(),
+            format!(
+                "deck RGVjazox, page 3, fetched 2025-06-01T10:00:00Z, duoload {}",
+                env!("CARGO_PKG_VERSION")
+            )
+        ]
+    );
+}
+
+#[test]
+fn test_known_count_field() {
+    let mut counted = create_test_card("Haus", "house", None, LearningStatus::Learning);
+    counted.known_count = Some(3);
+    let uncounted = create_test_card("Baum", "tree", None, LearningStatus::New);
+    let mut builder = AnkiPackageBuilder::new("Vocabulary")
+        .with_status_fields(true)
+        .with_known_count_field(true);
+    builder.add_note(counted).unwrap();
+    builder.add_note(uncounted).unwrap();
+
+    let temp_file = NamedTempFile::new().unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    let package = ExistingPackage::open(temp_file.path()).unwrap();
+    let known_counts: Vec<&str> = package
+        .notes
+        .iter()
+        .map(|note| note.fields.last().unwrap().as_str())
+        .collect();
+    assert_eq!(known_counts, ["3", ""]);
+}
+
+#[test]
+fn test_hint_field_on_question_side() {
+    let mut card = create_test_card(
+        "Haus",
+        "house",
+        Some("Das Haus ist groß."),
+        LearningStatus::New,
+    );
+    card.hint = Some("a building to live in".to_string());
+    let mut builder = AnkiPackageBuilder::new("Vocabulary")
+        .with_question_field(QuestionField::Hint)
+        .with_model_id(987654321);
+    builder.add_note(card).unwrap();
+
+    let temp_file = NamedTempFile::new().unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    let package = ExistingPackage::open(temp_file.path()).unwrap();
+    assert_eq!(
+        package.notes[0].fields[2..],
+        ["Das Haus ist groß.", "", "", "a building to live in"]
+    );
+    let models = PackageCollection::open(temp_file.path())
+        .unwrap()
+        .models()
+        .unwrap();
+    let template = &models["987654321"]["tmpls"][0];
+    assert!(template["qfmt"].as_str().unwrap().contains("{{Hint}}"));
+    assert!(template["afmt"].as_str().unwrap().contains("{{Example}}"));
+}
+
+#[test]
+fn test_example_on_question_side() {
+    let templates = CardTemplates::for_fields(Default::default(), QuestionField::Example);
+    assert!(templates.qfmt.contains("{{Example}}"));
+    assert!(!templates.afmt.contains("{{Example}}"));
+}
+
+#[test]
+fn test_collection_schedules_learned_cards() {
+    let new = create_test_card("Haus", "house", None, LearningStatus::New);
+    let mut learning = create_test_card("Hund", "dog", None, LearningStatus::Learning);
+    learning.known_count = Some(3);
+    // Due 2020-01-11, ten days after the creation of deterministic packages
+    learning.waiting_until = UtcDateTime::parse_rfc3339("2020-01-11T08:00:00Z");
+    let known = create_test_card("Katze", "cat", None, LearningStatus::Known);
+    let mut builder = AnkiPackageBuilder::new("Vocabulary")
+        .with_collection(true)
+        .with_package_options(ApkgOptions {
+            deterministic: true,
+            ..Default::default()
+        });
+    for card in [new, learning, known] {
+        builder.add_note(card).unwrap();
+    }
+
+    let temp_file = NamedTempFile::new().unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    let collection = PackageCollection::open(temp_file.path()).unwrap();
+    let cards: Vec<(i64, i64)> = collection
+        .cards()
+        .unwrap()
+        .iter()
+        .map(|card| (card.card_type, card.interval))
+        .collect();
+    assert_eq!(cards, [(0, 0), (2, 4), (2, 16)]);
+    let due: Vec<i64> = collection
+        .cards()
+        .unwrap()
+        .iter()
+        .map(|card| card.due)
+        .collect();
+    // The known card has no due date and is due on the export day
+    assert_eq!(due[1] - due[2], 10);
+    let notes = ExistingPackage::open(temp_file.path()).unwrap().notes;
+    assert_eq!(notes[0].guid, note_guid("Haus", "house"));
+}
+
+#[test]
+fn test_embeds_downloaded_pictures() {
+    let media_dir = tempfile::tempdir().unwrap();
+    let store = MediaStore::new(media_dir.path());
+    let downloaded = "https://cdn.example.com/house.svg";
+    std::fs::write(store.path(downloaded, MediaKind::Image), "<svg/>").unwrap();
+
+    let mut builder = AnkiPackageBuilder::new("Test Deck").with_media(store);
+    for (word, image) in [
+        ("house", Some(downloaded)),
+        ("home", Some(downloaded)),
+        // Never downloaded, so written without a picture
+        ("dog", Some("https://cdn.example.com/dog.svg")),
+        ("cat", None),
+    ] {
+        let mut card = create_test_card(word, "translation", None, LearningStatus::New);
+        card.image = image.map(str::to_string);
+        builder.add_note(card).unwrap();
+    }
+
+    let temp_file = NamedTempFile::new().unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(temp_file.path()).unwrap()).unwrap();
+    let mut media = String::new();
+    archive
+        .by_name("media")
+        .unwrap()
+        .read_to_string(&mut media)
+        .unwrap();
+    let media: serde_json::Value = serde_json::from_str(&media).unwrap();
+    assert_eq!(
+        media,
+        serde_json::json!({"0": MediaStore::file_name(downloaded, MediaKind::Image)})
+    );
+
+    let mut picture = String::new();
+    archive
+        .by_name("0")
+        .unwrap()
+        .read_to_string(&mut picture)
+        .unwrap();
+    assert_eq!(picture, "<svg/>");
+}
+
+#[test]
+fn test_unicode_paths() {
+    let dir = tempfile::tempdir().unwrap();
+    let media_dir = dir.path().join("médias 🎴");
+    std::fs::create_dir(&media_dir).unwrap();
+    let store = MediaStore::new(&media_dir);
+    let downloaded = "https://cdn.example.com/house.svg";
+    std::fs::write(store.path(downloaded, MediaKind::Image), "<svg/>").unwrap();
+
+    let mut builder = AnkiPackageBuilder::new("Test Deck").with_media(store);
+    let mut card = create_test_card("house", "Haus", None, LearningStatus::New);
+    card.image = Some(downloaded.to_string());
+    builder.add_note(card).unwrap();
+
+    let path = dir.path().join("Wörter 日本語 🎴.apkg");
+    builder.write(OutputDestination::File(&path)).unwrap();
+
+    assert_eq!(builder.count_written_notes(&path).unwrap(), Some(1));
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(&path).unwrap()).unwrap();
+    let mut picture = String::new();
+    archive
+        .by_name("0")
+        .unwrap()
+        .read_to_string(&mut picture)
+        .unwrap();
+    assert_eq!(picture, "<svg/>");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_non_utf8_paths() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let media_dir = dir.path().join(OsStr::from_bytes(b"media-\xff"));
+    std::fs::create_dir(&media_dir).unwrap();
+    let downloaded = "https://cdn.example.com/house.svg";
+    std::fs::write(
+        MediaStore::new(&media_dir).path(downloaded, MediaKind::Image),
+        "<svg/>",
+    )
+    .unwrap();
+
+    // Written by the same code as the repacked packages, and without it
+    let deterministic = ApkgOptions {
+        deterministic: true,
+        ..ApkgOptions::default()
+    };
+    for options in [ApkgOptions::default(), deterministic] {
+        let mut builder = AnkiPackageBuilder::new("Test Deck")
+            .with_media(MediaStore::new(&media_dir))
+            .with_package_options(options);
+        let mut card = create_test_card("house", "Haus", None, LearningStatus::New);
+        card.image = Some(downloaded.to_string());
+        builder.add_note(card).unwrap();
+
+        let path = dir.path().join(OsStr::from_bytes(b"deck-\xfe.apkg"));
+        builder.write(OutputDestination::File(&path)).unwrap();
+        assert_eq!(builder.count_written_notes(&path).unwrap(), Some(1));
+        let archive = zip::ZipArchive::new(std::fs::File::open(&path).unwrap()).unwrap();
+        assert!(archive.file_names().any(|name| name == "0"));
+    }
+}
+
+#[test]
+fn test_embeds_downloaded_audio() {
+    let media_dir = tempfile::tempdir().unwrap();
+    let store = MediaStore::new(media_dir.path());
+    let downloaded = "https://cdn.example.com/tts/haus";
+    std::fs::write(store.path(downloaded, MediaKind::Audio), "ID3").unwrap();
+
+    let mut builder = AnkiPackageBuilder::new("Test Deck").with_media(store);
+    for (word, audio) in [
+        ("Haus", Some(downloaded)),
+        // Never downloaded, so written without a pronunciation
+        ("Hund", Some("https://cdn.example.com/tts/hund")),
+    ] {
+        let mut card = create_test_card(word, "translation", None, LearningStatus::New);
+        card.audio = audio.map(str::to_string);
+        builder.add_note(card).unwrap();
+    }
+
+    let temp_file = NamedTempFile::new().unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    let file_name = MediaStore::file_name(downloaded, MediaKind::Audio);
+    assert!(file_name.ends_with(".mp3"));
+    let notes = PackageCollection::open(temp_file.path())
+        .unwrap()
+        .notes()
+        .unwrap();
+    assert_eq!(notes[0].fields[4], format!("[sound:{}]", file_name));
+    assert_eq!(notes[1].fields[4], "");
+
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(temp_file.path()).unwrap()).unwrap();
+    let mut media = String::new();
+    archive
+        .by_name("media")
+        .unwrap()
+        .read_to_string(&mut media)
+        .unwrap();
+    let media: serde_json::Value = serde_json::from_str(&media).unwrap();
+    assert_eq!(media, serde_json::json!({ "0": file_name }));
+}
+
+#[test]
+fn test_stable_guids() {
+    let deck = DeckMetadata {
+        id: "deck".to_string(),
+        ..DeckMetadata::default()
+    };
+    let export = |translation: &str| {
+        let mut card = create_test_card("Haus", translation, None, LearningStatus::New);
+        card.source_id = Some("card-1".to_string());
+        let mut builder = AnkiPackageBuilder::new("Vocabulary");
+        builder.set_deck_metadata(&deck);
+        builder.add_note(card).unwrap();
+        builder
+            .add_note(create_test_card("Hund", "dog", None, LearningStatus::New))
+            .unwrap();
+        let file = NamedTempFile::new().unwrap();
+        builder.write(OutputDestination::File(file.path())).unwrap();
+        ExistingPackage::open(file.path()).unwrap()
+    };
+
+    let first = export("house");
+    let second = export("home");
+
+    assert_eq!(first.notes[0].guid, note_guid("deck", "card-1"));
+    // The GUID follows the card, not its fields
+    assert_eq!(second.notes[0].guid, first.notes[0].guid);
+    assert_ne!(second.notes[0].fields, first.notes[0].fields);
+
+    // Merging replaces the note of the changed card instead of adding a second one
+    let mut builder = AnkiPackageBuilder::new("Vocabulary").with_existing(first);
+    builder.set_deck_metadata(&deck);
+    let mut card = create_test_card("Häuschen", "cottage", None, LearningStatus::New);
+    card.source_id = Some("card-1".to_string());
+    assert!(builder.add_note(card).unwrap());
+    let file = NamedTempFile::new().unwrap();
+    builder.write(OutputDestination::File(file.path())).unwrap();
+
+    let merged = ExistingPackage::open(file.path()).unwrap();
+    let words: Vec<&str> = merged.notes.iter().map(|note| note.word()).collect();
+    assert_eq!(words, ["Hund", "Häuschen"]);
+    assert_eq!(builder.count_written_notes(file.path()).unwrap(), Some(1));
+}
+
+#[test]
+fn test_merge_into_existing_package() {
+    let media_dir = tempfile::tempdir().unwrap();
+    let store = MediaStore::new(media_dir.path());
+    let picture = "https://cdn.example.com/house.svg";
+    std::fs::write(store.path(picture, MediaKind::Image), "<svg/>").unwrap();
+
+    let mut first = AnkiPackageBuilder::new("Test Deck").with_media(store);
+    let mut house = create_test_card("house", "Haus", None, LearningStatus::Known);
+    house.image = Some(picture.to_string());
+    first.add_note(house).unwrap();
+    first
+        .add_note(create_test_card("dog", "Hund", None, LearningStatus::New))
+        .unwrap();
+    let existing_file = NamedTempFile::new().unwrap();
+    first
+        .write(OutputDestination::File(existing_file.path()))
+        .unwrap();
+    let existing_notes = PackageCollection::open(existing_file.path())
+        .unwrap()
+        .notes()
+        .unwrap();
+
+    let existing = ExistingPackage::open(existing_file.path()).unwrap();
+    assert_eq!(existing.notes, existing_notes);
+    assert_eq!(existing.media_files.len(), 1);
+    let mut builder = AnkiPackageBuilder::new("Test Deck").with_existing(existing);
+    // Already in the package, even with a new translation
+    assert!(
+        !builder
+            .add_note(create_test_card(
+                "dog",
+                "Köter",
+                None,
+                LearningStatus::Known
+            ))
+            .unwrap()
+    );
+    assert!(
+        builder
+            .add_note(create_test_card("cat", "Katze", None, LearningStatus::New))
+            .unwrap()
+    );
+
+    let temp_file = NamedTempFile::new().unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    // Only the added note counts as written by this export
+    assert_eq!(
+        builder.count_written_notes(temp_file.path()).unwrap(),
+        Some(1)
+    );
+    let notes = PackageCollection::open(temp_file.path())
+        .unwrap()
+        .notes()
+        .unwrap();
+    assert_eq!(notes.len(), 3);
+    assert_eq!(notes[..2], existing_notes[..]);
+    assert_eq!(notes[2].word(), "cat");
+    assert_eq!(notes[2].tags[0], "duoload_new");
+
+    let merged = ExistingPackage::open(temp_file.path()).unwrap();
+    assert_eq!(merged.media_files.len(), 1);
+    assert_eq!(
+        std::fs::read_to_string(&merged.media_files[0]).unwrap(),
+        "<svg/>"
+    );
+}
+
+#[test]
+fn test_custom_deck_name_and_ids() {
+    let mut builder = AnkiPackageBuilder::new("Languages::German")
+        .with_deck_id(1234567890)
+        .with_model_id(987654321);
+    builder
+        .add_note(create_test_card("Haus", "house", None, LearningStatus::New))
+        .unwrap();
+
+    let temp_file = NamedTempFile::new().unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    let collection = PackageCollection::open(temp_file.path()).unwrap();
+    assert!(
+        collection
+            .deck_names()
+            .unwrap()
+            .contains(&"Languages::German".to_string())
+    );
+    assert!(collection.deck_ids().unwrap().contains(&1234567890));
+    assert_eq!(collection.model_ids().unwrap(), vec![987654321]);
+}
+
+#[test]
+fn test_name_suffix() {
+    let mut builder = AnkiPackageBuilder::new("German")
+        .with_deck_id(1234567890)
+        .with_name_suffix(" sentences");
+    builder
+        .add_note(create_test_card("Haus", "house", None, LearningStatus::New))
+        .unwrap();
+
+    let temp_file = NamedTempFile::new().unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    let collection = PackageCollection::open(temp_file.path()).unwrap();
+    assert!(
+        collection
+            .deck_names()
+            .unwrap()
+            .contains(&"German sentences".to_string())
+    );
+    // Imported next to the deck of the words
+    assert!(!collection.deck_ids().unwrap().contains(&1234567890));
+}
+
+#[test]
+fn test_fields_are_escaped() {
+    let card = || {
+        create_test_card(
+            "a < b & {{c}}",
+            "<b>bold</b>",
+            Some("x > y"),
+            LearningStatus::New,
+        )
+    };
+    let mut builder = AnkiPackageBuilder::new("German");
+    builder.add_note(card()).unwrap();
+    let temp_file = NamedTempFile::new().unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    let notes = PackageCollection::open(temp_file.path())
+        .unwrap()
+        .notes()
+        .unwrap();
+    assert_eq!(notes[0].fields[0], "a &lt; b &amp; &#123;{c}}");
+    assert_eq!(notes[0].fields[1], "&lt;b&gt;bold&lt;/b&gt;");
+    assert_eq!(notes[0].fields[2], "x &gt; y");
+
+    let mut builder = AnkiPackageBuilder::new("German").with_html_escaping(false);
+    builder.add_note(card()).unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+    let notes = PackageCollection::open(temp_file.path())
+        .unwrap()
+        .notes()
+        .unwrap();
+    assert_eq!(notes[0].fields[0], "a < b & {{c}}");
+    assert_eq!(notes[0].fields[1], "<b>bold</b>");
+}
+
+#[test]
+fn test_note_tags() {
+    let mut builder = AnkiPackageBuilder::new("Languages::German")
+        .with_tags(vec!["school".to_string(), "chapter 3".to_string()])
+        .with_export_time(UNIX_EPOCH + Duration::from_secs(1_748_747_700));
+    builder
+        .add_note(create_test_card(
+            "Haus",
+            "house",
+            None,
+            LearningStatus::Known,
+        ))
+        .unwrap();
+
+    let temp_file = NamedTempFile::new().unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    let notes = PackageCollection::open(temp_file.path())
+        .unwrap()
+        .notes()
+        .unwrap();
+    assert_eq!(
+        notes[0].tags,
+        vec![
+            "duoload_known",
+            "duoload::deck::Languages::German",
+            "duoload::exported::2025-06-01",
+            "school",
+            "chapter_3",
+        ]
+    );
+}
+
+#[test]
+fn test_tag_scheme() {
+    let mut card = create_test_card("Haus", "house", None, LearningStatus::Known);
+    card.labels = vec!["A1 course".to_string()];
+    let mut builder = AnkiPackageBuilder::new("German")
+        .with_tag_scheme(TagScheme {
+            prefix: "vocab".to_string(),
+            status_names: [parse_status_name("known=mature").unwrap()].into(),
+        })
+        .with_export_time(UNIX_EPOCH + Duration::from_secs(1_748_747_700));
+    builder.add_note(card).unwrap();
+    builder
+        .add_note(create_test_card("Baum", "tree", None, LearningStatus::New))
+        .unwrap();
+
+    let temp_file = NamedTempFile::new().unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    let notes = PackageCollection::open(temp_file.path())
+        .unwrap()
+        .notes()
+        .unwrap();
+    assert_eq!(
+        notes[0].tags,
+        vec![
+            "vocab_mature",
+            "vocab::label::A1_course",
+            "vocab::deck::German",
+            "vocab::exported::2025-06-01",
+        ]
+    );
+    assert_eq!(notes[1].tags[0], "vocab_new");
+
+    // Without a prefix, the tags are just the names
+    let scheme = TagScheme {
+        prefix: String::new(),
+        ..TagScheme::default()
+    };
+    assert_eq!(scheme.status_tag(&LearningStatus::Learning), "learning");
+    assert_eq!(scheme.tag("deck", "German"), "deck::German");
+    assert!(parse_status_name("forgotten=old").is_err());
+    assert!(parse_status_name("known").is_err());
+}
+
+#[test]
+fn test_custom_templates() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("front.html"), "<h1>{{Front}}</h1>").unwrap();
+    std::fs::write(dir.path().join("style.css"), ".card { color: teal; }").unwrap();
+    let templates = CardTemplates::from_dir(dir.path()).unwrap();
+    // Without a back.html the default answer side is kept
+    assert_eq!(templates.afmt, CardTemplates::default().afmt);
+
+    let mut builder = AnkiPackageBuilder::new("Test Deck")
+        .with_templates(templates)
+        .with_model_id(987654321);
+    builder
+        .add_note(create_test_card("Haus", "house", None, LearningStatus::New))
+        .unwrap();
+    let temp_file = NamedTempFile::new().unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    let models = PackageCollection::open(temp_file.path())
+        .unwrap()
+        .models()
+        .unwrap();
+    let model = &models["987654321"];
+    assert_eq!(model["css"], ".card { color: teal; }");
+    assert_eq!(model["tmpls"][0]["qfmt"], "<h1>{{Front}}</h1>");
+
+    assert!(CardTemplates::from_dir(&dir.path().join("missing")).is_err());
+}
+
+#[test]
+fn test_package_options() {
+    let media_dir = tempfile::tempdir().unwrap();
+    let store = MediaStore::new(media_dir.path());
+    let downloaded = "https://cdn.example.com/house.svg";
+    std::fs::write(store.path(downloaded, MediaKind::Image), "<svg/>").unwrap();
+
+    let mut builder = AnkiPackageBuilder::new("Test Deck")
+        .with_media(store)
+        .with_package_options(ApkgOptions {
+            compression: Some(0),
+            media: false,
+            scheme: ApkgScheme::Anki21,
+            deterministic: false,
+        });
+    let mut card = create_test_card("house", "Haus", None, LearningStatus::New);
+    card.image = Some(downloaded.to_string());
+    builder.add_note(card).unwrap();
+
+    let temp_file = NamedTempFile::new().unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(temp_file.path()).unwrap()).unwrap();
+    let mut names: Vec<&str> = archive.file_names().collect();
+    names.sort();
+    assert_eq!(names, ["collection.anki2", "collection.anki21", "media"]);
+    for index in 0..archive.len() {
+        let entry = archive.by_index(index).unwrap();
+        assert_eq!(entry.compression(), zip::CompressionMethod::Stored);
+    }
+
+    // The collection for Anki 2.1 holds the cards
+    let package = ExistingPackage::open(temp_file.path()).unwrap();
+    assert_eq!(package.notes.len(), 1);
+}
+
+#[test]
+fn test_deterministic_package() {
+    let media_dir = tempfile::tempdir().unwrap();
+    let store = MediaStore::new(media_dir.path());
+    let images = [
+        "https://cdn.example.com/house.svg",
+        "https://cdn.example.com/tree.svg",
+        "https://cdn.example.com/dog.svg",
+    ];
+    for image in images {
+        std::fs::write(store.path(image, MediaKind::Image), image).unwrap();
+    }
+
+    let write = || {
+        let mut builder = AnkiPackageBuilder::new("Test Deck")
+            .with_media(store.clone())
+            .with_package_options(ApkgOptions {
+                deterministic: true,
+                ..ApkgOptions::default()
+            });
+        for (word, image) in ["house", "tree", "dog"].into_iter().zip(images) {
+            let mut card = create_test_card(word, word, None, LearningStatus::New);
+            card.image = Some(image.to_string());
+            builder.add_note(card).unwrap();
+        }
+        let temp_file = NamedTempFile::new().unwrap();
+        builder
+            .write(OutputDestination::File(temp_file.path()))
+            .unwrap();
+        std::fs::read(temp_file.path()).unwrap()
+    };
+
+    let first = write();
+    std::thread::sleep(Duration::from_millis(10));
+    assert!(first == write(), "packages differ");
+
+    let temp_file = NamedTempFile::new().unwrap();
+    std::fs::write(temp_file.path(), &first).unwrap();
+    let package = ExistingPackage::open(temp_file.path()).unwrap();
+    assert_eq!(package.notes.len(), 3);
+    assert!(package.notes.iter().all(|note| {
+        !note
+            .tags
+            .iter()
+            .any(|tag| tag.starts_with("duoload::exported::"))
+    }));
+}
+
+#[test]
+fn test_disk_store_writes_same_package() {
+    let write = |disk_store: bool| {
+        let mut builder = AnkiPackageBuilder::new("Test Deck")
+            .with_disk_store(disk_store)
+            .with_batch_size(10)
+            .with_package_options(ApkgOptions {
+                deterministic: true,
+                ..ApkgOptions::default()
+            });
+        for index in 0..25 {
+            let word = format!("word{}", index % 24);
+            let card = create_test_card(&word, "translation", None, LearningStatus::New);
+            builder.add_note(card).unwrap();
+        }
+        let temp_file = NamedTempFile::new().unwrap();
+        builder
+            .write(OutputDestination::File(temp_file.path()))
+            .unwrap();
+        assert_eq!(
+            builder.count_written_notes(temp_file.path()).unwrap(),
+            Some(24)
+        );
+        std::fs::read(temp_file.path()).unwrap()
+    };
+
+    assert!(write(true) == write(false), "packages differ");
+}
+
+#[test]
+fn test_disk_store_with_status_subdecks() {
+    let mut builder = AnkiPackageBuilder::new("Test Deck")
+        .with_disk_store(true)
+        .with_batch_size(10)
+        .with_status_subdecks(true);
+    for index in 0..15 {
+        let status = match index % 3 {
+            0 => LearningStatus::New,
+            1 => LearningStatus::Learning,
+            _ => LearningStatus::Known,
+        };
+        let card = create_test_card(&format!("word{}", index), "translation", None, status);
+        builder.add_note(card).unwrap();
+    }
+    let temp_file = NamedTempFile::new().unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    let package = PackageCollection::open(temp_file.path()).unwrap();
+    assert_eq!(package.note_count().unwrap(), 15);
+    let mut names = package.deck_names().unwrap();
+    names.sort();
+    assert!(
+        names.ends_with(&[
+            "Test Deck::Known".to_string(),
+            "Test Deck::Learning".to_string(),
+            "Test Deck::New".to_string(),
+        ]),
+        "{:?}",
+        names
+    );
+}
@@ -0,0 +1,77 @@
+use duoload::duocards::models::{LearningStatus, VocabularyCard};
+use duoload::output::binary::{BinaryEncoding, BinaryOutputBuilder};
+use duoload::output::{OutputBuilder, OutputDestination};
+use tempfile::tempdir;
+
+fn create_test_card(word: &str, translation: &str, example: Option<&str>) -> VocabularyCard {
+    VocabularyCard {
+        word: word.to_string(),
+        translation: translation.to_string(),
+        example: example.map(|s| s.to_string()),
+        status: LearningStatus::New,
+        ..Default::default()
+    }
+}
+
+fn builder_with_cards(encoding: BinaryEncoding) -> BinaryOutputBuilder {
+    let mut builder = BinaryOutputBuilder::new(encoding);
+    builder
+        .add_note(create_test_card(
+            "hello",
+            "hallo",
+            Some("Hallo, wie geht's?"),
+        ))
+        .unwrap();
+    let mut card = create_test_card("line", "línea", None);
+    card.known_count = Some(3);
+    card.labels = vec!["verbs".to_string()];
+    builder.add_note(card).unwrap();
+    builder
+        .add_note(create_test_card("hello", "duplicate", None))
+        .unwrap();
+    builder
+}
+
+fn expected_cards() -> Vec<VocabularyCard> {
+    let mut card = create_test_card("line", "línea", None);
+    card.known_count = Some(3);
+    card.labels = vec!["verbs".to_string()];
+    vec![
+        create_test_card("hello", "hallo", Some("Hallo, wie geht's?")),
+        card,
+    ]
+}
+
+#[test]
+fn test_messagepack_round_trip() {
+    let builder = builder_with_cards(BinaryEncoding::MessagePack);
+    let mut output = Vec::new();
+    builder
+        .write(OutputDestination::Writer(&mut output))
+        .unwrap();
+
+    let cards: Vec<VocabularyCard> = rmp_serde::from_slice(&output).unwrap();
+    assert_eq!(cards, expected_cards());
+    // Cards are maps keyed by field name
+    let values: Vec<serde_json::Value> = rmp_serde::from_slice(&output).unwrap();
+    assert_eq!(values[1]["word"], "line");
+    assert_eq!(values[1]["known_count"], 3);
+}
+
+#[test]
+fn test_cbor_file_and_count() {
+    let builder = builder_with_cards(BinaryEncoding::Cbor);
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("deck.cbor");
+    builder.write(OutputDestination::File(&path)).unwrap();
+
+    let cards: Vec<VocabularyCard> =
+        ciborium::from_reader(std::fs::File::open(&path).unwrap()).unwrap();
+    assert_eq!(cards, expected_cards());
+    assert_eq!(builder.count_written_notes(&path).unwrap(), Some(2));
+
+    let path = dir.path().join("deck.msgpack");
+    let builder = builder_with_cards(BinaryEncoding::MessagePack);
+    builder.write(OutputDestination::File(&path)).unwrap();
+    assert_eq!(builder.count_written_notes(&path).unwrap(), Some(2));
+}
@@ -0,0 +1,259 @@
+use duoload::duocards::models::{LearningStatus, Provenance, VocabularyCard};
+use duoload::output::csv::{CsvHeaders, CsvOutputBuilder, CsvQuoting, parse_delimiter};
+use duoload::output::sort::SortOrder;
+use duoload::output::timestamp::UtcDateTime;
+use duoload::output::{OutputBuilder, OutputDestination};
+use duoload::transfer::duplicates::DuplicatePolicy;
+use tempfile::tempdir;
+
+fn create_test_card(
+    word: &str,
+    translation: &str,
+    example: Option<&str>,
+    status: LearningStatus,
+) -> VocabularyCard {
+    VocabularyCard {
+        word: word.to_string(),
+        translation: translation.to_string(),
+        example: example.map(|s| s.to_string()),
+        status,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_write_to_writer() {
+    let mut builder = CsvOutputBuilder::new();
+    builder
+        .add_note(create_test_card(
+            "hello",
+            "hola",
+            Some("Hello, \"world\"!"),
+            LearningStatus::New,
+        ))
+        .unwrap();
+    builder
+        .add_note(create_test_card(
+            "goodbye",
+            "adiós",
+            None,
+            LearningStatus::Known,
+        ))
+        .unwrap();
+
+    let mut output = Vec::new();
+    builder
+        .write(OutputDestination::Writer(&mut output))
+        .unwrap();
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "word,translation,example,status,source_id,created_at,waiting_until,known_count\n\
+         hello,hola,\"Hello, \"\"world\"\"!\",new,,,,\n\
+         goodbye,adiós,,known,,,,\n"
+    );
+}
+
+#[test]
+fn test_source_id_timestamps_and_known_count() {
+    let mut builder = CsvOutputBuilder::new();
+    let mut card = create_test_card("hello", "hola", None, LearningStatus::Learning);
+    card.source_id = Some("Q2FyZDox".to_string());
+    card.created_at = Some("2025-01-02T03:04:05Z".to_string());
+    card.waiting_until = UtcDateTime::parse_rfc3339("2025-02-01T00:00:00Z");
+    card.known_count = Some(3);
+    builder.add_note(card).unwrap();
+
+    let mut output = Vec::new();
+    builder
+        .write(OutputDestination::Writer(&mut output))
+        .unwrap();
+
+    assert_eq!(
+        String::from_utf8(output).unwrap().lines().nth(1),
+        Some("hello,hola,,learning,Q2FyZDox,2025-01-02T03:04:05Z,2025-02-01T00:00:00Z,3")
+    );
+}
+
+#[test]
+fn test_write_to_file_and_count() {
+    let mut builder = CsvOutputBuilder::new();
+    builder
+        .add_note(create_test_card(
+            "line",
+            "línea",
+            Some("first\nsecond"),
+            LearningStatus::Learning,
+        ))
+        .unwrap();
+    builder
+        .add_note(create_test_card(
+            "word",
+            "palabra",
+            None,
+            LearningStatus::New,
+        ))
+        .unwrap();
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("deck.csv");
+    builder.write(OutputDestination::File(&path)).unwrap();
+
+    // Quoted newlines stay inside their row
+    assert_eq!(builder.count_written_notes(&path).unwrap(), Some(2));
+}
+
+#[test]
+fn test_empty_deck() {
+    let builder = CsvOutputBuilder::new();
+    let mut output = Vec::new();
+    builder
+        .write(OutputDestination::Writer(&mut output))
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "word,translation,example,status,source_id,created_at,waiting_until,known_count\n"
+    );
+}
+
+#[test]
+fn test_duplicate_policy_last() {
+    let mut builder = CsvOutputBuilder::new();
+    builder.set_duplicate_policy(DuplicatePolicy::Last);
+
+    let card1 = create_test_card("hello", "hola", None, LearningStatus::New);
+    assert!(builder.add_note(card1).unwrap());
+    let card2 = create_test_card("hello", "bonjour", None, LearningStatus::Known);
+    assert!(!builder.add_note(card2).unwrap());
+
+    let mut output = Vec::new();
+    builder
+        .write(OutputDestination::Writer(&mut output))
+        .unwrap();
+    assert!(
+        String::from_utf8(output)
+            .unwrap()
+            .ends_with("hello,bonjour,,known,,,,\n")
+    );
+}
+
+#[test]
+fn test_sort_order() {
+    let mut builder = CsvOutputBuilder::new();
+    builder.set_sort_order(SortOrder::Alphabetical);
+    for word in ["zwei", "Apfel", "baum"] {
+        builder
+            .add_note(create_test_card(word, "", None, LearningStatus::New))
+            .unwrap();
+    }
+
+    let mut output = Vec::new();
+    builder
+        .write(OutputDestination::Writer(&mut output))
+        .unwrap();
+    let words: Vec<_> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .skip(1)
+        .map(|line| line.split(',').next().unwrap().to_string())
+        .collect();
+    assert_eq!(words, ["Apfel", "baum", "zwei"]);
+}
+
+#[test]
+fn test_spreadsheet_locale_options() {
+    let headers: CsvHeaders = "Wort, Übersetzung,Beispiel,Status".parse().unwrap();
+    let mut builder = CsvOutputBuilder::new()
+        .with_headers(headers.0)
+        .with_delimiter(parse_delimiter(";").unwrap())
+        .with_quoting(CsvQuoting::Always);
+    builder
+        .add_note(create_test_card(
+            "Haus",
+            "house; home",
+            Some("Das Haus"),
+            LearningStatus::Learning,
+        ))
+        .unwrap();
+
+    let mut output = Vec::new();
+    builder
+        .write(OutputDestination::Writer(&mut output))
+        .unwrap();
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "\"Wort\";\"Übersetzung\";\"Beispiel\";\"Status\"\n\
+         \"Haus\";\"house; home\";\"Das Haus\";\"learning\"\n"
+    );
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("deck.csv");
+    builder.write(OutputDestination::File(&path)).unwrap();
+    assert_eq!(builder.count_written_notes(&path).unwrap(), Some(1));
+}
+
+#[test]
+fn test_invalid_locale_options() {
+    assert!("".parse::<CsvHeaders>().is_err());
+    assert!("a,b,c,d,e,f,g,h,i".parse::<CsvHeaders>().is_err());
+    assert_eq!(parse_delimiter("tab").unwrap(), b'\t');
+    assert!(parse_delimiter(";;").is_err());
+    assert!(parse_delimiter("\"").is_err());
+}
+
+#[test]
+fn test_image_column() {
+    let headers: CsvHeaders = "Wort,Übersetzung".parse().unwrap();
+    let mut builder = CsvOutputBuilder::new()
+        .with_headers(headers.0)
+        .with_image_column(true);
+    let mut card = create_test_card("Haus", "house", None, LearningStatus::New);
+    card.image = Some("images/duoload-1a2b3c4d5e6f7a8b.svg".to_string());
+    builder.add_note(card).unwrap();
+    builder
+        .add_note(create_test_card("Baum", "tree", None, LearningStatus::New))
+        .unwrap();
+
+    let mut output = Vec::new();
+    builder
+        .write(OutputDestination::Writer(&mut output))
+        .unwrap();
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "Wort,Übersetzung,image\n\
+         Haus,house,images/duoload-1a2b3c4d5e6f7a8b.svg\n\
+         Baum,tree,\n"
+    );
+}
+
+#[test]
+fn test_provenance_column() {
+    let mut builder = CsvOutputBuilder::new()
+        .with_headers(vec!["word".to_string()])
+        .with_provenance_column(true);
+    let mut card = create_test_card("Haus", "house", None, LearningStatus::New);
+    card.provenance = Some(Provenance {
+        deck_id: "RGVjazox".to_string(),
+        fetched_at: UtcDateTime::parse_rfc3339("2025-06-01T10:00:00Z").unwrap(),
+        page: 2,
+        duoload_version: "0.1.2".to_string(),
+    });
+    builder.add_note(card).unwrap();
+    builder
+        .add_note(create_test_card("Baum", "tree", None, LearningStatus::New))
+        .unwrap();
+
+    let mut output = Vec::new();
+    builder
+        .write(OutputDestination::Writer(&mut output))
+        .unwrap();
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "word,provenance\n\
+         Haus,\"deck RGVjazox, page 2, fetched 2025-06-01T10:00:00Z, duoload 0.1.2\"\n\
+         Baum,\n"
+    );
+}
@@ -1,6 +1,8 @@
-use duoload::duocards::models::{LearningStatus, VocabularyCard};
+use duoload::duocards::models::{DeckMetadata, LearningStatus, VocabularyCard};
 use duoload::output::json::JsonOutputBuilder;
+use duoload::output::schema;
 use duoload::output::{OutputBuilder, OutputDestination};
+use duoload::transfer::duplicates::{DedupeKey, DuplicatePolicy};
 use std::fs::{self, File};
 use std::io::{BufWriter, Write};
 use tempfile::NamedTempFile;
@@ -16,6 +18,7 @@ fn create_test_card(
         translation: translation.to_string(),
         example: example.map(|s| s.to_string()),
         status,
+        ..Default::default()
     }
 }
 
@@ -98,6 +101,7 @@ fn test_write_invalid_path() {
         translation: "prueba".to_string(),
         example: Some("This is a test".to_string()),
         status: LearningStatus::New,
+        ..Default::default()
     };
     builder.add_note(card).unwrap();
 
@@ -105,10 +109,7 @@ fn test_write_invalid_path() {
     struct FailingWriter;
     impl Write for FailingWriter {
         fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
-            Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Test write error",
-            ))
+            Err(std::io::Error::other("Test write error"))
         }
         fn flush(&mut self) -> std::io::Result<()> {
             Ok(())
@@ -137,3 +138,346 @@ fn test_empty_deck() {
     let cards: Vec<VocabularyCard> = serde_json::from_str(&content).unwrap();
     assert!(cards.is_empty());
 }
+
+#[test]
+fn test_duplicate_policy_last() {
+    let mut builder = JsonOutputBuilder::new();
+    builder.set_duplicate_policy(DuplicatePolicy::Last);
+
+    let card1 = create_test_card("hello", "hola", Some("Hello, world!"), LearningStatus::New);
+    assert!(builder.add_note(card1).unwrap());
+
+    let card2 = create_test_card("hello", "bonjour", None, LearningStatus::Known);
+    assert!(!builder.add_note(card2).unwrap());
+
+    let mut output = Vec::new();
+    builder
+        .write(OutputDestination::Writer(&mut output))
+        .unwrap();
+
+    let cards: Vec<VocabularyCard> = serde_json::from_slice(&output).unwrap();
+    assert_eq!(cards.len(), 1);
+    assert_eq!(cards[0].translation, "bonjour");
+    assert_eq!(cards[0].status, LearningStatus::Known);
+}
+
+/// A writer whose content can be inspected while the builder still owns it
+#[derive(Clone, Default)]
+struct SharedBuffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedBuffer {
+    fn lines(&self) -> Vec<String> {
+        String::from_utf8(self.0.lock().unwrap().clone())
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+#[test]
+fn test_ndjson_streams_cards() {
+    let buffer = SharedBuffer::default();
+    let mut builder = JsonOutputBuilder::ndjson(buffer.clone());
+
+    let card = create_test_card("hello", "hola", Some("Hello, world!"), LearningStatus::New);
+    assert!(builder.add_note(card).unwrap());
+    // Written before the builder is asked to write
+    assert_eq!(buffer.lines().len(), 1);
+
+    assert!(
+        !builder
+            .add_note(create_test_card(
+                "hello",
+                "adiós",
+                None,
+                LearningStatus::Known
+            ))
+            .unwrap()
+    );
+    assert!(
+        builder
+            .add_note(create_test_card(
+                "goodbye",
+                "adiós",
+                None,
+                LearningStatus::Known
+            ))
+            .unwrap()
+    );
+    builder
+        .write(OutputDestination::Writer(&mut Vec::new()))
+        .unwrap();
+
+    let cards: Vec<VocabularyCard> = buffer
+        .lines()
+        .iter()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(cards.len(), 2);
+    assert_eq!(cards[0].translation, "hola");
+    assert_eq!(cards[1].word, "goodbye");
+}
+
+#[test]
+fn test_ndjson_duplicate_policies() {
+    let mut builder = JsonOutputBuilder::ndjson(SharedBuffer::default());
+    builder.set_duplicate_policy(DuplicatePolicy::KeepAll);
+    let card = create_test_card("hello", "hola", None, LearningStatus::New);
+    assert!(builder.add_note(card.clone()).unwrap());
+    assert!(builder.add_note(card.clone()).unwrap());
+
+    // Merging would have to rewrite a line that is already out
+    let mut builder = JsonOutputBuilder::ndjson(SharedBuffer::default());
+    builder.set_duplicate_policy(DuplicatePolicy::Merge);
+    assert!(builder.add_note(card.clone()).unwrap());
+    assert!(builder.add_note(card).is_err());
+}
+
+#[test]
+fn test_dedupe_key_word_translation() {
+    let bank = create_test_card("bank", "Bank", None, LearningStatus::New);
+    let shore = create_test_card("bank", "Ufer", None, LearningStatus::New);
+
+    let mut builder = JsonOutputBuilder::new();
+    builder.set_dedupe_key(DedupeKey::WordTranslation);
+    assert!(builder.add_note(bank.clone()).unwrap());
+    assert!(builder.add_note(shore.clone()).unwrap());
+    assert!(!builder.add_note(bank.clone()).unwrap());
+
+    let mut builder = JsonOutputBuilder::ndjson(SharedBuffer::default());
+    builder.set_dedupe_key(DedupeKey::WordTranslation);
+    assert!(builder.add_note(bank.clone()).unwrap());
+    assert!(builder.add_note(shore).unwrap());
+    assert!(!builder.add_note(bank).unwrap());
+}
+
+#[test]
+fn test_ndjson_count_written_notes() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut builder = JsonOutputBuilder::ndjson(File::create(&temp_file).unwrap());
+    for word in ["one", "two", "three"] {
+        builder
+            .add_note(create_test_card(
+                word,
+                "translation",
+                None,
+                LearningStatus::New,
+            ))
+            .unwrap();
+    }
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    assert_eq!(
+        builder.count_written_notes(temp_file.path()).unwrap(),
+        Some(3)
+    );
+}
+
+#[test]
+fn test_ndjson_file_appears_when_written() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("deck.jsonl");
+    fs::write(&path, "earlier export\n").unwrap();
+
+    let mut builder = JsonOutputBuilder::ndjson_file(&path).unwrap();
+    for word in ["one", "two"] {
+        builder
+            .add_note(create_test_card(
+                word,
+                "translation",
+                None,
+                LearningStatus::New,
+            ))
+            .unwrap();
+    }
+    // Lines stream into a temporary file until the export is complete
+    assert_eq!(fs::read_to_string(&path).unwrap(), "earlier export\n");
+
+    builder.write(OutputDestination::File(&path)).unwrap();
+    assert_eq!(builder.count_written_notes(&path).unwrap(), Some(2));
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn test_ndjson_file_dropped_without_write() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("deck.jsonl");
+
+    let mut builder = JsonOutputBuilder::ndjson_file(&path).unwrap();
+    builder
+        .add_note(create_test_card(
+            "one",
+            "translation",
+            None,
+            LearningStatus::New,
+        ))
+        .unwrap();
+    drop(builder);
+
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+}
+
+fn test_metadata() -> DeckMetadata {
+    DeckMetadata {
+        id: "deck1".to_string(),
+        name: Some("German".to_string()),
+        source_language: Some("de".to_string()),
+        target_language: Some("en".to_string()),
+    }
+}
+
+#[test]
+fn test_metadata_header() {
+    let mut builder = JsonOutputBuilder::new().with_metadata_header(true);
+    builder.set_deck_metadata(&test_metadata());
+    builder
+        .add_note(create_test_card("Haus", "house", None, LearningStatus::New))
+        .unwrap();
+
+    let temp_file = NamedTempFile::new().unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    let document: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(temp_file.path()).unwrap()).unwrap();
+    assert_eq!(
+        document["deck"],
+        serde_json::json!({
+            "id": "deck1",
+            "name": "German",
+            "source_language": "de",
+            "target_language": "en"
+        })
+    );
+    assert_eq!(document["cards"][0]["word"], "Haus");
+    assert_eq!(
+        builder.count_written_notes(temp_file.path()).unwrap(),
+        Some(1)
+    );
+
+    // Without the option the metadata is ignored
+    let mut builder = JsonOutputBuilder::new();
+    builder.set_deck_metadata(&test_metadata());
+    let mut output = Vec::new();
+    builder
+        .write(OutputDestination::Writer(&mut output))
+        .unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "[]");
+}
+
+#[test]
+fn test_compact_envelope() {
+    let mut builder = JsonOutputBuilder::new()
+        .with_envelope(true)
+        .with_pretty(false);
+    builder.set_deck_metadata(&test_metadata());
+    for translation in ["house", "home"] {
+        builder
+            .add_note(create_test_card(
+                "Haus",
+                translation,
+                None,
+                LearningStatus::New,
+            ))
+            .unwrap();
+    }
+
+    let temp_file = NamedTempFile::new().unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    let content = std::fs::read_to_string(temp_file.path()).unwrap();
+    assert!(!content.contains('\n'));
+    let document: serde_json::Value = serde_json::from_str(&content).unwrap();
+    assert_eq!(document["deck"]["name"], "German");
+    assert_eq!(document["stats"]["cards"], 1);
+    assert_eq!(document["stats"]["duplicates"], 1);
+    assert!(document["stats"]["exported_at"].is_string());
+    assert_eq!(document["cards"][0]["translation"], "house");
+    assert_eq!(
+        builder.count_written_notes(temp_file.path()).unwrap(),
+        Some(1)
+    );
+}
+
+#[test]
+fn test_ndjson_metadata_line() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut builder =
+        JsonOutputBuilder::ndjson(File::create(&temp_file).unwrap()).with_metadata_header(true);
+    builder.set_deck_metadata(&test_metadata());
+    builder
+        .add_note(create_test_card("Haus", "house", None, LearningStatus::New))
+        .unwrap();
+    builder
+        .write(OutputDestination::File(temp_file.path()))
+        .unwrap();
+
+    let content = std::fs::read_to_string(temp_file.path()).unwrap();
+    let lines: Vec<serde_json::Value> = content
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["deck"]["name"], "German");
+    assert_eq!(lines[1]["word"], "Haus");
+    assert_eq!(
+        builder.count_written_notes(temp_file.path()).unwrap(),
+        Some(1)
+    );
+}
+
+#[test]
+fn test_output_matches_schema() {
+    let mut card = create_test_card("hello", "hola", Some("Hello!"), LearningStatus::Known);
+    card.hint = Some("greeting".to_string());
+    card.image = Some("https://example.com/hello.png".to_string());
+    card.source_id = Some("Q2FyZDox".to_string());
+    card.created_at = Some("2024-01-02T03:04:05Z".to_string());
+    card.known_count = Some(5);
+    card.labels = vec!["Basics".to_string()];
+    let deck = DeckMetadata {
+        id: "deck".to_string(),
+        name: Some("Spanish".to_string()),
+        source_language: Some("en".to_string()),
+        target_language: None,
+    };
+
+    for (metadata, envelope) in [(false, false), (true, false), (false, true)] {
+        let mut builder = JsonOutputBuilder::new()
+            .with_metadata_header(metadata)
+            .with_envelope(envelope)
+            .with_validation(true);
+        builder.set_deck_metadata(&deck);
+        builder.add_note(card.clone()).unwrap();
+        let mut output = Vec::new();
+        builder
+            .write(OutputDestination::Writer(&mut output))
+            .unwrap();
+        let document: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        schema::validate_document(&document).unwrap();
+    }
+
+    let buffer = SharedBuffer::default();
+    let mut builder = JsonOutputBuilder::ndjson(buffer.clone())
+        .with_metadata_header(true)
+        .with_validation(true);
+    builder.set_deck_metadata(&deck);
+    builder.add_note(card).unwrap();
+    assert_eq!(buffer.lines().len(), 2);
+}
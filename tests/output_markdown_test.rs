@@ -0,0 +1,87 @@
+use duoload::duocards::models::{DeckMetadata, LearningStatus, VocabularyCard};
+use duoload::output::markdown::{MarkdownOutputBuilder, MarkdownStyle};
+use duoload::output::{OutputBuilder, OutputDestination};
+use tempfile::tempdir;
+
+fn create_test_card(word: &str, translation: &str, example: Option<&str>) -> VocabularyCard {
+    VocabularyCard {
+        word: word.to_string(),
+        translation: translation.to_string(),
+        example: example.map(|s| s.to_string()),
+        status: LearningStatus::New,
+        ..Default::default()
+    }
+}
+
+fn test_builder(style: MarkdownStyle) -> MarkdownOutputBuilder {
+    let mut builder = MarkdownOutputBuilder::new().with_style(style);
+    builder.set_deck_metadata(&DeckMetadata {
+        name: Some("German".to_string()),
+        ..DeckMetadata::default()
+    });
+    builder
+        .add_note(create_test_card(
+            "Haus",
+            "house",
+            Some("Das Haus ist groß.\n\nIt's a | pipe"),
+        ))
+        .unwrap();
+    builder
+        .add_note(create_test_card("Baum", "tree::plant", None))
+        .unwrap();
+    builder
+}
+
+fn write_to_string(builder: &MarkdownOutputBuilder) -> String {
+    let mut output = Vec::new();
+    builder
+        .write(OutputDestination::Writer(&mut output))
+        .unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn test_table() {
+    assert_eq!(
+        write_to_string(&test_builder(MarkdownStyle::Table)),
+        "# German\n\
+         \n\
+         | Word | Translation | Example |\n\
+         | --- | --- | --- |\n\
+         | Haus | house | Das Haus ist groß.<br><br>It's a \\| pipe |\n\
+         | Baum | tree::plant |  |\n"
+    );
+}
+
+#[test]
+fn test_obsidian_flashcards() {
+    assert_eq!(
+        write_to_string(&test_builder(MarkdownStyle::Obsidian)),
+        "# German\n\
+         \n\
+         #flashcards\n\
+         \n\
+         Haus\n\
+         ?\n\
+         house\n\
+         *Das Haus ist groß.*\n\
+         *It's a | pipe*\n\
+         \n\
+         Baum::tree::plant\n"
+    );
+}
+
+#[test]
+fn test_count_written_notes() {
+    let dir = tempdir().unwrap();
+    for style in [MarkdownStyle::Table, MarkdownStyle::Obsidian] {
+        let builder = test_builder(style);
+        let path = dir.path().join("deck.md");
+        builder.write(OutputDestination::File(&path)).unwrap();
+        assert_eq!(builder.count_written_notes(&path).unwrap(), Some(2));
+
+        let empty = MarkdownOutputBuilder::new().with_style(style);
+        empty.write(OutputDestination::File(&path)).unwrap();
+        assert_eq!(empty.count_written_notes(&path).unwrap(), Some(0));
+    }
+}
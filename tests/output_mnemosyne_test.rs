@@ -0,0 +1,100 @@
+use duoload::duocards::models::{DeckMetadata, LearningStatus, VocabularyCard};
+use duoload::output::mnemosyne::MnemosyneOutputBuilder;
+use duoload::output::{OutputBuilder, OutputDestination};
+use std::time::{Duration, UNIX_EPOCH};
+use tempfile::tempdir;
+
+fn create_test_card(word: &str, translation: &str, example: Option<&str>) -> VocabularyCard {
+    VocabularyCard {
+        word: word.to_string(),
+        translation: translation.to_string(),
+        example: example.map(|s| s.to_string()),
+        status: LearningStatus::New,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_write_to_writer() {
+    let mut builder = MnemosyneOutputBuilder::new()
+        .with_export_time(UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+    builder.set_deck_metadata(&DeckMetadata {
+        name: Some("Rock & Roll".to_string()),
+        ..DeckMetadata::default()
+    });
+    builder
+        .add_note(create_test_card(
+            "<b>Haus</b>",
+            "house",
+            Some("Das \"Haus\" ist groß."),
+        ))
+        .unwrap();
+
+    let mut output = Vec::new();
+    builder
+        .write(OutputDestination::Writer(&mut output))
+        .unwrap();
+    let xml = String::from_utf8(output).unwrap();
+
+    assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n"));
+    assert!(xml.contains(r#"<mnemosyne core_version="1" time_of_start="1700000000">"#));
+    assert!(xml.contains("<name>Rock &amp; Roll</name>"));
+    assert!(xml.contains("<cat>Rock &amp; Roll</cat>"));
+    assert!(xml.contains("<Q>&lt;b&gt;Haus&lt;/b&gt;</Q>"));
+    assert!(xml.contains("<A>house\nDas &quot;Haus&quot; ist groß.</A>"));
+    assert!(xml.ends_with("</mnemosyne>\n"));
+}
+
+#[test]
+fn test_write_to_file_and_count() {
+    let mut builder = MnemosyneOutputBuilder::new().with_category("German");
+    builder.set_deck_metadata(&DeckMetadata {
+        name: Some("Ignored".to_string()),
+        ..DeckMetadata::default()
+    });
+    assert!(
+        builder
+            .add_note(create_test_card("Haus", "house", None))
+            .unwrap()
+    );
+    assert!(
+        builder
+            .add_note(create_test_card("Baum", "tree", None))
+            .unwrap()
+    );
+    assert!(
+        !builder
+            .add_note(create_test_card("Haus", "home", None))
+            .unwrap()
+    );
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("deck.xml");
+    builder.write(OutputDestination::File(&path)).unwrap();
+
+    let xml = std::fs::read_to_string(&path).unwrap();
+    assert!(xml.contains("<cat>German</cat>"));
+    assert_eq!(builder.count_written_notes(&path).unwrap(), Some(2));
+}
+
+#[test]
+fn test_item_ids_are_stable() {
+    let write = |card: VocabularyCard| {
+        let mut builder = MnemosyneOutputBuilder::new();
+        builder.add_note(card).unwrap();
+        let mut output = Vec::new();
+        builder
+            .write(OutputDestination::Writer(&mut output))
+            .unwrap();
+        let xml = String::from_utf8(output).unwrap();
+        let start = xml.find("<item id=\"").unwrap() + 10;
+        xml[start..start + 8].to_string()
+    };
+
+    let id = write(create_test_card("Haus", "house", None));
+    assert_eq!(
+        id,
+        write(create_test_card("Haus", "house", Some("Example")))
+    );
+    assert_ne!(id, write(create_test_card("Haus", "home", None)));
+}
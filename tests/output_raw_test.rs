@@ -0,0 +1,72 @@
+use duoload::output::OutputDestination;
+use duoload::output::raw::{raw_cards, write_raw_cards};
+use serde_json::{Value, json};
+
+fn page(cards: &[(&str, &str, i32)], has_next_page: bool) -> Value {
+    let edges: Vec<Value> = cards
+        .iter()
+        .map(|(id, front, known_count)| {
+            json!({
+                "node": {
+                    "id": id,
+                    "front": front,
+                    "back": "translation",
+                    "hint": null,
+                    "waiting": {"days": 3},
+                    "knownCount": known_count,
+                    "svg": {"flatId": "flat", "url": null, "id": "svg-id"},
+                    "__typename": "Card"
+                },
+                "cursor": format!("cursor-{}", id)
+            })
+        })
+        .collect();
+    json!({
+        "data": {
+            "node": {
+                "__typename": "Deck",
+                "id": "deck",
+                "cards": {
+                    "edges": edges,
+                    "pageInfo": {"endCursor": null, "hasNextPage": has_next_page}
+                }
+            }
+        },
+        "extensions": {"releaseId": "2025-06-04T14:06:15.707Z"}
+    })
+}
+
+#[test]
+fn test_raw_cards_keep_api_fields() {
+    let pages = vec![
+        page(&[("1", "Haus", 2), ("2", "Hund", 0)], true),
+        page(&[("3", "Katze", 5)], false),
+    ];
+
+    let cards = raw_cards(&pages).unwrap();
+
+    assert_eq!(cards.len(), 3);
+    assert_eq!(cards[2].card.front, "Katze");
+    assert_eq!(cards[2].cursor, "cursor-3");
+
+    let mut output = Vec::new();
+    write_raw_cards(&cards, OutputDestination::Writer(&mut output)).unwrap();
+    let written: Value = serde_json::from_slice(&output).unwrap();
+
+    assert_eq!(
+        written[0],
+        json!({
+            "id": "1",
+            "front": "Haus",
+            "back": "translation",
+            "hint": null,
+            "createdAt": null,
+            "waiting": {"days": 3},
+            "knownCount": 2,
+            "svg": {"flatId": "flat", "url": null, "id": "svg-id"},
+            "audio": null,
+            "__typename": "Card",
+            "cursor": "cursor-1"
+        })
+    );
+}
@@ -0,0 +1,142 @@
+use duoload::duocards::models::{LearningStatus, VocabularyCard};
+use duoload::error::DuoloadError;
+use duoload::output::sqlite::SqliteOutputBuilder;
+use duoload::output::{OutputBuilder, OutputDestination};
+use rusqlite::Connection;
+use tempfile::tempdir;
+
+fn create_test_card(
+    word: &str,
+    translation: &str,
+    example: Option<&str>,
+    status: LearningStatus,
+) -> VocabularyCard {
+    VocabularyCard {
+        word: word.to_string(),
+        translation: translation.to_string(),
+        example: example.map(|s| s.to_string()),
+        status,
+        ..Default::default()
+    }
+}
+
+fn rows(path: &std::path::Path) -> Vec<(String, String, Option<String>, String, String)> {
+    let connection = Connection::open(path).unwrap();
+    let mut select = connection
+        .prepare(
+            "SELECT word, translation, example, status, deck_id FROM cards
+             ORDER BY deck_id, rowid",
+        )
+        .unwrap();
+    select
+        .query_map([], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+            ))
+        })
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+#[test]
+fn test_write_to_file_and_count() {
+    let mut builder = SqliteOutputBuilder::new("deck1");
+    builder
+        .add_note(create_test_card(
+            "hello",
+            "hola",
+            Some("Hello, world!"),
+            LearningStatus::New,
+        ))
+        .unwrap();
+    builder
+        .add_note(create_test_card(
+            "goodbye",
+            "adiós",
+            None,
+            LearningStatus::Known,
+        ))
+        .unwrap();
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("deck.db");
+    builder.write(OutputDestination::File(&path)).unwrap();
+
+    assert_eq!(builder.count_written_notes(&path).unwrap(), Some(2));
+    assert_eq!(
+        rows(&path),
+        vec![
+            (
+                "hello".to_string(),
+                "hola".to_string(),
+                Some("Hello, world!".to_string()),
+                "new".to_string(),
+                "deck1".to_string()
+            ),
+            (
+                "goodbye".to_string(),
+                "adiós".to_string(),
+                None,
+                "known".to_string(),
+                "deck1".to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_reexport_replaces_rows_of_the_same_deck() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("decks.db");
+
+    let mut first = SqliteOutputBuilder::new("deck1");
+    first
+        .add_note(create_test_card("old", "viejo", None, LearningStatus::New))
+        .unwrap();
+    first.write(OutputDestination::File(&path)).unwrap();
+
+    let mut other = SqliteOutputBuilder::new("deck2");
+    other
+        .add_note(create_test_card("other", "otro", None, LearningStatus::New))
+        .unwrap();
+    other.write(OutputDestination::File(&path)).unwrap();
+
+    let mut second = SqliteOutputBuilder::new("deck1");
+    second
+        .add_note(create_test_card(
+            "new",
+            "nuevo",
+            None,
+            LearningStatus::Learning,
+        ))
+        .unwrap();
+    second.write(OutputDestination::File(&path)).unwrap();
+
+    let words: Vec<_> = rows(&path)
+        .into_iter()
+        .map(|(word, _, _, _, deck_id)| (word, deck_id))
+        .collect();
+    assert_eq!(
+        words,
+        vec![
+            ("new".to_string(), "deck1".to_string()),
+            ("other".to_string(), "deck2".to_string()),
+        ]
+    );
+    assert_eq!(second.count_written_notes(&path).unwrap(), Some(1));
+}
+
+#[test]
+fn test_writer_destination_not_supported() {
+    let builder = SqliteOutputBuilder::new("deck1");
+    let mut output = Vec::new();
+    assert!(matches!(
+        builder.write(OutputDestination::Writer(&mut output)),
+        Err(DuoloadError::SqliteOutputNotSupported)
+    ));
+}
@@ -0,0 +1,69 @@
+use duoload::duocards::models::{LearningStatus, VocabularyCard};
+use duoload::output::yaml::YamlOutputBuilder;
+use duoload::output::{OutputBuilder, OutputDestination};
+use tempfile::tempdir;
+
+fn create_test_card(word: &str, translation: &str, example: Option<&str>) -> VocabularyCard {
+    VocabularyCard {
+        word: word.to_string(),
+        translation: translation.to_string(),
+        example: example.map(|s| s.to_string()),
+        status: LearningStatus::New,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_write_to_writer() {
+    let mut builder = YamlOutputBuilder::new();
+    builder
+        .add_note(create_test_card(
+            "hello",
+            "hallo",
+            Some("Hallo, wie geht's?"),
+        ))
+        .unwrap();
+    builder
+        .add_note(create_test_card("true", "no: really", None))
+        .unwrap();
+
+    let mut output = Vec::new();
+    builder
+        .write(OutputDestination::Writer(&mut output))
+        .unwrap();
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "- word: hello\n  \
+         translation: hallo\n  \
+         example: Hallo, wie geht's?\n  \
+         status: new\n\
+         - word: 'true'\n  \
+         translation: 'no: really'\n  \
+         example: null\n  \
+         status: new\n"
+    );
+}
+
+#[test]
+fn test_write_to_file_and_count() {
+    let mut builder = YamlOutputBuilder::new();
+    builder
+        .add_note(create_test_card("line", "línea", Some("first\nsecond")))
+        .unwrap();
+    builder
+        .add_note(create_test_card("line", "duplicate", None))
+        .unwrap();
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("deck.yaml");
+    builder.write(OutputDestination::File(&path)).unwrap();
+
+    let cards: Vec<VocabularyCard> =
+        serde_norway::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(
+        cards,
+        [create_test_card("line", "línea", Some("first\nsecond"))]
+    );
+    assert_eq!(builder.count_written_notes(&path).unwrap(), Some(1));
+}
@@ -0,0 +1,59 @@
+use duoload::duocards::network::NetworkOptions;
+use duoload::transfer::progress::{ProgressEvent, ProgressReporter};
+use duoload::transfer::webhook::WebhookReporter;
+use mockito::{Matcher, Server};
+use serde_json::json;
+
+#[tokio::test]
+async fn test_posts_events_in_order() {
+    let mut server = Server::new_async().await;
+    let page = server
+        .mock("POST", "/progress")
+        .match_body(Matcher::PartialJson(
+            json!({"event": "page_fetched", "page": 1, "cards": 20}),
+        ))
+        .with_status(204)
+        .expect(1)
+        .create_async()
+        .await;
+    let failed = server
+        .mock("POST", "/progress")
+        .match_body(Matcher::PartialJson(
+            json!({"event": "failed", "error": "boom"}),
+        ))
+        .with_status(204)
+        .expect(1)
+        .create_async()
+        .await;
+
+    let url = format!("{}/progress", server.url()).parse().unwrap();
+    let webhook = WebhookReporter::new(url, &NetworkOptions::default()).unwrap();
+    // Not posted: the start of a fetch is not logged
+    webhook.report(&ProgressEvent::FetchingPage { page: 1 });
+    webhook.report(&ProgressEvent::PageFetched { page: 1, cards: 20 });
+    webhook.finish(Some("boom".to_string())).await;
+
+    page.assert_async().await;
+    failed.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_unreachable_webhook_does_not_fail() {
+    let mut server = Server::new_async().await;
+    let mock = server
+        .mock("POST", "/progress")
+        .with_status(500)
+        .expect(2)
+        .create_async()
+        .await;
+
+    let url = format!("{}/progress", server.url()).parse().unwrap();
+    let webhook = WebhookReporter::new(url, &NetworkOptions::default()).unwrap();
+    webhook.report(&ProgressEvent::Writing);
+    webhook.report(&ProgressEvent::Written);
+    webhook.finish(None).await;
+
+    // Events after finishing are dropped
+    webhook.report(&ProgressEvent::Written);
+    mock.assert_async().await;
+}